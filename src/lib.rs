@@ -0,0 +1,1021 @@
+// The matching engine as a library: orders, wire prices, the book feeds and
+// the order book itself. The server binary adds the DB, HTTP and wiring.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub mod matching;
+
+pub use matching::{AuctionResult, BookSide, BookWrite, Fill, MatchMode, MatchPrice, MatchingStrategy, OcoPolicy, OrderBook, PriceTime, ProRata, SizePriority, TradeTotals};
+
+// --- Core Data Structures ---
+
+// Unique ID for each order
+pub type OrderId = u64;
+
+// Represents Buy or Sell
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    pub fn from_db(value: &str) -> Option<Self> {
+        match value {
+            "Buy" => Some(Side::Buy),
+            "Sell" => Some(Side::Sell),
+            _ => None,
+        }
+    }
+}
+
+// Limit orders rest at their price; market orders take whatever the book
+// offers and never rest.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderType {
+    #[default]
+    Limit,
+    Market,
+}
+
+// Good-till-cancelled orders rest whatever is left after matching; fill-or-kill
+// orders trade their full quantity on arrival or not at all.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TimeInForce {
+    #[default]
+    Gtc,
+    Fok,
+}
+
+// Represents the state of an order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderStatus {
+    Open,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Expired,
+    // Fill-or-kill that could not fill in full; never touched the book or DB
+    Killed,
+    // Post-only that would have taken liquidity; never touched the book or DB
+    Rejected,
+}
+
+impl OrderStatus {
+    pub fn from_db(value: &str) -> Option<Self> {
+        match value {
+            "Open" => Some(OrderStatus::Open),
+            "PartiallyFilled" => Some(OrderStatus::PartiallyFilled),
+            "Filled" => Some(OrderStatus::Filled),
+            "Cancelled" => Some(OrderStatus::Cancelled),
+            "Expired" => Some(OrderStatus::Expired),
+            "Killed" => Some(OrderStatus::Killed),
+            "Rejected" => Some(OrderStatus::Rejected),
+            _ => None,
+        }
+    }
+}
+
+// Why an order left the book without filling, for reporting. Stored in the DB
+// by its variant name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CancelReason {
+    // DELETE /orders/:id, or a modify down to zero
+    UserRequest,
+    // Outlived the max order age or lifetime
+    Expiry,
+    // Another member of its one-cancels-other group filled
+    OcoTriggered,
+    // Market order left over once the book ran dry
+    MarketRemainder,
+    // Partially filled and cancelled on shutdown (OMS_SHUTDOWN_PARTIALS=cancel)
+    ShutdownPolicy,
+    // Cancelled rather than adjusted by a corporate action
+    CorporateAction,
+    // Set to cancelled by an authoritative source via /admin/orders/ensure
+    Reconciliation,
+    // Swept up by DELETE /orders
+    MassCancel,
+    // Aggressed into a resting order from its own account with `stp` set
+    SelfTradePrevention,
+    // Its client session's control connection closed
+    Disconnect,
+    // Would have traded on arrival, but less than its `min_qty`
+    MinQuantity,
+    // Its account was suspended via /admin/accounts/:id/suspend
+    Suspension,
+}
+
+impl CancelReason {
+    pub fn from_db(value: &str) -> Option<Self> {
+        match value {
+            "UserRequest" => Some(CancelReason::UserRequest),
+            "Expiry" => Some(CancelReason::Expiry),
+            "OcoTriggered" => Some(CancelReason::OcoTriggered),
+            "MarketRemainder" => Some(CancelReason::MarketRemainder),
+            "ShutdownPolicy" => Some(CancelReason::ShutdownPolicy),
+            "CorporateAction" => Some(CancelReason::CorporateAction),
+            "Reconciliation" => Some(CancelReason::Reconciliation),
+            "MassCancel" => Some(CancelReason::MassCancel),
+            "SelfTradePrevention" => Some(CancelReason::SelfTradePrevention),
+            "Disconnect" => Some(CancelReason::Disconnect),
+            "MinQuantity" => Some(CancelReason::MinQuantity),
+            "Suspension" => Some(CancelReason::Suspension),
+            _ => None,
+        }
+    }
+}
+
+// Our main Order structure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Order {
+    pub id: OrderId,
+    // Instrument; each symbol trades in its own book
+    #[serde(default = "default_symbol")]
+    pub symbol: String,
+    pub side: Side,
+    pub price: u64,
+    pub quantity: u64,
+    // Wall-clock entry time, for display and audit only
+    pub timestamp: u128,
+    // Time priority: a monotonic sequence assigned under the book lock, so a
+    // wall-clock step backwards can't let a later order jump the queue
+    #[serde(default)]
+    pub priority: u64,
+    // Original creation time; unlike `timestamp` it never changes after entry
+    #[serde(default)]
+    pub created_at: u128,
+    pub status: OrderStatus,
+    // One-cancels-other group; a fill on one member cancels the rest
+    pub group_id: Option<u64>,
+    // Sandboxed paper-trading order, never mixed with live flow
+    #[serde(default)]
+    pub paper: bool,
+    // When the last modify was accepted, for modify throttling
+    #[serde(skip)]
+    pub last_modified_at: Option<u128>,
+    // Original order this one carries extra size for (split increase)
+    #[serde(default)]
+    pub linked_to: Option<OrderId>,
+    // Do-not-reduce: cancelled rather than adjusted by a corporate action
+    #[serde(default)]
+    pub dnr: bool,
+    #[serde(default)]
+    pub order_type: OrderType,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    // Set once the order is cancelled or expired
+    #[serde(default)]
+    pub cancel_reason: Option<CancelReason>,
+    // Owning client account, for self-trade prevention
+    #[serde(default)]
+    pub account_id: Option<u64>,
+    // Self-trade prevention: cancelled instead of trading against a resting
+    // order from the same account
+    #[serde(default)]
+    pub stp: bool,
+    // Good-till-date: expired by the sweep once this time (unix nanos) passes
+    #[serde(default)]
+    pub expires_at: Option<u128>,
+    // Maker-only: rejected on entry rather than crossing the spread
+    #[serde(default)]
+    pub post_only: bool,
+    // Least it must trade on arrival, or it trades nothing
+    #[serde(default)]
+    pub min_qty: Option<u64>,
+    // Iceberg: only this much shows in the book and can be traded against at
+    // a time; the rest is shown a slice at a time as each one fills
+    #[serde(default)]
+    pub display_qty: Option<u64>,
+    // What is left of an iceberg's current slice; kept by the book
+    #[serde(skip)]
+    pub slice_left: u64,
+}
+
+// What clients see of an order. Kept separate from `Order` so internal fields
+// (e.g. the nanosecond priority timestamp) can change without breaking the API.
+// Serialized by hand, since its quantities are written at its symbol's scale.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderView {
+    pub id: OrderId,
+    pub symbol: String,
+    pub side: Side,
+    pub price: u64,
+    // Remaining (unfilled) quantity
+    pub quantity: u64,
+    pub status: OrderStatus,
+    pub group_id: Option<u64>,
+    pub paper: bool,
+    pub linked_to: Option<OrderId>,
+    pub dnr: bool,
+    pub order_type: OrderType,
+    pub time_in_force: TimeInForce,
+    pub cancel_reason: Option<CancelReason>,
+    pub account_id: Option<u64>,
+    pub stp: bool,
+    pub expires_at: Option<u128>,
+    pub post_only: bool,
+    pub min_qty: Option<u64>,
+    pub display_qty: Option<u64>,
+}
+
+impl Serialize for OrderView {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        fn optional<V: Serialize, S: SerializeStruct>(view: &mut S, key: &'static str, value: Option<V>) -> Result<(), S::Error> {
+            match value {
+                Some(value) => view.serialize_field(key, &value),
+                None => view.skip_field(key),
+            }
+        }
+
+        let scale = quantity_scale(&self.symbol);
+        let quantity = |units: u64| Fixed { units: units.into(), scale };
+        let mut view = serializer.serialize_struct("OrderView", 19)?;
+        view.serialize_field("id", &self.id)?;
+        view.serialize_field("symbol", &self.symbol)?;
+        view.serialize_field("side", &self.side)?;
+        view.serialize_field("price", &Price(self.price))?;
+        view.serialize_field("quantity", &quantity(self.quantity))?;
+        view.serialize_field("status", &self.status)?;
+        optional(&mut view, "group_id", self.group_id)?;
+        optional(&mut view, "paper", self.paper.then_some(true))?;
+        optional(&mut view, "linked_to", self.linked_to)?;
+        optional(&mut view, "dnr", self.dnr.then_some(true))?;
+        view.serialize_field("order_type", &self.order_type)?;
+        view.serialize_field("time_in_force", &self.time_in_force)?;
+        optional(&mut view, "cancel_reason", self.cancel_reason)?;
+        optional(&mut view, "account_id", self.account_id)?;
+        optional(&mut view, "stp", self.stp.then_some(true))?;
+        optional(&mut view, "expires_at", self.expires_at)?;
+        optional(&mut view, "post_only", self.post_only.then_some(true))?;
+        optional(&mut view, "min_qty", self.min_qty.map(quantity))?;
+        optional(&mut view, "display_qty", self.display_qty.map(quantity))?;
+        view.end()
+    }
+}
+
+impl From<&Order> for OrderView {
+    fn from(order: &Order) -> Self {
+        OrderView {
+            id: order.id,
+            symbol: order.symbol.clone(),
+            side: order.side.clone(),
+            price: order.price,
+            quantity: order.quantity,
+            status: order.status.clone(),
+            group_id: order.group_id,
+            paper: order.paper,
+            linked_to: order.linked_to,
+            dnr: order.dnr,
+            order_type: order.order_type,
+            time_in_force: order.time_in_force,
+            cancel_reason: order.cancel_reason,
+            account_id: order.account_id,
+            stp: order.stp,
+            expires_at: order.expires_at,
+            post_only: order.post_only,
+            min_qty: order.min_qty,
+            display_qty: order.display_qty,
+        }
+    }
+}
+
+// Current wall-clock time in nanoseconds since the Unix epoch
+pub fn now_nanos() -> u128 {
+    nanos_since_epoch(SystemTime::now())
+}
+
+// A clock stepped before the epoch reads as 0 instead of taking the process
+// down. Timestamps are for display and audit; ids keep orders unique and
+// priority comes from the book's own sequence.
+fn nanos_since_epoch(time: SystemTime) -> u128 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => elapsed.as_nanos(),
+        Err(e) => {
+            tracing::warn!(behind_nanos = %e.duration().as_nanos(), "System clock reads before the Unix epoch; using 0");
+            0
+        }
+    }
+}
+
+// --- Clocks ---
+
+// Where order timestamps come from. Only stamps entry and requeue times; time
+// priority is the book's own sequence, never the clock.
+pub trait Clock: fmt::Debug + Send + Sync {
+    // Nanoseconds since the Unix epoch
+    fn now_nanos(&self) -> u128;
+}
+
+// The wall clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_nanos(&self) -> u128 {
+        now_nanos()
+    }
+}
+
+// For tests: each read returns the current time, then moves it on by `step`,
+// so with a non-zero step every order is stamped strictly after the last.
+#[derive(Debug, Default)]
+pub struct MockClock {
+    now: AtomicU64,
+    step: u64,
+}
+
+impl MockClock {
+    // Stands still until set or advanced
+    pub fn new(start: u64) -> Self {
+        MockClock::ticking(start, 0)
+    }
+
+    pub fn ticking(start: u64, step: u64) -> Self {
+        MockClock { now: AtomicU64::new(start), step }
+    }
+
+    pub fn set(&self, nanos: u64) {
+        self.now.store(nanos, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, nanos: u64) {
+        self.now.fetch_add(nanos, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now_nanos(&self) -> u128 {
+        self.now.fetch_add(self.step, Ordering::SeqCst) as u128
+    }
+}
+
+impl Order {
+    // Stamped by the wall clock
+    pub fn new(id: OrderId, side: Side, price: u64, quantity: u64) -> Self {
+        Order::with_clock(id, side, price, quantity, &SystemClock)
+    }
+
+    pub fn with_clock(id: OrderId, side: Side, price: u64, quantity: u64, clock: &dyn Clock) -> Self {
+        let now = clock.now_nanos();
+        Order {
+            id,
+            symbol: DEFAULT_SYMBOL.to_string(),
+            side,
+            price,
+            quantity,
+            timestamp: now,
+            priority: 0,
+            created_at: now,
+            status: OrderStatus::Open,
+            group_id: None,
+            paper: false,
+            last_modified_at: None,
+            linked_to: None,
+            dnr: false,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            cancel_reason: None,
+            account_id: None,
+            stp: false,
+            expires_at: None,
+            post_only: false,
+            min_qty: None,
+            display_qty: None,
+            slice_left: 0,
+        }
+    }
+
+    // What the book shows and lets trade: all of it, or an iceberg's slice
+    pub fn visible_quantity(&self) -> u64 {
+        match self.display_qty {
+            Some(_) => self.slice_left.min(self.quantity),
+            None => self.quantity,
+        }
+    }
+
+    // Starts an iceberg's next slice once the last one is used up
+    pub fn show_slice(&mut self) {
+        if let Some(display_qty) = self.display_qty {
+            if self.slice_left == 0 {
+                self.slice_left = display_qty.min(self.quantity);
+            }
+        }
+    }
+
+    // Status after a modify resizes it: a partial fill stays partial,
+    // anything else is open again
+    pub fn resized_status(&self) -> OrderStatus {
+        match self.status {
+            OrderStatus::PartiallyFilled => OrderStatus::PartiallyFilled,
+            _ => OrderStatus::Open,
+        }
+    }
+}
+
+// --- Symbols ---
+
+// Book for requests that don't name a symbol, and for rows from before
+// symbols existed
+pub const DEFAULT_SYMBOL: &str = "DEFAULT";
+const MAX_SYMBOL_LEN: usize = 16;
+
+pub fn default_symbol() -> String {
+    DEFAULT_SYMBOL.to_string()
+}
+
+// Upper-case letters, digits, '.' and '-'. Keeps symbols clear of the
+// lower-case fixed routes under /book.
+pub fn validate_symbol(symbol: &str) -> Result<(), String> {
+    let valid = !symbol.is_empty()
+        && symbol.len() <= MAX_SYMBOL_LEN
+        && symbol.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'.' || b == b'-');
+    if !valid {
+        return Err(format!("symbol '{}' must be 1-{} upper-case letters, digits, '.' or '-'", symbol, MAX_SYMBOL_LEN));
+    }
+    Ok(())
+}
+
+// --- Prices ---
+
+// Smallest price increment as an exact decimal, `units` / 10^`scale`: "0.05"
+// is 5 at scale 2. Prices are fixed-point integers at the tick's scale, so on
+// a 0.05 tick "100.25" is 10025 and must be a multiple of 5. The book, the
+// matcher and the DB only ever see those integers; decimals exist on the wire
+// alone. Aggregates (notional, spread statistics) stay integer too. Changing
+// the tick's scale for an existing database rescales every price in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TickSize {
+    units: u64,
+    scale: u32,
+}
+
+// Finest tick accepted; keeps every rescaling factor within a u64
+const MAX_TICK_SCALE: u32 = 18;
+
+impl Default for TickSize {
+    // Whole units: prices on the wire are the integers themselves
+    fn default() -> Self {
+        TickSize { units: 1, scale: 0 }
+    }
+}
+
+impl TickSize {
+    pub fn parse(text: &str) -> Option<TickSize> {
+        let (units, scale) = parse_decimal(text.trim())?;
+        (units > 0 && scale <= MAX_TICK_SCALE).then_some(TickSize { units, scale })
+    }
+
+    // Fixed-point integer for a decimal price. Only the precision is checked
+    // here; whether it lands on a tick is `check`'s job.
+    pub fn to_units(&self, text: &str) -> Result<u64, String> {
+        let (mantissa, scale) = parse_decimal(text).ok_or_else(|| format!("'{}' is not a decimal price", text))?;
+        let scaled = self.scale.checked_sub(scale)
+            .ok_or_else(|| format!("price {} has more decimal places than the tick size {}", text, self))?;
+        10u64.checked_pow(scaled)
+            .and_then(|factor| mantissa.checked_mul(factor))
+            .ok_or_else(|| format!("price {} is out of range", text))
+    }
+
+    // Decimal form of a fixed-point price, with exactly `scale` fractional digits
+    pub fn format(&self, units: u64) -> String {
+        format_fixed(units.into(), self.scale)
+    }
+
+    // Fixed-point size of one tick
+    pub fn units(&self) -> u64 {
+        self.units
+    }
+
+    // Decimal places of the fixed-point prices
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    // Orders may only be priced on a tick
+    pub fn check(&self, price: u64) -> Result<(), String> {
+        if !price.is_multiple_of(self.units) {
+            return Err(format!("price {} is not a multiple of the tick size {}", self.format(price), self));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TickSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format(self.units))
+    }
+}
+
+// Decimal form of a fixed-point amount, with exactly `scale` fractional digits
+pub fn format_fixed(units: u128, scale: u32) -> String {
+    if scale == 0 {
+        return units.to_string();
+    }
+    let factor = 10u128.pow(scale);
+    format!("{}.{:0width$}", units / factor, units % factor, width = scale as usize)
+}
+
+// Unsigned decimal ("100", "100.25") as (mantissa, scale), trailing
+// fractional zeros dropped so "1.50" and "1.5" are the same number
+fn parse_decimal(text: &str) -> Option<(u64, u32)> {
+    let (whole, fraction) = match text.split_once('.') {
+        Some((_, "")) => return None,
+        Some(parts) => parts,
+        None => (text, ""),
+    };
+    if whole.is_empty() || !whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let fraction = fraction.trim_end_matches('0');
+    let scale = u32::try_from(fraction.len()).ok()?;
+    let mantissa = format!("{}{}", whole, fraction).parse().ok()?;
+    Some((mantissa, scale))
+}
+
+// Serde has no way to hand `Config::tick_size` to a `Price`, so the server
+// installs it here once at startup
+static TICK_SIZE: std::sync::OnceLock<TickSize> = std::sync::OnceLock::new();
+
+// Errs with the tick size already installed
+pub fn install_tick_size(tick: TickSize) -> Result<(), TickSize> {
+    TICK_SIZE.set(tick)
+}
+
+#[cfg(any(test, feature = "test-util"))]
+thread_local! {
+    // Lets a test use its own tick size without touching the process-wide one
+    pub static TEST_TICK_SIZE: std::cell::Cell<Option<TickSize>> = const { std::cell::Cell::new(None) };
+}
+
+fn tick_size() -> TickSize {
+    #[cfg(any(test, feature = "test-util"))]
+    if let Some(tick) = TEST_TICK_SIZE.with(|tick| tick.get()) {
+        return tick;
+    }
+    TICK_SIZE.get().copied().unwrap_or_default()
+}
+
+// A fixed-point price as the API sees it. Written as a JSON number when the
+// tick size is a whole unit, otherwise as a decimal string ("100.25") so no
+// client reads it through a float. Either form is accepted on input, bar
+// bare JSON floats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Price(u64);
+
+impl Price {
+    pub fn from_units(units: u64) -> Self {
+        Price(units)
+    }
+}
+
+impl Serialize for Price {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let tick = tick_size();
+        if tick.scale == 0 {
+            serializer.serialize_u64(self.0)
+        } else {
+            serializer.serialize_str(&tick.format(self.0))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Price {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PriceVisitor;
+
+        impl serde::de::Visitor<'_> for PriceVisitor {
+            type Value = Price;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a price as a whole number or a decimal string")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Price, E> {
+                self.visit_str(&value.to_string())
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Price, E> {
+                tick_size().to_units(value).map(Price).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(PriceVisitor)
+    }
+}
+
+// `#[serde(with)]` adapters so wire structs keep plain integer prices
+pub mod price_ticks {
+    use super::Price;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(ticks: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        Price(*ticks).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        Price::deserialize(deserializer).map(|price| price.0)
+    }
+
+    pub mod option {
+        use super::Price;
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        pub fn serialize<S: Serializer>(ticks: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error> {
+            ticks.map(Price).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+            Option::<Price>::deserialize(deserializer).map(|price| price.map(|price| price.0))
+        }
+    }
+}
+
+// --- Quantities ---
+
+// Quantities are fixed-point integers too, at a scale each symbol sets for
+// itself, independent of the price's: at quantity scale 4 "1.5" is 15000,
+// whatever the tick. A price times a quantity is then exact at the two scales
+// added. Scale 0, every symbol's default, keeps quantities whole numbers,
+// written as JSON numbers. Otherwise orders take and report them as decimal
+// strings, like prices; the market data feeds and the admin order payloads
+// carry the fixed-point units as stored.
+static QUANTITY_SCALES: std::sync::OnceLock<HashMap<String, u32>> = std::sync::OnceLock::new();
+
+// Finest quantity scale accepted; keeps every rescaling factor within a u64
+pub const MAX_QUANTITY_SCALE: u32 = 18;
+
+// Errs with the scales already installed
+pub fn install_quantity_scales(scales: HashMap<String, u32>) -> Result<(), HashMap<String, u32>> {
+    QUANTITY_SCALES.set(scales)
+}
+
+#[cfg(any(test, feature = "test-util"))]
+thread_local! {
+    // Lets a test scale its symbols' quantities without touching the process-wide scales
+    pub static TEST_QUANTITY_SCALES: std::cell::RefCell<Option<HashMap<String, u32>>> = const { std::cell::RefCell::new(None) };
+}
+
+pub fn quantity_scale(symbol: &str) -> u32 {
+    #[cfg(any(test, feature = "test-util"))]
+    if let Some(scale) = TEST_QUANTITY_SCALES.with(|scales| scales.borrow().as_ref().map(|scales| scales.get(symbol).copied().unwrap_or(0))) {
+        return scale;
+    }
+    QUANTITY_SCALES.get().and_then(|scales| scales.get(symbol)).copied().unwrap_or(0)
+}
+
+// A fixed-point amount as the API writes it: a JSON number at scale 0,
+// otherwise a decimal string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fixed {
+    pub units: u128,
+    pub scale: u32,
+}
+
+impl Serialize for Fixed {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match u64::try_from(self.units) {
+            Ok(units) if self.scale == 0 => serializer.serialize_u64(units),
+            _ if self.scale == 0 => serializer.serialize_u128(self.units),
+            _ => serializer.serialize_str(&format_fixed(self.units, self.scale)),
+        }
+    }
+}
+
+// A quantity as a client sent it: a whole number, or a decimal string. Which
+// symbol's scale it is at isn't known until the rest of the request is read,
+// so it stays an exact decimal until `units` is given the scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WireQuantity {
+    mantissa: u64,
+    scale: u32,
+}
+
+impl From<u64> for WireQuantity {
+    fn from(whole: u64) -> Self {
+        WireQuantity { mantissa: whole, scale: 0 }
+    }
+}
+
+impl fmt::Display for WireQuantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_fixed(self.mantissa.into(), self.scale))
+    }
+}
+
+impl WireQuantity {
+    // Fixed-point at `scale`; refused if written more finely than that
+    pub fn units(&self, scale: u32) -> Result<u64, String> {
+        let shift = scale.checked_sub(self.scale)
+            .ok_or_else(|| format!("quantity {} has more decimal places than the quantity scale {}", self, scale))?;
+        10u64.checked_pow(shift)
+            .and_then(|factor| self.mantissa.checked_mul(factor))
+            .ok_or_else(|| format!("quantity {} is out of range", self))
+    }
+}
+
+impl<'de> Deserialize<'de> for WireQuantity {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct QuantityVisitor;
+
+        impl serde::de::Visitor<'_> for QuantityVisitor {
+            type Value = WireQuantity;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a quantity as a whole number or a decimal string")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<WireQuantity, E> {
+                Ok(WireQuantity::from(value))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<WireQuantity, E> {
+                match parse_decimal(value) {
+                    Some((mantissa, scale)) if scale <= MAX_QUANTITY_SCALE => Ok(WireQuantity { mantissa, scale }),
+                    _ => Err(E::custom(format!("'{}' is not a decimal quantity", value))),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(QuantityVisitor)
+    }
+}
+
+// --- Order Book Delta Feed ---
+
+// Incremental change to the set of resting orders. Applying every delta with
+// `seq` greater than a snapshot's `seq`, in order, reproduces the live book.
+// Wire format:
+//   {"type":"update","seq":7,"order_id":3,"side":"Buy","price":100,"new_quantity":5}
+//   {"type":"remove","seq":8,"order_id":3,"side":"Buy","price":100}
+// `update` covers both a newly rested order and a quantity change; `remove`
+// covers every way an order leaves the book (fill, cancel).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BookDelta {
+    Update { seq: u64, order_id: OrderId, side: Side, #[serde(with = "price_ticks")] price: u64, new_quantity: u64 },
+    Remove { seq: u64, order_id: OrderId, side: Side, #[serde(with = "price_ticks")] price: u64 },
+}
+
+impl BookDelta {
+    pub fn seq(&self) -> u64 {
+        match self {
+            BookDelta::Update { seq, .. } | BookDelta::Remove { seq, .. } => *seq,
+        }
+    }
+}
+
+// --- Order Event Bus ---
+
+// Everything that happens to orders, in book order, for push feeds. Unlike
+// deltas these carry the whole order and include trades.
+// Wire format: {"type":"trade","bid_id":1,"ask_id":2,"price":100,"quantity":5}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BookEvent {
+    OrderAdded { order: OrderView },
+    OrderModified { order: OrderView },
+    // Cancelled or expired; `order.status` says which
+    OrderCancelled { order: OrderView },
+    Trade(Fill),
+}
+
+impl BookEvent {
+    // Book the event happened in
+    pub fn symbol(&self) -> &str {
+        match self {
+            BookEvent::OrderAdded { order } | BookEvent::OrderModified { order } | BookEvent::OrderCancelled { order } => &order.symbol,
+            BookEvent::Trade(fill) => &fill.symbol,
+        }
+    }
+}
+
+// FNV-1a over resting (order_id, side, price, quantity), ordered by order id, so
+// a client can verify a locally maintained book against the server's.
+pub fn book_checksum(entries: impl IntoIterator<Item = (OrderId, Side, u64, u64)>) -> u64 {
+    let mut entries: Vec<_> = entries.into_iter().collect();
+    entries.sort_by_key(|entry| entry.0);
+    let mut hash = FNV_OFFSET;
+    for (order_id, side, price, quantity) in entries {
+        let bytes = order_id.to_le_bytes().into_iter()
+            .chain(std::iter::once(side_byte(&side)))
+            .chain(price.to_le_bytes())
+            .chain(quantity.to_le_bytes());
+        fnv_mix(&mut hash, bytes);
+    }
+    hash
+}
+
+// Same idea for an aggregated book: FNV-1a over (side, price, total_quantity,
+// order_count) per level, ordered by side then price.
+pub fn level_checksum(levels: impl IntoIterator<Item = (Side, PriceLevel)>) -> u64 {
+    let mut levels: Vec<_> = levels.into_iter().map(|(side, level)| (side_byte(&side), level)).collect();
+    levels.sort_by_key(|(side, level)| (*side, level.price));
+    let mut hash = FNV_OFFSET;
+    for (side, level) in levels {
+        let bytes = std::iter::once(side)
+            .chain(level.price.to_le_bytes())
+            .chain(level.total_quantity.to_le_bytes())
+            .chain(level.order_count.to_le_bytes());
+        fnv_mix(&mut hash, bytes);
+    }
+    hash
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn fnv_mix(hash: &mut u64, bytes: impl IntoIterator<Item = u8>) {
+    for byte in bytes {
+        *hash ^= byte as u64;
+        *hash = hash.wrapping_mul(FNV_PRIME);
+    }
+}
+
+fn side_byte(side: &Side) -> u8 {
+    match side {
+        Side::Buy => 0,
+        Side::Sell => 1,
+    }
+}
+
+// All resting orders at one price on one side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceLevel {
+    #[serde(with = "price_ticks")]
+    pub price: u64,
+    // Shown quantity: an iceberg counts only its current slice. Saturates at u64::MAX.
+    pub total_quantity: u64,
+    pub order_count: u64,
+}
+
+fn aggregate_level<'a>(price: u64, orders: impl IntoIterator<Item = &'a Order>) -> PriceLevel {
+    orders.into_iter().fold(
+        PriceLevel { price, total_quantity: 0, order_count: 0 },
+        |level, o| PriceLevel { total_quantity: level.total_quantity.saturating_add(o.visible_quantity()), order_count: level.order_count + 1, ..level },
+    )
+}
+
+// Net change to one price level. A zero `total_quantity` means the level is gone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LevelDelta {
+    pub side: Side,
+    #[serde(flatten)]
+    pub level: PriceLevel,
+}
+
+// Everything a client needs to bring an aggregated book from `since` to `seq`:
+// the current state of every level that changed in between. `checksum` is the
+// level_checksum of the whole aggregated book at `seq`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LevelDeltas {
+    pub since: u64,
+    pub seq: u64,
+    pub checksum: u64,
+    pub levels: Vec<LevelDelta>,
+}
+
+// --- Binary Top of Book ---
+
+// Fixed 40-byte little-endian quote for consumers that want to skip JSON:
+//   offset  0  u64  seq           book sequence the quote was taken at
+//   offset  8  u64  bid_price     0 if there are no bids
+//   offset 16  u64  bid_quantity  total resting at bid_price
+//   offset 24  u64  ask_price     0 if there are no asks
+//   offset 32  u64  ask_quantity  total resting at ask_price
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TopOfBook {
+    pub seq: u64,
+    pub bid_price: u64,
+    pub bid_quantity: u64,
+    pub ask_price: u64,
+    pub ask_quantity: u64,
+}
+
+impl TopOfBook {
+    pub const ENCODED_LEN: usize = 40;
+
+    pub fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut buf = [0u8; Self::ENCODED_LEN];
+        let fields = [self.seq, self.bid_price, self.bid_quantity, self.ask_price, self.ask_quantity];
+        for (chunk, field) in buf.chunks_exact_mut(8).zip(fields) {
+            chunk.copy_from_slice(&field.to_le_bytes());
+        }
+        buf
+    }
+
+    // Client-side decoder; None if `bytes` isn't exactly one quote.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::ENCODED_LEN {
+            return None;
+        }
+        let field = |i: usize| u64::from_le_bytes(bytes[i * 8..(i + 1) * 8].try_into().unwrap());
+        Some(TopOfBook {
+            seq: field(0),
+            bid_price: field(1),
+            bid_quantity: field(2),
+            ask_price: field(3),
+            ask_quantity: field(4),
+        })
+    }
+}
+
+// --- Corporate Actions ---
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CorporateAction {
+    // `to` new shares for every `from` held, e.g. a 2:1 split is {to: 2, from: 1}
+    Split { to: u64, from: u64 },
+    // Per-share cash amount, in price ticks
+    CashDividend { #[serde(with = "price_ticks")] amount: u64 },
+}
+
+impl CorporateAction {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            CorporateAction::Split { to, from } if *to == 0 || *from == 0 => Err("split ratio terms must be non-zero".to_string()),
+            CorporateAction::CashDividend { amount: 0 } => Err("dividend amount must be non-zero".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdjustmentOutcome {
+    Adjusted,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OrderAdjustment {
+    pub order_id: OrderId,
+    pub outcome: AdjustmentOutcome,
+    #[serde(with = "price_ticks")]
+    pub old_price: u64,
+    #[serde(with = "price_ticks")]
+    pub new_price: u64,
+    pub old_quantity: u64,
+    pub new_quantity: u64,
+    // An iceberg's display size after the action
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_display_qty: Option<u64>,
+}
+
+// --- Unit Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_creation() {
+        let order = Order::new(1, Side::Buy, 100, 50);
+        assert_eq!(order.id, 1);
+        assert_eq!(order.status, OrderStatus::Open);
+    }
+
+    #[test]
+    fn test_mock_clock_stamps_orders_in_order() {
+        let clock = MockClock::ticking(1_000, 5);
+        let stamps: Vec<u128> = (1..=3).map(|id| Order::with_clock(id, Side::Buy, 100, 1, &clock).timestamp).collect();
+        assert_eq!(stamps, vec![1_000, 1_005, 1_010]);
+
+        let frozen = MockClock::new(42);
+        assert_eq!((frozen.now_nanos(), frozen.now_nanos()), (42, 42));
+        frozen.advance(8);
+        assert_eq!(frozen.now_nanos(), 50);
+        frozen.set(7);
+        let order = Order::with_clock(1, Side::Sell, 100, 1, &frozen);
+        assert_eq!((order.timestamp, order.created_at), (7, 7));
+    }
+
+    #[test]
+    fn test_clock_before_epoch_saturates_to_zero() {
+        use std::time::Duration;
+        assert_eq!(nanos_since_epoch(UNIX_EPOCH - Duration::from_secs(1)), 0);
+        assert_eq!(nanos_since_epoch(UNIX_EPOCH + Duration::from_nanos(5)), 5);
+    }
+
+    #[test]
+    fn test_top_of_book_binary_round_trip() {
+        let quote = TopOfBook { seq: 7, bid_price: 100, bid_quantity: 25, ask_price: 0x0102, ask_quantity: u64::MAX };
+        let bytes = quote.encode();
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&[7, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[100, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[25, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[0x02, 0x01, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[0xff; 8]);
+        assert_eq!(bytes.to_vec(), expected);
+        assert_eq!(TopOfBook::decode(&bytes), Some(quote));
+        assert_eq!(TopOfBook::decode(&bytes[..39]), None);
+    }
+}
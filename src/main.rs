@@ -1,30 +1,38 @@
 use axum::{
     routing::{get, post, put, delete},
     Router,
-    response::Json,
-    extract::{State, Path},
-    http::StatusCode,
+    response::{IntoResponse, IntoResponseParts, Json, Response, ResponseParts},
+    response::sse::{Event, KeepAlive, Sse},
+    extract::{ConnectInfo, Request, State, Path, Query},
+    handler::Handler,
+    middleware::{self, Next},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
 };
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::collections::VecDeque;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 
 // --- DB & Async Task Imports ---
-use rusqlite::{Connection, Result as SqlResult, params};
+use rusqlite::{Connection, OpenFlags, Result as SqlResult, params};
+use tokio::sync::broadcast;
 use tokio::task;
 
 // Tracing / Logging
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use tracing;
 
 // --- Standard Error and Formatting imports ---
 use std::error::Error as StdError; // Alias for clarity
 use std::fmt;
 
+// --- Matching Engine ---
+use low_latency_oms::*;
+
+// --- WebSocket Framing ---
+mod ws;
+
 // --- Custom Error for DB Conversion ---
 #[derive(Debug)]
 struct ConversionError(String); // Our custom error struct holding a String
@@ -37,698 +45,9401 @@ impl fmt::Display for ConversionError {
 
 impl StdError for ConversionError {} // Implement the Error trait
 
-// --- Core Data Structures ---
+// OMS_TICK_SIZE="0.01"; whole units when unset or invalid
+fn tick_size_from(value: Option<&str>) -> TickSize {
+    let Some(value) = value else {
+        return TickSize::default();
+    };
+    TickSize::parse(value).unwrap_or_else(|| {
+        tracing::warn!(value = %value, "Ignoring invalid OMS_TICK_SIZE; using whole units");
+        TickSize::default()
+    })
+}
+
+// --- Order Event Bus ---
+
+// Events a subscriber may fall behind by before it starts missing them; the
+// matching path never waits on subscribers.
+const EVENT_BUS_CAPACITY: usize = 4096;
+
+// --- Paper Trading ---
 
-// Unique ID for each order
-type OrderId = u64;
+const ORDERS_TABLE: &str = "orders";
+const PAPER_ORDERS_TABLE: &str = "paper_orders";
+const TRADES_TABLE: &str = "trades";
+const PAPER_TRADES_TABLE: &str = "paper_trades";
+const ORDER_EVENTS_TABLE: &str = "order_events";
+const PAPER_ORDER_EVENTS_TABLE: &str = "paper_order_events";
 
-// Represents Buy or Sell
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub enum Side {
-    Buy,
-    Sell,
+// Paper orders live in their own book and table so they never touch live state.
+fn orders_table(paper: bool) -> &'static str {
+    if paper { PAPER_ORDERS_TABLE } else { ORDERS_TABLE }
 }
 
-// Represents the state of an order
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub enum OrderStatus {
-    Open,
-    PartiallyFilled,
-    Filled,
-    Cancelled,
+// So do their trades and audit trails
+fn trades_table(paper: bool) -> &'static str {
+    if paper { PAPER_TRADES_TABLE } else { TRADES_TABLE }
 }
 
-// Our main Order structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Order {
-    id: OrderId,
-    side: Side,
-    price: u64,
-    quantity: u64,
-    timestamp: u128,
-    status: OrderStatus,
+fn order_events_table(paper: bool) -> &'static str {
+    if paper { PAPER_ORDER_EVENTS_TABLE } else { ORDER_EVENTS_TABLE }
 }
 
-impl Order {
-    pub fn new(id: OrderId, side: Side, price: u64, quantity: u64) -> Self {
-        Order {
-            id,
-            side,
-            price,
-            quantity,
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_nanos(),
-            status: OrderStatus::Open,
+// --- Background DB Writes ---
+
+// How long shutdown waits for in-flight background writes
+const SHUTDOWN_DRAIN_TIMEOUT_SECS: u64 = 30;
+
+// Failed writes kept for inspection; the oldest are dropped past this
+const DB_WRITE_FAILURES_KEPT: usize = 1000;
+
+// The DB writes for what the book changed by itself (fills, auction results,
+// OCO and self-trade cancels), made without awaiting them. One writer thread
+// commits them one at a time in the order they were queued, so a later fill
+// can never land before an earlier one. Counted, so shutdown can wait for them
+// instead of dropping them. Clones share the queue and the count.
+#[derive(Debug, Clone)]
+struct DbWrites {
+    inner: Arc<DbWritesInner>,
+}
+
+type DbWriteJob = Box<dyn FnOnce() -> SqlResult<()> + Send>;
+
+// One queued write; `done` keeps the count until it has run
+struct QueuedDbWrite {
+    write: &'static str,
+    detail: String,
+    run: DbWriteJob,
+    done: DbWriteDone,
+}
+
+#[derive(Debug)]
+struct DbWritesInner {
+    queue: std::sync::mpsc::Sender<QueuedDbWrite>,
+    pending: std::sync::atomic::AtomicUsize,
+    idle: tokio::sync::Notify,
+    failed_total: AtomicU64,
+    // Dead letters: the book already moved on, so each one is a divergence
+    // between memory and the DB until reconciled (e.g. /admin/orders/ensure).
+    // They aren't replayed, as a stale absolute update could undo later ones.
+    failures: Mutex<VecDeque<FailedDbWrite>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct FailedDbWrite {
+    failed_at: u128,
+    write: &'static str,
+    detail: String,
+    error: String,
+}
+
+#[derive(Debug, Serialize)]
+struct DbWriteStatus {
+    pending: usize,
+    failed_total: u64,
+    recent_failures: Vec<FailedDbWrite>,
+}
+
+impl DbWritesInner {
+    fn record_failure(&self, write: &'static str, detail: String, error: String) {
+        let failed_total = self.failed_total.fetch_add(1, Ordering::SeqCst) + 1;
+        tracing::error!(write = write, detail = %detail, error = %error, failed_total = failed_total, "Background DB write failed; DB now diverges from the book");
+        let mut failures = self.failures.lock().expect("Mutex lock failed for DB write failures");
+        if failures.len() == DB_WRITE_FAILURES_KEPT {
+            failures.pop_front();
         }
+        failures.push_back(FailedDbWrite { failed_at: now_nanos(), write, detail, error });
     }
 }
 
-// Order Book Structure
-#[derive(Debug, Default)]
-pub struct OrderBook {
-    bids: VecDeque<Order>,
-    asks: VecDeque<Order>,
+// The kind of write and what it was for, as kept when it fails
+fn describe_book_write(write: &BookWrite) -> (&'static str, String) {
+    match write {
+        BookWrite::Match { fill, bid_left, ask_left, .. } => {
+            ("match", format!("trade {}: bid {} left {}, ask {} left {}", fill.trade_id, fill.bid_id, bid_left, fill.ask_id, ask_left))
+        }
+        BookWrite::SelfTradeCancel { order_id } => ("self_trade_cancel", format!("order {}", order_id)),
+        BookWrite::OcoCancel { group_id, order_ids } => ("oco_cancel", format!("group {} cancelling {:?}", group_id, order_ids)),
+        BookWrite::Auction { clearing_price, orders, .. } => ("auction", format!("auction at {} updating {} orders", clearing_price, orders.len())),
+        BookWrite::Replenished { order_id, priority, .. } => ("replenish", format!("order {} requeued at priority {}", order_id, priority)),
+    }
 }
 
-impl OrderBook {
-    pub fn new() -> Self {
-        OrderBook {
-            bids: VecDeque::new(),
-            asks: VecDeque::new(),
+// Applies one book change within the caller's transaction
+fn apply_book_write(tx: &Connection, paper: bool, write: &BookWrite) -> SqlResult<()> {
+    let table = orders_table(paper);
+    match write {
+        BookWrite::Match { fill, bid_left, bid_status, ask_left, ask_status, bid_rested } => {
+            // Fill writes can land after a later cancel of the same order; never
+            // undo it, nor put back quantity a later write already took
+            for (id, left, status) in [(fill.bid_id, bid_left, bid_status), (fill.ask_id, ask_left, ask_status)] {
+                let updated = tx.execute(
+                    &format!("UPDATE {} SET remaining_quantity = ?1, status = ?2 WHERE id = ?3 AND status NOT IN ('Cancelled', 'Expired') AND remaining_quantity >= ?1", table),
+                    params![left, format!("{:?}", status), id],
+                )?;
+                if updated > 0 {
+                    log_fill_event(tx, paper, id, status, *left, fill)?;
+                }
+            }
+            insert_trade(tx, paper, fill, Some(if *bid_rested { fill.bid_id } else { fill.ask_id }))?;
+            tracing::debug!(bid_id = fill.bid_id, ask_id = fill.ask_id, "Match written");
+        }
+        BookWrite::SelfTradeCancel { order_id } => {
+            tx.execute(
+                &format!("UPDATE {} SET status = ?1, remaining_quantity = 0, cancel_reason = ?2 WHERE id = ?3", table),
+                params![format!("{:?}", OrderStatus::Cancelled), format!("{:?}", CancelReason::SelfTradePrevention), order_id],
+            )?;
+            log_order_cancelled(tx, paper, *order_id, &OrderStatus::Cancelled, Some(CancelReason::SelfTradePrevention), now_nanos())?;
+            tracing::debug!(order_id = order_id, "Self-trade cancel written");
+        }
+        BookWrite::OcoCancel { group_id, order_ids } => {
+            let at = now_nanos();
+            for id in order_ids {
+                tx.execute(
+                    &format!("UPDATE {} SET status = ?1, remaining_quantity = 0, cancel_reason = ?2 WHERE id = ?3", table),
+                    params![format!("{:?}", OrderStatus::Cancelled), format!("{:?}", CancelReason::OcoTriggered), id],
+                )?;
+                log_order_cancelled(tx, paper, *id, &OrderStatus::Cancelled, Some(CancelReason::OcoTriggered), at)?;
+            }
+            tracing::debug!(group_id = group_id, "OCO cancellations written");
+        }
+        BookWrite::Auction { orders, fills, .. } => {
+            let at = fills.first().map_or_else(now_nanos, |fill| fill.executed_at);
+            for (id, remaining, status) in orders {
+                let updated = tx.execute(
+                    &format!("UPDATE {} SET remaining_quantity = ?1, status = ?2 WHERE id = ?3 AND status NOT IN ('Cancelled', 'Expired') AND remaining_quantity >= ?1", table),
+                    params![remaining, format!("{:?}", status), id],
+                )?;
+                if updated > 0 {
+                    log_order_event(tx, paper, *id, status, *remaining, "Auction", at)?;
+                }
+            }
+            for fill in fills {
+                insert_trade(tx, paper, fill, None)?;
+            }
+            tracing::debug!(orders = orders.len(), "Auction results written");
+        }
+        BookWrite::Replenished { order_id, priority, timestamp } => {
+            tx.execute(
+                &format!("UPDATE {} SET priority = ?1, timestamp = ?2 WHERE id = ?3 AND status IN ('Open', 'PartiallyFilled')", table),
+                params![priority, timestamp.to_string(), order_id],
+            )?;
+            tracing::debug!(order_id = order_id, "Iceberg requeue written");
         }
     }
+    Ok(())
+}
 
-    pub fn add_order(&mut self, order: Order, db_conn: Arc<Mutex<Connection>>) {
-        let order_id = order.id;
-        let side = order.side.clone();
+// Marks one write finished when dropped, including when the write panics
+struct DbWriteDone(Arc<DbWritesInner>);
 
-        match side {
-            Side::Buy => self.bids.push_back(order),
-            Side::Sell => self.asks.push_back(order),
+impl Default for DbWrites {
+    // Starts the writer thread. It holds only the receiving end, so it stops
+    // once every clone is gone and the queue has run dry.
+    fn default() -> Self {
+        let (queue, queued) = std::sync::mpsc::channel::<QueuedDbWrite>();
+        std::thread::Builder::new()
+            .name("db-writer".to_string())
+            .spawn(move || {
+                for QueuedDbWrite { write, detail, run, done } in queued {
+                    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(run)) {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => done.0.record_failure(write, detail, e.to_string()),
+                        Err(_) => done.0.record_failure(write, detail, "write panicked".to_string()),
+                    }
+                }
+            })
+            .expect("failed to start the DB writer thread");
+        DbWrites {
+            inner: Arc::new(DbWritesInner {
+                queue,
+                pending: Default::default(),
+                idle: Default::default(),
+                failed_total: Default::default(),
+                failures: Default::default(),
+            }),
         }
-        tracing::debug!(order_id = order_id, book = ?self, "Added order. Book state before match attempt");
-        self.try_match(db_conn);
-        tracing::debug!(book = ?self, "Book state after match attempt");
     }
+}
 
-    fn try_match(&mut self, db_conn: Arc<Mutex<Connection>>) {
-        tracing::debug!("Attempting match...");
-        while !self.bids.is_empty() && !self.asks.is_empty() {
-            let can_match = {
-                let best_bid = self.bids.front().unwrap();
-                let best_ask = self.asks.front().unwrap();
-                tracing::debug!(bid_price = best_bid.price, bid_qty = best_bid.quantity, ask_price = best_ask.price, ask_qty = best_ask.quantity, "Checking best bid/ask");
-                best_bid.price >= best_ask.price
-            };
-
-            if can_match {
-                let best_bid_mut = self.bids.front_mut().unwrap();
-                let best_ask_mut = self.asks.front_mut().unwrap();
+impl Drop for DbWriteDone {
+    fn drop(&mut self) {
+        if self.0.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
 
-                let bid_id_for_db = best_bid_mut.id;
-                let ask_id_for_db = best_ask_mut.id;
+impl DbWrites {
+    // Queues a write behind every earlier one. `write` names the kind of write
+    // and `detail` what it was for, both kept if it fails.
+    fn enqueue<F>(&self, write: &'static str, detail: String, run: F)
+    where
+        F: FnOnce() -> SqlResult<()> + Send + 'static,
+    {
+        self.inner.pending.fetch_add(1, Ordering::SeqCst);
+        let done = DbWriteDone(Arc::clone(&self.inner));
+        if let Err(unsent) = self.inner.queue.send(QueuedDbWrite { write, detail, run: Box::new(run), done }) {
+            let QueuedDbWrite { write, detail, done, .. } = unsent.0;
+            done.0.record_failure(write, detail, "DB writer thread has stopped".to_string());
+        }
+    }
 
-                tracing::info!(bid_id = bid_id_for_db, ask_id = ask_id_for_db, price = best_ask_mut.price, "MATCH FOUND!");
-                let matched_quantity = std::cmp::min(best_bid_mut.quantity, best_ask_mut.quantity);
-                tracing::info!(quantity = matched_quantity, "Matched Quantity");
+    // Runs `run` on the writer thread once every write queued before it has
+    // committed, and hands back its result. For handlers that read back what
+    // the book's writes left behind, or must commit behind them.
+    async fn run_after_queued<T, F>(&self, write: &'static str, run: F) -> Result<SqlResult<T>, tokio::sync::oneshot::error::RecvError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> SqlResult<T> + Send + 'static,
+    {
+        self.queue_after(write, run).await
+    }
 
-                best_bid_mut.quantity -= matched_quantity;
-                best_ask_mut.quantity -= matched_quantity;
+    // run_after_queued, but queued now rather than when first awaited: a
+    // caller holding a book lock takes its place in line there and awaits the
+    // result once the lock is released
+    fn queue_after<T, F>(&self, write: &'static str, run: F) -> tokio::sync::oneshot::Receiver<SqlResult<T>>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> SqlResult<T> + Send + 'static,
+    {
+        let (result_tx, result_rx) = tokio::sync::oneshot::channel();
+        self.enqueue(write, String::new(), move || {
+            // The caller reports its own failures; a dropped receiver just means it stopped waiting
+            let _ = result_tx.send(run());
+            Ok(())
+        });
+        result_rx
+    }
 
-                best_bid_mut.status = if best_bid_mut.quantity == 0 { OrderStatus::Filled } else { OrderStatus::PartiallyFilled };
-                best_ask_mut.status = if best_ask_mut.quantity == 0 { OrderStatus::Filled } else { OrderStatus::PartiallyFilled };
+    // Queues one write per book change, in the order the book made them
+    fn persist(&self, db_conn: &Arc<Mutex<Connection>>, paper: bool, writes: Vec<BookWrite>) {
+        for write in writes {
+            let (kind, detail) = describe_book_write(&write);
+            let db_conn_clone = Arc::clone(db_conn);
+            self.enqueue(kind, detail, move || {
+                let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB in book write");
+                let tx = conn_guard.transaction()?;
+                apply_book_write(&tx, paper, &write)?;
+                tx.commit()
+            });
+        }
+    }
 
-                let bid_status_db = format!("{:?}", best_bid_mut.status);
-                let ask_status_db = format!("{:?}", best_ask_mut.status);
-                let bid_remaining_qty_db = best_bid_mut.quantity;
-                let ask_remaining_qty_db = best_ask_mut.quantity;
+    fn status(&self) -> DbWriteStatus {
+        DbWriteStatus {
+            pending: self.pending(),
+            failed_total: self.inner.failed_total.load(Ordering::SeqCst),
+            recent_failures: self.inner.failures.lock().expect("Mutex lock failed for DB write failures").iter().cloned().collect(),
+        }
+    }
 
-                let db_conn_clone: Arc<Mutex<Connection>> = Arc::clone(&db_conn);
-                task::spawn_blocking(move || {
-                    let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB in try_match");
-                    tracing::debug!(bid_id = bid_id_for_db, ask_id = ask_id_for_db, "Acquired DB lock for UPDATE (match)");
-                    let tx = conn_guard.transaction().expect("Failed to start DB transaction in try_match");
-                    tx.execute(
-                        "UPDATE orders SET remaining_quantity = ?1, status = ?2 WHERE id = ?3",
-                        params![bid_remaining_qty_db, bid_status_db, bid_id_for_db],
-                    ).expect("DB error updating bid in match");
-                    tx.execute(
-                        "UPDATE orders SET remaining_quantity = ?1, status = ?2 WHERE id = ?3",
-                        params![ask_remaining_qty_db, ask_status_db, ask_id_for_db],
-                    ).expect("DB error updating ask in match");
-                    tx.commit().expect("Failed to commit DB transaction in try_match");
-                    tracing::debug!(bid_id = bid_id_for_db, ask_id = ask_id_for_db, "Released DB lock after UPDATE (match)");
-                });
+    fn pending(&self) -> usize {
+        self.inner.pending.load(Ordering::SeqCst)
+    }
 
-                if best_bid_mut.quantity == 0 {
-                    self.bids.pop_front();
-                    tracing::info!(order_id = bid_id_for_db, "Bid order fully filled and removed from memory.");
-                }
-                if best_ask_mut.quantity == 0 {
-                    self.asks.pop_front();
-                    tracing::info!(order_id = ask_id_for_db, "Ask order fully filled and removed from memory.");
-                }
-            } else {
-                tracing::debug!("No match possible (bid price < ask price)");
-                break;
+    // Waits until nothing is pending. Returns how many were pending on entry.
+    async fn drain(&self) -> usize {
+        let outstanding = self.pending();
+        loop {
+            let idle = self.inner.idle.notified();
+            tokio::pin!(idle);
+            // Registered before the check, so a write finishing in between still wakes us
+            idle.as_mut().enable();
+            if self.pending() == 0 {
+                return outstanding;
             }
+            idle.await;
         }
-        tracing::debug!("Finished matching cycle.");
     }
+}
+
+// --- Spread Statistics ---
+
+// Upper bound on touch snapshots kept in memory; oldest are dropped first.
+const SPREAD_SERIES_CAPACITY: usize = 100_000;
+
+// Best bid/ask as of `timestamp`. Recorded only when the touch changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TouchSample {
+    timestamp: u128,
+    best_bid: Option<u64>,
+    best_ask: Option<u64>,
+}
 
-    pub fn modify_order(&mut self, id: OrderId, new_quantity: u64) -> Option<Order> {
-        if new_quantity == 0 {
-            tracing::warn!(order_id = id, "Modification requested with quantity 0. Redirecting to cancel order.");
-            return self.cancel_order(id);
+impl TouchSample {
+    // The spread is only defined while both sides of the book are populated.
+    fn spread(&self) -> Option<u64> {
+        match (self.best_bid, self.best_ask) {
+            (Some(bid), Some(ask)) => Some(ask.saturating_sub(bid)),
+            _ => None,
         }
-        if let Some(order) = self.bids.iter_mut().find(|o| o.id == id) {
-            tracing::info!(order_id = id, old_qty = order.quantity, new_qty = new_quantity, "Modifying bid order quantity");
-            order.quantity = new_quantity;
-            // If order was filled, and now modified, it should become Open or PartiallyFilled
-            // For simplicity, let's set it to Open. A more complex logic might check original quantity.
-            if order.status == OrderStatus::Filled {
-                order.status = OrderStatus::Open;
-            } else if order.status != OrderStatus::PartiallyFilled { // If not already partially filled, it's open
-                 order.status = OrderStatus::Open;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SpreadSeries {
+    samples: VecDeque<TouchSample>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct SpreadStats {
+    from: u128,
+    to: u128,
+    // None when the book was never two-sided inside the window
+    average_spread: Option<f64>,
+    two_sided_nanos: u128,
+    // Time spent with a one-sided or empty book; excluded from the average
+    one_sided_nanos: u128,
+}
+
+impl SpreadSeries {
+    pub fn record(&mut self, timestamp: u128, best_bid: Option<u64>, best_ask: Option<u64>) {
+        if let Some(last) = self.samples.back() {
+            if last.best_bid == best_bid && last.best_ask == best_ask {
+                return;
             }
-            return Some(order.clone());
         }
-        if let Some(order) = self.asks.iter_mut().find(|o| o.id == id) {
-            tracing::info!(order_id = id, old_qty = order.quantity, new_qty = new_quantity, "Modifying ask order quantity");
-            order.quantity = new_quantity;
-            if order.status == OrderStatus::Filled {
-                order.status = OrderStatus::Open;
-            } else if order.status != OrderStatus::PartiallyFilled {
-                 order.status = OrderStatus::Open;
+        if self.samples.len() == SPREAD_SERIES_CAPACITY {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(TouchSample { timestamp, best_bid, best_ask });
+    }
+
+    // Each sample holds until the next one (or `to` for the last). Time before
+    // the first sample in the series is unknown and not counted.
+    pub fn time_weighted_average(&self, from: u128, to: u128) -> SpreadStats {
+        let mut weighted_sum: u128 = 0;
+        let mut two_sided_nanos: u128 = 0;
+        let mut one_sided_nanos: u128 = 0;
+
+        for (i, sample) in self.samples.iter().enumerate() {
+            let start = sample.timestamp.max(from);
+            let end = self.samples.get(i + 1).map_or(to, |next| next.timestamp).min(to);
+            if end <= start {
+                continue;
+            }
+            let duration = end - start;
+            match sample.spread() {
+                Some(spread) => {
+                    weighted_sum += spread as u128 * duration;
+                    two_sided_nanos += duration;
+                }
+                None => one_sided_nanos += duration,
             }
-            return Some(order.clone());
         }
-        tracing::warn!(order_id = id, "Order not found for modification");
-        None
+
+        let average_spread = if two_sided_nanos > 0 {
+            Some(weighted_sum as f64 / two_sided_nanos as f64)
+        } else {
+            None
+        };
+        SpreadStats { from, to, average_spread, two_sided_nanos, one_sided_nanos }
     }
+}
+
+// --- Delta Log ---
 
-    pub fn cancel_order(&mut self, id: OrderId) -> Option<Order> {
-        tracing::info!(order_id = id, "Attempting to cancel order");
-        if let Some(index) = self.bids.iter().position(|o| o.id == id) {
-            if let Some(mut order) = self.bids.remove(index) {
-                order.status = OrderStatus::Cancelled;
-                tracing::info!(order_id = id, "Cancelled bid order from memory.");
-                return Some(order);
+// Upper bound on deltas retained for catch-up; older ones require a resync.
+const DELTA_LOG_CAPACITY: usize = 100_000;
+
+#[derive(Debug, Default)]
+pub struct DeltaLog {
+    deltas: VecDeque<BookDelta>,
+}
+
+impl DeltaLog {
+    pub fn extend(&mut self, deltas: Vec<BookDelta>) {
+        for delta in deltas {
+            if self.deltas.len() == DELTA_LOG_CAPACITY {
+                self.deltas.pop_front();
             }
+            self.deltas.push_back(delta);
         }
-        if let Some(index) = self.asks.iter().position(|o| o.id == id) {
-            if let Some(mut order) = self.asks.remove(index) {
-                order.status = OrderStatus::Cancelled;
-                tracing::info!(order_id = id, "Cancelled ask order from memory.");
-                return Some(order);
+    }
+
+    // Deltas after `since`, or None if some of them have already been evicted
+    // and the client must re-initialise from a snapshot.
+    pub fn since(&self, since: u64) -> Option<Vec<BookDelta>> {
+        if let Some(first) = self.deltas.front() {
+            if first.seq() > since + 1 {
+                return None;
             }
         }
-        tracing::warn!(order_id = id, "Order not found for cancellation in memory.");
-        None
+        Some(self.deltas.iter().filter(|d| d.seq() > since).cloned().collect())
+    }
+}
+
+// Full set of resting orders at `seq`, the starting point for the delta feed.
+#[derive(Debug, Serialize)]
+pub struct OrderBookSnapshot {
+    seq: u64,
+    checksum: u64,
+    bids: Vec<OrderView>,
+    asks: Vec<OrderView>,
+}
+
+// Everything needed to prime a DR environment to the exact state of every
+// book at one moment, taken with all the book locks held at once. `trade_seq`
+// is the global marker: the next trade sequence number, so every trade up to
+// it is reflected in every book and none after it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrBundle {
+    taken_at: u128,
+    trade_seq: u64,
+    next_order_id: OrderId,
+    books: Vec<DrBook>,
+}
+
+// One symbol's share of a DR bundle: resting orders in priority order (with
+// their internal timestamps), the delta sequence, and what the book derives
+// from its trades (totals, last price and per-account positions)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrBook {
+    symbol: String,
+    seq: u64,
+    checksum: u64,
+    totals: TradeTotals,
+    last_price: Option<u64>,
+    positions: BTreeMap<u64, i128>,
+    bids: Vec<Order>,
+    asks: Vec<Order>,
+}
+
+// Aggregated depth: bids best (highest) first, asks best (lowest) first.
+#[derive(Debug, Serialize)]
+pub struct BookSnapshot {
+    seq: u64,
+    bids: Vec<PriceLevel>,
+    asks: Vec<PriceLevel>,
+}
+
+// --- API Errors ---
+
+// JSON error body: {"error":"not_found","message":"order 42 does not exist"}
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ApiError {
+    NotFound(String),
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    Conflict(String),
+    Gone(String),
+    Unprocessable(String),
+    TooManyRequests(String),
+    Internal(String),
+    Unavailable(String),
+    // 405 from a recovery-only replica for anything that would mutate
+    ReadOnly(String),
+}
+
+#[derive(Debug, Serialize)]
+struct ApiErrorBody {
+    error: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    fn order_not_found(order_id: OrderId) -> Self {
+        ApiError::NotFound(format!("order {} does not exist", order_id))
+    }
+
+    // (status, error code, message)
+    fn parts(&self) -> (StatusCode, &'static str, &str) {
+        match self {
+            ApiError::NotFound(message) => (StatusCode::NOT_FOUND, "not_found", message),
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, "bad_request", message),
+            ApiError::Unauthorized(message) => (StatusCode::UNAUTHORIZED, "unauthorized", message),
+            ApiError::Forbidden(message) => (StatusCode::FORBIDDEN, "forbidden", message),
+            ApiError::Conflict(message) => (StatusCode::CONFLICT, "conflict", message),
+            ApiError::Gone(message) => (StatusCode::GONE, "gone", message),
+            ApiError::Unprocessable(message) => (StatusCode::UNPROCESSABLE_ENTITY, "unprocessable", message),
+            ApiError::TooManyRequests(message) => (StatusCode::TOO_MANY_REQUESTS, "too_many_requests", message),
+            ApiError::Internal(message) => (StatusCode::INTERNAL_SERVER_ERROR, "internal", message),
+            ApiError::Unavailable(message) => (StatusCode::SERVICE_UNAVAILABLE, "unavailable", message),
+            ApiError::ReadOnly(message) => (StatusCode::METHOD_NOT_ALLOWED, "read_only", message),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.parts().2)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, error, message) = self.parts();
+        (status, Json(ApiErrorBody { error, message: message.to_string() })).into_response()
     }
 }
 
 // --- API Payload Structs ---
+// Quantities are fixed-point at the symbol's quantity scale; see
+// `CreateOrderWire` for how they arrive
 #[derive(Deserialize, Debug)]
+#[serde(try_from = "CreateOrderWire")]
 struct CreateOrderPayload {
     side: Side,
+    // Ignored (and may be omitted) for market orders
     price: u64,
     quantity: u64,
+    group_id: Option<u64>,
+    dnr: bool,
+    order_type: OrderType,
+    time_in_force: TimeInForce,
+    account_id: Option<u64>,
+    stp: bool,
+    // Book to trade in; the default book when omitted
+    symbol: String,
+    // Good-till-date expiry, unix nanos
+    expires_at: Option<u128>,
+    // Maker-only: rejected rather than matched if it would cross the spread
+    post_only: bool,
+    // Least it must trade on arrival if it trades at all
+    min_qty: Option<u64>,
+    // Iceberg: the most the book shows of it at once
+    display_qty: Option<u64>,
 }
 
-#[derive(Deserialize, Debug)]
-struct ModifyOrderPayload {
-    quantity: u64,
+// A create as it comes off the wire. Its quantities can only be scaled once
+// its symbol is known, wherever in the body that comes.
+#[derive(Deserialize)]
+struct CreateOrderWire {
+    side: Side,
+    #[serde(default, with = "price_ticks")]
+    price: u64,
+    quantity: WireQuantity,
+    #[serde(default)]
+    group_id: Option<u64>,
+    #[serde(default)]
+    dnr: bool,
+    #[serde(default)]
+    order_type: OrderType,
+    #[serde(default)]
+    time_in_force: TimeInForce,
+    #[serde(default)]
+    account_id: Option<u64>,
+    #[serde(default)]
+    stp: bool,
+    #[serde(default = "default_symbol")]
+    symbol: String,
+    #[serde(default)]
+    expires_at: Option<u128>,
+    #[serde(default)]
+    post_only: bool,
+    #[serde(default)]
+    min_qty: Option<WireQuantity>,
+    #[serde(default)]
+    display_qty: Option<WireQuantity>,
 }
 
-// --- Shared Application State ---
-struct AppState {
-    order_book: Mutex<OrderBook>,
-    next_order_id: AtomicU64,
-    db_conn: Arc<Mutex<Connection>>,
+impl TryFrom<CreateOrderWire> for CreateOrderPayload {
+    type Error = String;
+
+    fn try_from(wire: CreateOrderWire) -> Result<Self, String> {
+        let scale = quantity_scale(&wire.symbol);
+        let units = |quantity: WireQuantity| quantity.units(scale).map_err(|e| format!("{} for {}", e, wire.symbol));
+        Ok(CreateOrderPayload {
+            side: wire.side,
+            price: wire.price,
+            quantity: units(wire.quantity)?,
+            group_id: wire.group_id,
+            dnr: wire.dnr,
+            order_type: wire.order_type,
+            time_in_force: wire.time_in_force,
+            account_id: wire.account_id,
+            stp: wire.stp,
+            expires_at: wire.expires_at,
+            post_only: wire.post_only,
+            min_qty: wire.min_qty.map(units).transpose()?,
+            display_qty: wire.display_qty.map(units).transpose()?,
+            symbol: wire.symbol,
+        })
+    }
 }
 
-// --- Database Setup ---
-const DB_PATH: &str = "oms_data.db";
+impl CreateOrderPayload {
+    // An order that could never trade would just sit in the book. Market
+    // orders carry no price, so only limits are held to the tick.
+    fn validate(&self, tick_size: &TickSize, now: u128) -> Result<(), String> {
+        validate_symbol(&self.symbol)?;
+        if self.quantity == 0 {
+            return Err("quantity must be greater than zero".to_string());
+        }
+        if self.order_type == OrderType::Limit && self.price == 0 {
+            return Err("limit orders need a price greater than zero".to_string());
+        }
+        if self.order_type == OrderType::Limit {
+            tick_size.check(self.price)?;
+        }
+        // Both can only ever take liquidity
+        if self.post_only && self.order_type == OrderType::Market {
+            return Err("market orders cannot be post-only".to_string());
+        }
+        if self.post_only && self.time_in_force == TimeInForce::Fok {
+            return Err("fill-or-kill orders cannot be post-only".to_string());
+        }
+        if let Some(min_qty) = self.min_qty {
+            if min_qty == 0 || min_qty > self.quantity {
+                return Err(format!("min_qty {} must be between 1 and the order quantity {}", min_qty, self.quantity));
+            }
+            if self.time_in_force == TimeInForce::Fok {
+                return Err("fill-or-kill orders already need their full quantity; drop min_qty".to_string());
+            }
+            if self.post_only {
+                return Err("post-only orders never trade on arrival, so cannot carry min_qty".to_string());
+            }
+        }
+        if let Some(display_qty) = self.display_qty {
+            if display_qty == 0 || display_qty > self.quantity {
+                return Err(format!("display_qty {} must be between 1 and the order quantity {}", display_qty, self.quantity));
+            }
+            // Neither ever rests, so there's no size to hide
+            if self.order_type == OrderType::Market || self.time_in_force == TimeInForce::Fok {
+                return Err("only resting limit orders can hide size with display_qty".to_string());
+            }
+        }
+        match self.expires_at {
+            // Never rests, so there's nothing to expire
+            Some(_) if self.order_type == OrderType::Market => Err("market orders cannot carry an expiry".to_string()),
+            Some(expires_at) if expires_at <= now => Err(format!("expires_at {} is not in the future", expires_at)),
+            _ => Ok(()),
+        }
+    }
 
-fn init_db() -> SqlResult<Connection> {
-    tracing::info!(db_path = DB_PATH, "Initializing database...");
-    let conn = Connection::open(DB_PATH)?;
-    conn.pragma_update(None, "journal_mode", "WAL")?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS orders (
-            id INTEGER PRIMARY KEY,
-            side TEXT NOT NULL,
-            price INTEGER NOT NULL,
-            original_quantity INTEGER NOT NULL,
-            remaining_quantity INTEGER NOT NULL,
-            status TEXT NOT NULL,
-            timestamp TEXT NOT NULL -- CHANGED TO TEXT
-        )",
-        [],
-    )?;
-    tracing::info!("Database table 'orders' initialized.");
-    Ok(conn)
-}
+    // Before `validate`, which holds the result to the tick
+    fn normalize_price(&mut self, config: &Config) -> Result<(), String> {
+        if self.order_type == OrderType::Limit {
+            let normalized = normalize_price(config, &self.symbol, &self.side, self.price)?;
+            if normalized != self.price {
+                tracing::info!(symbol = %self.symbol, side = ?self.side, sent = self.price, normalized = normalized, "Rounded price to the symbol's precision");
+                self.price = normalized;
+            }
+        }
+        Ok(())
+    }
 
-fn load_open_orders(conn: &Connection) -> SqlResult<Vec<Order>> {
-    tracing::info!("Loading open orders from database...");
-    let mut stmt = conn.prepare("SELECT id, side, price, remaining_quantity, timestamp, status FROM orders WHERE status = 'Open' OR status = 'PartiallyFilled'")?;
-    let order_iter = stmt.query_map([], |row| {
-        let side_str: String = row.get(1)?;
-        let side = match side_str.as_str() {
-            "Buy" => Side::Buy,
-            "Sell" => Side::Sell,
-            other => return Err(rusqlite::Error::FromSqlConversionFailure(
-                1,
-                rusqlite::types::Type::Text,
-                Box::new(ConversionError(format!("Invalid side string: {}", other))) // USE ConversionError
-            )),
-        };
-        let status_str: String = row.get(5)?;
-        let status = match status_str.as_str() {
-            "Open" => OrderStatus::Open,
-            "PartiallyFilled" => OrderStatus::PartiallyFilled,
-            other => return Err(rusqlite::Error::FromSqlConversionFailure(
-                5,
-                rusqlite::types::Type::Text,
-                Box::new(ConversionError(format!("Invalid status string: {}", other))) // USE ConversionError
-            )),
+    // Fill-or-kill, post-only and min_qty orders can be refused by the book on
+    // entry, so their rows are only written once it has taken them
+    fn conditional(&self) -> bool {
+        self.time_in_force == TimeInForce::Fok || self.post_only || self.min_qty.is_some()
+    }
+
+    // Market orders are priced at 0; the book sweeps them at any price
+    fn to_order(&self, id: OrderId, paper: bool, clock: &dyn Clock) -> Order {
+        let price = match self.order_type {
+            OrderType::Limit => self.price,
+            OrderType::Market => 0,
         };
-        Ok(Order {
-            id: row.get(0)?,
-            side,
-            price: row.get(2)?,
-            quantity: row.get(3)?,
-            timestamp: {
-                let ts_str: String = row.get(4)?;
-                ts_str.parse::<u128>().map_err(|e| rusqlite::Error::FromSqlConversionFailure(
-                    4,
-                    rusqlite::types::Type::Text,
-                    Box::new(ConversionError(format!("Failed to parse u128 from timestamp string: {}", e))) // USE ConversionError
-                ))?
-            },
-            status,
-        })
-    })?;
-    let mut orders = Vec::new();
-    for order_result in order_iter {
-        orders.push(order_result?);
+        let mut order = Order::with_clock(id, self.side.clone(), price, self.quantity, clock);
+        order.group_id = self.group_id;
+        order.paper = paper;
+        order.dnr = self.dnr;
+        order.order_type = self.order_type;
+        order.time_in_force = self.time_in_force;
+        order.account_id = self.account_id;
+        order.stp = self.stp;
+        order.symbol = self.symbol.clone();
+        order.expires_at = self.expires_at;
+        order.post_only = self.post_only;
+        order.min_qty = self.min_qty;
+        order.display_qty = self.display_qty;
+        order
     }
-    tracing::info!("Loaded {} open/partially filled order(s).", orders.len());
-    Ok(orders)
 }
 
-// --- Main Application Entry Point ---
-#[tokio::main]
-async fn main() {
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "low_latency_oms=debug,tower_http=debug".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-    tracing::info!("Logger initialized");
+// Full desired state of one order, from an authoritative source
+#[derive(Deserialize, Debug)]
+struct EnsureOrderPayload {
+    id: OrderId,
+    #[serde(default = "default_symbol")]
+    symbol: String,
+    side: Side,
+    #[serde(with = "price_ticks")]
+    price: u64,
+    quantity: u64,
+    status: OrderStatus,
+    #[serde(default)]
+    group_id: Option<u64>,
+}
 
-    let connection = init_db().expect("Failed to initialize database");
-    let open_orders = load_open_orders(&connection).expect("Failed to load open orders");
+// One historical order, as recorded by the system being migrated from
+#[derive(Deserialize, Debug, Clone)]
+struct ImportOrderPayload {
+    id: OrderId,
+    #[serde(default = "default_symbol")]
+    symbol: String,
+    side: Side,
+    #[serde(with = "price_ticks")]
+    price: u64,
+    original_quantity: u64,
+    remaining_quantity: u64,
+    status: OrderStatus,
+    // Original entry time in nanoseconds; defaults to now
+    #[serde(default)]
+    timestamp: Option<u128>,
+    #[serde(default)]
+    group_id: Option<u64>,
+}
 
-    let mut initial_book = OrderBook::new();
-    let mut max_id = 0;
-    for order in open_orders {
-        if order.id > max_id { max_id = order.id; }
-        match order.side {
-            Side::Buy => initial_book.bids.push_back(order),
-            Side::Sell => initial_book.asks.push_back(order),
+impl ImportOrderPayload {
+    // Status must agree with how much of the order is left
+    fn validate(&self) -> Result<(), String> {
+        validate_symbol(&self.symbol)?;
+        let (original, remaining) = (self.original_quantity, self.remaining_quantity);
+        let consistent = match self.status {
+            OrderStatus::Open => remaining > 0 && remaining == original,
+            OrderStatus::PartiallyFilled => remaining > 0 && remaining < original,
+            OrderStatus::Filled => remaining == 0 && original > 0,
+            OrderStatus::Cancelled | OrderStatus::Expired => remaining <= original,
+            OrderStatus::Killed | OrderStatus::Rejected => remaining == original,
+        };
+        if consistent {
+            Ok(())
+        } else {
+            Err(format!(
+                "order {}: status {:?} inconsistent with {} of {} remaining",
+                self.id, self.status, remaining, original
+            ))
         }
     }
-    tracing::info!("Order book populated with loaded orders.");
-
-    let shared_state = Arc::new(AppState {
-        order_book: Mutex::new(initial_book),
-        next_order_id: AtomicU64::new(max_id + 1),
-        db_conn: Arc::new(Mutex::new(connection)),
-    });
-    tracing::info!(next_order_id = max_id + 1, "Shared AppState created.");
+}
 
-    let app = Router::new()
-        .route("/", get(root_handler))
-        .route("/orders", post(create_order_handler))
-        .route("/orders/:id", put(modify_order_handler))
-        .route("/orders/:id", delete(cancel_order_handler))
-        .with_state(shared_state);
-    tracing::info!("API routes defined.");
+#[derive(Debug, Serialize)]
+struct CancelAllSummary {
+    cancelled_count: usize,
+    order_ids: Vec<OrderId>,
+}
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    tracing::info!("Starting server on {}", addr);
-    let listener = TcpListener::bind(addr).await.unwrap();
-    tracing::info!("Server listening on {}", addr);
-    axum::serve(listener, app).await.unwrap();
+#[derive(Debug, Serialize)]
+struct ImportSummary {
+    imported: usize,
+    // How many of the imported orders now rest in the book
+    resting: usize,
 }
 
-// --- Basic Root Handler ---
-async fn root_handler() -> &'static str {
-    tracing::info!("Root handler called");
-    "Hello from Low Latency OMS!"
+#[derive(Debug, Serialize)]
+struct EnsureOrderResponse {
+    changed: bool,
+    order: OrderView,
+}
+
+#[derive(Deserialize, Debug)]
+struct ModifyOrderPayload {
+    // At the order's symbol's quantity scale
+    quantity: WireQuantity,
+    // New limit price; the order requeues (and may trade) when it differs
+    #[serde(default, with = "price_ticks::option")]
+    price: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct DepthQuery {
+    // Levels per side; all when absent
+    depth: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+struct DeltaQuery {
+    since: u64,
+}
+
+// GET /orders filters, all optional. Status and side are checked by hand so an
+// unknown value gets a 400 naming it.
+#[derive(Deserialize, Debug, Default)]
+struct OrderListQuery {
+    status: Option<String>,
+    side: Option<String>,
+    symbol: Option<String>,
+    limit: Option<u32>,
+}
+
+#[derive(Debug, Default)]
+struct OrderFilter {
+    status: Option<OrderStatus>,
+    side: Option<Side>,
+    symbol: Option<String>,
+    limit: Option<u32>,
+}
+
+impl OrderListQuery {
+    fn filter(&self) -> Result<OrderFilter, String> {
+        let status = match self.status.as_deref() {
+            None => None,
+            Some(value) => Some(OrderStatus::from_db(value).ok_or_else(|| format!("unknown status '{}'", value))?),
+        };
+        let side = match self.side.as_deref() {
+            None => None,
+            Some(value) => Some(Side::from_db(value).ok_or_else(|| format!("unknown side '{}'", value))?),
+        };
+        if let Some(symbol) = &self.symbol {
+            validate_symbol(symbol)?;
+        }
+        Ok(OrderFilter { status, side, symbol: self.symbol.clone(), limit: self.limit })
+    }
+}
+
+// Window bounds are unix nanoseconds; `to` defaults to now
+#[derive(Deserialize, Debug)]
+struct WindowQuery {
+    from: Option<u64>,
+    to: Option<u64>,
+}
+
+// --- Trading Session Schedule ---
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SessionPhase {
+    PreOpen,
+    Open,
+    Closed,
+}
+
+// Daily trading window. Times are seconds after local midnight, where local
+// time is UTC shifted by `utc_offset_secs`. Sessions don't span midnight.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SessionSchedule {
+    pre_open: u32,
+    open: u32,
+    close: u32,
+    utc_offset_secs: i32,
+}
+
+impl SessionSchedule {
+    // `hours` is "HH:MM-HH:MM"; `pre_open` is "HH:MM" and defaults to the open time.
+    fn parse(hours: &str, pre_open: Option<&str>, utc_offset_minutes: i32) -> Option<Self> {
+        let (open, close) = hours.split_once('-')?;
+        let open = parse_time_of_day(open)?;
+        let close = parse_time_of_day(close)?;
+        let pre_open = match pre_open {
+            Some(t) => parse_time_of_day(t)?,
+            None => open,
+        };
+        if !(pre_open <= open && open < close) {
+            return None;
+        }
+        Some(SessionSchedule { pre_open, open, close, utc_offset_secs: utc_offset_minutes * 60 })
+    }
+
+    fn phase_at(&self, now_nanos: u128) -> SessionPhase {
+        let utc_secs = (now_nanos / 1_000_000_000) as i64;
+        let local_secs = (utc_secs + self.utc_offset_secs as i64).rem_euclid(SECONDS_PER_DAY) as u32;
+        if local_secs >= self.open && local_secs < self.close {
+            SessionPhase::Open
+        } else if local_secs >= self.pre_open && local_secs < self.open {
+            SessionPhase::PreOpen
+        } else {
+            SessionPhase::Closed
+        }
+    }
+}
+
+fn parse_time_of_day(value: &str) -> Option<u32> {
+    let (hours, minutes) = value.trim().split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    if hours > 24 || minutes > 59 || (hours == 24 && minutes != 0) {
+        return None;
+    }
+    Some(hours * 3600 + minutes * 60)
+}
+
+// Order entry (create/modify) is only accepted while the market's session is
+// Open. Cancels are always allowed so clients can pull orders at any time.
+fn check_session_open(market: &Market, now_nanos: u128) -> Result<(), ApiError> {
+    match market.session_phase(now_nanos) {
+        SessionPhase::Open => Ok(()),
+        phase => Err(ApiError::Forbidden(format!("order entry for {} is not accepted while the session is {:?}", market.symbol, phase))),
+    }
+}
+
+// How a modify that raises an order's quantity is applied
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum IncreaseMode {
+    // Resize the order; it loses its time priority and requeues at the back
+    #[default]
+    InPlace,
+    // Keep the original size's priority and queue the extra as a linked order
+    Split,
+}
+
+// How a WebSocket subscribe to a symbol the connection already has is answered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum DuplicateSubscribe {
+    // Reply that it was already subscribed
+    #[default]
+    Acknowledge,
+    // Say nothing
+    Ignore,
+}
+
+// What happens to a limit price finer than its symbol's precision
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum PricePolicy {
+    #[default]
+    Reject,
+    // To the precision, away from the other side: buys down, sells up, so
+    // rounding never makes an order more aggressive than it was sent
+    Round,
+}
+
+// Whether cancel and modify are refused for an order another account
+// entered, and how
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum AccountOwnership {
+    // Any caller may act on any order
+    #[default]
+    Unchecked,
+    // 403
+    Forbid,
+    // 404, as though the order didn't exist
+    Hide,
+}
+
+// What happens to partially-filled resting orders on graceful shutdown
+// What entry does with a designated market maker's quote that breaches its
+// obligation; either way GET /accounts/:id/obligations reports the breach
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum ObligationAction {
+    // Accepted and logged
+    #[default]
+    Flag,
+    // 422
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum ShutdownPartials {
+    // Left resting; reloaded on the next startup
+    #[default]
+    Leave,
+    Cancel,
+}
+
+// --- Runtime Configuration ---
+#[derive(Debug, Clone, Default)]
+struct Config {
+    // Recovery-only replica: DB opened read-only, mutating endpoints return 405
+    read_only: bool,
+    // Trading hours; None means the market is always open
+    session: Option<SessionSchedule>,
+    // Symbols with their own trading hours instead of `session`
+    symbol_sessions: HashMap<String, SessionSchedule>,
+    // Resting orders older than this are expired instead of reloaded on startup
+    max_order_age_nanos: Option<u128>,
+    // Absolute lifetime from original creation; modifying doesn't extend it
+    max_order_lifetime_nanos: Option<u128>,
+    // Minimum gap between accepted modifies of the same order
+    min_modify_interval_nanos: Option<u128>,
+    increase_mode: IncreaseMode,
+    shutdown_partials: ShutdownPartials,
+    account_ownership: AccountOwnership,
+    // Shared secret for privileged admin operations; unset disables them
+    admin_token: Option<String>,
+    // Exposes debugging endpoints such as POST /admin/match
+    test_harness: bool,
+    oco_policy: OcoPolicy,
+    match_price: MatchPrice,
+    // How a price level's resting orders share each fill; PriceTime unless
+    // overridden per symbol
+    match_mode: MatchMode,
+    symbol_match_modes: HashMap<String, MatchMode>,
+    // Book only trades in periodic/admin-triggered auctions, never continuously
+    auction_only: bool,
+    auction_interval_secs: Option<u64>,
+    // Orders must be priced on a multiple of this
+    tick_size: TickSize,
+    // Decimal places a symbol's limit prices may carry, when coarser than the
+    // tick's scale
+    price_precisions: HashMap<String, u32>,
+    price_policy: PricePolicy,
+    // Decimal places of a symbol's quantities; 0 (whole units) when unset
+    quantity_scales: HashMap<String, u32>,
+    // How often good-till-date orders are checked for expiry
+    expiry_sweep_ms: Option<u64>,
+    // How long an Idempotency-Key is remembered; a day when unset
+    idempotency_window_nanos: Option<u128>,
+    // Orders per second per client, each order of a batch counted; unlimited when unset
+    order_rate_per_sec: Option<u32>,
+    // Orders a client may submit at once; defaults to the per-second rate
+    order_rate_burst: Option<u32>,
+    // WebSocket commands the client stamped (`sent_at`) longer ago than this
+    // are rejected as stale; their age is not checked when unset
+    ws_command_max_age_nanos: Option<u128>,
+    // Open WebSocket connections allowed at once; unlimited when unset
+    ws_max_connections: Option<u64>,
+    ws_duplicate_subscribe: DuplicateSubscribe,
+    // New orders are refused while the p99 match latency over a window
+    // exceeds this; never when unset
+    latency_sla_nanos: Option<u64>,
+    latency_sla_window_ms: Option<u64>,
+    // Orders that have left the book kept in memory for GET /orders/:id;
+    // DEFAULT_RECENT_ORDERS when unset, and 0 turns the cache off
+    recent_orders_cache: Option<usize>,
+    // Designated market makers' quoting obligations, by symbol then account
+    quote_obligations: HashMap<String, HashMap<u64, QuoteObligation>>,
+    obligation_action: ObligationAction,
+}
+
+impl Config {
+    fn from_env() -> Self {
+        Config {
+            read_only: env_flag("OMS_READ_ONLY"),
+            session: session_from_env(""),
+            symbol_sessions: symbol_sessions_from_env(),
+            max_order_age_nanos: env_secs_as_nanos("OMS_MAX_ORDER_AGE_SECS"),
+            max_order_lifetime_nanos: env_secs_as_nanos("OMS_MAX_ORDER_LIFETIME_SECS"),
+            min_modify_interval_nanos: std::env::var("OMS_MIN_MODIFY_INTERVAL_MS").ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(|ms| ms as u128 * 1_000_000),
+            increase_mode: match std::env::var("OMS_INCREASE_MODE").as_deref() {
+                Ok("split") => IncreaseMode::Split,
+                _ => IncreaseMode::InPlace,
+            },
+            shutdown_partials: match std::env::var("OMS_SHUTDOWN_PARTIALS").as_deref() {
+                Ok("cancel") => ShutdownPartials::Cancel,
+                _ => ShutdownPartials::Leave,
+            },
+            account_ownership: match std::env::var("OMS_ACCOUNT_OWNERSHIP").as_deref() {
+                Ok("forbid") => AccountOwnership::Forbid,
+                Ok("hide") => AccountOwnership::Hide,
+                _ => AccountOwnership::Unchecked,
+            },
+            admin_token: std::env::var("OMS_ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
+            test_harness: env_flag("OMS_TEST_HARNESS"),
+            oco_policy: match std::env::var("OMS_OCO_TRIGGER").as_deref() {
+                Ok("full_fill") => OcoPolicy::FullFill,
+                _ => OcoPolicy::AnyFill,
+            },
+            match_price: match std::env::var("OMS_MATCH_PRICE").as_deref() {
+                Ok("midpoint") => MatchPrice::Midpoint,
+                _ => MatchPrice::Maker,
+            },
+            match_mode: match std::env::var("OMS_MATCH_MODE").ok().and_then(|v| MatchMode::parse(&v)) {
+                Some(mode) => mode,
+                // The older switch, from before there were more than two
+                None if env_flag("OMS_PRO_RATA") => MatchMode::ProRata,
+                None => MatchMode::PriceTime,
+            },
+            symbol_match_modes: symbol_match_modes_from_env(),
+            auction_only: env_flag("OMS_AUCTION_ONLY"),
+            auction_interval_secs: std::env::var("OMS_AUCTION_INTERVAL_SECS").ok().and_then(|v| v.parse().ok()),
+            tick_size: tick_size_from(std::env::var("OMS_TICK_SIZE").ok().as_deref()),
+            price_precisions: price_precisions_from_env(),
+            price_policy: match std::env::var("OMS_PRICE_POLICY").as_deref() {
+                Ok("round") => PricePolicy::Round,
+                _ => PricePolicy::Reject,
+            },
+            quantity_scales: quantity_scales_from_env(),
+            expiry_sweep_ms: std::env::var("OMS_EXPIRY_SWEEP_MS").ok().and_then(|v| v.parse().ok()),
+            idempotency_window_nanos: env_secs_as_nanos("OMS_IDEMPOTENCY_WINDOW_SECS"),
+            order_rate_per_sec: std::env::var("OMS_ORDER_RATE_PER_SEC").ok().and_then(|v| v.parse().ok()),
+            order_rate_burst: std::env::var("OMS_ORDER_RATE_BURST").ok().and_then(|v| v.parse().ok()),
+            ws_command_max_age_nanos: std::env::var("OMS_WS_COMMAND_MAX_AGE_MS").ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(|ms| ms as u128 * 1_000_000),
+            ws_max_connections: std::env::var("OMS_WS_MAX_CONNECTIONS").ok().and_then(|v| v.parse().ok()),
+            ws_duplicate_subscribe: match std::env::var("OMS_WS_DUPLICATE_SUBSCRIBE").as_deref() {
+                Ok("ignore") => DuplicateSubscribe::Ignore,
+                _ => DuplicateSubscribe::Acknowledge,
+            },
+            latency_sla_nanos: std::env::var("OMS_LATENCY_SLA_US").ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(|us| us.saturating_mul(1_000)),
+            latency_sla_window_ms: std::env::var("OMS_LATENCY_SLA_WINDOW_MS").ok().and_then(|v| v.parse().ok()),
+            recent_orders_cache: std::env::var("OMS_RECENT_ORDERS_CACHE").ok().and_then(|v| v.parse().ok()),
+            quote_obligations: quote_obligations_from_env(),
+            obligation_action: match std::env::var("OMS_OBLIGATION_ACTION").as_deref() {
+                Ok("reject") => ObligationAction::Reject,
+                _ => ObligationAction::Flag,
+            },
+        }
+    }
+
+    fn session_for(&self, symbol: &str) -> Option<SessionSchedule> {
+        self.symbol_sessions.get(symbol).or(self.session.as_ref()).cloned()
+    }
+
+    fn match_mode_for(&self, symbol: &str) -> MatchMode {
+        self.symbol_match_modes.get(symbol).copied().unwrap_or(self.match_mode)
+    }
+}
+
+const DEFAULT_BIND_ADDR: ([u8; 4], u16) = ([127, 0, 0, 1], 3000);
+
+// Good-till-date orders outlive their expiry by at most this much
+const DEFAULT_EXPIRY_SWEEP_MS: u64 = 1000;
+
+// OMS_BIND_ADDR="0.0.0.0:8080"; the loopback default when unset or invalid
+fn bind_addr(value: Option<&str>) -> SocketAddr {
+    let default = SocketAddr::from(DEFAULT_BIND_ADDR);
+    let Some(value) = value else {
+        return default;
+    };
+    value.trim().parse().unwrap_or_else(|e| {
+        tracing::warn!(value = %value, error = %e, default = %default, "Ignoring unparseable OMS_BIND_ADDR");
+        default
+    })
+}
+
+fn env_flag(name: &str) -> bool {
+    matches!(std::env::var(name).as_deref(), Ok("1") | Ok("true"))
+}
+
+fn env_secs_as_nanos(name: &str) -> Option<u128> {
+    let value = std::env::var(name).ok()?;
+    match value.parse::<u64>() {
+        Ok(secs) => Some(secs as u128 * 1_000_000_000),
+        Err(_) => {
+            tracing::warn!(var = name, value = %value, "Ignoring non-numeric duration");
+            None
+        }
+    }
+}
+
+// OMS_SESSION_HOURS="09:30-16:00", optional OMS_SESSION_PRE_OPEN="09:00" and
+// OMS_SESSION_UTC_OFFSET_MINUTES="-300". With `suffix` "_MSFT" it reads
+// OMS_SESSION_HOURS_MSFT and so on, the offset falling back to the shared one.
+fn session_from_env(suffix: &str) -> Option<SessionSchedule> {
+    let var = |name: &str| std::env::var(format!("{}{}", name, suffix)).ok();
+    let hours = var("OMS_SESSION_HOURS")?;
+    let pre_open = var("OMS_SESSION_PRE_OPEN");
+    let offset = var("OMS_SESSION_UTC_OFFSET_MINUTES")
+        .or_else(|| std::env::var("OMS_SESSION_UTC_OFFSET_MINUTES").ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let schedule = SessionSchedule::parse(&hours, pre_open.as_deref(), offset);
+    if schedule.is_none() {
+        tracing::warn!(hours = %hours, suffix = suffix, "Invalid session schedule; order entry will not be restricted");
+    }
+    schedule
+}
+
+// Every OMS_SESSION_HOURS_<SYMBOL>, by symbol
+fn symbol_sessions_from_env() -> HashMap<String, SessionSchedule> {
+    std::env::vars()
+        .filter_map(|(name, _)| Some(name.strip_prefix("OMS_SESSION_HOURS_")?.to_string()))
+        .filter_map(|symbol| Some((symbol.clone(), session_from_env(&format!("_{}", symbol))?)))
+        .collect()
+}
+
+// Every OMS_MATCH_MODE_<SYMBOL> (price_time, pro_rata or size_priority), by symbol
+fn symbol_match_modes_from_env() -> HashMap<String, MatchMode> {
+    std::env::vars()
+        .filter_map(|(name, value)| Some((name.strip_prefix("OMS_MATCH_MODE_")?.to_string(), value)))
+        .filter_map(|(symbol, value)| {
+            let mode = MatchMode::parse(&value);
+            if mode.is_none() {
+                tracing::warn!(symbol = %symbol, value = %value, "Invalid match mode; the default applies");
+            }
+            Some((symbol, mode?))
+        })
+        .collect()
+}
+
+// OMS_PRICE_PRECISION_<SYMBOL>: decimal places
+fn price_precisions_from_env() -> HashMap<String, u32> {
+    std::env::vars()
+        .filter_map(|(name, value)| Some((name.strip_prefix("OMS_PRICE_PRECISION_")?.to_string(), value)))
+        .filter_map(|(symbol, value)| {
+            let precision = value.parse().ok();
+            if precision.is_none() {
+                tracing::warn!(symbol = %symbol, value = %value, "Invalid price precision; the tick's applies");
+            }
+            Some((symbol, precision?))
+        })
+        .collect()
+}
+
+// OMS_QUOTE_OBLIGATIONS_<SYMBOL>: comma-separated
+// `account:min_spread:max_spread:min_size`, in the book's fixed-point units
+fn quote_obligations_from_env() -> HashMap<String, HashMap<u64, QuoteObligation>> {
+    std::env::vars()
+        .filter_map(|(name, value)| Some((name.strip_prefix("OMS_QUOTE_OBLIGATIONS_")?.to_string(), value)))
+        .map(|(symbol, value)| {
+            let obligations = value.split(',')
+                .filter_map(|entry| {
+                    let parsed = QuoteObligation::parse(entry);
+                    if parsed.is_none() {
+                        tracing::warn!(symbol = %symbol, entry = %entry, "Invalid quote obligation; ignored");
+                    }
+                    parsed
+                })
+                .collect();
+            (symbol, obligations)
+        })
+        .collect()
+}
+
+// OMS_QUANTITY_SCALE_<SYMBOL>: decimal places
+fn quantity_scales_from_env() -> HashMap<String, u32> {
+    std::env::vars()
+        .filter_map(|(name, value)| Some((name.strip_prefix("OMS_QUANTITY_SCALE_")?.to_string(), value)))
+        .filter_map(|(symbol, value)| {
+            let scale = value.parse().ok().filter(|&scale| scale <= MAX_QUANTITY_SCALE);
+            if scale.is_none() {
+                tracing::warn!(symbol = %symbol, value = %value, "Invalid quantity scale; whole units apply");
+            }
+            Some((symbol, scale?))
+        })
+        .collect()
+}
+
+// `price` on its symbol's precision, per the configured policy
+fn normalize_price(config: &Config, symbol: &str, side: &Side, price: u64) -> Result<u64, String> {
+    let Some(&precision) = config.price_precisions.get(symbol) else {
+        return Ok(price);
+    };
+    let step = 10u64.pow(config.tick_size.scale().saturating_sub(precision));
+    let excess = price % step;
+    if excess == 0 {
+        return Ok(price);
+    }
+    let written = config.tick_size.format(price);
+    match (config.price_policy, side) {
+        (PricePolicy::Reject, _) => Err(format!("price {} has more decimal places than {}'s precision of {}", written, symbol, precision)),
+        (PricePolicy::Round, Side::Buy) if price > excess => Ok(price - excess),
+        (PricePolicy::Round, Side::Buy) => Err(format!("price {} rounds down to zero at {}'s precision of {}", written, symbol, precision)),
+        (PricePolicy::Round, Side::Sell) => price.checked_add(step - excess).ok_or_else(|| format!("price {} is out of range", written)),
+    }
+}
+
+// --- Shared Application State ---
+// One symbol's book and the state derived from it
+struct Market {
+    symbol: String,
+    order_book: Mutex<OrderBook>,
+    spread_series: Mutex<SpreadSeries>,
+    delta_log: Mutex<DeltaLog>,
+    // Trading hours; None means always open
+    session: Option<SessionSchedule>,
+    // Phase as of the last check, so each transition is seen once
+    phase: Mutex<Option<SessionPhase>>,
+}
+
+impl Market {
+    fn new(book: OrderBook, session: Option<SessionSchedule>) -> Self {
+        let mut spread_series = SpreadSeries::default();
+        // The book shares the state's clock
+        spread_series.record(book.clock.now_nanos(), book.best_bid(), book.best_ask());
+        Market {
+            symbol: book.symbol.clone(),
+            order_book: Mutex::new(book),
+            spread_series: Mutex::new(spread_series),
+            delta_log: Mutex::new(DeltaLog::default()),
+            session,
+            phase: Mutex::new(None),
+        }
+    }
+
+    // The session phase at `now_nanos`, moving the market into it when the
+    // clock has crossed a boundary since the last check
+    fn session_phase(&self, now_nanos: u128) -> SessionPhase {
+        let Some(schedule) = &self.session else {
+            return SessionPhase::Open;
+        };
+        let phase = schedule.phase_at(now_nanos);
+        let mut current = self.phase.lock().expect("Mutex lock failed for session phase");
+        if *current != Some(phase) {
+            tracing::info!(symbol = %self.symbol, from = ?*current, to = ?phase, "Session phase changed");
+            *current = Some(phase);
+        }
+        phase
+    }
+}
+
+// Picks the book for endpoints that work on one symbol; the default book
+// when none is given
+#[derive(Deserialize, Debug, Default)]
+struct SymbolQuery {
+    symbol: Option<String>,
+}
+
+struct AppState {
+    config: Config,
+    // Serves the sandboxed paper-trading book mounted under /paper
+    paper: bool,
+    // One book per symbol, added on the symbol's first order. Each has its
+    // own lock, so symbols never wait on each other's matching.
+    markets: RwLock<HashMap<String, Arc<Market>>>,
+    // For requests that don't name a symbol; also in `markets`
+    default_market: Arc<Market>,
+    // Symbol of every resting order, kept from the book deltas, so lookups by
+    // id lock only the order's own book. Held just long enough to apply a
+    // change's deltas, never across matching.
+    resting_symbols: RwLock<HashMap<OrderId, String>>,
+    // Orders entered under each client session, for cancel-on-disconnect
+    client_sessions: Mutex<HashMap<String, ClientSession>>,
+    idempotency_keys: Mutex<IdempotencyKeys>,
+    recent_orders: Mutex<RecentOrders>,
+    // Accounts barred from entering orders until unsuspended
+    suspended_accounts: RwLock<HashSet<u64>>,
+    // Risk limits by symbol; a symbol without an entry has none
+    symbol_limits: RwLock<HashMap<String, SymbolLimits>>,
+    // Per-client order submission limits; None when unlimited
+    rate_limiter: Option<Mutex<RateLimiter>>,
+    // None when no latency SLA is configured
+    load_shedder: Option<LoadShedder>,
+    // Global, so order ids are unique across symbols
+    next_order_id: AtomicU64,
+    db_conn: Arc<Mutex<Connection>>,
+    // Lookups that don't need to see the writer's uncommitted work
+    read_pool: Arc<ReadPool>,
+    next_ack_seq: AtomicU64,
+    recovery: RecoverySummary,
+    events: broadcast::Sender<BookEvent>,
+    // Persists what the books change by themselves (fills, OCO cancels, ...)
+    db_writes: DbWrites,
+    metrics: Metrics,
+    // Stamps new orders; the books share it
+    clock: Arc<dyn Clock>,
+    // Next trade sequence number. Every book draws from it under its own
+    // lock, so trades are numbered across symbols.
+    trade_seq: Arc<AtomicU64>,
+}
+
+impl AppState {
+    fn orders_table(&self) -> &'static str {
+        orders_table(self.paper)
+    }
+
+    // An empty book for `symbol`, set up the way this state's books are
+    fn new_book(&self, symbol: &str) -> OrderBook {
+        configured_book(&self.config, self.paper, symbol, &self.clock, &self.trade_seq)
+    }
+
+    fn market(&self, symbol: &str) -> Option<Arc<Market>> {
+        self.markets.read().expect("RwLock poisoned for markets").get(symbol).cloned()
+    }
+
+    // The symbol's market, opening an empty book on its first order
+    fn market_or_insert(&self, symbol: &str) -> Arc<Market> {
+        if let Some(market) = self.market(symbol) {
+            return market;
+        }
+        let mut markets = self.markets.write().expect("RwLock poisoned for markets");
+        let market = markets.entry(symbol.to_string()).or_insert_with(|| {
+            tracing::info!(paper = self.paper, symbol = symbol, "Opening book for new symbol");
+            Arc::new(Market::new(self.new_book(symbol), self.config.session_for(symbol)))
+        });
+        Arc::clone(market)
+    }
+
+    // Every market, by symbol
+    fn markets(&self) -> Vec<Arc<Market>> {
+        let mut markets: Vec<Arc<Market>> = self.markets.read().expect("RwLock poisoned for markets").values().cloned().collect();
+        markets.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        markets
+    }
+
+    // The market an order rests in, if it is resting anywhere
+    fn symbol_limits(&self, symbol: &str) -> SymbolLimits {
+        self.symbol_limits.read().expect("RwLock poisoned for symbol limits").get(symbol).copied().unwrap_or_default()
+    }
+
+    fn is_suspended(&self, account_id: Option<u64>) -> bool {
+        account_id.is_some_and(|id| self.suspended_accounts.read().expect("RwLock poisoned for suspended accounts").contains(&id))
+    }
+
+    fn market_of(&self, order_id: OrderId) -> Option<Arc<Market>> {
+        let symbols = self.resting_symbols.read().expect("RwLock poisoned for resting symbols");
+        self.market(symbols.get(&order_id)?)
+    }
+
+    // Call with the book lock held, in delta order
+    fn index_resting(&self, symbol: &str, deltas: &[BookDelta]) {
+        let mut symbols = self.resting_symbols.write().expect("RwLock poisoned for resting symbols");
+        for delta in deltas {
+            match delta {
+                BookDelta::Update { order_id, .. } => {
+                    if !symbols.contains_key(order_id) {
+                        symbols.insert(*order_id, symbol.to_string());
+                    }
+                }
+                BookDelta::Remove { order_id, .. } => {
+                    symbols.remove(order_id);
+                }
+            }
+        }
+    }
+
+    fn market_for(&self, query: &SymbolQuery) -> Result<Arc<Market>, ApiError> {
+        let Some(symbol) = query.symbol.as_deref() else {
+            return Ok(Arc::clone(&self.default_market));
+        };
+        validate_symbol(symbol).map_err(ApiError::BadRequest)?;
+        self.market(symbol).ok_or_else(|| ApiError::NotFound(format!("no book for symbol {}", symbol)))
+    }
+
+    fn track_session_orders(&self, session: &str, order_ids: impl IntoIterator<Item = OrderId>) {
+        let mut sessions = self.client_sessions.lock().expect("Mutex lock failed for client sessions");
+        sessions.entry(session.to_string()).or_default().orders.extend(order_ids);
+    }
+
+    // Every book's lock is held (taken in symbol order, as restore takes them)
+    // while the bundle is captured, so trading pauses across all symbols for
+    // that moment and the trade sequence stands still.
+    fn dr_snapshot(&self) -> DrBundle {
+        let markets = self.markets();
+        let books: Vec<_> = markets.iter().map(|market| market.order_book.lock().expect("Mutex lock failed for DR snapshot")).collect();
+        // Order ids are allocated before the book lock is taken, so this can run
+        // ahead of the books; restoring it just skips unused ids.
+        let next_order_id = self.next_order_id.load(Ordering::SeqCst);
+        DrBundle {
+            taken_at: now_nanos(),
+            trade_seq: self.trade_seq.load(Ordering::SeqCst),
+            next_order_id,
+            books: books.iter().map(|book| DrBook {
+                symbol: book.symbol.clone(),
+                seq: book.seq,
+                checksum: book.checksum(),
+                totals: book.totals.clone(),
+                last_price: book.last_price,
+                positions: book.positions.clone(),
+                bids: book.bids.iter().cloned().collect(),
+                asks: book.asks.iter().cloned().collect(),
+            }).collect(),
+        }
+    }
+
+    // A book rebuilt from its share of a DR bundle, checked against itself
+    fn dr_book(&self, bundle: DrBook) -> Result<OrderBook, ApiError> {
+        validate_symbol(&bundle.symbol).map_err(ApiError::BadRequest)?;
+        let mut book = self.new_book(&bundle.symbol);
+        book.seq = bundle.seq;
+        book.totals = bundle.totals;
+        book.last_price = bundle.last_price;
+        book.positions = bundle.positions;
+        if bundle.bids.iter().any(|o| o.side != Side::Buy) || bundle.asks.iter().any(|o| o.side != Side::Sell) {
+            return Err(ApiError::BadRequest(format!("bundle has orders on the wrong side of the {} book", bundle.symbol)));
+        }
+        if bundle.bids.iter().chain(bundle.asks.iter()).any(|o| o.symbol != bundle.symbol) {
+            return Err(ApiError::BadRequest(format!("bundle has orders for another symbol in the {} book", bundle.symbol)));
+        }
+        for order in bundle.bids.into_iter().chain(bundle.asks) {
+            book.side_mut(&order.side.clone()).push_back(Order { paper: self.paper, ..order });
+        }
+        if book.checksum() != bundle.checksum {
+            return Err(ApiError::BadRequest(format!("bundle checksum for {} does not match its orders", bundle.symbol)));
+        }
+        book.sort_queues();
+        book.rebuild_touch();
+        book.reseed_priority();
+        Ok(book)
+    }
+
+    // Replaces every book (memory and DB) with a DR bundle's. Meant for priming
+    // a fresh environment: books the bundle doesn't have are emptied, and any
+    // resting orders here are discarded. The DB write is queued behind every
+    // earlier book write; the returned receiver reports it.
+    fn restore_dr_bundle(&self, bundle: DrBundle) -> Result<tokio::sync::oneshot::Receiver<SqlResult<()>>, ApiError> {
+        let mut restored = Vec::new();
+        for dr_book in bundle.books {
+            if restored.iter().any(|book: &OrderBook| book.symbol == dr_book.symbol) {
+                return Err(ApiError::BadRequest(format!("bundle has the {} book twice", dr_book.symbol)));
+            }
+            restored.push(self.dr_book(dr_book)?);
+        }
+        for market in self.markets() {
+            if !restored.iter().any(|book| book.symbol == market.symbol) {
+                restored.push(self.new_book(&market.symbol));
+            }
+        }
+        restored.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+        let markets: Vec<Arc<Market>> = restored.iter().map(|book| self.market_or_insert(&book.symbol)).collect();
+        let mut guards: Vec<_> = markets.iter().map(|market| market.order_book.lock().expect("Mutex lock failed for DR restore")).collect();
+        let rows: Vec<(String, Vec<Order>)> = restored.iter()
+            .map(|book| (book.symbol.clone(), book.bids.iter().chain(book.asks.iter()).cloned().collect()))
+            .collect();
+        let db_conn_clone = Arc::clone(&self.db_conn);
+        let table = self.orders_table();
+        let persisted = self.db_writes.queue_after("dr_restore", move || {
+            let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB (DR restore)");
+            let tx = conn_guard.transaction()?;
+            for (symbol, orders) in &rows {
+                replace_resting_orders(&tx, table, symbol, orders.iter())?;
+            }
+            tx.commit()
+        });
+        for ((market, book_guard), mut book) in markets.iter().zip(guards.iter_mut()).zip(restored) {
+            // Trade ids keep counting so stream clients can still resume
+            book.next_trade_id = book_guard.next_trade_id;
+            {
+                // The restored book replaces this one without deltas of its own
+                let mut symbols = self.resting_symbols.write().expect("RwLock poisoned for resting symbols");
+                for order in book_guard.bids.iter().chain(book_guard.asks.iter()) {
+                    symbols.remove(&order.id);
+                }
+                for order in book.bids.iter().chain(book.asks.iter()) {
+                    symbols.insert(order.id, market.symbol.clone());
+                }
+            }
+            **book_guard = book;
+            *market.delta_log.lock().expect("Mutex lock failed for delta log") = DeltaLog::default();
+            self.on_book_change(market, book_guard);
+        }
+        // Never wind the global counters back
+        self.next_order_id.fetch_max(bundle.next_order_id, Ordering::SeqCst);
+        self.trade_seq.fetch_max(bundle.trade_seq, Ordering::SeqCst);
+        tracing::warn!(paper = self.paper, books = markets.len(), trade_seq = bundle.trade_seq, next_order_id = bundle.next_order_id, "Books restored from DR bundle");
+        Ok(persisted)
+    }
+
+    fn run_auction(&self, market: &Market) -> AuctionResult {
+        let mut book_guard = market.order_book.lock().expect("Mutex lock failed for book auction");
+        let result = book_guard.run_auction();
+        self.on_book_change(market, &mut book_guard);
+        result
+    }
+
+    // Call after every book mutation while still holding the book lock, so the
+    // touch series and delta log follow book order.
+    fn on_book_change(&self, market: &Market, book: &mut OrderBook) {
+        self.db_writes.persist(&self.db_conn, self.paper, book.take_writes());
+        {
+            let mut series = market.spread_series.lock().expect("Mutex lock failed for spread series");
+            series.record(self.clock.now_nanos(), book.best_bid(), book.best_ask());
+        }
+        let deltas = book.take_deltas();
+        if !deltas.is_empty() {
+            self.index_resting(&market.symbol, &deltas);
+            tracing::debug!(symbol = %market.symbol, count = deltas.len(), last_seq = book.seq, "Publishing book deltas");
+            market.delta_log.lock().expect("Mutex lock failed for delta log").extend(deltas);
+        }
+        // Never blocks: a subscriber that falls too far behind sees Lagged
+        // and skips ahead. Fails only when nobody is subscribed.
+        for event in book.take_events() {
+            self.metrics.record(&event);
+            let _ = self.events.send(event);
+        }
+        let departed = book.take_departed();
+        if !departed.is_empty() {
+            let mut recent = self.recent_orders.lock().expect("Mutex lock failed for recent orders");
+            for order in &departed {
+                recent.insert(OrderView::from(order));
+            }
+        }
+        self.metrics.set_resting(book);
+    }
+
+    // Call while still holding the book lock so the sequence reflects acceptance order.
+    fn next_ack(&self) -> AckSeq {
+        AckSeq(self.next_ack_seq.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+// --- Metrics ---
+
+// Operational counters for GET /metrics. Fed from the book events drained in
+// on_book_change, so they count exactly what subscribers see, and read
+// without the book lock.
+#[derive(Debug, Default)]
+struct Metrics {
+    orders_created: AtomicU64,
+    orders_cancelled: AtomicU64,
+    orders_modified: AtomicU64,
+    trades: AtomicU64,
+    matched_volume: AtomicU64,
+    // Open WebSocket connections, counted by WsConnectionSlot
+    ws_connections: AtomicU64,
+    // GET /orders/:id lookups for orders no longer in the book, by whether
+    // the recent orders cache had them
+    order_cache_hits: AtomicU64,
+    order_cache_misses: AtomicU64,
+    // Resting (bids, asks) per symbol
+    resting: Mutex<BTreeMap<String, (u64, u64)>>,
+    // Create order path: handler entry to matching done, the whole time the
+    // book lock is held, and just the add/match call within it
+    receipt_to_match: LatencyHistogram,
+    book_lock_held: LatencyHistogram,
+    matching: LatencyHistogram,
+}
+
+impl Metrics {
+    fn record(&self, event: &BookEvent) {
+        match event {
+            BookEvent::OrderAdded { .. } => { self.orders_created.fetch_add(1, Ordering::Relaxed); }
+            BookEvent::OrderModified { .. } => { self.orders_modified.fetch_add(1, Ordering::Relaxed); }
+            // Includes expiries and every other cancel reason
+            BookEvent::OrderCancelled { .. } => { self.orders_cancelled.fetch_add(1, Ordering::Relaxed); }
+            BookEvent::Trade(fill) => {
+                self.trades.fetch_add(1, Ordering::Relaxed);
+                self.matched_volume.fetch_add(fill.quantity, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // Call with the book lock held
+    fn set_resting(&self, book: &OrderBook) {
+        let counts = (book.bids.len() as u64, book.asks.len() as u64);
+        self.resting.lock().expect("Mutex lock failed for resting gauge").insert(book.symbol.clone(), counts);
+    }
+
+    // Prometheus text exposition format
+    fn render(&self, paper: bool) -> String {
+        let book = if paper { "paper" } else { "live" };
+        let counters = [
+            ("oms_orders_created_total", "Orders accepted into the book", &self.orders_created),
+            ("oms_orders_cancelled_total", "Orders cancelled or expired", &self.orders_cancelled),
+            ("oms_orders_modified_total", "Accepted order modifications", &self.orders_modified),
+            ("oms_trades_total", "Trades executed", &self.trades),
+            ("oms_matched_volume_total", "Quantity traded", &self.matched_volume),
+        ];
+        let mut out = String::new();
+        for (name, help, value) in counters {
+            out.push_str(&format!("# HELP {} {}\n# TYPE {} counter\n{}{{book=\"{}\"}} {}\n", name, help, name, name, book, value.load(Ordering::Relaxed)));
+        }
+        out.push_str("# HELP oms_resting_orders Orders resting in the book\n# TYPE oms_resting_orders gauge\n");
+        for (symbol, (bids, asks)) in self.resting.lock().expect("Mutex lock failed for resting gauge").iter() {
+            for (side, value) in [("bid", bids), ("ask", asks)] {
+                out.push_str(&format!("oms_resting_orders{{book=\"{}\",symbol=\"{}\",side=\"{}\"}} {}\n", book, symbol, side, value));
+            }
+        }
+        out.push_str(&format!(
+            "# HELP oms_ws_connections Open WebSocket connections\n# TYPE oms_ws_connections gauge\noms_ws_connections{{book=\"{}\"}} {}\n",
+            book, self.ws_connections.load(Ordering::Relaxed),
+        ));
+        let (hits, misses) = (self.order_cache_hits.load(Ordering::Relaxed), self.order_cache_misses.load(Ordering::Relaxed));
+        out.push_str("# HELP oms_order_cache_lookups_total Lookups of orders no longer in the book, by cache result\n# TYPE oms_order_cache_lookups_total counter\n");
+        for (result, value) in [("hit", hits), ("miss", misses)] {
+            out.push_str(&format!("oms_order_cache_lookups_total{{book=\"{}\",result=\"{}\"}} {}\n", book, result, value));
+        }
+        // 0 before the first lookup
+        let hit_ratio = if hits + misses > 0 { hits as f64 / (hits + misses) as f64 } else { 0.0 };
+        out.push_str(&format!(
+            "# HELP oms_order_cache_hit_ratio Share of those lookups served from the cache\n# TYPE oms_order_cache_hit_ratio gauge\noms_order_cache_hit_ratio{{book=\"{}\"}} {}\n",
+            book, hit_ratio,
+        ));
+        out.push_str("# HELP oms_create_order_latency_seconds Create order latency by stage\n# TYPE oms_create_order_latency_seconds summary\n");
+        for (stage, histogram) in [("receipt_to_match", &self.receipt_to_match), ("book_lock_held", &self.book_lock_held), ("matching", &self.matching)] {
+            for quantile in LATENCY_QUANTILES {
+                let seconds = histogram.quantile(quantile) as f64 / 1e9;
+                out.push_str(&format!("oms_create_order_latency_seconds{{book=\"{}\",stage=\"{}\",quantile=\"{}\"}} {}\n", book, stage, quantile, seconds));
+            }
+            out.push_str(&format!("oms_create_order_latency_seconds_count{{book=\"{}\",stage=\"{}\"}} {}\n", book, stage, histogram.count()));
+        }
+        out
+    }
+}
+
+const LATENCY_QUANTILES: [f64; 3] = [0.5, 0.99, 0.999];
+
+// Sub-buckets per power of two; values are kept within 1/16 (6.25%) of true
+const LATENCY_SUB_BUCKET_BITS: u32 = 4;
+const LATENCY_SUB_BUCKETS: usize = 1 << LATENCY_SUB_BUCKET_BITS;
+// Exact buckets below LATENCY_SUB_BUCKETS, then one row per remaining power of two
+const LATENCY_BUCKETS: usize = LATENCY_SUB_BUCKETS * (64 - LATENCY_SUB_BUCKET_BITS as usize + 1);
+
+// HDR-style log-linear histogram of nanosecond durations. Recording is one
+// relaxed atomic add, so it can sit on the hot path.
+#[derive(Debug)]
+struct LatencyHistogram {
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            buckets: (0..LATENCY_BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+        }
+    }
+}
+
+impl LatencyHistogram {
+    fn bucket_index(nanos: u64) -> usize {
+        if nanos < LATENCY_SUB_BUCKETS as u64 {
+            return nanos as usize;
+        }
+        let magnitude = 63 - nanos.leading_zeros();
+        let shift = magnitude - LATENCY_SUB_BUCKET_BITS;
+        let sub = (nanos >> shift) as usize - LATENCY_SUB_BUCKETS;
+        LATENCY_SUB_BUCKETS * (shift as usize + 1) + sub
+    }
+
+    // Largest value that lands in the bucket
+    fn bucket_upper(index: usize) -> u64 {
+        if index < LATENCY_SUB_BUCKETS {
+            return index as u64;
+        }
+        let shift = (index / LATENCY_SUB_BUCKETS - 1) as u32;
+        let sub = (index % LATENCY_SUB_BUCKETS) as u64;
+        let lower = (LATENCY_SUB_BUCKETS as u64 + sub) << shift;
+        lower + ((1 << shift) - 1)
+    }
+
+    fn record(&self, elapsed: std::time::Duration) {
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.buckets[Self::bucket_index(nanos)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    // Upper bound of the bucket holding the q-th value, in nanoseconds; 0 when empty
+    fn quantile(&self, q: f64) -> u64 {
+        Self::quantile_of(&self.counts(), q).unwrap_or(0)
+    }
+
+    // As `quantile`, over just the values recorded since `earlier` was taken
+    // from `counts`; None when there were none
+    fn quantile_since(&self, earlier: &[u64], q: f64) -> Option<u64> {
+        let recent: Vec<u64> = self.counts().iter().zip(earlier).map(|(now, then)| now.saturating_sub(*then)).collect();
+        Self::quantile_of(&recent, q)
+    }
+
+    fn counts(&self) -> Vec<u64> {
+        self.buckets.iter().map(|bucket| bucket.load(Ordering::Relaxed)).collect()
+    }
+
+    fn quantile_of(counts: &[u64], q: f64) -> Option<u64> {
+        let count: u64 = counts.iter().sum();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((q * count as f64).ceil() as u64).clamp(1, count);
+        let mut seen = 0;
+        for (index, bucket) in counts.iter().enumerate() {
+            seen += bucket;
+            if seen >= rank {
+                return Some(Self::bucket_upper(index));
+            }
+        }
+        Some(Self::bucket_upper(LATENCY_BUCKETS - 1))
+    }
+}
+
+// --- Load Shedding ---
+
+// How often match latency is judged against the SLA when
+// OMS_LATENCY_SLA_WINDOW_MS is unset
+const DEFAULT_LATENCY_SLA_WINDOW_MS: u64 = 1_000;
+
+// Refuses new orders while recent match latency is over the SLA, so the
+// orders already resting keep trading at speed. Judged once a window from
+// the p99 of the matches made during it. A window without a single match
+// counts as recovered: shedding leaves no new matches to measure, and the
+// backlog it was protecting has had the window to drain.
+#[derive(Debug)]
+struct LoadShedder {
+    sla_nanos: u64,
+    window_nanos: u128,
+    // When the current window opened, and the matching histogram's buckets then
+    window: Mutex<(u128, Vec<u64>)>,
+    shedding: AtomicBool,
+    // p99 of the last window judged; 0 if it had no matches
+    last_p99_nanos: AtomicU64,
+}
+
+impl LoadShedder {
+    // None when OMS_LATENCY_SLA_US is unset
+    fn from_config(config: &Config, histogram: &LatencyHistogram, now: u128) -> Option<LoadShedder> {
+        let sla_nanos = config.latency_sla_nanos?;
+        let window_ms = config.latency_sla_window_ms.filter(|&ms| ms > 0).unwrap_or(DEFAULT_LATENCY_SLA_WINDOW_MS);
+        Some(LoadShedder {
+            sla_nanos,
+            window_nanos: window_ms as u128 * 1_000_000,
+            window: Mutex::new((now, histogram.counts())),
+            shedding: AtomicBool::new(false),
+            last_p99_nanos: AtomicU64::new(0),
+        })
+    }
+
+    // Whether new orders are being refused as of `now`, closing the window
+    // first if it has run its length
+    fn check(&self, histogram: &LatencyHistogram, now: u128) -> bool {
+        let mut window = self.window.lock().expect("Mutex lock failed for load shedder");
+        if now.saturating_sub(window.0) >= self.window_nanos {
+            let p99 = histogram.quantile_since(&window.1, 0.99);
+            let shedding = p99.is_some_and(|p99| p99 > self.sla_nanos);
+            self.last_p99_nanos.store(p99.unwrap_or(0), Ordering::Relaxed);
+            if self.shedding.swap(shedding, Ordering::Relaxed) != shedding {
+                if shedding {
+                    tracing::warn!(p99_nanos = ?p99, sla_nanos = self.sla_nanos, "Match latency over SLA; shedding new orders");
+                } else {
+                    tracing::info!(p99_nanos = ?p99, sla_nanos = self.sla_nanos, "Match latency recovered; accepting new orders");
+                }
+            }
+            *window = (now, histogram.counts());
+        }
+        self.shedding.load(Ordering::Relaxed)
+    }
+
+    fn status(&self) -> LoadSheddingStatus {
+        LoadSheddingStatus {
+            active: self.shedding.load(Ordering::Relaxed),
+            sla_nanos: self.sla_nanos,
+            last_p99_nanos: self.last_p99_nanos.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct LoadSheddingStatus {
+    active: bool,
+    sla_nanos: u64,
+    last_p99_nanos: u64,
+}
+
+// 503 for a new order while the engine is shedding load. Cancels never come
+// through here.
+fn check_load_shedding(state: &AppState) -> Result<(), ApiError> {
+    let Some(shedder) = &state.load_shedder else {
+        return Ok(());
+    };
+    if shedder.check(&state.metrics.matching, state.clock.now_nanos()) {
+        tracing::warn!("Rejected new order: shedding load");
+        return Err(ApiError::Unavailable("match latency is over its SLA; new orders are refused until it recovers".to_string()));
+    }
+    Ok(())
+}
+
+// 403 for a new order from a suspended account
+fn check_account_active(state: &AppState, account_id: Option<u64>) -> Result<(), ApiError> {
+    if state.is_suspended(account_id) {
+        tracing::warn!(account_id = ?account_id, "Rejected new order: account suspended");
+        return Err(ApiError::Forbidden(format!("account {} is suspended", account_id.unwrap_or_default())));
+    }
+    Ok(())
+}
+
+// --- Symbol Risk Limits ---
+
+// Checked against each new order for the symbol as it arrives; a limit left
+// unset isn't checked. Quantities are fixed-point at the symbol's quantity
+// scale, notionals at the price and quantity scales added.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct SymbolLimits {
+    max_order_qty: Option<u64>,
+    // Limit orders only: a market order's price isn't known until it trades
+    max_notional: Option<u64>,
+    // Furthest a limit price may sit from the last trade, in basis points of
+    // it; unchecked until the symbol has traded
+    price_band_bps: Option<u32>,
+}
+
+// A price band wider than this would let a sell through at a price of zero
+const MAX_PRICE_BAND_BPS: u32 = 10_000;
+
+impl SymbolLimits {
+    fn check(&self, payload: &CreateOrderPayload, last_price: Option<u64>) -> Result<(), String> {
+        let quantity_scale = quantity_scale(&payload.symbol);
+        if let Some(max) = self.max_order_qty.filter(|&max| payload.quantity > max) {
+            return Err(format!("quantity {} exceeds the max order size {} for {}", format_fixed(payload.quantity.into(), quantity_scale), format_fixed(max.into(), quantity_scale), payload.symbol));
+        }
+        if payload.order_type == OrderType::Market {
+            return Ok(());
+        }
+        let notional = payload.price as u128 * payload.quantity as u128;
+        if let Some(max) = self.max_notional.filter(|&max| notional > max as u128) {
+            return Err(format!("notional {} exceeds the max notional {} for {} (units at the price and quantity scales)", notional, max, payload.symbol));
+        }
+        if let (Some(bps), Some(last)) = (self.price_band_bps, last_price) {
+            if payload.price.abs_diff(last) as u128 * 10_000 > last as u128 * bps as u128 {
+                return Err(format!("price {} is more than {} bps from the last trade at {} for {}", payload.price, bps, last, payload.symbol));
+            }
+        }
+        Ok(())
+    }
+}
+
+// New limits for a symbol, replacing all of its current ones: a limit left
+// out is lifted
+#[derive(Debug, Default, Deserialize)]
+struct SymbolLimitsPayload {
+    #[serde(default)]
+    max_order_qty: Option<WireQuantity>,
+    #[serde(default)]
+    max_notional: Option<WireQuantity>,
+    #[serde(default)]
+    price_band_bps: Option<u32>,
+}
+
+impl SymbolLimitsPayload {
+    fn limits(&self, symbol: &str, tick_size: &TickSize) -> Result<SymbolLimits, String> {
+        let quantity_scale = quantity_scale(symbol);
+        let max_order_qty = self.max_order_qty.map(|max| max.units(quantity_scale)).transpose().map_err(|e| format!("max_order_qty: {}", e))?;
+        let max_notional = self.max_notional.map(|max| max.units(tick_size.scale() + quantity_scale)).transpose().map_err(|e| format!("max_notional: {}", e))?;
+        if max_order_qty == Some(0) || max_notional == Some(0) {
+            return Err("max_order_qty and max_notional must be greater than zero; leave one out to lift it".to_string());
+        }
+        if let Some(bps) = self.price_band_bps.filter(|&bps| bps == 0 || bps > MAX_PRICE_BAND_BPS) {
+            return Err(format!("price_band_bps {} must be between 1 and {}", bps, MAX_PRICE_BAND_BPS));
+        }
+        Ok(SymbolLimits { max_order_qty, max_notional, price_band_bps: self.price_band_bps })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct SymbolLimitsView {
+    symbol: String,
+    max_order_qty: Option<Fixed>,
+    max_notional: Option<Fixed>,
+    price_band_bps: Option<u32>,
+}
+
+impl SymbolLimitsView {
+    fn new(symbol: &str, limits: &SymbolLimits, tick_size: &TickSize) -> Self {
+        let quantity_scale = quantity_scale(symbol);
+        SymbolLimitsView {
+            symbol: symbol.to_string(),
+            max_order_qty: limits.max_order_qty.map(|units| Fixed { units: units.into(), scale: quantity_scale }),
+            max_notional: limits.max_notional.map(|units| Fixed { units: units.into(), scale: tick_size.scale() + quantity_scale }),
+            price_band_bps: limits.price_band_bps,
+        }
+    }
+}
+
+// 422 for a new order outside its symbol's limits, as they stand on arrival
+fn check_symbol_limits(state: &AppState, market: &Market, payload: &CreateOrderPayload) -> Result<(), ApiError> {
+    let limits = state.symbol_limits(&payload.symbol);
+    if limits == SymbolLimits::default() {
+        return Ok(());
+    }
+    let last_price = market.order_book.lock().expect("Mutex lock failed for book").last_price;
+    limits.check(payload, last_price).map_err(|message| {
+        tracing::warn!(symbol = %payload.symbol, reason = %message, "Rejected new order: outside the symbol's limits");
+        ApiError::Unprocessable(message)
+    })
+}
+
+// --- Market Maker Obligations ---
+
+// What a designated market maker must keep quoting in a symbol: its best bid
+// and best ask between `min_spread` and `max_spread` apart (price units),
+// each with at least `min_size` (quantity units) resting at that price
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct QuoteObligation {
+    min_spread: u64,
+    max_spread: u64,
+    min_size: u64,
+}
+
+impl QuoteObligation {
+    // `account:min_spread:max_spread:min_size`
+    fn parse(entry: &str) -> Option<(u64, QuoteObligation)> {
+        let fields: Vec<u64> = entry.trim().split(':').map(|field| field.parse().ok()).collect::<Option<_>>()?;
+        let &[account_id, min_spread, max_spread, min_size] = fields.as_slice() else {
+            return None;
+        };
+        (min_spread <= max_spread).then_some((account_id, QuoteObligation { min_spread, max_spread, min_size }))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ObligationBreach {
+    OneSided,
+    SpreadTooNarrow,
+    SpreadTooWide,
+    BidTooSmall,
+    AskTooSmall,
+}
+
+// One market maker's quote in one symbol, measured against its obligation
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct QuoteStatus {
+    symbol: String,
+    account_id: u64,
+    bid: Option<u64>,
+    bid_size: u64,
+    ask: Option<u64>,
+    ask_size: u64,
+    spread: Option<u64>,
+    breaches: Vec<ObligationBreach>,
+}
+
+impl QuoteStatus {
+    // Over the account's resting `orders` in `symbol`
+    fn measure<'a>(symbol: &str, account_id: u64, obligation: &QuoteObligation, orders: impl Iterator<Item = &'a Order>) -> Self {
+        // (price, size) of the account's best level on each side
+        let mut bid: Option<(u64, u64)> = None;
+        let mut ask: Option<(u64, u64)> = None;
+        for order in orders.filter(|o| o.account_id == Some(account_id)) {
+            let slot = match order.side {
+                Side::Buy => &mut bid,
+                Side::Sell => &mut ask,
+            };
+            let better = |best: u64| match order.side {
+                Side::Buy => order.price > best,
+                Side::Sell => order.price < best,
+            };
+            match slot {
+                Some((price, size)) if *price == order.price => *size += order.quantity,
+                Some((price, _)) if !better(*price) => {}
+                _ => *slot = Some((order.price, order.quantity)),
+            }
+        }
+        let spread = bid.zip(ask).map(|((bid, _), (ask, _))| ask.saturating_sub(bid));
+        let mut breaches = Vec::new();
+        match spread {
+            None => breaches.push(ObligationBreach::OneSided),
+            Some(spread) if spread < obligation.min_spread => breaches.push(ObligationBreach::SpreadTooNarrow),
+            Some(spread) if spread > obligation.max_spread => breaches.push(ObligationBreach::SpreadTooWide),
+            Some(_) => {}
+        }
+        if bid.is_some_and(|(_, size)| size < obligation.min_size) {
+            breaches.push(ObligationBreach::BidTooSmall);
+        }
+        if ask.is_some_and(|(_, size)| size < obligation.min_size) {
+            breaches.push(ObligationBreach::AskTooSmall);
+        }
+        QuoteStatus {
+            symbol: symbol.to_string(),
+            account_id,
+            bid: bid.map(|(price, _)| price),
+            bid_size: bid.map_or(0, |(_, size)| size),
+            ask: ask.map(|(price, _)| price),
+            ask_size: ask.map_or(0, |(_, size)| size),
+            spread,
+            breaches,
+        }
+    }
+}
+
+// A designated market maker's new limit order, taken with what it already
+// has resting, before the book matches it. A quote still being built up is
+// one-sided, so only a two-sided quote is held to its obligation here.
+fn check_quote_obligation(config: &Config, book: &OrderBook, order: &Order) -> Result<(), ApiError> {
+    let Some((account_id, obligation)) = order.account_id
+        .filter(|_| order.order_type == OrderType::Limit)
+        .and_then(|account_id| Some((account_id, config.quote_obligations.get(&order.symbol)?.get(&account_id)?)))
+    else {
+        return Ok(());
+    };
+    let status = QuoteStatus::measure(&order.symbol, account_id, obligation, book.bids.iter().chain(book.asks.iter()).chain([order]));
+    if status.breaches.is_empty() || status.breaches.contains(&ObligationBreach::OneSided) {
+        return Ok(());
+    }
+    tracing::warn!(order_id = order.id, status = ?status, action = ?config.obligation_action, "Market maker quote breaches its obligation");
+    match config.obligation_action {
+        ObligationAction::Flag => Ok(()),
+        ObligationAction::Reject => Err(ApiError::Unprocessable(format!(
+            "quote from account {} breaches its obligation on {}: {:?} (bid {:?}, ask {:?})",
+            account_id, order.symbol, status.breaches, status.bid, status.ask,
+        ))),
+    }
+}
+
+// --- Account Ownership ---
+
+// The account a cancel or modify acts for
+const ACCOUNT_ID_HEADER: &str = "x-account-id";
+
+fn caller_account(headers: &HeaderMap) -> Result<Option<u64>, ApiError> {
+    let Some(value) = headers.get(ACCOUNT_ID_HEADER) else {
+        return Ok(None);
+    };
+    value.to_str().ok().and_then(|v| v.parse().ok()).map(Some)
+        .ok_or_else(|| ApiError::BadRequest(format!("{} must be an account number", ACCOUNT_ID_HEADER)))
+}
+
+// Refuses `caller` acting on an order `owner` entered. An order entered
+// without an account belongs to callers that name none.
+fn check_owner(config: &Config, order_id: OrderId, owner: Option<u64>, caller: Option<u64>) -> Result<(), ApiError> {
+    if owner == caller {
+        return Ok(());
+    }
+    let refusal = match config.account_ownership {
+        AccountOwnership::Unchecked => return Ok(()),
+        AccountOwnership::Forbid => ApiError::Forbidden(format!("order {} belongs to another account", order_id)),
+        AccountOwnership::Hide => ApiError::order_not_found(order_id),
+    };
+    tracing::warn!(order_id = order_id, owner = ?owner, caller = ?caller, "Rejected action on another account's order");
+    Err(refusal)
+}
+
+// --- Order Acknowledgment Sequence ---
+
+const ACK_SEQ_HEADER: &str = "x-ack-seq";
+
+// Global, strictly increasing token stamped on every accepted order operation.
+// Clients reading responses over concurrent streams can sort acks by it to
+// recover the order in which the engine accepted their requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct AckSeq(u64);
+
+impl IntoResponseParts for AckSeq {
+    type Error = std::convert::Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        res.headers_mut().insert(ACK_SEQ_HEADER, HeaderValue::from(self.0));
+        Ok(res)
+    }
+}
+
+// --- Database Setup ---
+const DB_PATH: &str = "oms_data.db";
+// OMS_DB_PATH value for an ephemeral database that lives only as long as the process
+const IN_MEMORY_DB_PATH: &str = ":memory:";
+
+// OMS_DB_PATH, or DB_PATH when unset or empty
+fn db_path(value: Option<String>) -> String {
+    value.filter(|path| !path.trim().is_empty()).unwrap_or_else(|| DB_PATH.to_string())
+}
+
+fn init_db(read_only: bool) -> SqlResult<Connection> {
+    open_db(&db_path(std::env::var("OMS_DB_PATH").ok()), read_only)
+}
+
+fn open_db(path: &str, read_only: bool) -> SqlResult<Connection> {
+    if path == IN_MEMORY_DB_PATH {
+        // Nothing to replicate from, so a read-only replica still gets a schema
+        tracing::info!(read_only = read_only, "Initializing in-memory database; nothing will be kept");
+        let conn = Connection::open_in_memory()?;
+        init_schema(&conn)?;
+        return Ok(conn);
+    }
+    if read_only {
+        // Schema and journal mode are owned by the primary; just open for reads.
+        tracing::info!(db_path = path, "Opening database read-only...");
+        return Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY);
+    }
+    tracing::info!(db_path = path, "Initializing database...");
+    let conn = Connection::open(path)?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    init_schema(&conn)?;
+    Ok(conn)
+}
+
+fn init_schema(conn: &Connection) -> SqlResult<()> {
+    for table in [ORDERS_TABLE, PAPER_ORDERS_TABLE] {
+        create_orders_table(conn, table)?;
+    }
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS recovery_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            paper INTEGER NOT NULL,
+            started_at TEXT NOT NULL,
+            orders_loaded INTEGER NOT NULL,
+            orders_expired INTEGER NOT NULL,
+            rows_quarantined INTEGER NOT NULL,
+            reconciliation_corrections INTEGER NOT NULL DEFAULT 0,
+            duration_micros INTEGER NOT NULL,
+            next_order_id INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    add_column_if_missing(conn, "recovery_log", "reconciliation_corrections", "INTEGER NOT NULL DEFAULT 0")?;
+    tracing::info!("Database table 'recovery_log' initialized.");
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS corporate_action_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            paper INTEGER NOT NULL,
+            applied_at TEXT NOT NULL,
+            action TEXT NOT NULL,
+            order_id INTEGER NOT NULL,
+            outcome TEXT NOT NULL,
+            old_price INTEGER NOT NULL,
+            new_price INTEGER NOT NULL,
+            old_quantity INTEGER NOT NULL,
+            new_quantity INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    tracing::info!("Database table 'corporate_action_log' initialized.");
+    for table in [TRADES_TABLE, PAPER_TRADES_TABLE] {
+        create_trades_table(conn, table)?;
+    }
+    move_paper_rows(conn, TRADES_TABLE, PAPER_TRADES_TABLE, TRADE_COLUMNS)?;
+    tracing::info!("Database tables 'trades' and 'paper_trades' initialized.");
+    for table in [ORDER_EVENTS_TABLE, PAPER_ORDER_EVENTS_TABLE] {
+        create_order_events_table(conn, table)?;
+    }
+    // The append-only triggers go on afterwards, or the move couldn't delete
+    if move_paper_rows(conn, ORDER_EVENTS_TABLE, PAPER_ORDER_EVENTS_TABLE, ORDER_EVENT_COLUMNS)? > 0 {
+        tracing::info!("Moved paper audit entries out of 'order_events'");
+    }
+    for table in [ORDER_EVENTS_TABLE, PAPER_ORDER_EVENTS_TABLE] {
+        // Append-only, enforced by the DB itself rather than by convention
+        for verb in ["UPDATE", "DELETE"] {
+            conn.execute(
+                &format!("CREATE TRIGGER IF NOT EXISTS {table}_no_{} BEFORE {} ON {table} BEGIN SELECT RAISE(ABORT, '{table} is append-only'); END", verb.to_lowercase(), verb),
+                [],
+            )?;
+        }
+    }
+    tracing::info!("Database tables 'order_events' and 'paper_order_events' initialized.");
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS account_suspensions (
+            paper INTEGER NOT NULL,
+            account_id INTEGER NOT NULL,
+            suspended_at TEXT NOT NULL,
+            PRIMARY KEY (paper, account_id)
+        )",
+        [],
+    )?;
+    tracing::info!("Database table 'account_suspensions' initialized.");
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS symbol_limits (
+            paper INTEGER NOT NULL,
+            symbol TEXT NOT NULL,
+            max_order_qty INTEGER,
+            max_notional INTEGER,
+            price_band_bps INTEGER,
+            updated_at TEXT NOT NULL,
+            PRIMARY KEY (paper, symbol)
+        )",
+        [],
+    )?;
+    tracing::info!("Database table 'symbol_limits' initialized.");
+    Ok(())
+}
+
+const TRADE_COLUMNS: &str = "paper, trade_id, executed_at, bid_id, ask_id, price, quantity, symbol, trade_seq, maker_id";
+
+fn create_trades_table(conn: &Connection, table: &str) -> SqlResult<()> {
+    conn.execute(
+        &format!("CREATE TABLE IF NOT EXISTS {} (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            paper INTEGER NOT NULL,
+            trade_id INTEGER NOT NULL DEFAULT 0,
+            executed_at TEXT NOT NULL,
+            bid_id INTEGER NOT NULL,
+            ask_id INTEGER NOT NULL,
+            price INTEGER NOT NULL,
+            quantity INTEGER NOT NULL,
+            symbol TEXT NOT NULL DEFAULT 'DEFAULT',
+            trade_seq INTEGER NOT NULL DEFAULT 0,
+            maker_id INTEGER
+        )", table),
+        [],
+    )?;
+    add_column_if_missing(conn, table, "trade_id", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, table, "symbol", "TEXT NOT NULL DEFAULT 'DEFAULT'")?;
+    add_column_if_missing(conn, table, "trade_seq", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, table, "maker_id", "INTEGER")?;
+    // Replay and lookups go by per-symbol trade id, the analytics by time.
+    // executed_at is text, so time ranges are indexed (and must be queried)
+    // by its integer value.
+    conn.execute(&format!("CREATE INDEX IF NOT EXISTS {table}_by_symbol_id ON {table} (paper, symbol, trade_id)"), [])?;
+    conn.execute(&format!("CREATE INDEX IF NOT EXISTS {table}_by_symbol_time ON {table} (paper, symbol, CAST(executed_at AS INTEGER))"), [])?;
+    Ok(())
+}
+
+const ORDER_EVENT_COLUMNS: &str = "paper, order_id, from_status, to_status, remaining_quantity, reason, timestamp, trade_seq";
+
+fn create_order_events_table(conn: &Connection, table: &str) -> SqlResult<()> {
+    conn.execute(
+        &format!("CREATE TABLE IF NOT EXISTS {} (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            paper INTEGER NOT NULL,
+            order_id INTEGER NOT NULL,
+            from_status TEXT,
+            to_status TEXT NOT NULL,
+            remaining_quantity INTEGER NOT NULL,
+            reason TEXT NOT NULL,
+            timestamp TEXT NOT NULL,
+            trade_seq INTEGER
+        )", table),
+        [],
+    )?;
+    add_column_if_missing(conn, table, "trade_seq", "INTEGER")?;
+    conn.execute(&format!("CREATE INDEX IF NOT EXISTS {table}_by_order ON {table} (paper, order_id, id)"), [])?;
+    Ok(())
+}
+
+// Paper rows written to a live table before paper trading had tables of its
+// own are moved across, oldest first. Returns how many were moved.
+fn move_paper_rows(conn: &Connection, live: &str, paper: &str, columns: &str) -> SqlResult<usize> {
+    let stranded: i64 = conn.query_row(&format!("SELECT COUNT(*) FROM {} WHERE paper = 1", live), [], |row| row.get(0))?;
+    if stranded == 0 {
+        return Ok(0);
+    }
+    tracing::info!(from = live, to = paper, rows = stranded, "Migrating schema: moving paper rows to their own table");
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(&format!("DROP TRIGGER IF EXISTS {}_no_delete", live), [])?;
+    tx.execute(&format!("INSERT INTO {paper} ({columns}) SELECT {columns} FROM {live} WHERE paper = 1 ORDER BY id"), [])?;
+    let moved = tx.execute(&format!("DELETE FROM {} WHERE paper = 1", live), [])?;
+    tx.commit()?;
+    Ok(moved)
+}
+
+// --- Order Audit Log ---
+
+// Appends one state change to an order's audit trail. `from_status` is what
+// its previous entry moved it to (none for its first), so log every change,
+// in the same transaction as the write it records. `reason` is "Created",
+// "Fill", "Modified" and so on, or the cancel reason's name.
+fn log_order_event(conn: &Connection, paper: bool, order_id: OrderId, to_status: &OrderStatus, remaining_quantity: u64, reason: &str, at: u128) -> SqlResult<usize> {
+    conn.execute(&order_event_insert(paper), params![paper, order_id, format!("{:?}", to_status), remaining_quantity, reason, at.to_string(), None::<u64>])
+}
+
+// A fill, tagged with its trade so the order's state as of that trade can be found again
+fn log_fill_event(conn: &Connection, paper: bool, order_id: OrderId, to_status: &OrderStatus, remaining_quantity: u64, fill: &Fill) -> SqlResult<usize> {
+    conn.execute(&order_event_insert(paper), params![paper, order_id, format!("{:?}", to_status), remaining_quantity, "Fill", fill.executed_at.to_string(), fill.trade_seq])
+}
+
+fn order_event_insert(paper: bool) -> String {
+    let table = order_events_table(paper);
+    format!("INSERT INTO {table} (paper, order_id, from_status, to_status, remaining_quantity, reason, timestamp, trade_seq)
+     VALUES (?1, ?2, (SELECT to_status FROM {table} WHERE paper = ?1 AND order_id = ?2 ORDER BY id DESC LIMIT 1), ?3, ?4, ?5, ?6, ?7)")
+}
+
+// Cancelled or expired, with nothing left
+fn log_order_cancelled(conn: &Connection, paper: bool, order_id: OrderId, status: &OrderStatus, reason: Option<CancelReason>, at: u128) -> SqlResult<usize> {
+    let reason = reason.map_or_else(|| "Cancelled".to_string(), |reason| format!("{:?}", reason));
+    log_order_event(conn, paper, order_id, status, 0, &reason, at)
+}
+
+// One entry of an order's audit trail
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct OrderEventView {
+    from_status: Option<OrderStatus>,
+    to_status: OrderStatus,
+    remaining_quantity: u64,
+    reason: String,
+    timestamp: u128,
+}
+
+// An order's audit trail, oldest first
+fn load_order_events(conn: &Connection, paper: bool, order_id: OrderId) -> SqlResult<Vec<OrderEventView>> {
+    let mut stmt = conn.prepare(&format!("SELECT from_status, to_status, remaining_quantity, reason, timestamp FROM {} WHERE paper = ?1 AND order_id = ?2 ORDER BY id", order_events_table(paper)))?;
+    let rows = stmt.query_map(params![paper, order_id], |row| {
+        let status = |index: usize, value: String| OrderStatus::from_db(&value).ok_or_else(|| rusqlite::Error::FromSqlConversionFailure(
+            index,
+            rusqlite::types::Type::Text,
+            Box::new(ConversionError(format!("Invalid status string: {}", value)))
+        ));
+        Ok(OrderEventView {
+            from_status: row.get::<_, Option<String>>(0)?.map(|value| status(0, value)).transpose()?,
+            to_status: status(1, row.get(1)?)?,
+            remaining_quantity: row.get(2)?,
+            reason: row.get(3)?,
+            timestamp: nanos_from_text(row, 4, "timestamp")?.unwrap_or(0),
+        })
+    })?;
+    rows.collect()
+}
+
+// --- Read Connection Pool ---
+
+// Idle read connections kept for reuse; more are opened on demand under load
+const READ_POOL_MAX_IDLE: usize = 8;
+
+// Where lookups get a connection. Under WAL, readers on their own connections
+// wait neither for the writer's mutex nor for each other. An in-memory DB (as
+// in tests) can't be opened twice, so there reads go through the writer.
+enum ReadPool {
+    File { path: String, idle: Mutex<Vec<Connection>> },
+    Writer(Arc<Mutex<Connection>>),
+}
+
+enum ReadConn<'a> {
+    Pooled { idle: &'a Mutex<Vec<Connection>>, conn: Option<Connection> },
+    Writer(std::sync::MutexGuard<'a, Connection>),
+}
+
+impl ReadPool {
+    // Pools connections to the writer's database file, if it has one
+    fn for_writer(db_conn: &Arc<Mutex<Connection>>) -> Self {
+        let path = db_conn.lock().expect("Mutex lock failed for DB path")
+            .path()
+            .filter(|path| !path.is_empty())
+            .map(str::to_string);
+        match path {
+            Some(path) => ReadPool::File { path, idle: Mutex::new(Vec::new()) },
+            None => ReadPool::Writer(Arc::clone(db_conn)),
+        }
+    }
+
+    fn get(&self) -> SqlResult<ReadConn<'_>> {
+        match self {
+            ReadPool::File { path, idle } => {
+                let reused = idle.lock().expect("Mutex lock failed for read pool").pop();
+                let conn = match reused {
+                    Some(conn) => conn,
+                    None => {
+                        tracing::debug!(db_path = %path, "Opening read connection");
+                        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+                        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+                        conn
+                    }
+                };
+                Ok(ReadConn::Pooled { idle, conn: Some(conn) })
+            }
+            ReadPool::Writer(db_conn) => Ok(ReadConn::Writer(db_conn.lock().expect("Mutex lock failed for DB (read)"))),
+        }
+    }
+}
+
+impl std::ops::Deref for ReadConn<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            ReadConn::Pooled { conn, .. } => conn.as_ref().expect("read connection already returned"),
+            ReadConn::Writer(guard) => guard,
+        }
+    }
+}
+
+impl Drop for ReadConn<'_> {
+    fn drop(&mut self) {
+        if let ReadConn::Pooled { idle, conn } = self {
+            let mut idle = idle.lock().expect("Mutex lock failed for read pool");
+            if idle.len() < READ_POOL_MAX_IDLE {
+                idle.extend(conn.take());
+            }
+        }
+    }
+}
+
+// `maker_id` is the side that was resting; auction trades have none
+fn insert_trade(conn: &Connection, paper: bool, fill: &Fill, maker_id: Option<OrderId>) -> SqlResult<usize> {
+    conn.execute(
+        &format!("INSERT INTO {} (paper, symbol, trade_id, trade_seq, executed_at, bid_id, ask_id, price, quantity, maker_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)", trades_table(paper)),
+        params![paper, fill.symbol, fill.trade_id, fill.trade_seq, fill.executed_at.to_string(), fill.bid_id, fill.ask_id, fill.price, fill.quantity, maker_id],
+    )
+}
+
+// Trades in one symbol newer than `after`, oldest first, for replay to a
+// reconnecting client
+fn load_trades_after(conn: &Connection, paper: bool, symbol: &str, after: u64) -> SqlResult<Vec<Fill>> {
+    let mut stmt = conn.prepare(&format!("SELECT trade_id, symbol, bid_id, ask_id, price, quantity, trade_seq, executed_at FROM {} WHERE paper = ?1 AND symbol = ?2 AND trade_id > ?3 ORDER BY trade_id", trades_table(paper)))?;
+    let rows = stmt.query_map(params![paper, symbol, after], |row| {
+        Ok(Fill {
+            trade_id: row.get(0)?,
+            trade_seq: row.get(6)?,
+            executed_at: nanos_from_text(row, 7, "executed_at")?.unwrap_or(0),
+            symbol: row.get(1)?,
+            bid_id: row.get(2)?,
+            ask_id: row.get(3)?,
+            price: row.get(4)?,
+            quantity: row.get(5)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// The one time-range read over trades; every window statistic is built on it.
+// The CAST must match trades_by_symbol_time's for SQLite to use the index.
+fn trades_between_sql(table: &str) -> String {
+    format!("SELECT trade_id, symbol, bid_id, ask_id, price, quantity, trade_seq, executed_at FROM {}
+     WHERE paper = ?1 AND symbol = ?2 AND CAST(executed_at AS INTEGER) BETWEEN ?3 AND ?4
+     ORDER BY CAST(executed_at AS INTEGER), trade_id", table)
+}
+
+// The symbol's trades executed in [from, to], oldest first
+fn load_trades_between(conn: &Connection, paper: bool, symbol: &str, from: u128, to: u128) -> SqlResult<Vec<Fill>> {
+    let bound = |nanos: u128| i64::try_from(nanos).unwrap_or(i64::MAX);
+    let mut stmt = conn.prepare(&trades_between_sql(trades_table(paper)))?;
+    let rows = stmt.query_map(params![paper, symbol, bound(from), bound(to)], |row| {
+        Ok(Fill {
+            trade_id: row.get(0)?,
+            trade_seq: row.get(6)?,
+            executed_at: nanos_from_text(row, 7, "executed_at")?.unwrap_or(0),
+            symbol: row.get(1)?,
+            bid_id: row.get(2)?,
+            ask_id: row.get(3)?,
+            price: row.get(4)?,
+            quantity: row.get(5)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// One trade by its per-symbol id, with the id of the order that was resting
+// (none for auction trades and ones recorded before makers were)
+fn load_trade(conn: &Connection, paper: bool, symbol: &str, trade_id: u64) -> SqlResult<Option<(Fill, Option<OrderId>)>> {
+    let mut stmt = conn.prepare(&format!("SELECT trade_id, symbol, bid_id, ask_id, price, quantity, trade_seq, executed_at, maker_id FROM {} WHERE paper = ?1 AND symbol = ?2 AND trade_id = ?3", trades_table(paper)))?;
+    let mut rows = stmt.query_map(params![paper, symbol, trade_id], |row| {
+        let fill = Fill {
+            trade_id: row.get(0)?,
+            trade_seq: row.get(6)?,
+            executed_at: nanos_from_text(row, 7, "executed_at")?.unwrap_or(0),
+            symbol: row.get(1)?,
+            bid_id: row.get(2)?,
+            ask_id: row.get(3)?,
+            price: row.get(4)?,
+            quantity: row.get(5)?,
+        };
+        Ok((fill, row.get(8)?))
+    })?;
+    rows.next().transpose()
+}
+
+// An order's status and remaining quantity right after `fill`, and whether
+// that comes from the fill's own audit entry. When the DB declined the fill
+// (the order had already been cancelled) or it was logged before entries
+// named their trade, it is the last entry before it instead.
+fn order_state_at_trade(conn: &Connection, paper: bool, order_id: OrderId, fill: &Fill) -> SqlResult<Option<(OrderStatus, u64, bool)>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT to_status, remaining_quantity, COALESCE(trade_seq = ?3, 0) AS own FROM {}
+         WHERE paper = ?1 AND order_id = ?2
+           AND (trade_seq = ?3 OR ((trade_seq IS NULL OR trade_seq < ?3) AND CAST(timestamp AS INTEGER) <= ?4))
+         ORDER BY own DESC, id DESC LIMIT 1",
+        order_events_table(paper),
+    ))?;
+    let executed_at = i64::try_from(fill.executed_at).unwrap_or(i64::MAX);
+    let mut rows = stmt.query_map(params![paper, order_id, fill.trade_seq, executed_at], |row| {
+        let status: String = row.get(0)?;
+        let status = OrderStatus::from_db(&status).ok_or_else(|| rusqlite::Error::FromSqlConversionFailure(
+            0,
+            rusqlite::types::Type::Text,
+            Box::new(ConversionError(format!("Invalid status string: {}", status)))
+        ))?;
+        Ok((status, row.get(1)?, row.get(2)?))
+    })?;
+    rows.next().transpose()
+}
+
+// Highest trade sequence number handed out; it runs across symbols
+fn last_trade_seq(conn: &Connection, paper: bool) -> SqlResult<u64> {
+    conn.query_row(&format!("SELECT COALESCE(MAX(trade_seq), 0) FROM {} WHERE paper = ?1", trades_table(paper)), params![paper], |row| row.get(0))
+}
+
+fn suspended_accounts(conn: &Connection, paper: bool) -> SqlResult<HashSet<u64>> {
+    let mut stmt = conn.prepare("SELECT account_id FROM account_suspensions WHERE paper = ?1")?;
+    let rows = stmt.query_map(params![paper], |row| row.get(0))?;
+    rows.collect()
+}
+
+fn load_symbol_limits(conn: &Connection, paper: bool) -> SqlResult<HashMap<String, SymbolLimits>> {
+    let mut stmt = conn.prepare("SELECT symbol, max_order_qty, max_notional, price_band_bps FROM symbol_limits WHERE paper = ?1")?;
+    let rows = stmt.query_map(params![paper], |row| {
+        Ok((row.get(0)?, SymbolLimits { max_order_qty: row.get(1)?, max_notional: row.get(2)?, price_band_bps: row.get(3)? }))
+    })?;
+    rows.collect()
+}
+
+// Trade ids count per symbol
+fn last_trade_ids(conn: &Connection, paper: bool) -> SqlResult<HashMap<String, u64>> {
+    let mut stmt = conn.prepare(&format!("SELECT symbol, MAX(trade_id) FROM {} WHERE paper = ?1 GROUP BY symbol", trades_table(paper)))?;
+    let rows = stmt.query_map(params![paper], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+fn create_orders_table(conn: &Connection, table: &str) -> SqlResult<()> {
+    conn.execute(
+        &format!("CREATE TABLE IF NOT EXISTS {} (
+            id INTEGER PRIMARY KEY,
+            side TEXT NOT NULL,
+            price INTEGER NOT NULL,
+            original_quantity INTEGER NOT NULL,
+            remaining_quantity INTEGER NOT NULL,
+            status TEXT NOT NULL,
+            timestamp TEXT NOT NULL, -- CHANGED TO TEXT
+            group_id INTEGER,
+            created_at TEXT,
+            linked_to INTEGER,
+            priority INTEGER,
+            dnr INTEGER NOT NULL DEFAULT 0,
+            order_type TEXT NOT NULL DEFAULT 'Limit',
+            time_in_force TEXT NOT NULL DEFAULT 'Gtc',
+            cancel_reason TEXT,
+            account_id INTEGER,
+            stp INTEGER NOT NULL DEFAULT 0,
+            symbol TEXT NOT NULL DEFAULT 'DEFAULT',
+            expires_at TEXT,
+            post_only INTEGER NOT NULL DEFAULT 0,
+            min_qty INTEGER,
+            display_qty INTEGER
+        )", table),
+        [],
+    )?;
+    add_column_if_missing(conn, table, "group_id", "INTEGER")?;
+    add_column_if_missing(conn, table, "created_at", "TEXT")?;
+    add_column_if_missing(conn, table, "linked_to", "INTEGER")?;
+    add_column_if_missing(conn, table, "priority", "INTEGER")?;
+    add_column_if_missing(conn, table, "dnr", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, table, "order_type", "TEXT NOT NULL DEFAULT 'Limit'")?;
+    add_column_if_missing(conn, table, "cancel_reason", "TEXT")?;
+    add_column_if_missing(conn, table, "time_in_force", "TEXT NOT NULL DEFAULT 'Gtc'")?;
+    add_column_if_missing(conn, table, "account_id", "INTEGER")?;
+    add_column_if_missing(conn, table, "stp", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, table, "symbol", "TEXT NOT NULL DEFAULT 'DEFAULT'")?;
+    add_column_if_missing(conn, table, "expires_at", "TEXT")?;
+    add_column_if_missing(conn, table, "post_only", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, table, "min_qty", "INTEGER")?;
+    add_column_if_missing(conn, table, "display_qty", "INTEGER")?;
+    tracing::info!("Database table '{}' initialized.", table);
+    Ok(())
+}
+
+// Last persisted state of a single order, whatever its status.
+const ORDER_VIEW_COLUMNS: &str = "id, side, price, remaining_quantity, status, group_id, linked_to, dnr, order_type, cancel_reason, time_in_force, account_id, stp, symbol, expires_at, post_only, min_qty, display_qty";
+
+// Nanosecond times are stored as text, as they don't fit an INTEGER
+fn nanos_from_text(row: &rusqlite::Row, index: usize, column: &str) -> SqlResult<Option<u128>> {
+    let Some(text) = row.get::<_, Option<String>>(index)? else {
+        return Ok(None);
+    };
+    text.parse::<u128>().map(Some).map_err(|e| rusqlite::Error::FromSqlConversionFailure(
+        index,
+        rusqlite::types::Type::Text,
+        Box::new(ConversionError(format!("Failed to parse u128 from {} string: {}", column, e)))
+    ))
+}
+
+// Maps a row selected with ORDER_VIEW_COLUMNS
+fn order_view_from_row(row: &rusqlite::Row, paper: bool) -> SqlResult<OrderView> {
+    let side_str: String = row.get(1)?;
+    let side = Side::from_db(&side_str).ok_or_else(|| rusqlite::Error::FromSqlConversionFailure(
+        1,
+        rusqlite::types::Type::Text,
+        Box::new(ConversionError(format!("Invalid side string: {}", side_str)))
+    ))?;
+    let status_str: String = row.get(4)?;
+    let status = OrderStatus::from_db(&status_str).ok_or_else(|| rusqlite::Error::FromSqlConversionFailure(
+        4,
+        rusqlite::types::Type::Text,
+        Box::new(ConversionError(format!("Invalid status string: {}", status_str)))
+    ))?;
+    Ok(OrderView {
+        id: row.get(0)?,
+        side,
+        price: row.get(2)?,
+        quantity: row.get(3)?,
+        status,
+        group_id: row.get(5)?,
+        paper,
+        linked_to: row.get(6)?,
+        dnr: row.get(7)?,
+        order_type: if row.get::<_, String>(8)? == "Market" { OrderType::Market } else { OrderType::Limit },
+        cancel_reason: row.get::<_, Option<String>>(9)?.as_deref().and_then(CancelReason::from_db),
+        time_in_force: if row.get::<_, String>(10)? == "Fok" { TimeInForce::Fok } else { TimeInForce::Gtc },
+        account_id: row.get(11)?,
+        stp: row.get(12)?,
+        symbol: row.get(13)?,
+        expires_at: nanos_from_text(row, 14, "expires_at")?,
+        post_only: row.get(15)?,
+        min_qty: row.get(16)?,
+        display_qty: row.get(17)?,
+    })
+}
+
+fn load_order_view(conn: &Connection, table: &str, order_id: OrderId, paper: bool) -> SqlResult<Option<OrderView>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM {} WHERE id = ?1", ORDER_VIEW_COLUMNS, table))?;
+    let mut rows = stmt.query_map(params![order_id], |row| order_view_from_row(row, paper))?;
+    rows.next().transpose()
+}
+
+// Newest first. Filter values are always bound, never spliced into the SQL.
+fn load_order_views(conn: &Connection, table: &str, paper: bool, filter: &OrderFilter) -> SqlResult<Vec<OrderView>> {
+    let mut clauses = Vec::new();
+    let mut values: Vec<rusqlite::types::Value> = Vec::new();
+    if let Some(status) = &filter.status {
+        clauses.push("status = ?");
+        values.push(format!("{:?}", status).into());
+    }
+    if let Some(side) = &filter.side {
+        clauses.push("side = ?");
+        values.push(format!("{:?}", side).into());
+    }
+    if let Some(symbol) = &filter.symbol {
+        clauses.push("symbol = ?");
+        values.push(symbol.clone().into());
+    }
+    let mut sql = format!("SELECT {} FROM {}", ORDER_VIEW_COLUMNS, table);
+    if !clauses.is_empty() {
+        sql.push_str(" WHERE ");
+        sql.push_str(&clauses.join(" AND "));
+    }
+    sql.push_str(" ORDER BY id DESC");
+    if let Some(limit) = filter.limit {
+        sql.push_str(" LIMIT ?");
+        values.push(i64::from(limit).into());
+    }
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| order_view_from_row(row, paper))?;
+    rows.collect()
+}
+
+// Writes every column of an order row. `verb` is "INSERT" or "INSERT OR REPLACE".
+fn insert_order_row(conn: &Connection, verb: &str, table: &str, order: &Order, original_quantity: u64) -> SqlResult<usize> {
+    conn.execute(
+        &format!("{} INTO {} (id, side, price, original_quantity, remaining_quantity, status, timestamp, group_id, created_at, linked_to, priority, dnr, order_type, cancel_reason, time_in_force, account_id, stp, symbol, expires_at, post_only, min_qty, display_qty) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22)", verb, table),
+        params![
+            order.id,
+            format!("{:?}", order.side),
+            order.price,
+            original_quantity,
+            order.quantity,
+            format!("{:?}", order.status),
+            order.timestamp.to_string(), // STORE TIMESTAMP AS STRING
+            order.group_id,
+            order.created_at.to_string(),
+            order.linked_to,
+            order.priority,
+            order.dnr,
+            format!("{:?}", order.order_type),
+            order.cancel_reason.map(|reason| format!("{:?}", reason)),
+            format!("{:?}", order.time_in_force),
+            order.account_id,
+            order.stp,
+            order.symbol,
+            order.expires_at.map(|t| t.to_string()),
+            order.post_only,
+            order.min_qty,
+            order.display_qty,
+        ],
+    )
+}
+
+// A newly accepted order's row, with the first entry of its audit trail
+// A limit order that left the book while being added: filled, unless one of
+// the writes its matching made cancelled it
+fn settled_on_entry(order: Order, writes: &[BookWrite]) -> Order {
+    let cancelled_by = writes.iter().find_map(|write| match write {
+        BookWrite::SelfTradeCancel { order_id } if *order_id == order.id => Some(CancelReason::SelfTradePrevention),
+        BookWrite::OcoCancel { order_ids, .. } if order_ids.contains(&order.id) => Some(CancelReason::OcoTriggered),
+        _ => None,
+    });
+    match cancelled_by {
+        Some(reason) => Order { quantity: 0, status: OrderStatus::Cancelled, cancel_reason: Some(reason), ..order },
+        None => Order { quantity: 0, status: OrderStatus::Filled, ..order },
+    }
+}
+
+// A created order's row, then every change its matching made, then where it
+// ended up: the priority it rests at, or a market remainder's cancel. One
+// transaction, so the order and its trades are durable together or not at all.
+fn commit_created_order(conn: &mut Connection, order: &Order, writes: &[BookWrite], outcome: &Result<u64, Order>, at: u128) -> SqlResult<()> {
+    let table = orders_table(order.paper);
+    let tx = conn.transaction()?;
+    insert_order_row(&tx, "INSERT", table, order, order.quantity)?;
+    log_order_event(&tx, order.paper, order.id, &order.status, order.quantity, "Created", order.created_at)?;
+    for write in writes {
+        apply_book_write(&tx, order.paper, write)?;
+    }
+    match outcome {
+        Ok(priority) => {
+            tx.execute(&format!("UPDATE {} SET priority = ?1 WHERE id = ?2", table), params![priority, order.id])?;
+        }
+        Err(market) if market.status == OrderStatus::Cancelled => {
+            tx.execute(
+                &format!("UPDATE {} SET status = 'Cancelled', remaining_quantity = 0, cancel_reason = ?1 WHERE id = ?2", table),
+                params![market.cancel_reason.map(|reason| format!("{:?}", reason)), order.id],
+            )?;
+            log_order_cancelled(&tx, order.paper, order.id, &market.status, market.cancel_reason, at)?;
+        }
+        Err(_) => {}
+    }
+    tx.commit()
+}
+
+fn replace_resting_orders<'a>(tx: &Connection, table: &str, symbol: &str, orders: impl Iterator<Item = &'a Order>) -> SqlResult<()> {
+    tx.execute(&format!("DELETE FROM {} WHERE symbol = ?1 AND (status = 'Open' OR status = 'PartiallyFilled')", table), params![symbol])?;
+    let restored_at = now_nanos();
+    for order in orders {
+        insert_order_row(tx, "INSERT OR REPLACE", table, order, order.quantity)?;
+        log_order_event(tx, order.paper, order.id, &order.status, order.quantity, "Restored", restored_at)?;
+    }
+    Ok(())
+}
+
+// Brings tables created by older versions up to date; CREATE TABLE IF NOT EXISTS won't.
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, decl: &str) -> SqlResult<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<SqlResult<Vec<_>>>()?
+        .iter()
+        .any(|name| name == column);
+    if !exists {
+        tracing::info!(table = table, column = column, "Migrating schema: adding column");
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl), [])?;
+    }
+    Ok(())
+}
+
+// Result of reloading resting orders at startup
+#[derive(Debug, Default)]
+struct LoadedOrders {
+    orders: Vec<Order>,
+    // Older than the configured max age; marked Expired instead of loaded
+    expired: usize,
+    // Rows that failed to parse; left untouched in the DB and not loaded
+    quarantined: usize,
+    // Rows whose stored state contradicted itself and were fixed in place
+    corrected: usize,
+}
+
+fn load_open_orders(conn: &Connection, paper: bool, config: &Config, now: u128) -> SqlResult<LoadedOrders> {
+    let table = orders_table(paper);
+    tracing::info!(table = table, "Loading open orders from database...");
+    let mut stmt = conn.prepare(&format!("SELECT id, side, price, remaining_quantity, timestamp, status, group_id, COALESCE(created_at, timestamp), linked_to, COALESCE(priority, 0), dnr, order_type, time_in_force, account_id, stp, symbol, expires_at, post_only, min_qty, display_qty FROM {} WHERE status = 'Open' OR status = 'PartiallyFilled'", table))?;
+    let order_iter = stmt.query_map([], |row| {
+        let side_str: String = row.get(1)?;
+        let side = match side_str.as_str() {
+            "Buy" => Side::Buy,
+            "Sell" => Side::Sell,
+            other => return Err(rusqlite::Error::FromSqlConversionFailure(
+                1,
+                rusqlite::types::Type::Text,
+                Box::new(ConversionError(format!("Invalid side string: {}", other))) // USE ConversionError
+            )),
+        };
+        let status_str: String = row.get(5)?;
+        let status = match status_str.as_str() {
+            "Open" => OrderStatus::Open,
+            "PartiallyFilled" => OrderStatus::PartiallyFilled,
+            other => return Err(rusqlite::Error::FromSqlConversionFailure(
+                5,
+                rusqlite::types::Type::Text,
+                Box::new(ConversionError(format!("Invalid status string: {}", other))) // USE ConversionError
+            )),
+        };
+        Ok(Order {
+            id: row.get(0)?,
+            symbol: row.get(15)?,
+            side,
+            price: row.get(2)?,
+            quantity: row.get(3)?,
+            timestamp: {
+                let ts_str: String = row.get(4)?;
+                ts_str.parse::<u128>().map_err(|e| rusqlite::Error::FromSqlConversionFailure(
+                    4,
+                    rusqlite::types::Type::Text,
+                    Box::new(ConversionError(format!("Failed to parse u128 from timestamp string: {}", e))) // USE ConversionError
+                ))?
+            },
+            created_at: {
+                let created_str: String = row.get(7)?;
+                created_str.parse::<u128>().map_err(|e| rusqlite::Error::FromSqlConversionFailure(
+                    7,
+                    rusqlite::types::Type::Text,
+                    Box::new(ConversionError(format!("Failed to parse u128 from created_at string: {}", e)))
+                ))?
+            },
+            status,
+            group_id: row.get(6)?,
+            paper,
+            last_modified_at: None,
+            linked_to: row.get(8)?,
+            priority: row.get(9)?,
+            dnr: row.get(10)?,
+            order_type: {
+                let type_str: String = row.get(11)?;
+                match type_str.as_str() {
+                    "Limit" => OrderType::Limit,
+                    "Market" => OrderType::Market,
+                    other => return Err(rusqlite::Error::FromSqlConversionFailure(
+                        11,
+                        rusqlite::types::Type::Text,
+                        Box::new(ConversionError(format!("Invalid order type string: {}", other)))
+                    )),
+                }
+            },
+            time_in_force: {
+                let tif_str: String = row.get(12)?;
+                match tif_str.as_str() {
+                    "Gtc" => TimeInForce::Gtc,
+                    "Fok" => TimeInForce::Fok,
+                    other => return Err(rusqlite::Error::FromSqlConversionFailure(
+                        12,
+                        rusqlite::types::Type::Text,
+                        Box::new(ConversionError(format!("Invalid time in force string: {}", other)))
+                    )),
+                }
+            },
+            account_id: row.get(13)?,
+            stp: row.get(14)?,
+            // Only open orders are loaded
+            cancel_reason: None,
+            expires_at: nanos_from_text(row, 16, "expires_at")?,
+            post_only: row.get(17)?,
+            min_qty: row.get(18)?,
+            display_qty: row.get(19)?,
+            // The book starts a fresh slice as it queues the order
+            slice_left: 0,
+        })
+    })?;
+    let mut orders = Vec::new();
+    let mut quarantined = 0;
+    for order_result in order_iter {
+        match order_result {
+            Ok(order) => orders.push(order),
+            // A corrupt row shouldn't keep the whole engine from starting
+            Err(e @ rusqlite::Error::FromSqlConversionFailure(..)) => {
+                tracing::error!(table = table, error = %e, "Quarantining unreadable order row");
+                quarantined += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    // Too old to reload, or past a good-till-date that lapsed while we were down
+    let (fresh, stale): (Vec<Order>, Vec<Order>) = orders.into_iter().partition(|o| {
+        // Age runs from entry; a modify refreshes `timestamp` but not `created_at`
+        let too_old = config.max_order_age_nanos.is_some_and(|max_age| now.saturating_sub(o.created_at) > max_age);
+        let lapsed = o.expires_at.is_some_and(|expires_at| expires_at <= now);
+        !too_old && !lapsed
+    });
+    // Reconciliation: a live row with nothing left was filled by a write that
+    // never updated its status
+    let (live, exhausted): (Vec<Order>, Vec<Order>) = fresh.into_iter().partition(|o| o.quantity > 0);
+    orders = live;
+    if !config.read_only && (!stale.is_empty() || !exhausted.is_empty()) {
+        // Each expiry or correction lands with its audit event or not at all
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut expire_stmt = tx.prepare(&format!("UPDATE {} SET status = 'Expired', remaining_quantity = 0, cancel_reason = 'Expiry' WHERE id = ?1", table))?;
+            for order in &stale {
+                expire_stmt.execute(params![order.id])?;
+                log_order_cancelled(&tx, paper, order.id, &OrderStatus::Expired, Some(CancelReason::Expiry), now)?;
+            }
+            let mut fill_stmt = tx.prepare(&format!("UPDATE {} SET status = 'Filled' WHERE id = ?1", table))?;
+            for order in &exhausted {
+                fill_stmt.execute(params![order.id])?;
+                log_order_event(&tx, paper, order.id, &OrderStatus::Filled, 0, &format!("{:?}", CancelReason::Reconciliation), now)?;
+            }
+        }
+        tx.commit()?;
+    }
+    if !stale.is_empty() {
+        tracing::warn!(expired = stale.len(), max_age_nanos = ?config.max_order_age_nanos, "Expired stale order(s) instead of loading them.");
+    }
+    if !exhausted.is_empty() {
+        tracing::warn!(corrected = exhausted.len(), "Marked live order row(s) with no remaining quantity Filled instead of loading them.");
+    }
+    let (expired, corrected) = (stale.len(), exhausted.len());
+    tracing::info!("Loaded {} open/partially filled order(s).", orders.len());
+    Ok(LoadedOrders { orders, expired, quarantined, corrected })
+}
+
+// --- Recovery Summary ---
+
+// What the last startup recovered, kept in memory and appended to `recovery_log`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct RecoverySummary {
+    started_at: u128,
+    orders_loaded: usize,
+    orders_expired: usize,
+    rows_quarantined: usize,
+    reconciliation_corrections: usize,
+    duration_micros: u64,
+    next_order_id: OrderId,
+}
+
+fn persist_recovery_summary(conn: &Connection, paper: bool, summary: &RecoverySummary) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO recovery_log (paper, started_at, orders_loaded, orders_expired, rows_quarantined, reconciliation_corrections, duration_micros, next_order_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            paper,
+            summary.started_at.to_string(),
+            summary.orders_loaded,
+            summary.orders_expired,
+            summary.rows_quarantined,
+            summary.reconciliation_corrections,
+            summary.duration_micros,
+            summary.next_order_id,
+        ],
+    )?;
+    Ok(())
+}
+
+// --- Backtest ---
+
+// `--backtest <file> [--speed <factor>]` replays a file of timestamped order
+// events through fresh, empty books instead of serving, writing every fill to
+// stdout as a JSON line. The books' clock is set to each event's time, so the
+// fills come out the same, stamped the same, at whatever speed the file plays.
+
+// How fast a backtest plays its events: as fast as possible, or `factor`
+// times the pace of their timestamps (1 is real time, 10 ten times faster)
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReplaySpeed {
+    Max,
+    Scaled(f64),
+}
+
+impl ReplaySpeed {
+    fn parse(text: &str) -> Option<ReplaySpeed> {
+        if text.eq_ignore_ascii_case("max") {
+            return Some(ReplaySpeed::Max);
+        }
+        text.parse().ok().filter(|factor: &f64| factor.is_finite() && *factor > 0.0).map(ReplaySpeed::Scaled)
+    }
+}
+
+// The backtest asked for on the command line, if any
+fn backtest_args(mut args: impl Iterator<Item = String>) -> Result<Option<(std::path::PathBuf, ReplaySpeed)>, String> {
+    let (mut file, mut speed) = (None, None);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--backtest" => file = Some(args.next().ok_or("--backtest needs an event file")?.into()),
+            "--speed" => {
+                let value = args.next().ok_or("--speed needs a factor or \"max\"")?;
+                speed = Some(ReplaySpeed::parse(&value).ok_or_else(|| format!("invalid --speed {:?}: expected a positive factor or \"max\"", value))?);
+            }
+            other => return Err(format!("unknown argument {:?}", other)),
+        }
+    }
+    match (file, speed) {
+        (Some(file), speed) => Ok(Some((file, speed.unwrap_or(ReplaySpeed::Max)))),
+        (None, Some(_)) => Err("--speed only applies with --backtest".to_string()),
+        (None, None) => Ok(None),
+    }
+}
+
+// One line of a backtest file, e.g.
+// {"at":1000,"op":"limit","id":1,"side":"Sell","price":100,"quantity":5}
+// `at` is unix nanos. Quantities are written as in an order: whole numbers or
+// decimal strings at the symbol's quantity scale.
+#[derive(Debug, Clone, Deserialize)]
+struct BacktestEvent {
+    at: u64,
+    #[serde(flatten)]
+    action: BacktestAction,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "BacktestActionWire")]
+enum BacktestAction {
+    Limit {
+        id: OrderId,
+        symbol: String,
+        side: Side,
+        price: u64,
+        quantity: u64,
+    },
+    Market {
+        id: OrderId,
+        symbol: String,
+        side: Side,
+        quantity: u64,
+    },
+    Cancel {
+        id: OrderId,
+    },
+}
+
+// An action as the file writes it, its quantity scaled once its symbol is known
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum BacktestActionWire {
+    Limit {
+        id: OrderId,
+        #[serde(default = "default_symbol")]
+        symbol: String,
+        side: Side,
+        #[serde(with = "price_ticks")]
+        price: u64,
+        quantity: WireQuantity,
+    },
+    Market {
+        id: OrderId,
+        #[serde(default = "default_symbol")]
+        symbol: String,
+        side: Side,
+        quantity: WireQuantity,
+    },
+    Cancel {
+        id: OrderId,
+    },
+}
+
+impl TryFrom<BacktestActionWire> for BacktestAction {
+    type Error = String;
+
+    fn try_from(wire: BacktestActionWire) -> Result<Self, String> {
+        let units = |quantity: WireQuantity, symbol: &str| quantity.units(quantity_scale(symbol)).map_err(|e| format!("{} for {}", e, symbol));
+        Ok(match wire {
+            BacktestActionWire::Limit { id, symbol, side, price, quantity } => {
+                BacktestAction::Limit { id, side, price, quantity: units(quantity, &symbol)?, symbol }
+            }
+            BacktestActionWire::Market { id, symbol, side, quantity } => {
+                BacktestAction::Market { id, side, quantity: units(quantity, &symbol)?, symbol }
+            }
+            BacktestActionWire::Cancel { id } => BacktestAction::Cancel { id },
+        })
+    }
+}
+
+// Blank lines are skipped. Events are put in time order; those at the same
+// time keep their order in the file.
+fn parse_backtest(text: &str) -> Result<Vec<BacktestEvent>, String> {
+    let mut events = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| serde_json::from_str(line).map_err(|e| format!("line {}: {}", index + 1, e)))
+        .collect::<Result<Vec<BacktestEvent>, String>>()?;
+    events.sort_by_key(|event| event.at);
+    Ok(events)
+}
+
+// Plays `events` (in time order) through books set up as `config` sets up the
+// live ones, handing each fill to `emit` as it happens. Returns the fill count.
+async fn run_backtest(config: &Config, events: &[BacktestEvent], speed: ReplaySpeed, mut emit: impl FnMut(&Fill)) -> usize {
+    let first_at = events.first().map_or(0, |event| event.at);
+    let clock = Arc::new(MockClock::new(first_at));
+    let book_clock: Arc<dyn Clock> = Arc::clone(&clock) as Arc<dyn Clock>;
+    let trade_seq = Arc::new(AtomicU64::new(1));
+    let mut books: HashMap<String, OrderBook> = HashMap::new();
+    // Which book each order went to, for cancels
+    let mut homes: HashMap<OrderId, String> = HashMap::new();
+    let started = tokio::time::Instant::now();
+    let mut count = 0;
+
+    for event in events {
+        if let ReplaySpeed::Scaled(factor) = speed {
+            let offset = (event.at - first_at) as f64 / factor;
+            tokio::time::sleep_until(started + std::time::Duration::from_nanos(offset as u64)).await;
+        }
+        clock.set(event.at);
+
+        let symbol = match &event.action {
+            BacktestAction::Limit { id, symbol, .. } | BacktestAction::Market { id, symbol, .. } => {
+                homes.insert(*id, symbol.clone());
+                symbol.clone()
+            }
+            BacktestAction::Cancel { id } => match homes.get(id) {
+                Some(symbol) => symbol.clone(),
+                None => {
+                    tracing::warn!(order_id = id, at = event.at, "Backtest cancel of an unknown order; skipped");
+                    continue;
+                }
+            },
+        };
+        let book = books.entry(symbol.clone()).or_insert_with(|| configured_book(config, false, &symbol, &book_clock, &trade_seq));
+
+        let fills = match &event.action {
+            BacktestAction::Limit { id, side, price, quantity, .. } => {
+                let order = Order { symbol, ..Order::with_clock(*id, side.clone(), *price, *quantity, &*clock) };
+                book.add_order(order).1
+            }
+            BacktestAction::Market { id, side, quantity, .. } => {
+                let order = Order { symbol, order_type: OrderType::Market, ..Order::with_clock(*id, side.clone(), 0, *quantity, &*clock) };
+                book.execute_market(order).1
+            }
+            BacktestAction::Cancel { id } => {
+                if book.cancel_order(*id, CancelReason::UserRequest).is_none() {
+                    tracing::warn!(order_id = id, at = event.at, "Backtest cancel of an order no longer resting; skipped");
+                }
+                Vec::new()
+            }
+        };
+        // Nothing is persisted or streamed; drop what the book queued for that
+        book.take_deltas();
+        book.take_events();
+        book.take_departed();
+        book.take_writes();
+
+        for fill in &fills {
+            emit(fill);
+        }
+        count += fills.len();
+    }
+    count
+}
+
+// The CLI path: reads `path`, replays it and prints the fills
+async fn backtest_file(config: &Config, path: &std::path::Path, speed: ReplaySpeed) -> Result<usize, String> {
+    use std::io::Write;
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let events = parse_backtest(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    tracing::info!(file = %path.display(), events = events.len(), speed = ?speed, "Starting backtest");
+    let mut out = std::io::stdout().lock();
+    let mut failed = None;
+    let count = run_backtest(config, &events, speed, |fill| {
+        if failed.is_none() {
+            failed = serde_json::to_writer(&mut out, fill).map_err(|e| e.to_string()).and_then(|_| writeln!(out).map_err(|e| e.to_string())).err();
+        }
+    })
+    .await;
+    match failed {
+        Some(e) => Err(format!("writing fills: {}", e)),
+        None => Ok(count),
+    }
+}
+
+// --- Main Application Entry Point ---
+#[tokio::main]
+async fn main() {
+    let backtest = backtest_args(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+    // A backtest's fills go to stdout, so its logs go to stderr
+    let log_writer = match backtest {
+        Some(_) => tracing_subscriber::fmt::writer::BoxMakeWriter::new(std::io::stderr),
+        None => tracing_subscriber::fmt::writer::BoxMakeWriter::new(std::io::stdout),
+    };
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "low_latency_oms=debug,tower_http=debug".into()),
+        )
+        .with(tracing_subscriber::fmt::layer().with_writer(log_writer))
+        .init();
+    tracing::info!("Logger initialized");
+
+    let config = Config::from_env();
+    install_tick_size(config.tick_size).expect("tick size is only set at startup");
+    tracing::info!(tick_size = %config.tick_size, "Price tick size set");
+    install_quantity_scales(config.quantity_scales.clone()).expect("quantity scales are only set at startup");
+    tracing::info!(quantity_scales = ?config.quantity_scales, "Quantity scales set");
+
+    if let Some((path, speed)) = backtest {
+        match backtest_file(&config, &path, speed).await {
+            Ok(fills) => tracing::info!(fills = fills, "Backtest finished"),
+            Err(e) => {
+                tracing::error!("Backtest failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if config.read_only {
+        tracing::warn!("Running in read-only replica mode; mutating endpoints are disabled");
+    }
+
+    let connection = init_db(config.read_only).expect("Failed to initialize database");
+    let db_conn = Arc::new(Mutex::new(connection));
+
+    let shared_state = build_state(config.clone(), Arc::clone(&db_conn), false).expect("Failed to load open orders");
+    let paper_state = build_state(config, db_conn, true).expect("Failed to load open paper orders");
+
+    for state in [&shared_state, &paper_state] {
+        if let (true, false, Some(secs)) = (state.config.auction_only, state.config.read_only, state.config.auction_interval_secs) {
+            spawn_auction_schedule(Arc::clone(state), secs);
+        }
+        if !state.config.read_only {
+            spawn_expiry_sweep(Arc::clone(state), state.config.expiry_sweep_ms.unwrap_or(DEFAULT_EXPIRY_SWEEP_MS));
+        }
+        if state.config.session.is_some() || !state.config.symbol_sessions.is_empty() {
+            spawn_session_watch(Arc::clone(state));
+        }
+    }
+
+    let app = build_router(Arc::clone(&shared_state)).nest("/paper", build_router(Arc::clone(&paper_state)));
+    tracing::info!("API routes defined.");
+
+    let addr = bind_addr(std::env::var("OMS_BIND_ADDR").ok().as_deref());
+    tracing::info!("Starting server on {}", addr);
+    let listener = TcpListener::bind(addr).await.unwrap();
+    tracing::info!("Server listening on {}", addr);
+    // Peer addresses let the rate limiter tell apart clients without X-Client-Id
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).with_graceful_shutdown(shutdown_signal()).await.unwrap();
+
+    tracing::info!("Server stopped; draining background DB writes");
+    for state in [&shared_state, &paper_state] {
+        let timeout = std::time::Duration::from_secs(SHUTDOWN_DRAIN_TIMEOUT_SECS);
+        match tokio::time::timeout(timeout, state.db_writes.drain()).await {
+            Ok(drained) => tracing::info!(paper = state.paper, drained = drained, "Background DB writes drained"),
+            Err(_) => tracing::error!(paper = state.paper, still_pending = state.db_writes.pending(), "Timed out draining background DB writes"),
+        }
+    }
+
+    tracing::info!("Applying shutdown policy");
+    for state in [&shared_state, &paper_state] {
+        if let Err(e) = apply_shutdown_policy(state) {
+            tracing::error!(paper = state.paper, "Failed to apply shutdown policy: {}", e);
+        }
+    }
+}
+
+async fn shutdown_signal() {
+    tokio::signal::ctrl_c().await.expect("Failed to install Ctrl+C handler");
+    tracing::info!("Shutdown signal received");
+}
+
+// Cancels partially-filled resting orders before exit when configured to, so
+// they don't come back on the next startup. Returns how many were cancelled.
+fn apply_shutdown_policy(state: &AppState) -> SqlResult<usize> {
+    if state.config.shutdown_partials != ShutdownPartials::Cancel || state.config.read_only {
+        return Ok(0);
+    }
+    let mut cancelled: Vec<Order> = Vec::new();
+    for market in state.markets() {
+        let mut book_guard = market.order_book.lock().expect("Mutex lock failed for shutdown policy");
+        let partial_ids: Vec<OrderId> = book_guard.bids.iter().chain(book_guard.asks.iter())
+            .filter(|o| o.status == OrderStatus::PartiallyFilled)
+            .map(|o| o.id)
+            .collect();
+        cancelled.extend(partial_ids.into_iter().filter_map(|id| book_guard.cancel_order(id, CancelReason::ShutdownPolicy)));
+        state.on_book_change(&market, &mut book_guard);
+    }
+
+    let mut conn_guard = state.db_conn.lock().expect("Mutex lock failed for DB (shutdown policy)");
+    let tx = conn_guard.transaction()?;
+    let at = state.clock.now_nanos();
+    for order in &cancelled {
+        tx.execute(
+            &format!("UPDATE {} SET status = 'Cancelled', remaining_quantity = 0, cancel_reason = 'ShutdownPolicy' WHERE id = ?1", state.orders_table()),
+            params![order.id],
+        )?;
+        log_order_cancelled(&tx, state.paper, order.id, &OrderStatus::Cancelled, Some(CancelReason::ShutdownPolicy), at)?;
+    }
+    tx.commit()?;
+    tracing::info!(paper = state.paper, cancelled = cancelled.len(), "Cancelled partially-filled orders on shutdown");
+    Ok(cancelled.len())
+}
+
+fn spawn_expiry_sweep(state: Arc<AppState>, interval_ms: u64) {
+    tracing::info!(paper = state.paper, interval_ms = interval_ms, "Sweeping good-till-date orders");
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_millis(interval_ms.max(1)));
+        loop {
+            interval.tick().await;
+            sweep_expired(&state).await;
+        }
+    });
+}
+
+// Moves each market through its session phases as the clock crosses them,
+// rather than only when an order next arrives
+fn spawn_session_watch(state: Arc<AppState>) {
+    tracing::info!(paper = state.paper, "Watching trading session phases");
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            for market in state.markets() {
+                market.session_phase(state.clock.now_nanos());
+            }
+        }
+    });
+}
+
+fn spawn_auction_schedule(state: Arc<AppState>, interval_secs: u64) {
+    tracing::info!(paper = state.paper, interval_secs = interval_secs, "Scheduling periodic auctions");
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs.max(1)));
+        interval.tick().await; // first tick fires immediately
+        loop {
+            interval.tick().await;
+            for market in state.markets() {
+                let result = state.run_auction(&market);
+                tracing::info!(paper = state.paper, symbol = %market.symbol, clearing_price = ?result.clearing_price, volume = result.volume, "Scheduled auction ran");
+            }
+        }
+    });
+}
+
+// An empty book for `symbol` with the configured matching policies
+fn configured_book(config: &Config, paper: bool, symbol: &str, clock: &Arc<dyn Clock>, trade_seq: &Arc<AtomicU64>) -> OrderBook {
+    let mut book = OrderBook::new();
+    book.clock = Arc::clone(clock);
+    book.trade_seq = Arc::clone(trade_seq);
+    book.symbol = symbol.to_string();
+    book.oco_policy = config.oco_policy;
+    book.match_price = config.match_price;
+    book.set_match_mode(config.match_mode_for(symbol));
+    book.tick_size = config.tick_size;
+    book.paper = paper;
+    book.auction_only = config.auction_only;
+    book
+}
+
+// Loads the resting orders for the live (or paper) books and wraps them in an AppState.
+fn build_state(config: Config, db_conn: Arc<Mutex<Connection>>, paper: bool) -> SqlResult<Arc<AppState>> {
+    build_state_with_clock(config, db_conn, paper, Arc::new(SystemClock))
+}
+
+// `clock` stamps every order the state creates, and those its books create
+fn build_state_with_clock(config: Config, db_conn: Arc<Mutex<Connection>>, paper: bool, clock: Arc<dyn Clock>) -> SqlResult<Arc<AppState>> {
+    let started = std::time::Instant::now();
+    let started_at = now_nanos();
+    let (loaded, last_trades, last_seq, suspended, symbol_limits) = {
+        let conn_guard = db_conn.lock().expect("Mutex lock failed for DB load");
+        (
+            load_open_orders(&conn_guard, paper, &config, started_at)?,
+            last_trade_ids(&conn_guard, paper)?,
+            last_trade_seq(&conn_guard, paper)?,
+            suspended_accounts(&conn_guard, paper)?,
+            load_symbol_limits(&conn_guard, paper)?,
+        )
+    };
+    let trade_seq = Arc::new(AtomicU64::new(last_seq + 1));
+    let mut open_orders = loaded.orders;
+    let orders_loaded = open_orders.len();
+
+    let db_writes = DbWrites::default();
+    let mut books: HashMap<String, OrderBook> = HashMap::new();
+    books.insert(DEFAULT_SYMBOL.to_string(), configured_book(&config, paper, DEFAULT_SYMBOL, &clock, &trade_seq));
+    // Rows from before priorities were persisted fall back to entry time
+    open_orders.sort_by_key(|o| (o.priority, o.timestamp, o.id));
+    let mut max_id = 0;
+    let mut resting_symbols = HashMap::with_capacity(open_orders.len());
+    for order in open_orders {
+        if order.id > max_id { max_id = order.id; }
+        resting_symbols.insert(order.id, order.symbol.clone());
+        let book = books.entry(order.symbol.clone()).or_insert_with(|| configured_book(&config, paper, &order.symbol, &clock, &trade_seq));
+        book.side_mut(&order.side.clone()).push_back(order);
+    }
+    let metrics = Metrics::default();
+    let mut markets = HashMap::new();
+    for (symbol, mut book) in books {
+        book.sort_queues();
+        book.rebuild_touch();
+        book.reseed_priority();
+        book.next_trade_id = last_trades.get(&symbol).copied().unwrap_or(0) + 1;
+        metrics.set_resting(&book);
+        tracing::info!(paper = paper, symbol = %symbol, bids = book.bids.len(), asks = book.asks.len(), "Order book populated with loaded orders.");
+        let session = config.session_for(&symbol);
+        markets.insert(symbol, Arc::new(Market::new(book, session)));
+    }
+    let default_market = Arc::clone(&markets[DEFAULT_SYMBOL]);
+
+    let recovery = RecoverySummary {
+        started_at,
+        orders_loaded,
+        orders_expired: loaded.expired,
+        rows_quarantined: loaded.quarantined,
+        reconciliation_corrections: loaded.corrected,
+        duration_micros: started.elapsed().as_micros() as u64,
+        next_order_id: max_id + 1,
+    };
+    tracing::info!(paper = paper, summary = ?recovery, "Recovery complete.");
+    if !config.read_only {
+        let conn_guard = db_conn.lock().expect("Mutex lock failed for DB recovery log");
+        persist_recovery_summary(&conn_guard, paper, &recovery)?;
+    }
+
+    let rate_limiter = RateLimiter::from_config(&config);
+    let load_shedder = LoadShedder::from_config(&config, &metrics.matching, clock.now_nanos());
+    let recent_orders = RecentOrders::new(config.recent_orders_cache.unwrap_or(DEFAULT_RECENT_ORDERS));
+    let state = Arc::new(AppState {
+        config,
+        paper,
+        markets: RwLock::new(markets),
+        default_market,
+        resting_symbols: RwLock::new(resting_symbols),
+        client_sessions: Mutex::new(HashMap::new()),
+        idempotency_keys: Mutex::new(IdempotencyKeys::default()),
+        recent_orders: Mutex::new(recent_orders),
+        suspended_accounts: RwLock::new(suspended),
+        symbol_limits: RwLock::new(symbol_limits),
+        rate_limiter: rate_limiter.map(Mutex::new),
+        load_shedder,
+        next_order_id: AtomicU64::new(max_id + 1),
+        read_pool: Arc::new(ReadPool::for_writer(&db_conn)),
+        db_conn,
+        next_ack_seq: AtomicU64::new(1),
+        recovery,
+        events: broadcast::channel(EVENT_BUS_CAPACITY).0,
+        db_writes,
+        metrics,
+        clock,
+        trade_seq,
+    });
+    tracing::info!(paper = paper, next_order_id = max_id + 1, "Shared AppState created.");
+    Ok(state)
+}
+
+fn build_router(state: Arc<AppState>) -> Router {
+    let router = Router::new()
+        .route("/", get(root_handler))
+        .route("/health", get(health_handler))
+        .route("/healthz", get(healthz_handler))
+        .route("/ready", get(ready_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/stats/spread", get(spread_stats_handler))
+        .route("/book/snapshot", get(book_snapshot_handler))
+        .route("/book/deltas", get(book_deltas_handler))
+        .route("/book/delta", get(level_deltas_handler))
+        .route("/book", get(book_depth_handler))
+        .route("/book/top", get(top_of_book_handler))
+        .route("/book/:symbol", get(symbol_book_snapshot_handler))
+        .route("/session", get(session_handler))
+        .route("/sessions/control", get(session_control_handler))
+        .route("/trades/stream", get(trade_stream_handler))
+        .route("/trades/:id/orders", get(trade_orders_handler))
+        .route("/ws", get(ws_handler))
+        .route("/stats/volume", get(volume_stats_handler))
+        .route("/stats/trades", get(trade_window_stats_handler))
+        .route("/accounts/:id/activity", get(account_activity_handler))
+        .route("/accounts/:id/obligations", get(account_obligations_handler))
+        .route("/admin/recovery", get(recovery_handler))
+        .route("/admin/db-writes", get(db_writes_handler))
+        .route("/admin/dr/snapshot", get(dr_snapshot_handler))
+        .route("/orders", get(list_orders_handler))
+        .route("/orders/:id", get(get_order_handler))
+        .route("/orders/:id/history", get(order_history_handler))
+        .route("/orders/simulate", post(simulate_order_handler))
+        .route("/symbols/:symbol/match-mode", get(get_match_mode_handler))
+        .route("/symbols/:symbol/limits", get(get_symbol_limits_handler));
+
+    let router = if state.config.read_only {
+        router
+            .route("/orders", post(read_only_handler).delete(read_only_handler))
+            .route("/orders/batch", post(read_only_handler))
+            .route("/orders/:id", put(read_only_handler).delete(read_only_handler))
+            .route("/admin/auction", post(read_only_handler))
+            .route("/admin/dr/restore", post(read_only_handler))
+            .route("/admin/orders/ensure", post(read_only_handler))
+            .route("/admin/orders/import", post(read_only_handler))
+            .route("/admin/corporate-action", post(read_only_handler))
+            .route("/symbols/:symbol/match-mode", put(read_only_handler))
+            .route("/symbols/:symbol/limits", put(read_only_handler))
+            .route("/admin/accounts/:id/suspend", post(read_only_handler))
+            .route("/admin/accounts/:id/unsuspend", post(read_only_handler))
+    } else {
+        let rate_limited = middleware::from_fn_with_state(Arc::clone(&state), rate_limit_orders);
+        let batch_rate_limited = middleware::from_fn_with_state(Arc::clone(&state), rate_limit_batch);
+        router
+            .route("/orders", post(create_order_handler.layer(rate_limited)).delete(cancel_all_orders_handler))
+            .route("/orders/batch", post(create_orders_batch_handler.layer(batch_rate_limited)))
+            .route("/orders/:id", put(modify_order_handler))
+            .route("/orders/:id", delete(cancel_order_handler))
+            .route("/admin/auction", post(auction_handler))
+            .route("/admin/dr/restore", post(dr_restore_handler))
+            .route("/admin/orders/ensure", post(ensure_order_handler))
+            .route("/admin/orders/import", post(import_orders_handler))
+            .route("/admin/corporate-action", post(corporate_action_handler))
+            .route("/symbols/:symbol/match-mode", put(put_match_mode_handler))
+            .route("/symbols/:symbol/limits", put(put_symbol_limits_handler))
+            .route("/admin/accounts/:id/suspend", post(suspend_account_handler))
+            .route("/admin/accounts/:id/unsuspend", post(unsuspend_account_handler))
+    };
+    let router = if state.config.test_harness && !state.config.read_only {
+        router.route("/admin/match", post(force_match_handler))
+    } else {
+        router
+    };
+    router.with_state(state)
+}
+
+// --- Basic Root Handler ---
+async fn root_handler() -> &'static str {
+    tracing::info!("Root handler called");
+    "Hello from Low Latency OMS!"
+}
+
+// --- Health Probes ---
+
+// Liveness: the process is up and serving
+async fn health_handler() -> &'static str {
+    "ok"
+}
+
+#[derive(Debug, Serialize)]
+struct HealthStatus {
+    // "ok", or "shedding" while new orders are refused
+    status: &'static str,
+    // None when no latency SLA is configured
+    load_shedding: Option<LoadSheddingStatus>,
+}
+
+// Liveness with the engine's self-protection state. Still 200 while shedding:
+// the process is healthy, it is only refusing new orders.
+async fn healthz_handler(State(state): State<Arc<AppState>>) -> Json<HealthStatus> {
+    let load_shedding = state.load_shedder.as_ref().map(LoadShedder::status);
+    let status = if load_shedding.as_ref().is_some_and(|shedding| shedding.active) { "shedding" } else { "ok" };
+    Json(HealthStatus { status, load_shedding })
+}
+
+#[derive(Debug, Serialize)]
+struct ReadyStatus {
+    // Open and partially filled orders as persisted
+    open_orders: u64,
+}
+
+// Readiness: the database answers. Goes through the read pool only, never the
+// book lock.
+async fn ready_handler(State(state): State<Arc<AppState>>) -> Result<Json<ReadyStatus>, ApiError> {
+    let read_pool = Arc::clone(&state.read_pool);
+    let table = state.orders_table();
+    task::spawn_blocking(move || -> SqlResult<u64> {
+        let conn = read_pool.get()?;
+        conn.query_row("SELECT 1", [], |_| Ok(()))?;
+        conn.query_row(&format!("SELECT COUNT(*) FROM {} WHERE status IN ('Open', 'PartiallyFilled')", table), [], |row| row.get(0))
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("Task join error for readiness check: {}", e);
+        ApiError::Unavailable("readiness check failed".to_string())
+    })?
+    .map_err(|e| {
+        tracing::warn!("Readiness check failed: {}", e);
+        ApiError::Unavailable("database unavailable".to_string())
+    })
+    .map(|open_orders| Json(ReadyStatus { open_orders }))
 }
 
 // --- API Handlers ---
+async fn read_only_handler() -> ApiError {
+    tracing::warn!("Rejected mutating request in read-only mode");
+    ApiError::ReadOnly("this instance is a read-only replica; send changes to the primary".to_string())
+}
+
+// One execution as seen by the order that was just submitted
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct OrderFill {
+    counter_order_id: OrderId,
+    price: u64,
+    quantity: u64,
+    // Its symbol's, for writing `quantity`
+    quantity_scale: u32,
+    trade_seq: u64,
+    executed_at: u128,
+}
+
+impl OrderFill {
+    fn for_order(order_id: OrderId, fill: &Fill) -> Self {
+        let counter_order_id = if fill.bid_id == order_id { fill.ask_id } else { fill.bid_id };
+        OrderFill {
+            counter_order_id,
+            price: fill.price,
+            quantity: fill.quantity,
+            quantity_scale: quantity_scale(&fill.symbol),
+            trade_seq: fill.trade_seq,
+            executed_at: fill.executed_at,
+        }
+    }
+}
+
+impl Serialize for OrderFill {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut fill = serializer.serialize_struct("OrderFill", 5)?;
+        fill.serialize_field("counter_order_id", &self.counter_order_id)?;
+        fill.serialize_field("price", &Price::from_units(self.price))?;
+        fill.serialize_field("quantity", &Fixed { units: self.quantity.into(), scale: self.quantity_scale })?;
+        fill.serialize_field("trade_seq", &self.trade_seq)?;
+        fill.serialize_field("executed_at", &self.executed_at)?;
+        fill.end()
+    }
+}
+
+// Order types the book's mode can't take, beyond what the payload checks itself
+fn check_order_kind(config: &Config, payload: &CreateOrderPayload) -> Result<(), ApiError> {
+    match payload.order_type {
+        OrderType::Market if config.auction_only => {
+            Err(ApiError::Unprocessable("market orders are not accepted on an auction-only book".to_string()))
+        }
+        _ if payload.time_in_force == TimeInForce::Fok && config.auction_only => {
+            Err(ApiError::Unprocessable("fill-or-kill orders are not accepted on an auction-only book".to_string()))
+        }
+        // Auction orders trade at the clearing price whichever side they're on
+        _ if payload.post_only && config.auction_only => {
+            Err(ApiError::Unprocessable("post-only orders are not accepted on an auction-only book".to_string()))
+        }
+        _ if payload.min_qty.is_some() && config.auction_only => {
+            Err(ApiError::Unprocessable("min_qty orders are not accepted on an auction-only book".to_string()))
+        }
+        OrderType::Market if payload.price != 0 => {
+            tracing::debug!(price = payload.price, "Ignoring price on market order");
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+// The accepted order plus whatever it traded on arrival (empty if it rested).
+// `fills` is left out and `execution` given instead when the client asks for
+// a summary; see `FillReport`.
+#[derive(Debug, Clone, Serialize)]
+struct CreateOrderResponse {
+    order: OrderView,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fills: Option<Vec<OrderFill>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    execution: Option<ExecutionSummary>,
+}
+
+impl CreateOrderResponse {
+    fn new(order: &Order, fills: Vec<OrderFill>) -> Self {
+        CreateOrderResponse { order: OrderView::from(order), fills: Some(fills), execution: None }
+    }
+
+    // Stored responses keep every fill; this trims a copy to what was asked for
+    fn reported(mut self, report: FillReport, tick_size: &TickSize) -> Self {
+        let fills = self.fills.take().unwrap_or_default();
+        if report != FillReport::Fills {
+            self.execution = Some(ExecutionSummary::from_fills(&fills, tick_size, quantity_scale(&self.order.symbol)));
+        }
+        if report != FillReport::Summary {
+            self.fills = Some(fills);
+        }
+        self
+    }
+}
+
+// Set by clients that would rather see a sweep across many makers netted
+// into one execution: "fills" (the default), "summary", or "both"
+const FILL_REPORT_HEADER: &str = "x-fill-report";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum FillReport {
+    #[default]
+    Fills,
+    Summary,
+    Both,
+}
+
+fn fill_report(headers: &HeaderMap) -> Result<FillReport, ApiError> {
+    match headers.get(FILL_REPORT_HEADER).map(|value| value.to_str()) {
+        None => Ok(FillReport::Fills),
+        Some(Ok("fills")) => Ok(FillReport::Fills),
+        Some(Ok("summary")) => Ok(FillReport::Summary),
+        Some(Ok("both")) => Ok(FillReport::Both),
+        Some(_) => Err(ApiError::BadRequest(format!("{} must be fills, summary or both", FILL_REPORT_HEADER))),
+    }
+}
+
+// Places the VWAP carries beyond the tick's scale
+const VWAP_EXTRA_DECIMALS: u32 = 6;
+
+// An order's fills netted together. `notional` is exact, at the price and
+// quantity scales added; `vwap` is a price at the tick's scale plus
+// VWAP_EXTRA_DECIMALS, rounded half up there, and None when nothing traded.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct ExecutionSummary {
+    fill_count: u64,
+    quantity: Fixed,
+    notional: Fixed,
+    vwap: Option<Fixed>,
+}
+
+impl ExecutionSummary {
+    fn from_fills(fills: &[OrderFill], tick_size: &TickSize, quantity_scale: u32) -> Self {
+        let quantity: u64 = fills.iter().map(|fill| fill.quantity).sum();
+        let notional = Fixed {
+            units: fills.iter().map(|fill| fill.price as u128 * fill.quantity as u128).sum(),
+            scale: tick_size.scale() + quantity_scale,
+        };
+        // Quantity units cancel, leaving fixed-point price units. Long division,
+        // so the extra places can't overflow however large the notional.
+        let vwap = (quantity > 0).then(|| {
+            let (quantity, factor) = (quantity as u128, 10u128.pow(VWAP_EXTRA_DECIMALS));
+            let fraction = notional.units % quantity * factor;
+            let rounded = fraction / quantity + u128::from(fraction % quantity * 2 >= quantity);
+            Fixed { units: (notional.units / quantity).saturating_mul(factor).saturating_add(rounded), scale: tick_size.scale() + VWAP_EXTRA_DECIMALS }
+        });
+        ExecutionSummary { fill_count: fills.len() as u64, quantity: Fixed { units: quantity.into(), scale: quantity_scale }, notional, vwap }
+    }
+}
+
 async fn create_order_handler(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<CreateOrderPayload>,
-) -> Result<(StatusCode, Json<Order>), StatusCode> {
-    tracing::info!(payload = ?payload, "Received create order request");
+    headers: HeaderMap,
+    Json(mut payload): Json<CreateOrderPayload>,
+) -> Result<(StatusCode, AckSeq, Json<CreateOrderResponse>), ApiError> {
+    let received = std::time::Instant::now();
+    tracing::info!(payload = ?payload, "Received create order request");
+    let session = client_session_id(&headers)?;
+    let report = fill_report(&headers)?;
+    check_load_shedding(&state)?;
+
+    if let Err(message) = payload.normalize_price(&state.config).and_then(|_| payload.validate(&state.config.tick_size, state.clock.now_nanos())) {
+        tracing::warn!(reason = %message, "Rejected invalid create order request");
+        return Err(ApiError::BadRequest(message));
+    }
+    check_order_kind(&state.config, &payload)?;
+    check_account_active(&state, payload.account_id)?;
+    let market = state.market_or_insert(&payload.symbol);
+    if let Err(rejection) = check_session_open(&market, state.clock.now_nanos()) {
+        tracing::warn!(reason = %rejection, "Rejected create order outside session");
+        return Err(rejection);
+    }
+    check_symbol_limits(&state, &market, &payload)?;
+
+    let idempotency = match idempotency_key(&headers)? {
+        Some(key) => {
+            let scope = (payload.account_id, key);
+            if let Some(original) = state.reserve_idempotency_key(&scope, &payload)? {
+                tracing::info!(order_id = original.order.id, "Idempotency-Key seen before; returning the original order");
+                return Ok((StatusCode::OK, state.next_ack(), Json(original.reported(report, &state.config.tick_size))));
+            }
+            IdempotencyReservation { state: &state, scope: Some(scope) }
+        }
+        None => IdempotencyReservation { state: &state, scope: None },
+    };
+    let order_id = state.next_order_id.fetch_add(1, Ordering::Relaxed);
+    // Before it can rest, so a disconnect racing the insert still finds it
+    if let Some(session) = &session {
+        state.track_session_orders(session, [order_id]);
+    }
+    let order_for_book = payload.to_order(order_id, state.paper, &*state.clock);
+    let order_for_db = order_for_book.clone();
+
+    // The row goes in only once the book has taken the order, in the same
+    // transaction as every write its matching caused and its final state.
+    // That transaction is queued under the book lock, so it lands behind
+    // every earlier change in book order, and the response waits for it: a
+    // create is acknowledged (201) only once the order and its trades are
+    // durable. If the commit fails the book has still taken the order, so it
+    // is answered 202 with the order as it stands, the failure is kept with
+    // the writer's dead letters for reconciliation, and the idempotency key
+    // keeps that answer so a retry can't place it twice. An order refused on
+    // entry leaves no row behind.
+    let (order_to_return, fills, committed, ack) = {
+        let mut book_guard = market.order_book.lock().expect("Mutex lock failed for book");
+        let locked = std::time::Instant::now();
+        tracing::debug!(order_id = order_id, "Acquired book lock for adding order");
+        // Again under the lock: a suspension's sweep may have just passed this book
+        check_account_active(&state, order_for_book.account_id)?;
+        check_quote_obligation(&state.config, &book_guard, &order_for_book)?;
+        if let Some(refused) = book_guard.entry_refusal(&order_for_book) {
+            let available = book_guard.fillable_quantity(&order_for_book);
+            tracing::info!(order_id = order_id, status = ?refused.status, cancel_reason = ?refused.cancel_reason, quantity = order_for_book.quantity, available = available, "Order refused on entry; book untouched");
+            let response = CreateOrderResponse::new(&refused, Vec::new());
+            idempotency.complete(&response);
+            return Ok((StatusCode::OK, state.next_ack(), Json(response.reported(report, &state.config.tick_size))));
+        }
+        let order_to_return = order_for_book.clone();
+        let matching = std::time::Instant::now();
+        let (outcome, fills) = match order_for_book.order_type {
+            OrderType::Limit => {
+                let (priority, fills) = book_guard.add_order(order_for_book);
+                (Ok(priority), fills)
+            }
+            OrderType::Market => {
+                let (market, fills) = book_guard.execute_market(order_for_book);
+                (Err(market), fills)
+            }
+        };
+        state.metrics.matching.record(matching.elapsed());
+
+        let writes = book_guard.take_writes();
+        // Its final state: resting, or gone by the time add_order returned
+        let order_to_return = match &outcome {
+            Ok(_) => match book_guard.find_order(order_id) {
+                Some(resting) => resting.clone(),
+                None => settled_on_entry(order_to_return, &writes),
+            },
+            Err(market) => market.clone(),
+        };
+        let (committed_tx, committed) = tokio::sync::oneshot::channel();
+        let db_conn_clone: Arc<Mutex<Connection>> = Arc::clone(&state.db_conn);
+        let cancelled_at = state.clock.now_nanos();
+        let detail = format!("order {} with {} book writes", order_id, writes.len());
+        state.db_writes.enqueue("create", detail, move || {
+            let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB insert");
+            tracing::debug!(order_id = order_id, "Acquired DB lock for INSERT");
+            let result = commit_created_order(&mut conn_guard, &order_for_db, &writes, &outcome, cancelled_at);
+            let _ = committed_tx.send(result.as_ref().map(|_| ()).map_err(|e| e.to_string()));
+            result
+        });
+
+        state.on_book_change(&market, &mut book_guard);
+        let ack = state.next_ack();
+        state.metrics.book_lock_held.record(locked.elapsed());
+        state.metrics.receipt_to_match.record(received.elapsed());
+        (order_to_return, fills, committed, ack)
+    };
+    tracing::debug!(order_id = order_id, "Released book lock after adding order");
+
+    let status = match committed.await {
+        Ok(Ok(())) => {
+            tracing::debug!(order_id = order_id, "Order and its trades committed");
+            StatusCode::CREATED
+        }
+        Ok(Err(e)) => {
+            tracing::error!(order_id = order_id, status = ?order_to_return.status, "DB error committing order; it is live but not persisted: {}", e);
+            StatusCode::ACCEPTED
+        }
+        Err(e) => {
+            tracing::error!(order_id = order_id, status = ?order_to_return.status, "DB writer stopped before committing order; it is live but not persisted: {}", e);
+            StatusCode::ACCEPTED
+        }
+    };
+
+    let fills = fills.iter()
+        .filter(|fill| fill.bid_id == order_id || fill.ask_id == order_id)
+        .map(|fill| OrderFill::for_order(order_id, fill))
+        .collect();
+    let response = CreateOrderResponse::new(&order_to_return, fills);
+    idempotency.complete(&response);
+    Ok((status, ack, Json(response.reported(report, &state.config.tick_size))))
+}
+
+// --- Dry Run ---
+
+// Carried by simulated orders; real ids start at 1
+const SIMULATED_ORDER_ID: OrderId = 0;
+
+// What an order would do if submitted now. `remaining_quantity` is what would
+// be left resting (0 once filled, cancelled or killed).
+#[derive(Debug, Clone, Serialize)]
+struct SimulateOrderResponse {
+    order: OrderView,
+    fills: Vec<OrderFill>,
+    filled_quantity: u64,
+    remaining_quantity: u64,
+}
+
+// Checked like POST /orders, but no id is assigned and nothing is persisted or
+// published. The book lock is held while the copy is matched; a symbol with
+// no book yet is simulated against an empty one.
+async fn simulate_order_handler(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateOrderPayload>,
+) -> Result<Json<SimulateOrderResponse>, ApiError> {
+    tracing::debug!(payload = ?payload, "Received simulate order request");
+    if let Err(message) = payload.validate(&state.config.tick_size, state.clock.now_nanos()) {
+        return Err(ApiError::BadRequest(message));
+    }
+    check_order_kind(&state.config, &payload)?;
+    let order = payload.to_order(SIMULATED_ORDER_ID, state.paper, &*state.clock);
+    let (order, fills) = match state.market(&payload.symbol) {
+        Some(market) => {
+            let book_guard = market.order_book.lock().expect("Mutex lock failed for book");
+            book_guard.simulate(order)
+        }
+        None => state.new_book(&payload.symbol).simulate(order),
+    };
+    let fills: Vec<OrderFill> = fills.iter()
+        .filter(|fill| fill.bid_id == SIMULATED_ORDER_ID || fill.ask_id == SIMULATED_ORDER_ID)
+        .map(|fill| OrderFill::for_order(SIMULATED_ORDER_ID, fill))
+        .collect();
+    let filled_quantity = fills.iter().map(|fill| fill.quantity).sum();
+    tracing::debug!(status = ?order.status, filled_quantity = filled_quantity, "Simulated order");
+    Ok(Json(SimulateOrderResponse { remaining_quantity: order.quantity, order: OrderView::from(&order), fills, filled_quantity }))
+}
+
+// --- Batch Orders ---
+
+// The whole batch is matched under one book lock, so keep it bounded
+const MAX_BATCH_ORDERS: usize = 10_000;
+
+// All or nothing: every payload is checked before any id is assigned. Rows are
+// written in one transaction, then the orders enter the book in request order
+// under a single lock. Orders the book could refuse on entry (fill-or-kill,
+// post-only, min_qty) are not accepted here.
+async fn create_orders_batch_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(mut payloads): Json<Vec<CreateOrderPayload>>,
+) -> Result<(StatusCode, AckSeq, Json<Vec<OrderView>>), ApiError> {
+    tracing::info!(count = payloads.len(), "Received batch create order request");
+    let session = client_session_id(&headers)?;
+    check_load_shedding(&state)?;
+
+    if payloads.len() > MAX_BATCH_ORDERS {
+        return Err(ApiError::BadRequest(format!("a batch holds at most {} orders", MAX_BATCH_ORDERS)));
+    }
+    let first_symbol = payloads.first().map(|payload| payload.symbol.clone()).unwrap_or_default();
+    for (index, payload) in payloads.iter_mut().enumerate() {
+        if let Err(message) = payload.normalize_price(&state.config).and_then(|_| payload.validate(&state.config.tick_size, state.clock.now_nanos())) {
+            tracing::warn!(index = index, reason = %message, "Rejected invalid batch create request");
+            return Err(ApiError::BadRequest(format!("order {}: {}", index, message)));
+        }
+        if payload.conditional() {
+            return Err(ApiError::Unprocessable(format!("order {}: fill-or-kill, post-only and min_qty orders are not accepted in a batch", index)));
+        }
+        if payload.order_type == OrderType::Market && state.config.auction_only {
+            return Err(ApiError::Unprocessable(format!("order {}: market orders are not accepted on an auction-only book", index)));
+        }
+        // One book lock covers the batch, so it can only touch one book
+        if payload.symbol != first_symbol {
+            return Err(ApiError::Unprocessable(format!("order {}: a batch must be for a single symbol", index)));
+        }
+        check_account_active(&state, payload.account_id)?;
+    }
+    let market = state.market_or_insert(payloads.first().map_or(DEFAULT_SYMBOL, |p| p.symbol.as_str()));
+    if let Err(rejection) = check_session_open(&market, state.clock.now_nanos()) {
+        tracing::warn!(reason = %rejection, "Rejected batch create outside session");
+        return Err(rejection);
+    }
+    for (index, payload) in payloads.iter().enumerate() {
+        check_symbol_limits(&state, &market, payload).map_err(|rejection| match rejection {
+            ApiError::Unprocessable(message) => ApiError::Unprocessable(format!("order {}: {}", index, message)),
+            other => other,
+        })?;
+    }
+
+    let first_id = state.next_order_id.fetch_add(payloads.len() as u64, Ordering::Relaxed);
+    if let Some(session) = &session {
+        state.track_session_orders(session, first_id..first_id + payloads.len() as u64);
+    }
+    let orders: Vec<Order> = payloads.iter().zip(first_id..).map(|(payload, order_id)| payload.to_order(order_id, state.paper, &*state.clock)).collect();
+    let order_ids: Vec<OrderId> = orders.iter().map(|o| o.id).collect();
+    let table = state.orders_table();
+
+    // Persist before any of them can trade, as for a single order
+    let orders_for_db = orders.clone();
+    let db_conn_clone: Arc<Mutex<Connection>> = Arc::clone(&state.db_conn);
+    task::spawn_blocking(move || {
+        let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB insert (batch)");
+        let tx = conn_guard.transaction()?;
+        for order in &orders_for_db {
+            insert_order_row(&tx, "INSERT", table, order, order.quantity)?;
+            log_order_event(&tx, order.paper, order.id, &order.status, order.quantity, "Created", order.created_at)?;
+        }
+        tx.commit()
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("Task join error for batch insert: {}", e);
+        ApiError::Internal("failed to persist orders".to_string())
+    })?
+    .map_err(|e| {
+        tracing::error!("DB error inserting batch starting at {}: {}", first_id, e);
+        ApiError::Internal("failed to persist orders".to_string())
+    })?;
+    tracing::debug!(first_id = first_id, count = order_ids.len(), "DB batch INSERT successful");
+
+    let (outcomes, ack) = {
+        let mut book_guard = market.order_book.lock().expect("Mutex lock failed for book (batch)");
+        let outcomes: Vec<(OrderId, Result<u64, Order>)> = orders.into_iter().map(|order| {
+            let order_id = order.id;
+            // Suspended since the check above; its row is in, so cancel it
+            if state.is_suspended(order.account_id) {
+                return (order_id, Err(Order { quantity: 0, status: OrderStatus::Cancelled, cancel_reason: Some(CancelReason::Suspension), ..order }));
+            }
+            let outcome = match order.order_type {
+                OrderType::Limit => Ok(book_guard.add_order(order).0),
+                OrderType::Market => Err(book_guard.execute_market(order).0),
+            };
+            (order_id, outcome)
+        }).collect();
+        state.on_book_change(&market, &mut book_guard);
+        (outcomes, state.next_ack())
+    };
+    tracing::debug!(first_id = first_id, "Released book lock after adding batch");
+
+    // Same follow-up as a single order, then read every order back so later
+    // orders in the batch are reflected in the earlier ones they traded with
+    let db_conn_clone: Arc<Mutex<Connection>> = Arc::clone(&state.db_conn);
+    let paper = state.paper;
+    let cancelled_at = state.clock.now_nanos();
+    let views = state.db_writes.run_after_queued("batch", move || {
+        let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB update (batch)");
+        let tx = conn_guard.transaction()?;
+        for (order_id, outcome) in outcomes {
+            match outcome {
+                Ok(priority) => {
+                    tx.execute(&format!("UPDATE {} SET priority = ?1 WHERE id = ?2", table), params![priority, order_id])?;
+                }
+                Err(market) if market.status == OrderStatus::Cancelled => {
+                    tx.execute(
+                        &format!("UPDATE {} SET status = 'Cancelled', remaining_quantity = 0, cancel_reason = ?1 WHERE id = ?2", table),
+                        params![market.cancel_reason.map(|reason| format!("{:?}", reason)), order_id],
+                    )?;
+                    log_order_cancelled(&tx, paper, order_id, &market.status, market.cancel_reason, cancelled_at)?;
+                }
+                Err(_) => {}
+            }
+        }
+        tx.commit()?;
+        order_ids.into_iter()
+            .map(|order_id| load_order_view(&conn_guard, table, order_id, paper)?.ok_or(rusqlite::Error::QueryReturnedNoRows))
+            .collect::<SqlResult<Vec<OrderView>>>()
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("DB writer stopped before the batch update: {}", e);
+        ApiError::Internal("failed to persist orders".to_string())
+    })?
+    .map_err(|e| {
+        tracing::error!("DB error updating batch starting at {}: {}", first_id, e);
+        ApiError::Internal("failed to persist orders".to_string())
+    })?;
+
+    Ok((StatusCode::CREATED, ack, Json(views)))
+}
+
+// The modified order, plus the linked order carrying the extra size when an
+// increase was split.
+#[derive(Debug, Serialize)]
+struct ModifyResponse {
+    #[serde(flatten)]
+    order: OrderView,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    linked_order: Option<OrderView>,
+    // What a reprice traded on crossing the spread
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fills: Vec<OrderFill>,
+}
+
+enum ModifyRejection {
+    // Outlived its max lifetime; carries its id if it was still resting
+    Expired(Option<OrderId>),
+    Throttled { retry_after_nanos: u128 },
+}
+
+// Persisted orders, newest first. Resting orders show their last written
+// state, which can trail the book by in-flight fill writes.
+async fn list_orders_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<OrderListQuery>,
+) -> Result<Json<Vec<OrderView>>, ApiError> {
+    tracing::debug!(query = ?query, "Received list orders request");
+    let filter = query.filter().map_err(|message| {
+        tracing::warn!(reason = %message, "Rejected list orders request");
+        ApiError::BadRequest(message)
+    })?;
+
+    let read_pool = Arc::clone(&state.read_pool);
+    let table = state.orders_table();
+    let paper = state.paper;
+    task::spawn_blocking(move || {
+        let conn = read_pool.get()?;
+        load_order_views(&conn, table, paper, &filter)
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("Task join error for order list: {}", e);
+        ApiError::Internal("failed to list orders".to_string())
+    })?
+    .map_err(|e| {
+        tracing::error!("DB error listing orders: {}", e);
+        ApiError::Internal("failed to list orders".to_string())
+    })
+    .map(Json)
+}
+
+// Live copy from the book when resting (it has the current remaining quantity),
+// otherwise the last persisted state.
+async fn get_order_handler(
+    State(state): State<Arc<AppState>>,
+    Path(order_id): Path<OrderId>,
+) -> Result<Json<OrderView>, ApiError> {
+    tracing::debug!(order_id = order_id, "Received get order request");
+    let resting = state.market_of(order_id).and_then(|market| {
+        market.order_book.lock().expect("Mutex lock failed for book lookup")
+            .find_order(order_id)
+            .map(OrderView::from)
+    });
+    if let Some(view) = resting {
+        return Ok(Json(view));
+    }
+    let cached = state.recent_orders.lock().expect("Mutex lock failed for recent orders").get(order_id);
+    if let Some(view) = cached {
+        state.metrics.order_cache_hits.fetch_add(1, Ordering::Relaxed);
+        return Ok(Json(view));
+    }
+    state.metrics.order_cache_misses.fetch_add(1, Ordering::Relaxed);
+
+    let read_pool = Arc::clone(&state.read_pool);
+    let table = state.orders_table();
+    let paper = state.paper;
+    task::spawn_blocking(move || {
+        let conn = read_pool.get()?;
+        load_order_view(&conn, table, order_id, paper)
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("Task join error for order lookup: {}", e);
+        ApiError::Internal("failed to look up order".to_string())
+    })?
+    .map_err(|e| {
+        tracing::error!("DB error looking up order {}: {}", order_id, e);
+        ApiError::Internal("failed to look up order".to_string())
+    })?
+    .map(Json)
+    .ok_or_else(|| ApiError::order_not_found(order_id))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum TradeRole {
+    Maker,
+    Taker,
+}
+
+#[derive(Debug, Serialize)]
+struct TradeOrderState {
+    // The order's terms, with its status and remaining quantity as of the trade
+    order: OrderView,
+    // Auction trades have neither
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<TradeRole>,
+    // False when the order's audit trail has no entry for this fill: the DB
+    // declined it because the order had already been cancelled, so the trade
+    // never counted against the order. The state shown is the one before it.
+    recorded: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct TradeOrders {
+    trade: Fill,
+    // None for an order with no row or audit trail to rebuild it from
+    bid: Option<TradeOrderState>,
+    ask: Option<TradeOrderState>,
+}
+
+// Both orders behind one trade, each as it stood right after the trade,
+// rebuilt from its row and its audit trail
+async fn trade_orders_handler(
+    State(state): State<Arc<AppState>>,
+    Path(trade_id): Path<u64>,
+    Query(query): Query<SymbolQuery>,
+) -> Result<Json<TradeOrders>, ApiError> {
+    let symbol = query.symbol.unwrap_or_else(default_symbol);
+    validate_symbol(&symbol).map_err(ApiError::BadRequest)?;
+    tracing::debug!(trade_id = trade_id, symbol = %symbol, "Received trade orders request");
+    let read_pool = Arc::clone(&state.read_pool);
+    let table = state.orders_table();
+    let paper = state.paper;
+    let lookup_symbol = symbol.clone();
+    task::spawn_blocking(move || -> SqlResult<Option<TradeOrders>> {
+        let conn = read_pool.get()?;
+        let Some((trade, maker_id)) = load_trade(&conn, paper, &lookup_symbol, trade_id)? else {
+            return Ok(None);
+        };
+        let state_of = |order_id: OrderId| -> SqlResult<Option<TradeOrderState>> {
+            let (Some(view), Some((status, remaining, recorded))) = (
+                load_order_view(&conn, table, order_id, paper)?,
+                order_state_at_trade(&conn, paper, order_id, &trade)?,
+            ) else {
+                return Ok(None);
+            };
+            let cancel_reason = view.cancel_reason.filter(|_| matches!(status, OrderStatus::Cancelled | OrderStatus::Expired));
+            let role = maker_id.map(|maker_id| if maker_id == order_id { TradeRole::Maker } else { TradeRole::Taker });
+            Ok(Some(TradeOrderState { order: OrderView { quantity: remaining, status, cancel_reason, ..view }, role, recorded }))
+        };
+        let (bid, ask) = (state_of(trade.bid_id)?, state_of(trade.ask_id)?);
+        Ok(Some(TradeOrders { trade, bid, ask }))
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("Task join error for trade orders: {}", e);
+        ApiError::Internal("failed to look up trade".to_string())
+    })?
+    .map_err(|e| {
+        tracing::error!("DB error looking up orders of trade {}: {}", trade_id, e);
+        ApiError::Internal("failed to look up trade".to_string())
+    })?
+    .map(Json)
+    .ok_or_else(|| ApiError::NotFound(format!("no trade {} for symbol {}", trade_id, symbol)))
+}
+
+// Every recorded state change of an order, oldest first
+async fn order_history_handler(
+    State(state): State<Arc<AppState>>,
+    Path(order_id): Path<OrderId>,
+) -> Result<Json<Vec<OrderEventView>>, ApiError> {
+    tracing::debug!(order_id = order_id, "Received order history request");
+    let read_pool = Arc::clone(&state.read_pool);
+    let table = state.orders_table();
+    let paper = state.paper;
+    task::spawn_blocking(move || -> SqlResult<Option<Vec<OrderEventView>>> {
+        let conn = read_pool.get()?;
+        let events = load_order_events(&conn, paper, order_id)?;
+        // Orders from before the log was kept have a row but no entries
+        let known = !events.is_empty() || load_order_view(&conn, table, order_id, paper)?.is_some();
+        Ok(known.then_some(events))
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("Task join error for order history: {}", e);
+        ApiError::Internal("failed to look up order history".to_string())
+    })?
+    .map_err(|e| {
+        tracing::error!("DB error looking up history of order {}: {}", order_id, e);
+        ApiError::Internal("failed to look up order history".to_string())
+    })?
+    .map(Json)
+    .ok_or_else(|| ApiError::order_not_found(order_id))
+}
+
+async fn modify_order_handler(
+    State(state): State<Arc<AppState>>,
+    Path(order_id): Path<OrderId>,
+    headers: HeaderMap,
+    Json(payload): Json<ModifyOrderPayload>,
+) -> Result<(AckSeq, Json<ModifyResponse>), ApiError> {
+    tracing::info!(order_id = order_id, payload = ?payload, "Received modify order request");
+    let caller = caller_account(&headers)?;
+
+    if let Some(price) = payload.price {
+        let valid = if price == 0 { Err("price must be greater than zero".to_string()) } else { state.config.tick_size.check(price) };
+        if let Err(message) = valid {
+            tracing::warn!(order_id = order_id, reason = %message, "Rejected invalid modify order request");
+            return Err(ApiError::BadRequest(message));
+        }
+    }
+
+    // An order resting nowhere gets the default book, which won't have it either
+    let market = state.market_of(order_id).unwrap_or_else(|| Arc::clone(&state.default_market));
+    if let Err(rejection) = check_session_open(&market, state.clock.now_nanos()) {
+        tracing::warn!(order_id = order_id, reason = %rejection, "Rejected modify order outside session");
+        return Err(rejection);
+    }
+    let modify_outcome = {
+        let mut book_guard = market.order_book.lock().expect("Mutex lock failed for book modify");
+        tracing::debug!(order_id = order_id, "Acquired book lock for modifying order");
+        // The order's symbol sets the scale of the new quantity
+        let (quantity, price) = match book_guard.find_order(order_id) {
+            Some(resting) => {
+                check_owner(&state.config, order_id, resting.account_id, caller)?;
+                let quantity = payload.quantity.units(quantity_scale(&resting.symbol)).map_err(ApiError::BadRequest)?;
+                let price = payload.price
+                    .map(|price| normalize_price(&state.config, &resting.symbol, &resting.side, price).and_then(|price| state.config.tick_size.check(price).map(|_| price)))
+                    .transpose()
+                    .map_err(ApiError::BadRequest)?;
+                (quantity, price)
+            }
+            // Not resting: everything below misses it, whatever the size
+            None => (0, payload.price),
+        };
+        let now = state.clock.now_nanos();
+        let past_deadline = state.config.max_order_lifetime_nanos.is_some_and(|lifetime| {
+            book_guard
+                .find_order(order_id)
+                .is_some_and(|o| now.saturating_sub(o.created_at) > lifetime)
+        });
+        let throttled_for = state.config.min_modify_interval_nanos.and_then(|interval| {
+            let last = book_guard.find_order(order_id)?.last_modified_at?;
+            let elapsed = now.saturating_sub(last);
+            (elapsed < interval).then(|| interval - elapsed)
+        });
+        if past_deadline {
+            let expired = book_guard.expire_order(order_id).map(|order| order.id);
+            state.on_book_change(&market, &mut book_guard);
+            Err(ModifyRejection::Expired(expired))
+        } else if let Some(retry_after_nanos) = throttled_for {
+            Err(ModifyRejection::Throttled { retry_after_nanos })
+        } else {
+            // Repricing to the current price is a plain resize
+            let reprice = price.filter(|&price| {
+                quantity > 0 && book_guard.find_order(order_id).is_some_and(|o| o.price != price)
+            });
+            let split = reprice.is_none()
+                && state.config.increase_mode == IncreaseMode::Split
+                && book_guard.find_order(order_id).is_some_and(|o| quantity > o.quantity);
+            let modified = if let Some(price) = reprice {
+                // Queued before it can trade, behind any fills of the old price
+                // and ahead of those it makes now, so each lands on the row it
+                // was matched against
+                let status = book_guard.find_order(order_id).map(Order::resized_status).unwrap_or(OrderStatus::Open);
+                let db_conn_clone = Arc::clone(&state.db_conn);
+                let (table, paper) = (state.orders_table(), state.paper);
+                let repriced_at = state.clock.now_nanos();
+                state.db_writes.enqueue("reprice", format!("order {} to {} x {}", order_id, price, quantity), move || {
+                    let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB update (reprice)");
+                    let tx = conn_guard.transaction()?;
+                    tx.execute(
+                        &format!("UPDATE {} SET price = ?1, remaining_quantity = ?2, status = ?3 WHERE id = ?4", table),
+                        params![price, quantity, format!("{:?}", status), order_id],
+                    )?;
+                    log_order_event(&tx, paper, order_id, &status, quantity, "Repriced", repriced_at)?;
+                    tx.commit()
+                });
+                book_guard.reprice_order(order_id, price, quantity).map(|repriced| {
+                    let fills = book_guard.rematch();
+                    let order = book_guard.find_order(order_id).cloned().unwrap_or_else(|| {
+                        // Gone from the book: filled, unless self-trade prevention cancelled the rest
+                        let traded: u64 = fills.iter().filter(|f| f.bid_id == order_id || f.ask_id == order_id).map(|f| f.quantity).sum();
+                        if traded == repriced.quantity {
+                            Order { quantity: 0, status: OrderStatus::Filled, ..repriced.clone() }
+                        } else {
+                            Order { quantity: 0, status: OrderStatus::Cancelled, cancel_reason: Some(CancelReason::SelfTradePrevention), ..repriced.clone() }
+                        }
+                    });
+                    (order, None, Some(repriced), fills)
+                })
+            } else if split {
+                let linked_id = state.next_order_id.fetch_add(1, Ordering::SeqCst);
+                book_guard.split_increase(order_id, quantity, linked_id)
+                    .map(|(original, extra)| (original, Some(extra), None, Vec::new()))
+            } else {
+                book_guard.modify_order(order_id, quantity).map(|order| (order, None, None, Vec::new()))
+            };
+            state.on_book_change(&market, &mut book_guard);
+            Ok(modified.map(|(order, linked, repriced, fills)| (order, linked, repriced, fills, state.next_ack())))
+        }
+    };
+    tracing::debug!(order_id = order_id, "Released book lock after attempting modify");
+
+    let modified_order_from_book = match modify_outcome {
+        Ok(modified) => modified,
+        Err(ModifyRejection::Expired(expired)) => {
+            if let Some(expired_id) = expired {
+                persist_expiry(&state, vec![expired_id]).await;
+            }
+            tracing::warn!(order_id = order_id, "Rejected modify: order exceeded its max lifetime and was expired");
+            return Err(ApiError::Gone(format!("order {} exceeded its maximum lifetime and was expired", order_id)));
+        }
+        Err(ModifyRejection::Throttled { retry_after_nanos }) => {
+            let retry_after_ms = retry_after_nanos.div_ceil(1_000_000);
+            tracing::warn!(order_id = order_id, retry_after_ms = retry_after_ms as u64, "Rejected modify: too soon after the previous one");
+            return Err(ApiError::TooManyRequests(format!("order {} modified too recently; retry after {} ms", order_id, retry_after_ms)));
+        }
+    };
+
+    // Filled or cancelled orders have left the book and stay that way
+    let (order_for_db, linked_order, repriced, fills, ack) = match modified_order_from_book {
+        Some(modified) => modified,
+        None => return Err(match departed_order(&state, order_id).await? {
+            Some(view) => {
+                check_owner(&state.config, order_id, view.account_id, caller)?;
+                tracing::warn!(order_id = order_id, status = ?view.status, "Rejected modify: order is no longer in the book");
+                ApiError::Conflict(format!("order {} is already {:?}", order_id, view.status))
+            }
+            None => ApiError::NotFound(format!("order {} not found", order_id)),
+        }),
+    };
+
+    let db_conn_clone: Arc<Mutex<Connection>> = Arc::clone(&state.db_conn);
+    let status_for_db = format!("{:?}", order_for_db.status);
+    let reason_for_db = order_for_db.cancel_reason.map(|reason| format!("{:?}", reason));
+    let quantity_for_db = order_for_db.quantity;
+    // An increase requeues the order, so its priority and timestamp may have moved
+    let priority_for_db = order_for_db.priority;
+    let timestamp_for_db = order_for_db.timestamp.to_string();
+    let id_for_db = order_for_db.id;
+    let linked_for_db = linked_order.clone();
+    let table = state.orders_table();
+    // The price, size and status of a reprice were written before it matched,
+    // and its fills are written by the matcher; only its new place is left
+    let requeued_for_db = repriced.as_ref().map(|order| (order.priority, order.timestamp.to_string()));
+    let paper = state.paper;
+    let logged_status = order_for_db.status.clone();
+    let logged_reason = order_for_db.cancel_reason.map_or_else(|| "Modified".to_string(), |reason| format!("{:?}", reason));
+    let modified_at = order_for_db.last_modified_at.unwrap_or_else(|| state.clock.now_nanos());
+
+    state.db_writes.run_after_queued("modify", move || {
+        let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB update (modify)");
+        tracing::debug!(order_id = id_for_db, "Acquired DB lock for UPDATE (modify)");
+        let tx = conn_guard.transaction()?;
+        if let Some((priority, timestamp)) = requeued_for_db {
+            tx.execute(
+                &format!("UPDATE {} SET priority = ?1, timestamp = ?2 WHERE id = ?3", table),
+                params![priority, timestamp, id_for_db],
+            )?;
+            return tx.commit();
+        }
+        tx.execute(
+            &format!("UPDATE {} SET remaining_quantity = ?1, status = ?2, cancel_reason = ?3, priority = ?4, timestamp = ?5 WHERE id = ?6", table),
+            params![quantity_for_db, status_for_db, reason_for_db, priority_for_db, timestamp_for_db, id_for_db],
+        )?;
+        log_order_event(&tx, paper, id_for_db, &logged_status, quantity_for_db, &logged_reason, modified_at)?;
+        if let Some(linked) = linked_for_db {
+            insert_order_row(&tx, "INSERT", table, &linked, linked.quantity)?;
+            log_order_event(&tx, paper, linked.id, &linked.status, linked.quantity, "Created", linked.created_at)?;
+        }
+        tx.commit()
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("DB writer stopped before order update (modify): {}", e);
+        ApiError::Internal("failed to persist modification".to_string())
+    })?
+    .map_err(|e| {
+        tracing::error!("DB error updating order {} (modify): {}", order_id, e);
+        ApiError::Internal("failed to persist modification".to_string())
+    })?;
+    tracing::debug!(order_id = order_id, "DB UPDATE (modify) successful");
+
+    Ok((ack, Json(ModifyResponse {
+        order: OrderView::from(&order_for_db),
+        linked_order: linked_order.as_ref().map(OrderView::from),
+        fills: fills.iter()
+            .filter(|fill| fill.bid_id == order_id || fill.ask_id == order_id)
+            .map(|fill| OrderFill::for_order(order_id, fill))
+            .collect(),
+    })))
+}
+
+async fn cancel_order_handler(
+    State(state): State<Arc<AppState>>,
+    Path(order_id): Path<OrderId>,
+    headers: HeaderMap,
+) -> Result<(AckSeq, Json<OrderView>), Response> {
+    tracing::info!(order_id = order_id, "Received cancel order request");
+    let caller = caller_account(&headers).map_err(IntoResponse::into_response)?;
+
+    let cancelled_order_from_book = match state.market_of(order_id) {
+        Some(market) => {
+            let mut book_guard = market.order_book.lock().expect("Mutex lock failed for book cancel");
+            tracing::debug!(order_id = order_id, "Acquired book lock for cancelling order");
+            if let Some(resting) = book_guard.find_order(order_id) {
+                check_owner(&state.config, order_id, resting.account_id, caller).map_err(IntoResponse::into_response)?;
+            }
+            let cancelled = book_guard.cancel_order(order_id, CancelReason::UserRequest);
+            state.on_book_change(&market, &mut book_guard);
+            cancelled.map(|order| (order, state.next_ack()))
+        }
+        None => None,
+    };
+    tracing::debug!(order_id = order_id, "Released book lock after attempting cancel");
+
+    let (order_for_db, ack) = match cancelled_order_from_book {
+        Some(cancelled) => cancelled,
+        None => return Err(cancel_miss_response(&state, order_id, caller).await),
+    };
+
+    let db_conn_clone: Arc<Mutex<Connection>> = Arc::clone(&state.db_conn);
+    let status_for_db = format!("{:?}", order_for_db.status);
+    let reason_for_db = order_for_db.cancel_reason.map(|reason| format!("{:?}", reason));
+    let id_for_db = order_for_db.id;
+    let table = state.orders_table();
+    let (paper, logged_status, cancel_reason) = (state.paper, order_for_db.status.clone(), order_for_db.cancel_reason);
+    let cancelled_at = state.clock.now_nanos();
+
+    state.db_writes.run_after_queued("cancel", move || {
+        let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB update (cancel)");
+        tracing::debug!(order_id = id_for_db, "Acquired DB lock for UPDATE (cancel)");
+        let tx = conn_guard.transaction()?;
+        tx.execute(
+            &format!("UPDATE {} SET status = ?1, remaining_quantity = 0, cancel_reason = ?2 WHERE id = ?3", table),
+            params![status_for_db, reason_for_db, id_for_db],
+        )?;
+        log_order_cancelled(&tx, paper, id_for_db, &logged_status, cancel_reason, cancelled_at)?;
+        tx.commit()
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("DB writer stopped before order update (cancel): {}", e);
+        ApiError::Internal("failed to persist cancellation".to_string()).into_response()
+    })?
+    .map_err(|e| {
+        tracing::error!("DB error updating order {} (cancel): {}", order_id, e);
+        ApiError::Internal("failed to persist cancellation".to_string()).into_response()
+    })?;
+    tracing::debug!(order_id = order_id, "DB UPDATE (cancel) successful");
+
+    Ok((ack, Json(OrderView::from(&order_for_db))))
+}
+
+// Flushes the book, e.g. at end of day. The book lock only covers taking the
+// orders out; the DB catches up afterwards in one transaction.
+async fn cancel_all_orders_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<(AckSeq, Json<CancelAllSummary>), ApiError> {
+    tracing::warn!(paper = state.paper, "Received cancel all orders request");
+
+    // One book at a time; the ack is taken under the last book's lock
+    let mut order_ids = Vec::new();
+    let mut ack = None;
+    for market in state.markets() {
+        let mut book_guard = market.order_book.lock().expect("Mutex lock failed for book cancel all");
+        order_ids.extend(book_guard.cancel_all(CancelReason::MassCancel));
+        state.on_book_change(&market, &mut book_guard);
+        ack = Some(state.next_ack());
+    }
+    // The default book is always there
+    let ack = ack.expect("at least one market");
+
+    let db_conn_clone = Arc::clone(&state.db_conn);
+    let table = state.orders_table();
+    let ids_for_db = order_ids.clone();
+    let (paper, cancelled_at) = (state.paper, state.clock.now_nanos());
+    state.db_writes.run_after_queued("cancel all", move || {
+        let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB update (cancel all)");
+        let tx = conn_guard.transaction()?;
+        {
+            let mut stmt = tx.prepare(&format!("UPDATE {} SET status = 'Cancelled', remaining_quantity = 0, cancel_reason = 'MassCancel' WHERE id = ?1", table))?;
+            for id in &ids_for_db {
+                stmt.execute(params![id])?;
+                log_order_cancelled(&tx, paper, *id, &OrderStatus::Cancelled, Some(CancelReason::MassCancel), cancelled_at)?;
+            }
+        }
+        tx.commit()
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("DB writer stopped before order update (cancel all): {}", e);
+        ApiError::Internal("failed to persist cancellations".to_string())
+    })?
+    .map_err(|e| {
+        tracing::error!("DB error cancelling all orders: {}", e);
+        ApiError::Internal("failed to persist cancellations".to_string())
+    })?;
+    tracing::info!(cancelled = order_ids.len(), "DB UPDATE (cancel all) successful");
+
+    Ok((ack, Json(CancelAllSummary { cancelled_count: order_ids.len(), order_ids })))
+}
+
+async fn persist_expiry(state: &AppState, order_ids: Vec<OrderId>) {
+    let db_conn_clone = Arc::clone(&state.db_conn);
+    let table = state.orders_table();
+    let count = order_ids.len();
+    let (paper, expired_at) = (state.paper, state.clock.now_nanos());
+    let result = state.db_writes.run_after_queued("expire", move || {
+        let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB update (expire)");
+        let tx = conn_guard.transaction()?;
+        for order_id in &order_ids {
+            tx.execute(
+                &format!("UPDATE {} SET status = 'Expired', remaining_quantity = 0, cancel_reason = 'Expiry' WHERE id = ?1", table),
+                params![order_id],
+            )?;
+            log_order_cancelled(&tx, paper, *order_id, &OrderStatus::Expired, Some(CancelReason::Expiry), expired_at)?;
+        }
+        tx.commit()
+    })
+    .await;
+    match result {
+        Ok(Ok(())) => tracing::debug!(count = count, "DB UPDATE (expire) successful"),
+        Ok(Err(e)) => tracing::error!("DB error expiring {} order(s): {}", count, e),
+        Err(e) => tracing::error!("DB writer stopped before order update (expire): {}", e),
+    }
+}
+
+// Expires every order past its good-till-date or its maximum lifetime, book by
+// book, and persists them
+async fn sweep_expired(state: &AppState) -> Vec<Order> {
+    let now = state.clock.now_nanos();
+    let mut expired = Vec::new();
+    for market in state.markets() {
+        let mut book_guard = market.order_book.lock().expect("Mutex lock failed for expiry sweep");
+        let due = book_guard.expire_due(now, state.config.max_order_lifetime_nanos);
+        if !due.is_empty() {
+            state.on_book_change(&market, &mut book_guard);
+            tracing::info!(paper = state.paper, symbol = %market.symbol, expired = due.len(), "Expired order(s) past their deadline");
+        }
+        expired.extend(due);
+    }
+    if !expired.is_empty() {
+        persist_expiry(state, expired.iter().map(|o| o.id).collect()).await;
+    }
+    expired
+}
+
+// The order isn't resting in the book. If the DB knows it, it already left the
+// book (typically filled), so report its last known state as 409 rather than 404.
+// The row can briefly lag the book while the fill is still being persisted.
+async fn cancel_miss_response(state: &AppState, order_id: OrderId, caller: Option<u64>) -> Response {
+    match departed_order(state, order_id).await {
+        Ok(Some(view)) => {
+            if let Err(refusal) = check_owner(&state.config, order_id, view.account_id, caller) {
+                return refusal.into_response();
+            }
+            tracing::info!(order_id = order_id, status = ?view.status, "Cancel target no longer in book");
+            (StatusCode::CONFLICT, Json(view)).into_response()
+        }
+        Ok(None) => ApiError::order_not_found(order_id).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+// Last persisted state of an order that isn't in the book; None if it never existed
+async fn departed_order(state: &AppState, order_id: OrderId) -> Result<Option<OrderView>, ApiError> {
+    let db_conn_clone = Arc::clone(&state.db_conn);
+    let table = state.orders_table();
+    let paper = state.paper;
+    task::spawn_blocking(move || {
+        let conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB lookup (departed order)");
+        load_order_view(&conn_guard, table, order_id, paper)
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("Task join error for order lookup: {}", e);
+        ApiError::Internal("failed to look up order".to_string())
+    })?
+    .map_err(|e| {
+        tracing::error!("DB error looking up order {}: {}", order_id, e);
+        ApiError::Internal("failed to look up order".to_string())
+    })
+}
+
+async fn spread_stats_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<WindowQuery>,
+    Query(symbol): Query<SymbolQuery>,
+) -> Result<Json<SpreadStats>, ApiError> {
+    tracing::info!(query = ?query, "Received spread stats request");
+    let from = query.from.map(u128::from).unwrap_or(0);
+    let to = query.to.map(u128::from).unwrap_or_else(|| state.clock.now_nanos());
+    if from > to {
+        tracing::warn!(from = from, to = to, "Spread stats window is inverted");
+        return Err(ApiError::BadRequest(format!("from ({}) is after to ({})", from, to)));
+    }
+    let market = state.market_for(&symbol)?;
+    let series = market.spread_series.lock().expect("Mutex lock failed for spread series");
+    Ok(Json(series.time_weighted_average(from, to)))
+}
+
+// Open, high, low and close (None when nothing traded), with totals
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+struct TradeWindowStats {
+    symbol: String,
+    from: u128,
+    to: u128,
+    totals: TradeTotals,
+    #[serde(with = "price_ticks::option")]
+    open: Option<u64>,
+    #[serde(with = "price_ticks::option")]
+    high: Option<u64>,
+    #[serde(with = "price_ticks::option")]
+    low: Option<u64>,
+    #[serde(with = "price_ticks::option")]
+    close: Option<u64>,
+}
+
+impl TradeWindowStats {
+    fn from_trades(symbol: String, from: u128, to: u128, trades: &[Fill]) -> Self {
+        let mut totals = TradeTotals::default();
+        for fill in trades {
+            totals.record(fill.price, fill.quantity);
+        }
+        TradeWindowStats {
+            symbol,
+            from,
+            to,
+            totals,
+            open: trades.first().map(|fill| fill.price),
+            high: trades.iter().map(|fill| fill.price).max(),
+            low: trades.iter().map(|fill| fill.price).min(),
+            close: trades.last().map(|fill| fill.price),
+        }
+    }
+}
+
+// Per-account order activity over a window, from the audit trail. `placed`
+// counts orders created in the window; `filled` and `cancelled` count orders
+// that ended that way in it, so a partial fill then cancelled is a cancel.
+#[derive(Debug, Serialize)]
+struct AccountActivity {
+    account_id: u64,
+    from: u128,
+    to: u128,
+    placed: u64,
+    filled: u64,
+    cancelled: u64,
+    // Of `placed`; None when nothing was placed
+    fill_ratio: Option<f64>,
+    cancel_ratio: Option<f64>,
+}
+
+fn load_account_activity(conn: &Connection, table: &str, paper: bool, account_id: u64, from: u128, to: u128) -> SqlResult<(u64, u64, u64)> {
+    let bound = |nanos: u128| i64::try_from(nanos).unwrap_or(i64::MAX);
+    conn.query_row(
+        &format!("SELECT COUNT(DISTINCT CASE WHEN e.reason = 'Created' THEN e.order_id END),
+                COUNT(DISTINCT CASE WHEN e.to_status = 'Filled' THEN e.order_id END),
+                COUNT(DISTINCT CASE WHEN e.to_status = 'Cancelled' THEN e.order_id END)
+             FROM {} e JOIN {} o ON o.id = e.order_id
+             WHERE e.paper = ?1 AND o.account_id = ?2 AND CAST(e.timestamp AS INTEGER) BETWEEN ?3 AND ?4", order_events_table(paper), table),
+        params![paper, account_id, bound(from), bound(to)],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )
+}
+
+// `to` defaults to now. High cancel ratios are what surveillance looks at
+// for spoofing.
+async fn account_activity_handler(
+    State(state): State<Arc<AppState>>,
+    Path(account_id): Path<u64>,
+    Query(query): Query<WindowQuery>,
+) -> Result<Json<AccountActivity>, ApiError> {
+    tracing::debug!(account_id = account_id, query = ?query, "Received account activity request");
+    let from = query.from.map(u128::from).unwrap_or(0);
+    let to = query.to.map(u128::from).unwrap_or_else(|| state.clock.now_nanos());
+    if from > to {
+        return Err(ApiError::BadRequest(format!("from ({}) is after to ({})", from, to)));
+    }
+    let read_pool = Arc::clone(&state.read_pool);
+    let table = state.orders_table();
+    let paper = state.paper;
+    let (placed, filled, cancelled) = task::spawn_blocking(move || {
+        let conn = read_pool.get()?;
+        load_account_activity(&conn, table, paper, account_id, from, to)
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("Task join error for account activity: {}", e);
+        ApiError::Internal("failed to load account activity".to_string())
+    })?
+    .map_err(|e| {
+        tracing::error!("DB error loading activity of account {}: {}", account_id, e);
+        ApiError::Internal("failed to load account activity".to_string())
+    })?;
+    let ratio = |count: u64| (placed > 0).then(|| count as f64 / placed as f64);
+    Ok(Json(AccountActivity { account_id, from, to, placed, filled, cancelled, fill_ratio: ratio(filled), cancel_ratio: ratio(cancelled) }))
+}
+
+// The account's quote in every symbol it has an obligation in, by symbol,
+// each from that book as it stands; empty when it has none
+async fn account_obligations_handler(
+    State(state): State<Arc<AppState>>,
+    Path(account_id): Path<u64>,
+) -> Json<Vec<QuoteStatus>> {
+    let mut symbols: Vec<(&String, &QuoteObligation)> = state.config.quote_obligations.iter()
+        .filter_map(|(symbol, obligations)| Some((symbol, obligations.get(&account_id)?)))
+        .collect();
+    symbols.sort_by_key(|(symbol, _)| symbol.as_str());
+    let statuses = symbols.into_iter().map(|(symbol, obligation)| match state.market(symbol) {
+        Some(market) => {
+            let book_guard = market.order_book.lock().expect("Mutex lock failed for book");
+            QuoteStatus::measure(symbol, account_id, obligation, book_guard.bids.iter().chain(book_guard.asks.iter()))
+        }
+        None => QuoteStatus::measure(symbol, account_id, obligation, std::iter::empty()),
+    });
+    Json(statuses.collect())
+}
+
+// OHLC and volume over a window of the trades table; `to` defaults to now
+async fn trade_window_stats_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<WindowQuery>,
+    Query(symbol): Query<SymbolQuery>,
+) -> Result<Json<TradeWindowStats>, ApiError> {
+    tracing::debug!(query = ?query, "Received trade window stats request");
+    let from = query.from.map(u128::from).unwrap_or(0);
+    let to = query.to.map(u128::from).unwrap_or_else(|| state.clock.now_nanos());
+    if from > to {
+        return Err(ApiError::BadRequest(format!("from ({}) is after to ({})", from, to)));
+    }
+    let symbol = symbol.symbol.unwrap_or_else(default_symbol);
+    validate_symbol(&symbol).map_err(ApiError::BadRequest)?;
+    let read_pool = Arc::clone(&state.read_pool);
+    let paper = state.paper;
+    task::spawn_blocking(move || {
+        let conn = read_pool.get()?;
+        let trades = load_trades_between(&conn, paper, &symbol, from, to)?;
+        Ok(TradeWindowStats::from_trades(symbol, from, to, &trades))
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("Task join error for trade window stats: {}", e);
+        ApiError::Internal("failed to load trades".to_string())
+    })?
+    .map_err(|e: rusqlite::Error| {
+        tracing::error!("DB error loading trade window: {}", e);
+        ApiError::Internal("failed to load trades".to_string())
+    })
+    .map(Json)
+}
+
+#[derive(Debug, Serialize)]
+struct SessionStatus {
+    symbol: String,
+    phase: SessionPhase,
+}
+
+// --- Recent Orders Cache ---
+
+const DEFAULT_RECENT_ORDERS: usize = 10_000;
+
+// Least-recently-used views of orders that have left the book, so
+// GET /orders/:id answers for a just-filled or cancelled order without a DB
+// read. Filled from on_book_change as orders depart, so never behind the
+// book; an admin rewriting an order drops its entry.
+#[derive(Debug, Default)]
+struct RecentOrders {
+    capacity: usize,
+    // Each order with the tick it was last used at
+    entries: HashMap<OrderId, (OrderView, u64)>,
+    // Use tick to order, least recently used first
+    by_use: BTreeMap<u64, OrderId>,
+    next_tick: u64,
+}
+
+impl RecentOrders {
+    fn new(capacity: usize) -> Self {
+        RecentOrders { capacity, ..RecentOrders::default() }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+
+    fn insert(&mut self, view: OrderView) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.forget(view.id);
+        if self.entries.len() >= self.capacity {
+            if let Some((_, evicted)) = self.by_use.pop_first() {
+                self.entries.remove(&evicted);
+            }
+        }
+        let tick = self.tick();
+        self.by_use.insert(tick, view.id);
+        self.entries.insert(view.id, (view, tick));
+    }
+
+    fn get(&mut self, id: OrderId) -> Option<OrderView> {
+        let tick = self.tick();
+        let (view, used) = self.entries.get_mut(&id)?;
+        self.by_use.remove(used);
+        *used = tick;
+        self.by_use.insert(tick, id);
+        Some(view.clone())
+    }
+
+    fn forget(&mut self, id: OrderId) {
+        if let Some((_, used)) = self.entries.remove(&id) {
+            self.by_use.remove(&used);
+        }
+    }
+}
+
+// --- Idempotency Keys ---
+
+// A create retried with the same key gets the original order back (200)
+// instead of placing a second one. Keys are remembered in memory for the
+// configured window, so they don't survive a restart.
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+const DEFAULT_IDEMPOTENCY_WINDOW_NANOS: u128 = 24 * 60 * 60 * 1_000_000_000;
+
+// Keys are per account (and per book), so clients can't collide on them
+type IdempotencyScope = (Option<u64>, String);
+
+#[derive(Debug)]
+enum IdempotentOutcome {
+    // First request still being processed
+    Pending,
+    Done(Box<CreateOrderResponse>),
+}
+
+#[derive(Debug)]
+struct IdempotencyEntry {
+    // Of the request, so a reused key can't silently stand for another order
+    fingerprint: u64,
+    recorded_at: u128,
+    outcome: IdempotentOutcome,
+}
+
+#[derive(Debug, Default)]
+struct IdempotencyKeys {
+    entries: HashMap<IdempotencyScope, IdempotencyEntry>,
+    // Arrival order, which with one window for all keys is also expiry order
+    arrivals: VecDeque<(u128, IdempotencyScope)>,
+}
+
+impl IdempotencyKeys {
+    fn prune(&mut self, now: u128, window: u128) {
+        while self.arrivals.front().is_some_and(|(at, _)| now.saturating_sub(*at) >= window) {
+            let (at, scope) = self.arrivals.pop_front().expect("front checked above");
+            // Released and reserved again since: the entry is newer than this arrival
+            if self.entries.get(&scope).is_some_and(|entry| entry.recorded_at == at) {
+                self.entries.remove(&scope);
+            }
+        }
+    }
+}
+
+fn idempotency_key(headers: &HeaderMap) -> Result<Option<String>, ApiError> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+    let key = value.to_str().map_err(|_| ApiError::BadRequest("Idempotency-Key must be printable ASCII".to_string()))?;
+    if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+        return Err(ApiError::BadRequest(format!("Idempotency-Key must be 1 to {} characters", MAX_IDEMPOTENCY_KEY_LEN)));
+    }
+    Ok(Some(key.to_string()))
+}
+
+fn request_fingerprint(payload: &CreateOrderPayload) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    format!("{:?}", payload).hash(&mut hasher);
+    hasher.finish()
+}
+
+impl AppState {
+    // None reserves the key for this request; Some is the original response
+    fn reserve_idempotency_key(&self, scope: &IdempotencyScope, payload: &CreateOrderPayload) -> Result<Option<CreateOrderResponse>, ApiError> {
+        let now = self.clock.now_nanos();
+        let window = self.config.idempotency_window_nanos.unwrap_or(DEFAULT_IDEMPOTENCY_WINDOW_NANOS);
+        let fingerprint = request_fingerprint(payload);
+        let mut keys = self.idempotency_keys.lock().expect("Mutex lock failed for idempotency keys");
+        keys.prune(now, window);
+        match keys.entries.get(scope) {
+            Some(entry) if entry.fingerprint != fingerprint => {
+                Err(ApiError::Unprocessable("Idempotency-Key was already used for a different order".to_string()))
+            }
+            Some(IdempotencyEntry { outcome: IdempotentOutcome::Pending, .. }) => {
+                Err(ApiError::Conflict("an order with this Idempotency-Key is still being processed".to_string()))
+            }
+            Some(IdempotencyEntry { outcome: IdempotentOutcome::Done(original), .. }) => Ok(Some(original.as_ref().clone())),
+            None => {
+                keys.entries.insert(scope.clone(), IdempotencyEntry { fingerprint, recorded_at: now, outcome: IdempotentOutcome::Pending });
+                keys.arrivals.push_back((now, scope.clone()));
+                Ok(None)
+            }
+        }
+    }
+}
+
+// A key reserved by a create that hasn't finished. Dropped without
+// `complete` (the create failed), it frees the key so a retry can go through.
+struct IdempotencyReservation<'a> {
+    state: &'a AppState,
+    scope: Option<IdempotencyScope>,
+}
+
+impl IdempotencyReservation<'_> {
+    fn complete(mut self, response: &CreateOrderResponse) {
+        let Some(scope) = self.scope.take() else { return };
+        let mut keys = self.state.idempotency_keys.lock().expect("Mutex lock failed for idempotency keys");
+        if let Some(entry) = keys.entries.get_mut(&scope) {
+            entry.outcome = IdempotentOutcome::Done(Box::new(response.clone()));
+        }
+    }
+}
+
+impl Drop for IdempotencyReservation<'_> {
+    fn drop(&mut self) {
+        let Some(scope) = self.scope.take() else { return };
+        let mut keys = self.state.idempotency_keys.lock().expect("Mutex lock failed for idempotency keys");
+        if keys.entries.get(&scope).is_some_and(|entry| matches!(entry.outcome, IdempotentOutcome::Pending)) {
+            keys.entries.remove(&scope);
+        }
+    }
+}
+
+// --- Rate Limiting ---
+
+// Clients name themselves with this header; others are told apart by source IP
+const CLIENT_ID_HEADER: &str = "x-client-id";
+// Past this many tracked clients, buckets that have refilled are forgotten
+const MAX_RATE_LIMITED_CLIENTS: usize = 10_000;
+// Tokens are counted in billionths, so a nanosecond of refill is a whole unit
+const TOKEN: u128 = 1_000_000_000;
+
+// Token bucket per client for order submission: up to `burst` at once, then
+// `per_sec` more each second. Every order costs a token, those in a batch too.
+#[derive(Debug)]
+struct RateLimiter {
+    per_sec: u32,
+    burst: u32,
+    buckets: HashMap<String, TokenBucket>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    // In units of TOKEN
+    tokens: u128,
+    updated_at: u128,
+}
+
+impl RateLimiter {
+    // None when OMS_ORDER_RATE_PER_SEC is unset or zero
+    fn from_config(config: &Config) -> Option<RateLimiter> {
+        let per_sec = config.order_rate_per_sec.filter(|&n| n > 0)?;
+        let burst = config.order_rate_burst.filter(|&n| n > 0).unwrap_or(per_sec);
+        Some(RateLimiter { per_sec, burst, buckets: HashMap::new() })
+    }
+
+    fn capacity(&self) -> u128 {
+        self.burst as u128 * TOKEN
+    }
+
+    // Tokens in the bucket as of `now`
+    fn refilled(&self, bucket: &TokenBucket, now: u128) -> u128 {
+        let elapsed = now.saturating_sub(bucket.updated_at);
+        bucket.tokens.saturating_add(elapsed.saturating_mul(self.per_sec as u128)).min(self.capacity())
+    }
+
+    // Takes `orders` tokens for `client`, or none and returns how many nanos
+    // until that many are free
+    fn acquire(&mut self, client: &str, now: u128, orders: u32) -> Result<(), u128> {
+        if self.buckets.len() >= MAX_RATE_LIMITED_CLIENTS && !self.buckets.contains_key(client) {
+            let capacity = self.capacity();
+            let full: Vec<String> = self.buckets.iter()
+                .filter(|(_, bucket)| self.refilled(bucket, now) == capacity)
+                .map(|(client, _)| client.clone())
+                .collect();
+            for client in full {
+                self.buckets.remove(&client);
+            }
+        }
+        let fresh = TokenBucket { tokens: self.capacity(), updated_at: now };
+        let bucket = self.buckets.get(client).copied().unwrap_or(fresh);
+        let tokens = self.refilled(&bucket, now);
+        let cost = orders as u128 * TOKEN;
+        if tokens < cost {
+            self.buckets.insert(client.to_string(), TokenBucket { tokens, updated_at: now });
+            return Err((cost - tokens).div_ceil(self.per_sec as u128));
+        }
+        self.buckets.insert(client.to_string(), TokenBucket { tokens: tokens - cost, updated_at: now });
+        Ok(())
+    }
+}
+
+// The X-Client-Id header, else the peer's IP, else one bucket shared by
+// every client the server can't tell apart
+fn rate_limit_client(request: &Request) -> String {
+    if let Some(id) = request.headers().get(CLIENT_ID_HEADER).and_then(|value| value.to_str().ok()).filter(|id| !id.is_empty()) {
+        return format!("id:{}", id);
+    }
+    match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+        None => "unknown".to_string(),
+    }
+}
+
+// Takes a token per order for `client`; when it hasn't that many, returns the
+// 429 to answer with. A batch bigger than the whole burst could never go
+// through, so it gets a 422 instead.
+fn throttle_orders(state: &AppState, client: &str, orders: usize) -> Option<Response> {
+    let limiter = state.rate_limiter.as_ref()?;
+    let mut limiter = limiter.lock().expect("Mutex lock failed for rate limiter");
+    let Some(orders) = u32::try_from(orders).ok().filter(|&orders| orders <= limiter.burst) else {
+        tracing::warn!(client = %client, orders = orders, burst = limiter.burst, "Rejected order submission: batch exceeds the rate limit burst");
+        return Some(ApiError::Unprocessable(format!("a batch of {} orders exceeds the rate limit burst of {}", orders, limiter.burst)).into_response());
+    };
+    let throttled = limiter.acquire(client, state.clock.now_nanos(), orders);
+    if let Err(wait_nanos) = throttled {
+        // Retry-After is in whole seconds
+        let retry_after_secs = wait_nanos.div_ceil(1_000_000_000).max(1);
+        tracing::warn!(client = %client, retry_after_secs = retry_after_secs as u64, "Rejected order submission: rate limit exceeded");
+        return Some((
+            [(header::RETRY_AFTER, retry_after_secs.to_string())],
+            ApiError::TooManyRequests(format!("order rate limit exceeded; retry after {} s", retry_after_secs)),
+        ).into_response());
+    }
+    None
+}
+
+// Runs ahead of the order handlers, before the body is even parsed, so a
+// throttled request costs one map lookup and never touches the book lock
+async fn rate_limit_orders(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    if state.rate_limiter.is_some() {
+        if let Some(throttled) = throttle_orders(&state, &rate_limit_client(&request), 1) {
+            return throttled;
+        }
+    }
+    next.run(request).await
+}
+
+// The most a batch body is read for counting; the Json extractor's own default
+const BATCH_BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+// As rate_limit_orders, but a batch costs a token per order, all or nothing.
+// Its orders are only counted here, not parsed; a body that isn't a JSON
+// array is charged one and left for the handler to reject.
+async fn rate_limit_batch(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    if state.rate_limiter.is_none() {
+        return next.run(request).await;
+    }
+    let client = rate_limit_client(&request);
+    let (parts, body) = request.into_parts();
+    let bytes = match axum::body::to_bytes(body, BATCH_BODY_LIMIT).await {
+        Ok(bytes) => bytes,
+        Err(e) => return ApiError::BadRequest(format!("unreadable batch body: {}", e)).into_response(),
+    };
+    let orders = serde_json::from_slice::<Vec<serde::de::IgnoredAny>>(&bytes).map_or(1, |orders| orders.len().max(1));
+    if let Some(throttled) = throttle_orders(&state, &client, orders) {
+        return throttled;
+    }
+    next.run(Request::from_parts(parts, axum::body::Body::from(bytes))).await
+}
+
+// --- Client Sessions ---
+
+// Orders created with this header belong to that client session. While the
+// session has a control connection open (GET /sessions/control), closing the
+// last one cancels every order of the session still resting. Orders sent
+// without the header are never cancelled on disconnect.
+const CLIENT_SESSION_HEADER: &str = "x-session-id";
+const MAX_CLIENT_SESSION_LEN: usize = 64;
+
+#[derive(Debug, Default)]
+struct ClientSession {
+    // Every order entered under the session; ones that have since left the
+    // book are skipped when it disconnects
+    orders: Vec<OrderId>,
+    // Open control connections
+    connections: usize,
+}
+
+fn client_session_id(headers: &HeaderMap) -> Result<Option<String>, ApiError> {
+    let Some(value) = headers.get(CLIENT_SESSION_HEADER) else {
+        return Ok(None);
+    };
+    let id = value.to_str().map_err(|_| ApiError::BadRequest(format!("{} must be printable ASCII", CLIENT_SESSION_HEADER)))?;
+    if id.is_empty() || id.len() > MAX_CLIENT_SESSION_LEN {
+        return Err(ApiError::BadRequest(format!("{} must be 1 to {} characters", CLIENT_SESSION_HEADER, MAX_CLIENT_SESSION_LEN)));
+    }
+    Ok(Some(id.to_string()))
+}
+
+// Cancels a session's resting orders, and forgets the session unless a new
+// control connection has opened in the meantime
+async fn cancel_session_orders(state: &AppState, session: &str) -> Vec<Order> {
+    let order_ids = {
+        let mut sessions = state.client_sessions.lock().expect("Mutex lock failed for client sessions");
+        match sessions.get_mut(session) {
+            Some(entry) if entry.connections > 0 => std::mem::take(&mut entry.orders),
+            Some(_) => sessions.remove(session).map(|s| s.orders).unwrap_or_default(),
+            None => Vec::new(),
+        }
+    };
+    let mut cancelled = Vec::new();
+    for order_id in order_ids {
+        let Some(market) = state.market_of(order_id) else { continue };
+        let mut book_guard = market.order_book.lock().expect("Mutex lock failed for session cancel");
+        if let Some(order) = book_guard.cancel_order(order_id, CancelReason::Disconnect) {
+            cancelled.push(order);
+        }
+        state.on_book_change(&market, &mut book_guard);
+    }
+    tracing::warn!(paper = state.paper, session = session, cancelled = cancelled.len(), "Client session disconnected; cancelled its orders");
+    if cancelled.is_empty() {
+        return cancelled;
+    }
+
+    let db_conn_clone = Arc::clone(&state.db_conn);
+    let table = state.orders_table();
+    let ids_for_db: Vec<OrderId> = cancelled.iter().map(|o| o.id).collect();
+    let (paper, cancelled_at) = (state.paper, state.clock.now_nanos());
+    let result = state.db_writes.run_after_queued("session cancel", move || -> SqlResult<()> {
+        let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB update (session cancel)");
+        let tx = conn_guard.transaction()?;
+        for order_id in &ids_for_db {
+            tx.execute(
+                &format!("UPDATE {} SET status = 'Cancelled', remaining_quantity = 0, cancel_reason = 'Disconnect' WHERE id = ?1", table),
+                params![order_id],
+            )?;
+            log_order_cancelled(&tx, paper, *order_id, &OrderStatus::Cancelled, Some(CancelReason::Disconnect), cancelled_at)?;
+        }
+        tx.commit()
+    })
+    .await;
+    match result {
+        Ok(Ok(())) => tracing::debug!(session = session, "DB UPDATE (session cancel) successful"),
+        Ok(Err(e)) => tracing::error!("DB error cancelling orders of session {}: {}", session, e),
+        Err(e) => tracing::error!("DB writer stopped before session cancel: {}", e),
+    }
+    cancelled
+}
+
+// Lives as long as one control connection; dropping the last one for a
+// session cancels its orders
+struct SessionControl {
+    state: Arc<AppState>,
+    session: String,
+}
+
+impl Drop for SessionControl {
+    fn drop(&mut self) {
+        let last = {
+            let mut sessions = self.state.client_sessions.lock().expect("Mutex lock failed for client sessions");
+            let entry = sessions.entry(self.session.clone()).or_default();
+            entry.connections = entry.connections.saturating_sub(1);
+            entry.connections == 0
+        };
+        if !last {
+            return;
+        }
+        let (state, session) = (Arc::clone(&self.state), std::mem::take(&mut self.session));
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move { cancel_session_orders(&state, &session).await; });
+            }
+            Err(_) => tracing::error!(session = %session, "No runtime to cancel a disconnected session's orders"),
+        }
+    }
+}
+
+// The session's control connection: a stream of keep-alive comments that
+// stays open until the client goes away. (A WebSocket would do the same job,
+// but this build has no WebSocket support.)
+async fn session_control_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Sse<impl futures_util::Stream<Item = Result<Event, std::convert::Infallible>>>, ApiError> {
+    let Some(session) = client_session_id(&headers)? else {
+        return Err(ApiError::BadRequest(format!("a control connection needs the {} header", CLIENT_SESSION_HEADER)));
+    };
+    state.client_sessions.lock().expect("Mutex lock failed for client sessions")
+        .entry(session.clone()).or_default().connections += 1;
+    tracing::info!(paper = state.paper, session = %session, "Client session control connection opened");
+    let control = SessionControl { state, session };
+    // Never yields; the keep-alives are what notice a dead peer
+    let stream = futures_util::stream::unfold(control, |control| async move {
+        std::future::pending::<()>().await;
+        Some((Ok(Event::default()), control))
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+// --- Trade Stream (SSE) ---
+
+const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+
+// How long a replay waits for trades that executed before the client
+// subscribed but are still being written to the trades table
+const TRADE_BACKLOG_RETRIES: u32 = 20;
+const TRADE_BACKLOG_RETRY_MS: u64 = 10;
+
+struct TradeStream {
+    state: Arc<AppState>,
+    market: Arc<Market>,
+    events: broadcast::Receiver<BookEvent>,
+    // Trades waiting to be sent, oldest first
+    pending: VecDeque<Fill>,
+    last_sent: u64,
+}
+
+fn trade_sse_event(fill: &Fill) -> Event {
+    Event::default()
+        .event("trade")
+        .id(fill.trade_id.to_string())
+        .data(serde_json::to_string(fill).expect("fill serializes"))
+}
+
+// Subscribes to the bus and returns it with the id of the market's last trade
+// already published, taken under the book lock so the two line up exactly.
+fn subscribe_trades(state: &AppState, market: &Market) -> (broadcast::Receiver<BookEvent>, u64) {
+    let book_guard = market.order_book.lock().expect("Mutex lock failed for trade stream subscribe");
+    (state.events.subscribe(), book_guard.next_trade_id - 1)
+}
+
+// The market's trades in (after, through] from the trades table
+async fn load_trade_backlog(state: &AppState, market: &Market, after: u64, through: u64) -> VecDeque<Fill> {
+    let mut backlog = Vec::new();
+    for attempt in 0..TRADE_BACKLOG_RETRIES {
+        let read_pool = Arc::clone(&state.read_pool);
+        let paper = state.paper;
+        let symbol = market.symbol.clone();
+        let loaded = task::spawn_blocking(move || {
+            let conn = read_pool.get()?;
+            load_trades_after(&conn, paper, &symbol, after)
+        })
+        .await;
+        match loaded {
+            Ok(Ok(trades)) => backlog = trades,
+            Ok(Err(e)) => tracing::error!("DB error loading trade backlog: {}", e),
+            Err(e) => tracing::error!("Task join error loading trade backlog: {}", e),
+        }
+        if backlog.last().map_or(after, |fill| fill.trade_id) >= through {
+            break;
+        }
+        if attempt + 1 == TRADE_BACKLOG_RETRIES {
+            tracing::warn!(after = after, through = through, loaded = backlog.len(), "Trade backlog incomplete; some trades will not be replayed");
+        } else {
+            tokio::time::sleep(std::time::Duration::from_millis(TRADE_BACKLOG_RETRY_MS)).await;
+        }
+    }
+    backlog.into_iter().filter(|fill| fill.trade_id <= through).collect()
+}
+
+// Live trades for one symbol as SSE `trade` events with the trade id as event
+// id. A client reconnecting with Last-Event-ID first gets every later trade
+// from the table.
+async fn trade_stream_handler(
+    State(state): State<Arc<AppState>>,
+    Query(symbol): Query<SymbolQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<impl futures_util::Stream<Item = Result<Event, std::convert::Infallible>>>, ApiError> {
+    let market = state.market_for(&symbol)?;
+    let resume_after = headers.get(LAST_EVENT_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    let (events, published) = subscribe_trades(&state, &market);
+    tracing::info!(paper = state.paper, symbol = %market.symbol, resume_after = ?resume_after, published = published, "Trade stream subscriber connected");
+    let pending = match resume_after {
+        Some(after) if after < published => load_trade_backlog(&state, &market, after, published).await,
+        _ => VecDeque::new(),
+    };
+    let last_sent = resume_after.map_or(published, |after| after.min(published));
+
+    let stream = futures_util::stream::unfold(
+        TradeStream { state, market, events, pending, last_sent },
+        |mut stream| async move {
+            loop {
+                if let Some(fill) = stream.pending.pop_front() {
+                    if fill.trade_id <= stream.last_sent {
+                        continue;
+                    }
+                    stream.last_sent = fill.trade_id;
+                    return Some((Ok(trade_sse_event(&fill)), stream));
+                }
+                match stream.events.recv().await {
+                    Ok(BookEvent::Trade(fill)) if fill.symbol == stream.market.symbol => stream.pending.push_back(fill),
+                    Ok(_) => {}
+                    // Fell behind the bus: catch up from the table instead
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!(missed = missed, last_sent = stream.last_sent, "Trade stream subscriber lagged; replaying from the trades table");
+                        let published = stream.market.order_book.lock().expect("Mutex lock failed for trade stream catch-up").next_trade_id - 1;
+                        stream.pending = load_trade_backlog(&stream.state, &stream.market, stream.last_sent, published).await;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        },
+    );
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+// --- WebSocket Feed ---
+
+// Messages a connection may have waiting from its client before the reader
+// stops taking more off the socket
+const WS_INCOMING_BUFFER: usize = 16;
+
+// Largest HTTP-style body a command reply carries
+const WS_REPLY_BODY_LIMIT: usize = 1 << 20;
+
+// Sent alongside the BookEvents, which carry their own "type"
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WsNotice {
+    // The connection fell behind the bus and these events were skipped
+    Lagged { missed: u64 },
+    // A command's outcome: the status and body the HTTP endpoint would have
+    // answered with, and the ack sequence if it was accepted
+    Reply {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        request_id: Option<u64>,
+        status: u16,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        ack_seq: Option<u64>,
+        body: serde_json::Value,
+    },
+}
+
+// A text message from the client, e.g.
+// {"op":"cancel","request_id":7,"order_id":42}
+#[derive(Debug, Deserialize)]
+struct WsRequest {
+    // Echoed on the reply so the client can pair them up
+    #[serde(default)]
+    request_id: Option<u64>,
+    // When the client sent it, unix nanos; checked against the configured max age
+    #[serde(default)]
+    sent_at: Option<u128>,
+    // Not to be executed after this, unix nanos
+    #[serde(default)]
+    expires_at: Option<u128>,
+    #[serde(flatten)]
+    command: WsCommand,
+}
+
+// Network delay can deliver a command after the client stopped wanting it;
+// such a command is refused rather than run late.
+fn check_ws_command_fresh(config: &Config, request: &WsRequest, now: u128) -> Result<(), ApiError> {
+    if let Some(expires_at) = request.expires_at.filter(|&expires_at| now > expires_at) {
+        return Err(ApiError::Gone(format!("command expired at {} before it arrived at {}", expires_at, now)));
+    }
+    if let (Some(sent_at), Some(max_age)) = (request.sent_at, config.ws_command_max_age_nanos) {
+        let age = now.saturating_sub(sent_at);
+        if age > max_age {
+            return Err(ApiError::Gone(format!("command is stale: sent {} ns ago, more than the {} ns allowed", age, max_age)));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum WsCommand {
+    Create { order: CreateOrderPayload },
+    Modify { order_id: OrderId, quantity: WireQuantity, #[serde(default, with = "price_ticks::option")] price: Option<u64> },
+    Cancel { order_id: OrderId },
+    // Narrows the feed to the subscribed symbols
+    Subscribe { symbol: String },
+    Unsubscribe { symbol: String },
+}
+
+// Body of a subscribe or unsubscribe reply
+#[derive(Debug, Serialize)]
+struct WsSubscription {
+    symbol: String,
+    subscribed: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    already_subscribed: bool,
+}
+
+// The symbols a connection gets events for. A new connection gets the whole
+// feed; its first subscribe narrows it to the subscribed symbols, each held
+// once, so subscribing again never delivers an event twice.
+#[derive(Debug, Default)]
+struct WsSubscriptions {
+    symbols: Option<std::collections::HashSet<String>>,
+}
+
+impl WsSubscriptions {
+    fn wants(&self, event: &BookEvent) -> bool {
+        self.symbols.as_ref().is_none_or(|symbols| symbols.contains(event.symbol()))
+    }
+
+    // None when the reply is to be left out
+    fn subscribe(&mut self, symbol: String, duplicate: DuplicateSubscribe) -> Result<Option<WsSubscription>, ApiError> {
+        validate_symbol(&symbol).map_err(ApiError::BadRequest)?;
+        let already_subscribed = !self.symbols.get_or_insert_with(Default::default).insert(symbol.clone());
+        if already_subscribed && duplicate == DuplicateSubscribe::Ignore {
+            return Ok(None);
+        }
+        Ok(Some(WsSubscription { symbol, subscribed: true, already_subscribed }))
+    }
+
+    fn unsubscribe(&mut self, symbol: String) -> Result<WsSubscription, ApiError> {
+        if !self.symbols.as_mut().is_some_and(|symbols| symbols.remove(&symbol)) {
+            return Err(ApiError::NotFound(format!("not subscribed to {}", symbol)));
+        }
+        Ok(WsSubscription { symbol, subscribed: false, already_subscribed: false })
+    }
+}
+
+fn ws_text<T: Serialize>(message: &T) -> ws::Message {
+    ws::Message::Text(serde_json::to_string(message).expect("WebSocket message serializes"))
+}
+
+// Whether a comma-separated header such as Connection lists `token`
+fn header_has_token(headers: &HeaderMap, name: header::HeaderName, token: &str) -> bool {
+    headers.get_all(name).iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.trim().eq_ignore_ascii_case(token))
+}
+
+// One of the configured WebSocket connections, held from the upgrade until
+// the connection's task ends, however it ends
+struct WsConnectionSlot {
+    state: Arc<AppState>,
+}
+
+impl WsConnectionSlot {
+    // None when every slot is taken
+    fn acquire(state: &Arc<AppState>) -> Option<Self> {
+        let max = state.config.ws_max_connections;
+        state.metrics.ws_connections
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |open| match max {
+                Some(max) if open >= max => None,
+                _ => Some(open + 1),
+            })
+            .ok()?;
+        Some(WsConnectionSlot { state: Arc::clone(state) })
+    }
+}
+
+impl Drop for WsConnectionSlot {
+    fn drop(&mut self) {
+        self.state.metrics.ws_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Runs an order command through the same handler as its HTTP endpoint, so
+// the two accept and reject alike. Creates count against the client's rate
+// limit. None when there is nothing to reply.
+async fn run_ws_command(state: &Arc<AppState>, client: &str, caller: &HeaderMap, subscriptions: &mut WsSubscriptions, text: &str) -> Option<WsNotice> {
+    let (request_id, response) = match serde_json::from_str::<WsRequest>(text) {
+        Err(e) => (None, ApiError::BadRequest(format!("invalid command: {}", e)).into_response()),
+        Ok(request) => {
+            tracing::info!(request_id = ?request.request_id, command = ?request.command, "Received WebSocket command");
+            if let Err(stale) = check_ws_command_fresh(&state.config, &request, state.clock.now_nanos()) {
+                tracing::warn!(request_id = ?request.request_id, reason = %stale, "Rejected stale WebSocket command");
+                return Some(ws_reply(request.request_id, stale.into_response()).await);
+            }
+            let WsRequest { request_id, command, .. } = request;
+            let response = match command {
+                // A replica's routes refuse these; so does its socket
+                WsCommand::Create { .. } | WsCommand::Modify { .. } | WsCommand::Cancel { .. } if state.config.read_only => {
+                    read_only_handler().await.into_response()
+                }
+                WsCommand::Create { order } => match throttle_orders(state, client, 1) {
+                    None => create_order_handler(State(Arc::clone(state)), HeaderMap::new(), Json(order)).await.into_response(),
+                    Some(throttled) => throttled,
+                },
+                WsCommand::Modify { order_id, quantity, price } => {
+                    modify_order_handler(State(Arc::clone(state)), Path(order_id), caller.clone(), Json(ModifyOrderPayload { quantity, price })).await.into_response()
+                }
+                WsCommand::Cancel { order_id } => cancel_order_handler(State(Arc::clone(state)), Path(order_id), caller.clone()).await.into_response(),
+                WsCommand::Subscribe { symbol } => match subscriptions.subscribe(symbol, state.config.ws_duplicate_subscribe) {
+                    Ok(Some(subscribed)) => Json(subscribed).into_response(),
+                    Ok(None) => return None,
+                    Err(e) => e.into_response(),
+                },
+                WsCommand::Unsubscribe { symbol } => match subscriptions.unsubscribe(symbol) {
+                    Ok(unsubscribed) => Json(unsubscribed).into_response(),
+                    Err(e) => e.into_response(),
+                },
+            };
+            (request_id, response)
+        }
+    };
+    Some(ws_reply(request_id, response).await)
+}
+
+// Carries an HTTP-style response over the WebSocket
+async fn ws_reply(request_id: Option<u64>, response: Response) -> WsNotice {
+    let status = response.status().as_u16();
+    let ack_seq = response.headers().get(ACK_SEQ_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok());
+    let body = match axum::body::to_bytes(response.into_body(), WS_REPLY_BODY_LIMIT).await {
+        Ok(bytes) if bytes.is_empty() => serde_json::Value::Null,
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(&bytes).into_owned())),
+        Err(e) => {
+            tracing::error!("Failed to read reply body for WebSocket command: {}", e);
+            serde_json::Value::Null
+        }
+    };
+    WsNotice::Reply { request_id, status, ack_seq, body }
+}
+
+// Upgrades to a WebSocket that pushes every BookEvent as JSON, tagged by
+// "type" like {"type":"trade",...}, or just those of the symbols it
+// subscribes to, and takes order commands (WsRequest),
+// answering each with a reply carrying its ack sequence. Each connection
+// reads the bus on a task of its own, so a slow client only falls behind
+// (and is told how far) and never holds up matching.
+async fn ws_handler(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+) -> Result<Response, ApiError> {
+    let headers = request.headers();
+    if !header_has_token(headers, header::UPGRADE, "websocket") || !header_has_token(headers, header::CONNECTION, "upgrade") {
+        return Err(ApiError::BadRequest("expected a WebSocket upgrade request".to_string()));
+    }
+    if headers.get(header::SEC_WEBSOCKET_VERSION).and_then(|v| v.to_str().ok()) != Some("13") {
+        return Err(ApiError::BadRequest("unsupported WebSocket version; expected 13".to_string()));
+    }
+    let Some(key) = headers.get(header::SEC_WEBSOCKET_KEY).and_then(|v| v.to_str().ok()) else {
+        return Err(ApiError::BadRequest("missing Sec-WebSocket-Key".to_string()));
+    };
+    let accept = ws::accept_key(key);
+    // Its commands act for the account the upgrade named
+    let mut caller = HeaderMap::new();
+    if let Some(account) = headers.get(ACCOUNT_ID_HEADER) {
+        caller.insert(ACCOUNT_ID_HEADER, account.clone());
+    }
+    let Some(slot) = WsConnectionSlot::acquire(&state) else {
+        let max = state.config.ws_max_connections.unwrap_or_default();
+        tracing::warn!(max = max, "Rejected WebSocket upgrade: connection limit reached");
+        return Err(ApiError::Unavailable(format!("WebSocket connection limit of {} reached; try again later", max)));
+    };
+    let client = rate_limit_client(&request);
+    let on_upgrade = hyper::upgrade::on(&mut request);
+    tokio::spawn(async move {
+        match on_upgrade.await {
+            Ok(upgraded) => run_ws_connection(state, client, caller, hyper_util::rt::TokioIo::new(upgraded)).await,
+            Err(e) => tracing::warn!("WebSocket upgrade failed: {}", e),
+        }
+        drop(slot);
+    });
+    Ok((
+        StatusCode::SWITCHING_PROTOCOLS,
+        [(header::CONNECTION, "upgrade".to_string()), (header::UPGRADE, "websocket".to_string()), (header::SEC_WEBSOCKET_ACCEPT, accept)],
+    ).into_response())
+}
+
+async fn run_ws_connection<S>(state: Arc<AppState>, client: String, caller: HeaderMap, socket: S)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + 'static,
+{
+    let (read_half, mut write_half) = tokio::io::split(socket);
+    let mut events = state.events.subscribe();
+    let mut subscriptions = WsSubscriptions::default();
+    tracing::info!(paper = state.paper, client = %client, "WebSocket subscriber connected");
+
+    // Frame reads aren't cancel safe, so they get their own task rather than
+    // a select! branch
+    let (incoming_tx, mut incoming) = tokio::sync::mpsc::channel(WS_INCOMING_BUFFER);
+    let reader = tokio::spawn(async move {
+        let mut reader = ws::Reader::new(read_half, true);
+        loop {
+            let message = reader.next().await;
+            let failed = message.is_err();
+            if incoming_tx.send(message).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    loop {
+        let outgoing = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) if subscriptions.wants(&event) => ws_text(&event),
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    tracing::warn!(missed = missed, "WebSocket subscriber lagged; skipping ahead");
+                    ws_text(&WsNotice::Lagged { missed })
+                }
+                Err(broadcast::error::RecvError::Closed) => ws::Message::Close(Some(ws::CLOSE_NORMAL)),
+            },
+            message = incoming.recv() => match message {
+                Some(Ok(ws::Message::Ping(data))) => ws::Message::Pong(data),
+                Some(Ok(ws::Message::Close(_))) | None => ws::Message::Close(Some(ws::CLOSE_NORMAL)),
+                Some(Ok(ws::Message::Text(text))) => match run_ws_command(&state, &client, &caller, &mut subscriptions, &text).await {
+                    Some(reply) => ws_text(&reply),
+                    None => continue,
+                },
+                Some(Ok(_)) => continue,
+                Some(Err(e)) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    tracing::debug!("Closing WebSocket on protocol error: {}", e);
+                    ws::Message::Close(Some(ws::CLOSE_PROTOCOL_ERROR))
+                }
+                // Dropped without a close frame
+                Some(Err(e)) => {
+                    tracing::debug!("WebSocket read failed: {}", e);
+                    break;
+                }
+            },
+        };
+        let closing = matches!(outgoing, ws::Message::Close(_));
+        if let Err(e) = ws::write_message(&mut write_half, &outgoing, None).await {
+            tracing::debug!("WebSocket write failed: {}", e);
+            break;
+        }
+        if closing {
+            break;
+        }
+    }
+    reader.abort();
+    tracing::info!(paper = state.paper, "WebSocket subscriber disconnected");
+}
+
+async fn session_handler(
+    State(state): State<Arc<AppState>>,
+    Query(symbol): Query<SymbolQuery>,
+) -> Result<Json<SessionStatus>, ApiError> {
+    let market = state.market_for(&symbol)?;
+    let phase = market.session_phase(state.clock.now_nanos());
+    Ok(Json(SessionStatus { symbol: market.symbol.clone(), phase }))
+}
+
+async fn auction_handler(
+    State(state): State<Arc<AppState>>,
+    Query(symbol): Query<SymbolQuery>,
+    headers: HeaderMap,
+) -> Result<Json<AuctionResult>, ApiError> {
+    check_admin(&state.config, &headers).inspect_err(|rejection| {
+        tracing::warn!(reason = %rejection, "Rejected auction trigger");
+    })?;
+    let market = state.market_for(&symbol)?;
+    tracing::info!(paper = state.paper, symbol = %market.symbol, "Received auction trigger");
+    Ok(Json(state.run_auction(&market)))
+}
+
+#[derive(Debug, Serialize)]
+struct MatchModeStatus {
+    symbol: String,
+    mode: MatchMode,
+    // The book's delta sequence when the mode took effect; 0 for the
+    // configured mode the book opened with
+    since_seq: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct MatchModePayload {
+    mode: String,
+}
+
+async fn get_match_mode_handler(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+) -> Result<Json<MatchModeStatus>, ApiError> {
+    let market = state.market_for(&SymbolQuery { symbol: Some(symbol) })?;
+    let (mode, since_seq) = market.order_book.lock().expect("Mutex lock failed for book").match_mode();
+    Ok(Json(MatchModeStatus { symbol: market.symbol.clone(), mode, since_seq }))
+}
+
+// Switches how the symbol's price levels are shared out from the next match
+// on. Taken under the book lock, so no match is ever split across two modes.
+// Not persisted: a restart goes back to the configured mode.
+async fn put_match_mode_handler(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+    headers: HeaderMap,
+    Json(payload): Json<MatchModePayload>,
+) -> Result<Json<MatchModeStatus>, ApiError> {
+    check_admin(&state.config, &headers).inspect_err(|rejection| {
+        tracing::warn!(reason = %rejection, "Rejected match mode change");
+    })?;
+    let mode = MatchMode::parse(&payload.mode)
+        .ok_or_else(|| ApiError::BadRequest(format!("unknown match mode '{}'; expected price_time, pro_rata or size_priority", payload.mode)))?;
+    let market = state.market_for(&SymbolQuery { symbol: Some(symbol) })?;
+    let mut book_guard = market.order_book.lock().expect("Mutex lock failed for book");
+    let (previous, _) = book_guard.match_mode();
+    book_guard.set_match_mode(mode);
+    let since_seq = book_guard.seq;
+    tracing::info!(paper = state.paper, symbol = %market.symbol, previous = ?previous, mode = ?mode, seq = since_seq, "Match mode changed");
+    Ok(Json(MatchModeStatus { symbol: market.symbol.clone(), mode, since_seq }))
+}
+
+async fn get_symbol_limits_handler(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+) -> Result<Json<SymbolLimitsView>, ApiError> {
+    validate_symbol(&symbol).map_err(ApiError::BadRequest)?;
+    Ok(Json(SymbolLimitsView::new(&symbol, &state.symbol_limits(&symbol), &state.config.tick_size)))
+}
+
+// Replaces the symbol's limits as a whole, for orders that arrive from here
+// on; orders already accepted or resting are left as they are. The write is
+// queued under the limits lock, so the DB keeps the last of racing updates.
+async fn put_symbol_limits_handler(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+    headers: HeaderMap,
+    Json(payload): Json<SymbolLimitsPayload>,
+) -> Result<Json<SymbolLimitsView>, ApiError> {
+    check_admin(&state.config, &headers).inspect_err(|rejection| {
+        tracing::warn!(reason = %rejection, "Rejected symbol limits change");
+    })?;
+    validate_symbol(&symbol).map_err(ApiError::BadRequest)?;
+    let limits = payload.limits(&symbol, &state.config.tick_size).map_err(ApiError::BadRequest)?;
+
+    let committed = {
+        let mut all_limits = state.symbol_limits.write().expect("RwLock poisoned for symbol limits");
+        let previous = all_limits.insert(symbol.clone(), limits).unwrap_or_default();
+        tracing::info!(paper = state.paper, symbol = %symbol, previous = ?previous, limits = ?limits, "Symbol limits changed");
+        let db_conn_clone = Arc::clone(&state.db_conn);
+        let (paper, updated_at, symbol) = (state.paper, state.clock.now_nanos(), symbol.clone());
+        state.db_writes.queue_after("symbol limits", move || {
+            let conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB update (symbol limits)");
+            conn_guard.execute(
+                "INSERT OR REPLACE INTO symbol_limits (paper, symbol, max_order_qty, max_notional, price_band_bps, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![paper, symbol, limits.max_order_qty, limits.max_notional, limits.price_band_bps, updated_at.to_string()],
+            )
+        })
+    };
+    match committed.await {
+        Ok(Ok(_)) => Ok(Json(SymbolLimitsView::new(&symbol, &limits, &state.config.tick_size))),
+        Ok(Err(e)) => {
+            tracing::error!(symbol = %symbol, "DB error persisting symbol limits: {}", e);
+            Err(ApiError::Internal("limits applied but not persisted".to_string()))
+        }
+        Err(e) => {
+            tracing::error!(symbol = %symbol, "DB writer stopped before persisting symbol limits: {}", e);
+            Err(ApiError::Internal("limits applied but not persisted".to_string()))
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AccountSuspension {
+    account_id: u64,
+    suspended: bool,
+    // Resting orders cancelled by this suspension, across every symbol
+    cancelled: Vec<OrderId>,
+}
+
+// Bars the account from entering orders and cancels everything it has
+// resting. The flag goes up before the sweep, and entry checks it again
+// under the book lock, so nothing can slip in behind the sweep.
+async fn suspend_account_handler(
+    State(state): State<Arc<AppState>>,
+    Path(account_id): Path<u64>,
+    headers: HeaderMap,
+) -> Result<Json<AccountSuspension>, ApiError> {
+    check_admin(&state.config, &headers).inspect_err(|rejection| {
+        tracing::warn!(reason = %rejection, "Rejected account suspension");
+    })?;
+    state.suspended_accounts.write().expect("RwLock poisoned for suspended accounts").insert(account_id);
+
+    let mut cancelled = Vec::new();
+    for market in state.markets() {
+        let mut book_guard = market.order_book.lock().expect("Mutex lock failed for account suspension");
+        cancelled.extend(book_guard.cancel_account(account_id, CancelReason::Suspension));
+        state.on_book_change(&market, &mut book_guard);
+    }
+    tracing::warn!(paper = state.paper, account_id = account_id, cancelled = cancelled.len(), "Account suspended");
+
+    let db_conn_clone = Arc::clone(&state.db_conn);
+    let table = state.orders_table();
+    let ids_for_db = cancelled.clone();
+    let (paper, suspended_at) = (state.paper, state.clock.now_nanos());
+    state.db_writes.run_after_queued("account suspension", move || -> SqlResult<()> {
+        let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB update (account suspension)");
+        let tx = conn_guard.transaction()?;
+        tx.execute(
+            "INSERT OR IGNORE INTO account_suspensions (paper, account_id, suspended_at) VALUES (?1, ?2, ?3)",
+            params![paper, account_id, suspended_at.to_string()],
+        )?;
+        for order_id in &ids_for_db {
+            tx.execute(
+                &format!("UPDATE {} SET status = 'Cancelled', remaining_quantity = 0, cancel_reason = 'Suspension' WHERE id = ?1", table),
+                params![order_id],
+            )?;
+            log_order_cancelled(&tx, paper, *order_id, &OrderStatus::Cancelled, Some(CancelReason::Suspension), suspended_at)?;
+        }
+        tx.commit()
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("DB writer stopped before account suspension: {}", e);
+        ApiError::Internal("failed to persist suspension".to_string())
+    })?
+    .map_err(|e| {
+        tracing::error!("DB error suspending account {}: {}", account_id, e);
+        ApiError::Internal("failed to persist suspension".to_string())
+    })?;
+
+    Ok(Json(AccountSuspension { account_id, suspended: true, cancelled }))
+}
+
+// Lets the account enter orders again. Its cancelled orders stay cancelled.
+async fn unsuspend_account_handler(
+    State(state): State<Arc<AppState>>,
+    Path(account_id): Path<u64>,
+    headers: HeaderMap,
+) -> Result<Json<AccountSuspension>, ApiError> {
+    check_admin(&state.config, &headers).inspect_err(|rejection| {
+        tracing::warn!(reason = %rejection, "Rejected account unsuspension");
+    })?;
+    let db_conn_clone = Arc::clone(&state.db_conn);
+    let paper = state.paper;
+    state.db_writes.run_after_queued("account unsuspension", move || {
+        let conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB update (account unsuspension)");
+        conn_guard.execute("DELETE FROM account_suspensions WHERE paper = ?1 AND account_id = ?2", params![paper, account_id])
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("DB writer stopped before account unsuspension: {}", e);
+        ApiError::Internal("failed to persist unsuspension".to_string())
+    })?
+    .map_err(|e| {
+        tracing::error!("DB error unsuspending account {}: {}", account_id, e);
+        ApiError::Internal("failed to persist unsuspension".to_string())
+    })?;
+    state.suspended_accounts.write().expect("RwLock poisoned for suspended accounts").remove(&account_id);
+    tracing::info!(paper = state.paper, account_id = account_id, "Account unsuspended");
+
+    Ok(Json(AccountSuspension { account_id, suspended: false, cancelled: Vec::new() }))
+}
+
+const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+fn check_admin(config: &Config, headers: &HeaderMap) -> Result<(), ApiError> {
+    let Some(expected) = config.admin_token.as_deref() else {
+        return Err(ApiError::Forbidden("admin operations are disabled; set OMS_ADMIN_TOKEN".to_string()));
+    };
+    match headers.get(ADMIN_TOKEN_HEADER).and_then(|v| v.to_str().ok()) {
+        Some(token) if token == expected => Ok(()),
+        _ => Err(ApiError::Unauthorized("missing or invalid admin token".to_string())),
+    }
+}
+
+// Idempotently makes the book and DB match the desired order state.
+async fn ensure_order_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<EnsureOrderPayload>,
+) -> Result<Json<EnsureOrderResponse>, ApiError> {
+    check_admin(&state.config, &headers).inspect_err(|rejection| {
+        tracing::warn!(reason = %rejection, "Rejected ensure order request");
+    })?;
+    tracing::info!(payload = ?payload, "Received ensure order request");
+
+    let resting = matches!(payload.status, OrderStatus::Open | OrderStatus::PartiallyFilled);
+    if resting && payload.quantity == 0 {
+        return Err(ApiError::BadRequest("a resting order needs a non-zero quantity".to_string()));
+    }
+    validate_symbol(&payload.symbol).map_err(ApiError::BadRequest)?;
+    if let Some(current) = state.market_of(payload.id).filter(|market| market.symbol != payload.symbol) {
+        return Err(ApiError::Conflict(format!("order {} rests in the {} book", payload.id, current.symbol)));
+    }
+    let market = state.market_or_insert(&payload.symbol);
+    let mut desired = Order::with_clock(payload.id, payload.side, payload.price, payload.quantity, &*state.clock);
+    desired.symbol = payload.symbol;
+    desired.status = payload.status;
+    desired.group_id = payload.group_id;
+    if desired.status == OrderStatus::Cancelled {
+        desired.cancel_reason = Some(CancelReason::Reconciliation);
+    }
+    desired.paper = state.paper;
+    if !resting {
+        desired.quantity = 0;
+    }
+
+    let changed_in_book = {
+        let mut book_guard = market.order_book.lock().expect("Mutex lock failed for book ensure");
+        let changed = book_guard.ensure_order(&mut desired);
+        state.on_book_change(&market, &mut book_guard);
+        state.next_order_id.fetch_max(desired.id + 1, Ordering::SeqCst);
+        changed
+    };
+
+    let db_conn_clone = Arc::clone(&state.db_conn);
+    let table = state.orders_table();
+    let order_for_db = desired.clone();
+    let ensured_at = state.clock.now_nanos();
+    let changed = state.db_writes.run_after_queued("ensure", move || -> SqlResult<bool> {
+        let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB (ensure)");
+        // A terminal order that was already out of the book may still differ in the DB
+        let changed = changed_in_book || load_order_view(&conn_guard, table, order_for_db.id, order_for_db.paper)?
+            .is_none_or(|current| current.status != order_for_db.status);
+        if changed {
+            let tx = conn_guard.transaction()?;
+            tx.execute(
+                &format!("INSERT INTO {} (id, side, price, original_quantity, remaining_quantity, status, timestamp, group_id, created_at, priority, cancel_reason, symbol) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+                    ON CONFLICT(id) DO UPDATE SET symbol = excluded.symbol, side = excluded.side, price = excluded.price, remaining_quantity = excluded.remaining_quantity, status = excluded.status, group_id = excluded.group_id, priority = excluded.priority, cancel_reason = excluded.cancel_reason", table),
+                params![
+                    order_for_db.id,
+                    format!("{:?}", order_for_db.side),
+                    order_for_db.price,
+                    order_for_db.quantity,
+                    order_for_db.quantity,
+                    format!("{:?}", order_for_db.status),
+                    order_for_db.timestamp.to_string(),
+                    order_for_db.group_id,
+                    order_for_db.created_at.to_string(),
+                    order_for_db.priority,
+                    order_for_db.cancel_reason.map(|reason| format!("{:?}", reason)),
+                    order_for_db.symbol,
+                ],
+            )?;
+            log_order_event(&tx, order_for_db.paper, order_for_db.id, &order_for_db.status, order_for_db.quantity, &format!("{:?}", CancelReason::Reconciliation), ensured_at)?;
+            tx.commit()?;
+        }
+        Ok(changed)
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("DB writer stopped before ensure order: {}", e);
+        ApiError::Internal("failed to persist order".to_string())
+    })?
+    .map_err(|e| {
+        tracing::error!("DB error ensuring order {}: {}", desired.id, e);
+        ApiError::Internal("failed to persist order".to_string())
+    })?;
+    // The DB now has the reconciled state; a cached view may not
+    state.recent_orders.lock().expect("Mutex lock failed for recent orders").forget(desired.id);
+    tracing::info!(order_id = desired.id, changed = changed, "Ensure order complete");
+
+    Ok(Json(EnsureOrderResponse { changed, order: OrderView::from(&desired) }))
+}
+
+// Loads historical orders in their recorded states without matching them, for
+// migrations. All-or-nothing: any invalid or already-known order rejects the batch.
+async fn import_orders_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<Vec<ImportOrderPayload>>,
+) -> Result<Json<ImportSummary>, ApiError> {
+    check_admin(&state.config, &headers).inspect_err(|rejection| {
+        tracing::warn!(reason = %rejection, "Rejected order import request");
+    })?;
+    tracing::info!(count = payload.len(), "Received order import request");
+
+    let mut seen = std::collections::HashSet::new();
+    for record in &payload {
+        record.validate().map_err(ApiError::Unprocessable)?;
+        if !seen.insert(record.id) {
+            return Err(ApiError::Unprocessable(format!("order {} appears more than once", record.id)));
+        }
+    }
+    if let Some(max_id) = payload.iter().map(|r| r.id).max() {
+        // Keep new orders from being handed an imported id while this runs
+        state.next_order_id.fetch_max(max_id + 1, Ordering::SeqCst);
+    }
+
+    let orders: Vec<Order> = payload.iter().map(|record| {
+        let mut order = Order::with_clock(record.id, record.side.clone(), record.price, record.remaining_quantity, &*state.clock);
+        order.status = record.status.clone();
+        order.group_id = record.group_id;
+        order.paper = state.paper;
+        order.symbol = record.symbol.clone();
+        if let Some(timestamp) = record.timestamp {
+            order.timestamp = timestamp;
+            order.created_at = timestamp;
+        }
+        order
+    }).collect();
+
+    let db_conn_clone = Arc::clone(&state.db_conn);
+    let table = state.orders_table();
+    let rows: Vec<(Order, u64)> = orders.iter().cloned().zip(payload.iter().map(|r| r.original_quantity)).collect();
+    task::spawn_blocking(move || -> SqlResult<()> {
+        let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB (import)");
+        let tx = conn_guard.transaction()?;
+        for (order, original_quantity) in &rows {
+            insert_order_row(&tx, "INSERT", table, order, *original_quantity)?;
+            log_order_event(&tx, order.paper, order.id, &order.status, order.quantity, "Imported", order.created_at)?;
+        }
+        tx.commit()
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("Task join error for order import: {}", e);
+        ApiError::Internal("failed to import orders".to_string())
+    })?
+    .map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(err, _) if err.code == rusqlite::ErrorCode::ConstraintViolation => {
+            ApiError::Conflict("batch contains an order id that already exists".to_string())
+        }
+        e => {
+            tracing::error!("DB error importing orders: {}", e);
+            ApiError::Internal("failed to import orders".to_string())
+        }
+    })?;
+    {
+        let mut recent = state.recent_orders.lock().expect("Mutex lock failed for recent orders");
+        for record in &payload {
+            recent.forget(record.id);
+        }
+    }
+
+    let resting: Vec<Order> = orders
+        .into_iter()
+        .filter(|o| matches!(o.status, OrderStatus::Open | OrderStatus::PartiallyFilled))
+        .collect();
+    let resting_count = resting.len();
+    let mut by_symbol: BTreeMap<String, Vec<Order>> = BTreeMap::new();
+    for order in resting {
+        by_symbol.entry(order.symbol.clone()).or_default().push(order);
+    }
+    let mut priorities: Vec<(OrderId, u64)> = Vec::with_capacity(resting_count);
+    for (symbol, orders) in by_symbol {
+        let market = state.market_or_insert(&symbol);
+        let mut book_guard = market.order_book.lock().expect("Mutex lock failed for book import");
+        priorities.extend(orders.into_iter().map(|order| (order.id, book_guard.rest_order(order))));
+        state.on_book_change(&market, &mut book_guard);
+    }
+
+    let db_conn_clone = Arc::clone(&state.db_conn);
+    state.db_writes.run_after_queued("import priority", move || -> SqlResult<()> {
+        let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB (import priority)");
+        let tx = conn_guard.transaction()?;
+        for (id, priority) in &priorities {
+            tx.execute(&format!("UPDATE {} SET priority = ?1 WHERE id = ?2", table), params![priority, id])?;
+        }
+        tx.commit()
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("DB writer stopped before import priority update: {}", e);
+        ApiError::Internal("failed to import orders".to_string())
+    })?
+    .map_err(|e| {
+        tracing::error!("DB error recording import priorities: {}", e);
+        ApiError::Internal("failed to import orders".to_string())
+    })?;
+
+    tracing::info!(imported = payload.len(), resting = resting_count, "Order import complete");
+    Ok(Json(ImportSummary { imported: payload.len(), resting: resting_count }))
+}
+
+// Test harness: runs continuous matching once, even on a book that doesn't
+// match on entry (auction-only).
+async fn force_match_handler(
+    State(state): State<Arc<AppState>>,
+    Query(symbol): Query<SymbolQuery>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Fill>>, ApiError> {
+    check_admin(&state.config, &headers).inspect_err(|rejection| {
+        tracing::warn!(reason = %rejection, "Rejected forced match request");
+    })?;
+    let market = state.market_for(&symbol)?;
+    tracing::warn!(paper = state.paper, symbol = %market.symbol, "Forcing a match attempt");
+    let mut book_guard = market.order_book.lock().expect("Mutex lock failed for forced match");
+    let fills = book_guard.try_match();
+    state.on_book_change(&market, &mut book_guard);
+    Ok(Json(fills))
+}
+
+async fn corporate_action_handler(
+    State(state): State<Arc<AppState>>,
+    Query(symbol): Query<SymbolQuery>,
+    headers: HeaderMap,
+    Json(action): Json<CorporateAction>,
+) -> Result<Json<Vec<OrderAdjustment>>, ApiError> {
+    check_admin(&state.config, &headers).inspect_err(|rejection| {
+        tracing::warn!(reason = %rejection, "Rejected corporate action request");
+    })?;
+    action.validate().map_err(ApiError::Unprocessable)?;
+    let market = state.market_for(&symbol)?;
+    tracing::info!(symbol = %market.symbol, action = ?action, "Received corporate action");
+
+    let adjustments = {
+        let mut book_guard = market.order_book.lock().expect("Mutex lock failed for corporate action");
+        let adjustments = book_guard.apply_corporate_action(&action);
+        state.on_book_change(&market, &mut book_guard);
+        adjustments
+    };
+
+    // Order updates and their audit trail land together
+    let db_conn_clone = Arc::clone(&state.db_conn);
+    let table = state.orders_table();
+    let paper = state.paper;
+    let action_json = serde_json::to_string(&action).expect("corporate action serializes");
+    let audit = adjustments.clone();
+    state.db_writes.run_after_queued("corporate action", move || -> SqlResult<()> {
+        let mut conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB (corporate action)");
+        let tx = conn_guard.transaction()?;
+        let applied_at_nanos = now_nanos();
+        let applied_at = applied_at_nanos.to_string();
+        for adjustment in &audit {
+            let status = match adjustment.outcome {
+                AdjustmentOutcome::Adjusted => None,
+                AdjustmentOutcome::Cancelled => Some(("Cancelled", format!("{:?}", CancelReason::CorporateAction))),
+            };
+            let (status, reason) = status.unzip();
+            tx.execute(
+                &format!("UPDATE {} SET price = ?1, remaining_quantity = ?2, status = COALESCE(?3, status), cancel_reason = COALESCE(?4, cancel_reason), display_qty = ?5 WHERE id = ?6", table),
+                params![adjustment.new_price, adjustment.new_quantity, status, reason, adjustment.new_display_qty, adjustment.order_id],
+            )?;
+            // An adjusted order keeps whatever status it had
+            let status: String = tx.query_row(&format!("SELECT status FROM {} WHERE id = ?1", table), params![adjustment.order_id], |row| row.get(0))?;
+            let status = OrderStatus::from_db(&status).unwrap_or(OrderStatus::Open);
+            log_order_event(&tx, paper, adjustment.order_id, &status, adjustment.new_quantity, &format!("{:?}", CancelReason::CorporateAction), applied_at_nanos)?;
+            tx.execute(
+                "INSERT INTO corporate_action_log (paper, applied_at, action, order_id, outcome, old_price, new_price, old_quantity, new_quantity) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    paper,
+                    applied_at,
+                    action_json,
+                    adjustment.order_id,
+                    format!("{:?}", adjustment.outcome),
+                    adjustment.old_price,
+                    adjustment.new_price,
+                    adjustment.old_quantity,
+                    adjustment.new_quantity,
+                ],
+            )?;
+        }
+        tx.commit()
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("DB writer stopped before corporate action: {}", e);
+        ApiError::Internal("failed to persist corporate action".to_string())
+    })?
+    .map_err(|e| {
+        tracing::error!("DB error persisting corporate action: {}", e);
+        ApiError::Internal("failed to persist corporate action".to_string())
+    })?;
+
+    Ok(Json(adjustments))
+}
+
+async fn dr_snapshot_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<DrBundle>, ApiError> {
+    check_admin(&state.config, &headers).inspect_err(|rejection| {
+        tracing::warn!(reason = %rejection, "Rejected DR snapshot request");
+    })?;
+    tracing::info!(paper = state.paper, "Received DR snapshot request");
+    Ok(Json(state.dr_snapshot()))
+}
+
+// Restores every book in the bundle, opening those not open here
+async fn dr_restore_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(bundle): Json<DrBundle>,
+) -> Result<StatusCode, ApiError> {
+    check_admin(&state.config, &headers).inspect_err(|rejection| {
+        tracing::warn!(reason = %rejection, "Rejected DR restore request");
+    })?;
+    tracing::info!(paper = state.paper, books = bundle.books.len(), trade_seq = bundle.trade_seq, "Received DR restore request");
+    state.restore_dr_bundle(bundle)?
+        .await
+        .map_err(|e| {
+            tracing::error!("DB writer stopped before DR restore: {}", e);
+            ApiError::Internal("books restored but not persisted".to_string())
+        })?
+        .map_err(|e| {
+            tracing::error!("DB error restoring DR bundle: {}", e);
+            ApiError::Internal("books restored but not persisted".to_string())
+        })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn recovery_handler(State(state): State<Arc<AppState>>) -> Json<RecoverySummary> {
+    Json(state.recovery.clone())
+}
+
+async fn metrics_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], state.metrics.render(state.paper))
+}
+
+// Background write backlog and the writes that failed after the book moved on
+async fn db_writes_handler(State(state): State<Arc<AppState>>) -> Json<DbWriteStatus> {
+    Json(state.db_writes.status())
+}
+
+async fn volume_stats_handler(
+    State(state): State<Arc<AppState>>,
+    Query(symbol): Query<SymbolQuery>,
+) -> Result<Json<TradeTotals>, ApiError> {
+    let market = state.market_for(&symbol)?;
+    let book_guard = market.order_book.lock().expect("Mutex lock failed for volume stats");
+    Ok(Json(book_guard.totals.clone()))
+}
+
+async fn top_of_book_handler(
+    State(state): State<Arc<AppState>>,
+    Query(symbol): Query<SymbolQuery>,
+) -> Result<Response, ApiError> {
+    let market = state.market_for(&symbol)?;
+    let quote = market.order_book.lock().expect("Mutex lock failed for top of book").top_of_book();
+    Ok(([(header::CONTENT_TYPE, "application/octet-stream")], quote.encode().to_vec()).into_response())
+}
+
+fn book_snapshot(market: &Market) -> OrderBookSnapshot {
+    let book_guard = market.order_book.lock().expect("Mutex lock failed for book snapshot");
+    // Icebergs show their current slice, and nothing of being one
+    let shown = |order: &Order| OrderView { quantity: order.visible_quantity(), display_qty: None, ..OrderView::from(order) };
+    OrderBookSnapshot {
+        seq: book_guard.seq,
+        checksum: book_guard.checksum(),
+        bids: book_guard.bids.iter().map(shown).collect(),
+        asks: book_guard.asks.iter().map(shown).collect(),
+    }
+}
+
+async fn book_snapshot_handler(
+    State(state): State<Arc<AppState>>,
+    Query(symbol): Query<SymbolQuery>,
+) -> Result<Json<OrderBookSnapshot>, ApiError> {
+    tracing::info!(symbol = ?symbol.symbol, "Received book snapshot request");
+    let market = state.market_for(&symbol)?;
+    Ok(Json(book_snapshot(&market)))
+}
+
+// GET /book/:symbol, the snapshot with the symbol in the path
+async fn symbol_book_snapshot_handler(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+) -> Result<Json<OrderBookSnapshot>, ApiError> {
+    book_snapshot_handler(State(state), Query(SymbolQuery { symbol: Some(symbol) })).await
+}
+
+async fn book_deltas_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DeltaQuery>,
+    Query(symbol): Query<SymbolQuery>,
+) -> Result<Json<Vec<BookDelta>>, Response> {
+    tracing::info!(since = query.since, symbol = ?symbol.symbol, "Received book deltas request");
+    let market = state.market_for(&symbol).map_err(IntoResponse::into_response)?;
+    let log = market.delta_log.lock().expect("Mutex lock failed for delta log");
+    match log.since(query.since) {
+        Some(deltas) => Ok(Json(deltas)),
+        None => {
+            tracing::warn!(since = query.since, "Requested deltas no longer retained; client must resync");
+            Err(StatusCode::GONE.into_response())
+        }
+    }
+}
+
+async fn book_depth_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DepthQuery>,
+    Query(symbol): Query<SymbolQuery>,
+) -> Result<Json<BookSnapshot>, ApiError> {
+    tracing::debug!(depth = ?query.depth, symbol = ?symbol.symbol, "Received book depth request");
+    let market = state.market_for(&symbol)?;
+    let book_guard = market.order_book.lock().expect("Mutex lock failed for book depth");
+    let depth = query.depth.unwrap_or(usize::MAX);
+    let capped = |mut levels: Vec<PriceLevel>| {
+        levels.truncate(depth);
+        levels
+    };
+    Ok(Json(BookSnapshot {
+        seq: book_guard.seq,
+        bids: capped(book_guard.levels(&Side::Buy)),
+        asks: capped(book_guard.levels(&Side::Sell)),
+    }))
+}
+
+// Aggregated catch-up: the current state of every level touched since `since`.
+async fn level_deltas_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DeltaQuery>,
+    Query(symbol): Query<SymbolQuery>,
+) -> Result<Json<LevelDeltas>, Response> {
+    tracing::info!(since = query.since, symbol = ?symbol.symbol, "Received level deltas request");
+    let market = state.market_for(&symbol).map_err(IntoResponse::into_response)?;
+    let book_guard = market.order_book.lock().expect("Mutex lock failed for level deltas");
+    let deltas = market.delta_log.lock().expect("Mutex lock failed for delta log").since(query.since);
+    let Some(deltas) = deltas else {
+        tracing::warn!(since = query.since, "Requested deltas no longer retained; client must resync");
+        return Err(StatusCode::GONE.into_response());
+    };
+
+    let mut touched: Vec<(Side, u64)> = Vec::new();
+    for delta in &deltas {
+        let key = match delta {
+            BookDelta::Update { side, price, .. } | BookDelta::Remove { side, price, .. } => (side.clone(), *price),
+        };
+        if !touched.contains(&key) {
+            touched.push(key);
+        }
+    }
+    let levels = touched
+        .into_iter()
+        .map(|(side, price)| LevelDelta { level: book_guard.level_at(&side, price), side })
+        .collect();
+    Ok(Json(LevelDeltas {
+        since: query.since,
+        seq: book_guard.seq,
+        checksum: book_guard.level_checksum(),
+        levels,
+    }))
+}
+
+// --- Unit Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Handlers await the DB writer thread, so tests that go through AppState run on a Tokio runtime.
+    fn dummy_db_conn() -> Arc<Mutex<Connection>> {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        Arc::new(Mutex::new(conn))
+    }
+
+    fn test_state() -> Arc<AppState> {
+        test_state_with_config(Config::default())
+    }
+
+    fn test_state_with_config(config: Config) -> Arc<AppState> {
+        build_state(config, dummy_db_conn(), false).unwrap()
+    }
+
+    // A good-till-cancelled limit order for the default symbol, with every
+    // option off; tests override the rest with struct update syntax
+    fn order_payload(side: Side, price: u64, quantity: u64) -> CreateOrderPayload {
+        CreateOrderPayload { side, price, quantity, group_id: None, dnr: false, order_type: OrderType::Limit, time_in_force: TimeInForce::Gtc, account_id: None, stp: false, symbol: default_symbol(), expires_at: None, post_only: false, min_qty: None, display_qty: None }
+    }
+
+    // Trades are stamped at 1_000
+    fn test_state_with_frozen_clock(config: Config) -> Arc<AppState> {
+        build_state_with_clock(config, dummy_db_conn(), false, Arc::new(MockClock::new(1_000))).unwrap()
+    }
+
+    #[test]
+    fn test_time_weighted_spread() {
+        let mut series = SpreadSeries::default();
+        series.record(0, Some(100), Some(102)); // spread 2 for 10ns
+        series.record(10, Some(100), Some(104)); // spread 4 for 30ns
+        series.record(40, Some(100), Some(104)); // unchanged touch, not recorded
+        series.record(40, None, Some(104)); // one-sided for 20ns, excluded
+        series.record(60, Some(101), Some(102)); // spread 1 until `to`
+
+        let stats = series.time_weighted_average(0, 100);
+        assert_eq!(stats.two_sided_nanos, 80);
+        assert_eq!(stats.one_sided_nanos, 20);
+        // (2*10 + 4*30 + 1*40) / 80
+        assert_eq!(stats.average_spread, Some(180.0 / 80.0));
+
+        // Window clipped to the middle of the series
+        let stats = series.time_weighted_average(5, 20);
+        assert_eq!(stats.two_sided_nanos, 15);
+        assert_eq!(stats.average_spread, Some((2.0 * 5.0 + 4.0 * 10.0) / 15.0));
+
+        // Only the one-sided period
+        let stats = series.time_weighted_average(45, 55);
+        assert_eq!(stats.average_spread, None);
+        assert_eq!(stats.one_sided_nanos, 10);
+    }
+
+    #[test]
+    fn test_float_price_and_quantity_rejected() {
+        let ok: CreateOrderPayload = serde_json::from_str(r#"{"side":"Buy","price":100,"quantity":10}"#).unwrap();
+        assert_eq!((ok.price, ok.quantity), (100, 10));
+
+        // Bare JSON floats never reach the book as silently truncated integers
+        for body in [r#"{"side":"Buy","price":100.1,"quantity":10}"#, r#"{"side":"Buy","price":100,"quantity":1.5}"#] {
+            let err = serde_json::from_str::<CreateOrderPayload>(body).unwrap_err();
+            assert!(err.to_string().contains("floating point"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_tick_size_converts_decimals_exactly() {
+        let cents = TickSize::parse("0.01").unwrap();
+        assert_eq!(cents.to_units("100.25"), Ok(10025));
+        assert_eq!(cents.to_units("100.2"), Ok(10020));
+        assert_eq!(cents.to_units("100"), Ok(10000));
+        assert_eq!(cents.format(10025), "100.25");
+        assert_eq!(cents.format(5), "0.05");
+        // Past the 2^53 a float can hold exactly
+        assert_eq!(cents.to_units("90071992547409.93"), Ok(9_007_199_254_740_993));
+        assert_eq!(cents.format(9_007_199_254_740_993), "90071992547409.93");
+        assert!(cents.to_units("1.001").unwrap_err().contains("decimal places"));
+
+        let nickels = TickSize::parse("0.05").unwrap();
+        assert_eq!(nickels.to_string(), "0.05");
+        assert_eq!(nickels.to_units("1.15"), Ok(115));
+        assert_eq!(nickels.check(115), Ok(()));
+        assert!(nickels.check(112).unwrap_err().contains("tick size 0.05"));
+
+        for text in ["", "1.", ".5", "-1", "1e3", "1.2.3", "0"] {
+            assert!(TickSize::parse(text).is_none(), "{}", text);
+        }
+        assert_eq!(tick_size_from(Some("bogus")), TickSize::default());
+    }
+
+    #[tokio::test]
+    async fn test_decimal_price_round_trips_through_create_and_get() {
+        TEST_TICK_SIZE.with(|tick| tick.set(TickSize::parse("0.01")));
+        let state = test_state();
+        for (price, ticks) in [("100.25", 10025), ("90071992547409.93", 9_007_199_254_740_993)] {
+            let body = format!(r#"{{"side":"Buy","price":"{}","quantity":10}}"#, price);
+            let payload: CreateOrderPayload = serde_json::from_str(&body).unwrap();
+            assert_eq!(payload.price, ticks);
+            let (_, _, Json(created)) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+            assert_eq!(serde_json::to_value(&created.order).unwrap()["price"], price);
+
+            let Json(stored) = get_order_handler(State(Arc::clone(&state)), Path(created.order.id)).await.unwrap();
+            assert_eq!(stored.price, ticks);
+            assert_eq!(serde_json::to_value(&stored).unwrap()["price"], price);
+        }
+        let too_precise = serde_json::from_str::<CreateOrderPayload>(r#"{"side":"Buy","price":"100.255","quantity":10}"#).unwrap_err();
+        assert!(too_precise.to_string().contains("tick size"), "{}", too_precise);
+        TEST_TICK_SIZE.with(|tick| tick.set(None));
+    }
+
+    #[tokio::test]
+    async fn test_fractional_quantity_round_trips_at_its_own_scale() {
+        // Prices to 2 places, XYZ's quantities to 4
+        let tick = TickSize::parse("0.01").unwrap();
+        TEST_TICK_SIZE.with(|cell| cell.set(Some(tick)));
+        TEST_QUANTITY_SCALES.with(|scales| *scales.borrow_mut() = Some(HashMap::from([("XYZ".to_string(), 4)])));
+        let state = test_state_with_config(Config { tick_size: tick, ..Config::default() });
+        let order = |side: &str, quantity: &str| -> CreateOrderPayload {
+            serde_json::from_str(&format!(r#"{{"side":"{}","price":"100.25","quantity":"{}","symbol":"XYZ"}}"#, side, quantity)).unwrap()
+        };
+
+        let ask = order("Sell", "1.2345");
+        assert_eq!((ask.price, ask.quantity), (10025, 12345));
+        let (_, _, Json(resting)) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(ask)).await.unwrap();
+        assert_eq!(serde_json::to_value(&resting.order).unwrap()["quantity"], "1.2345");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(FILL_REPORT_HEADER, "both".parse().unwrap());
+        let (_, _, Json(taken)) = create_order_handler(State(Arc::clone(&state)), headers, Json(order("Buy", "0.5"))).await.unwrap();
+        let json = serde_json::to_value(&taken).unwrap();
+        assert_eq!(json["fills"][0]["quantity"], "0.5000");
+        assert_eq!(json["order"]["quantity"], "0.0000");
+        // 100.25 * 0.5000, exact at 2 + 4 places
+        assert_eq!(json["execution"]["notional"], "50.125000");
+        assert_eq!(json["execution"]["vwap"], "100.25000000");
+
+        let Json(left) = get_order_handler(State(Arc::clone(&state)), Path(resting.order.id)).await.unwrap();
+        assert_eq!((left.quantity, serde_json::to_value(&left).unwrap()["quantity"].clone()), (7345, "0.7345".into()));
+        // Whole numbers still mean whole units; finer than the scale is refused
+        assert_eq!(serde_json::from_str::<CreateOrderPayload>(r#"{"side":"Buy","price":1,"quantity":2,"symbol":"XYZ"}"#).unwrap().quantity, 20000);
+        let too_fine = serde_json::from_str::<CreateOrderPayload>(r#"{"side":"Buy","price":1,"quantity":"0.00001","symbol":"XYZ"}"#).unwrap_err();
+        assert!(too_fine.to_string().contains("more decimal places than the quantity scale 4 for XYZ"), "{}", too_fine);
+        TEST_QUANTITY_SCALES.with(|scales| *scales.borrow_mut() = None);
+        TEST_TICK_SIZE.with(|cell| cell.set(None));
+    }
+
+    #[tokio::test]
+    async fn test_off_tick_price_rejected() {
+        let state = test_state_with_config(Config { tick_size: TickSize::parse("0.05").unwrap(), ..Config::default() });
+        let payload = |price, order_type| CreateOrderPayload { order_type, ..order_payload(Side::Buy, price, 10) };
+
+        let err = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload(10012, OrderType::Limit))).await.unwrap_err();
+        assert_eq!(err, ApiError::BadRequest("price 100.12 is not a multiple of the tick size 0.05".to_string()));
+        assert!(state.default_market.order_book.lock().unwrap().bids.is_empty());
+
+        let (status, _, _) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload(10015, OrderType::Limit))).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        // Market orders carry no price to check
+        let (status, _, _) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload(10012, OrderType::Market))).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_zero_quantity_or_limit_price_rejected() {
+        let state = test_state();
+        let payload = |price, quantity, order_type| CreateOrderPayload { order_type, ..order_payload(Side::Buy, price, quantity) };
+
+        let err = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload(100, 0, OrderType::Limit))).await.unwrap_err();
+        assert!(matches!(&err, ApiError::BadRequest(message) if message.contains("quantity")), "{:?}", err);
+        let err = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload(0, 10, OrderType::Limit))).await.unwrap_err();
+        assert!(matches!(&err, ApiError::BadRequest(message) if message.contains("price")), "{:?}", err);
+        assert!(state.default_market.order_book.lock().unwrap().bids.is_empty());
+
+        // Market orders don't need a price, but still need a quantity
+        let err = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload(0, 0, OrderType::Market))).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)), "{:?}", err);
+        let (status, _, _) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload(0, 10, OrderType::Market))).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_create_order_response_lists_fills() {
+        let state = test_state_with_frozen_clock(Config::default());
+        let submit = |side, price, quantity| {
+            let payload = order_payload(side, price, quantity);
+            create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload))
+        };
+        for (side, price, quantity) in [(Side::Sell, 101, 3), (Side::Sell, 102, 4)] {
+            let (_, _, Json(rested)) = submit(side, price, quantity).await.unwrap();
+            assert!(rested.fills.unwrap().is_empty());
+        }
+
+        let (_, _, Json(response)) = submit(Side::Buy, 102, 5).await.unwrap();
+        assert_eq!(response.order.id, 3);
+        assert_eq!(response.fills, Some(vec![
+            OrderFill { counter_order_id: 1, price: 101, quantity: 3, quantity_scale: 0, trade_seq: 1, executed_at: 1_000 },
+            OrderFill { counter_order_id: 2, price: 102, quantity: 2, quantity_scale: 0, trade_seq: 2, executed_at: 1_000 },
+        ]));
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["fills"][0], serde_json::json!({"counter_order_id": 1, "price": 101, "quantity": 3, "trade_seq": 1, "executed_at": 1_000}));
+        assert_eq!(json["order"]["id"], 3);
+    }
+
+    #[tokio::test]
+    async fn test_fill_report_summary_nets_a_sweep() {
+        let state = test_state_with_frozen_clock(Config::default());
+        let submit = |side, price, quantity, report: &'static str| {
+            let payload = order_payload(side, price, quantity);
+            let mut headers = HeaderMap::new();
+            headers.insert(FILL_REPORT_HEADER, HeaderValue::from_static(report));
+            create_order_handler(State(Arc::clone(&state)), headers, Json(payload))
+        };
+        for (price, quantity) in [(100, 1), (101, 2), (103, 3)] {
+            let _ = submit(Side::Sell, price, quantity, "fills").await.unwrap();
+        }
+
+        // Three levels swept, reported as one execution
+        let (_, _, Json(response)) = submit(Side::Buy, 103, 6, "summary").await.unwrap();
+        assert_eq!(response.order.status, OrderStatus::Filled);
+        assert_eq!(response.execution, Some(ExecutionSummary { fill_count: 3, quantity: Fixed { units: 6, scale: 0 }, notional: Fixed { units: 100 + 202 + 309, scale: 0 }, vwap: Some(Fixed { units: 101_833_333, scale: 6 }) }));
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["execution"]["vwap"], "101.833333");
+        assert!(json.get("fills").is_none(), "{}", json);
+
+        // Both: the summary alongside each print; nothing traded, nothing to average
+        let _ = submit(Side::Sell, 105, 2, "fills").await.unwrap();
+        let (_, _, Json(both)) = submit(Side::Buy, 105, 2, "both").await.unwrap();
+        assert_eq!(both.fills.map(|fills| fills.len()), Some(1));
+        assert_eq!(both.execution.map(|execution| execution.vwap), Some(Some(Fixed { units: 105_000_000, scale: 6 })));
+        let (_, _, Json(rested)) = submit(Side::Buy, 90, 1, "summary").await.unwrap();
+        assert_eq!(rested.execution, Some(ExecutionSummary { fill_count: 0, quantity: Fixed { units: 0, scale: 0 }, notional: Fixed { units: 0, scale: 0 }, vwap: None }));
+
+        let err = submit(Side::Buy, 90, 1, "net").await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_order_response_exposes_only_public_fields() {
+        let state = test_state();
+        let payload = order_payload(Side::Buy, 100, 10);
+        let (_, _, Json(CreateOrderResponse { order: view, .. })) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+
+        let json = serde_json::to_value(&view).unwrap();
+        let mut keys: Vec<&str> = json.as_object().unwrap().keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        assert_eq!(keys, vec!["id", "order_type", "price", "quantity", "side", "status", "symbol", "time_in_force"]);
+
+        let grouped = OrderView::from(&Order { group_id: Some(7), ..Order::new(2, Side::Sell, 101, 5) });
+        let json = serde_json::to_value(&grouped).unwrap();
+        assert_eq!(json["group_id"], 7);
+        assert!(json.get("timestamp").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ack_seq_strictly_increasing() {
+        let state = test_state();
+        let mut acks = Vec::new();
+
+        for (side, price) in [(Side::Buy, 100), (Side::Sell, 105), (Side::Buy, 101)] {
+            let payload = order_payload(side, price, 10);
+            let (status, ack, _) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+            assert_eq!(status, StatusCode::CREATED);
+            acks.push(ack);
+        }
+        let (ack, _) = modify_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 5.into(), price: None }))
+            .await
+            .unwrap();
+        acks.push(ack);
+        let (ack, _) = cancel_order_handler(State(Arc::clone(&state)), Path(2), HeaderMap::new()).await.unwrap();
+        acks.push(ack);
+
+        // A rejected operation doesn't consume a sequence number
+        assert!(cancel_order_handler(State(Arc::clone(&state)), Path(999), HeaderMap::new()).await.is_err());
+        let (ack, _) = cancel_order_handler(State(Arc::clone(&state)), Path(3), HeaderMap::new()).await.unwrap();
+        acks.push(ack);
+
+        assert_eq!(acks.len(), 6);
+        assert!(acks.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(acks.last().unwrap().0, 6);
+    }
+
+    #[test]
+    fn test_delta_log_requires_resync_when_evicted() {
+        let mut log = DeltaLog::default();
+        let remove = |seq| BookDelta::Remove { seq, order_id: seq, side: Side::Buy, price: 100 };
+        log.extend((1..=DELTA_LOG_CAPACITY as u64 + 2).map(remove).collect());
+
+        assert!(log.since(0).is_none());
+        assert!(log.since(1).is_none());
+        assert_eq!(log.since(2).unwrap().len(), DELTA_LOG_CAPACITY);
+        assert!(log.since(DELTA_LOG_CAPACITY as u64 + 2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_session_phases() {
+        let schedule = SessionSchedule::parse("09:30-16:00", Some("09:00"), -300).unwrap();
+        let at = |h: u128, m: u128| ((h * 3600 + m * 60) * 1_000_000_000) + 19_000 * 86_400 * 1_000_000_000;
+        // UTC-5: 09:30 local is 14:30 UTC
+        assert_eq!(schedule.phase_at(at(13, 59)), SessionPhase::Closed);
+        assert_eq!(schedule.phase_at(at(14, 0)), SessionPhase::PreOpen);
+        assert_eq!(schedule.phase_at(at(14, 30)), SessionPhase::Open);
+        assert_eq!(schedule.phase_at(at(20, 59)), SessionPhase::Open);
+        assert_eq!(schedule.phase_at(at(21, 0)), SessionPhase::Closed);
+
+        assert!(SessionSchedule::parse("16:00-09:30", None, 0).is_none());
+        assert!(SessionSchedule::parse("09:30", None, 0).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_order_entry_only_during_symbol_session() {
+        let clock = Arc::new(MockClock::new(0));
+        let config = Config {
+            session: SessionSchedule::parse("09:30-16:00", Some("09:00"), 0),
+            symbol_sessions: HashMap::from([("NIGHT".to_string(), SessionSchedule::parse("20:00-23:00", None, 0).unwrap())]),
+            ..Config::default()
+        };
+        let state = build_state_with_clock(config, dummy_db_conn(), false, clock.clone()).unwrap();
+        let at = |h: u64, m: u64| (h * 3600 + m * 60) * 1_000_000_000;
+        let create = |symbol: &str| {
+            let payload = CreateOrderPayload { symbol: symbol.to_string(), ..order_payload(Side::Buy, 100, 1) };
+            create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload))
+        };
+        let session = |symbol: &str| session_handler(State(Arc::clone(&state)), Query(SymbolQuery { symbol: Some(symbol.to_string()) }));
+
+        clock.set(at(9, 15));
+        let err = create(DEFAULT_SYMBOL).await.unwrap_err();
+        assert_eq!(err, ApiError::Forbidden(format!("order entry for {} is not accepted while the session is PreOpen", DEFAULT_SYMBOL)));
+        assert!(state.default_market.order_book.lock().unwrap().bids.is_empty());
+
+        clock.set(at(9, 30));
+        let (status, _, _) = create(DEFAULT_SYMBOL).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        // Another symbol keeps its own hours
+        assert!(matches!(create("NIGHT").await, Err(ApiError::Forbidden(_))));
+        assert_eq!(session("NIGHT").await.unwrap().phase, SessionPhase::Closed);
+
+        clock.set(at(20, 0));
+        assert!(create("NIGHT").await.is_ok());
+        assert_eq!(session("NIGHT").await.unwrap().phase, SessionPhase::Open);
+        assert_eq!(session(DEFAULT_SYMBOL).await.unwrap().phase, SessionPhase::Closed);
+        assert!(matches!(create(DEFAULT_SYMBOL).await, Err(ApiError::Forbidden(_))));
+    }
+
+    #[tokio::test]
+    async fn test_drain_waits_for_background_fill_writes() {
+        let mut book = OrderBook::new();
+        let db_conn = dummy_db_conn();
+        let tracker = DbWrites::default();
+        // Writes queue behind this thread's hold on the DB until it's released
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let holder = {
+            let db_conn = Arc::clone(&db_conn);
+            std::thread::spawn(move || {
+                let _conn_guard = db_conn.lock().unwrap();
+                locked_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+            })
+        };
+        locked_rx.recv().unwrap();
+        for id in [1, 3] {
+            book.add_order(Order::new(id, Side::Sell, 100, 5));
+            book.add_order(Order::new(id + 1, Side::Buy, 100, 5));
+        }
+        tracker.persist(&db_conn, false, book.take_writes());
+        assert_eq!(tracker.pending(), 2);
+
+        let draining = {
+            let tracker = tracker.clone();
+            tokio::spawn(async move { tracker.drain().await })
+        };
+        tokio::task::yield_now().await;
+        release_tx.send(()).unwrap();
+        holder.join().unwrap();
+        assert_eq!(draining.await.unwrap(), 2);
+        assert_eq!(tracker.pending(), 0);
+        let trades: i64 = db_conn.lock().unwrap().query_row("SELECT COUNT(*) FROM trades", [], |row| row.get(0)).unwrap();
+        assert_eq!(trades, 2);
+        // Nothing pending returns at once
+        assert_eq!(tracker.drain().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_fill_writes_commit_in_book_order() {
+        let state = test_state();
+        let limit = |side, quantity| order_payload(side, 100, quantity);
+        let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(limit(Side::Sell, 20))).await.unwrap();
+        for _ in 0..20 {
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(limit(Side::Buy, 1))).await.unwrap();
+        }
+        state.db_writes.drain().await;
+
+        // Twenty fills on one order, journaled and applied one after the other
+        let Json(history) = order_history_handler(State(Arc::clone(&state)), Path(1)).await.unwrap();
+        let remaining: Vec<u64> = history.iter().skip(1).map(|event| event.remaining_quantity).collect();
+        assert_eq!(remaining, (0..20).rev().collect::<Vec<u64>>());
+        let conn = state.db_conn.lock().unwrap();
+        let row: (u64, String) = conn.query_row("SELECT remaining_quantity, status FROM orders WHERE id = 1", [], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        assert_eq!(row, (0, "Filled".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_failed_match_write_is_recorded_not_panicked() {
+        let state = test_state();
+        // No schema: every write through this connection fails
+        let broken = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        {
+            let mut book = state.default_market.order_book.lock().unwrap();
+            book.add_order(Order::new(1, Side::Sell, 100, 5));
+            book.add_order(Order::new(2, Side::Buy, 100, 3));
+            state.db_writes.persist(&broken, false, book.take_writes());
+            // Memory has moved on regardless
+            assert_eq!(book.asks.front().unwrap().quantity, 2);
+        }
+        state.db_writes.drain().await;
+
+        let Json(status) = db_writes_handler(State(Arc::clone(&state))).await;
+        assert_eq!((status.pending, status.failed_total), (0, 1));
+        let failure = &status.recent_failures[0];
+        assert_eq!((failure.write, failure.detail.as_str()), ("match", "trade 1: bid 2 left 0, ask 1 left 2"));
+        assert!(failure.error.contains("no such table"), "{}", failure.error);
+        // The connection is still usable afterwards
+        assert!(!broken.is_poisoned());
+    }
+
+    #[tokio::test]
+    async fn test_create_commits_order_and_trades_before_responding() {
+        let state = test_state();
+        let create = |side: Side, quantity: u64| {
+            let payload = order_payload(side, 100, quantity);
+            create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload))
+        };
+        let _ = create(Side::Sell, 10).await.unwrap();
+        let (status, _, Json(response)) = create(Side::Buy, 4).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!((response.order.status, response.order.quantity), (OrderStatus::Filled, 0));
+        assert_eq!(response.fills.unwrap().iter().map(|fill| fill.quantity).collect::<Vec<_>>(), vec![4]);
+
+        // Read straight away, without waiting on the writer
+        {
+            let conn = state.db_conn.lock().unwrap();
+            let trade: (u64, u64, u64) = conn.query_row("SELECT bid_id, ask_id, quantity FROM trades", [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap();
+            assert_eq!(trade, (2, 1, 4));
+            let mut stmt = conn.prepare("SELECT id, status, remaining_quantity FROM orders ORDER BY id").unwrap();
+            let rows: Vec<(u64, String, u64)> = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap().map(Result::unwrap).collect();
+            assert_eq!(rows, vec![(1, "PartiallyFilled".to_string(), 6), (2, "Filled".to_string(), 0)]);
+        }
+
+        // All or nothing: when the trade can't be written, neither is the order
+        state.db_conn.lock().unwrap().execute("DROP TABLE trades", []).unwrap();
+        let keyed = || {
+            let payload = order_payload(Side::Buy, 100, 1);
+            let mut headers = HeaderMap::new();
+            headers.insert(IDEMPOTENCY_KEY_HEADER, "retry-me".parse().unwrap());
+            create_order_handler(State(Arc::clone(&state)), headers, Json(payload))
+        };
+        let rows = || state.db_conn.lock().unwrap().query_row("SELECT COUNT(*) FROM orders WHERE id = 3", [], |row| row.get::<_, i64>(0)).unwrap();
+        let (status, _, Json(response)) = keyed().await.unwrap();
+        assert_eq!(rows(), 0);
+        // The failure is tallied just after the handler hears of it
+        state.db_writes.drain().await;
+        assert_eq!(state.db_writes.status().failed_total, 1);
+        // But it traded in the book, so it is answered as live rather than failed
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!((response.order.id, response.order.status), (3, OrderStatus::Filled));
+        assert_eq!(state.default_market.order_book.lock().unwrap().asks.front().unwrap().quantity, 5);
+
+        // A retry gets that order back instead of placing another
+        let (status, _, Json(retried)) = keyed().await.unwrap();
+        assert_eq!((status, retried.order.id), (StatusCode::OK, 3));
+        assert_eq!(state.next_order_id.load(Ordering::SeqCst), 4);
+        assert_eq!(state.default_market.order_book.lock().unwrap().asks.front().unwrap().quantity, 5);
+    }
+
+    #[tokio::test]
+    async fn test_trade_orders_show_both_sides_as_of_the_trade() {
+        let state = test_state_with_frozen_clock(Config::default());
+        let create = |side: Side, price: u64, quantity: u64| {
+            let payload = order_payload(side, price, quantity);
+            create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload))
+        };
+        let _ = create(Side::Sell, 100, 10).await.unwrap();
+        let _ = create(Side::Buy, 100, 4).await.unwrap();
+        let _ = create(Side::Buy, 100, 6).await.unwrap();
+        let trade_orders = |trade_id: u64| trade_orders_handler(State(Arc::clone(&state)), Path(trade_id), Query(SymbolQuery::default()));
+        let summary = |side: &Option<TradeOrderState>| side.as_ref().map(|s| (s.order.id, s.order.status.clone(), s.order.quantity, s.role, s.recorded));
+
+        // The maker was only part filled by the first trade, though filled now
+        let Json(first) = trade_orders(1).await.unwrap();
+        assert_eq!((first.trade.bid_id, first.trade.ask_id, first.trade.quantity), (2, 1, 4));
+        assert_eq!(summary(&first.ask), Some((1, OrderStatus::PartiallyFilled, 6, Some(TradeRole::Maker), true)));
+        assert_eq!(summary(&first.bid), Some((2, OrderStatus::Filled, 0, Some(TradeRole::Taker), true)));
+        assert_eq!(first.ask.as_ref().unwrap().order.price, 100);
+        let Json(second) = trade_orders(2).await.unwrap();
+        assert_eq!(summary(&second.ask), Some((1, OrderStatus::Filled, 0, Some(TradeRole::Maker), true)));
+
+        // A fill the DB declined because both orders were already cancelled
+        let _ = create(Side::Sell, 105, 5).await.unwrap();
+        let _ = create(Side::Buy, 95, 5).await.unwrap();
+        for id in [4, 5] {
+            let _ = cancel_order_handler(State(Arc::clone(&state)), Path(id), HeaderMap::new()).await.unwrap();
+        }
+        let fill = Fill { trade_id: 3, trade_seq: 3, executed_at: 1_000, symbol: default_symbol(), bid_id: 5, ask_id: 4, price: 100, quantity: 5 };
+        let late = BookWrite::Match { fill, bid_left: 0, bid_status: OrderStatus::Filled, ask_left: 0, ask_status: OrderStatus::Filled, bid_rested: true };
+        state.db_writes.persist(&state.db_conn, false, vec![late]);
+        state.db_writes.drain().await;
+        let Json(declined) = trade_orders(3).await.unwrap();
+        assert_eq!(summary(&declined.bid), Some((5, OrderStatus::Cancelled, 0, Some(TradeRole::Maker), false)));
+        assert_eq!(summary(&declined.ask), Some((4, OrderStatus::Cancelled, 0, Some(TradeRole::Taker), false)));
+
+        let err = trade_orders(99).await.unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_account_activity_ratios_from_order_events() {
+        let state = test_state_with_frozen_clock(Config::default());
+        let create = |account_id, side, price, quantity| {
+            let payload = CreateOrderPayload { account_id: Some(account_id), ..order_payload(side, price, quantity) };
+            create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload))
+        };
+        let cancel = |id| cancel_order_handler(State(Arc::clone(&state)), Path(id), HeaderMap::new());
+        let activity = |account_id, to| account_activity_handler(State(Arc::clone(&state)), Path(account_id), Query(WindowQuery { from: None, to }));
+
+        // Account 7: one order filled, one cancelled untouched, one cancelled after a partial fill, one resting
+        let _ = create(7, Side::Sell, 100, 5).await.unwrap();
+        let _ = create(8, Side::Buy, 100, 5).await.unwrap();
+        let _ = create(7, Side::Sell, 105, 3).await.unwrap();
+        let _ = cancel(3).await.unwrap();
+        let _ = create(7, Side::Sell, 106, 4).await.unwrap();
+        let _ = create(8, Side::Buy, 106, 1).await.unwrap();
+        let _ = cancel(4).await.unwrap();
+        let _ = create(7, Side::Buy, 90, 1).await.unwrap();
+        state.db_writes.drain().await;
+
+        let Json(quoter) = activity(7, None).await.unwrap();
+        assert_eq!((quoter.placed, quoter.filled, quoter.cancelled), (4, 1, 2));
+        assert_eq!((quoter.fill_ratio, quoter.cancel_ratio), (Some(0.25), Some(0.5)));
+        let Json(taker) = activity(8, None).await.unwrap();
+        assert_eq!((taker.placed, taker.filled, taker.cancelled, taker.fill_ratio), (2, 2, 0, Some(1.0)));
+
+        // Everything happened at 1_000
+        let Json(before) = activity(7, Some(999)).await.unwrap();
+        assert_eq!((before.placed, before.fill_ratio, before.cancel_ratio), (0, None, None));
+    }
+
+    #[tokio::test]
+    async fn test_suspended_account_is_swept_and_refused_until_unsuspended() {
+        let config = Config { admin_token: Some("s3cret".to_string()), ..Config::default() };
+        let state = test_state_with_config(config.clone());
+        let create = |account_id, symbol: &str| {
+            let payload = CreateOrderPayload { account_id: Some(account_id), symbol: symbol.to_string(), ..order_payload(Side::Buy, 100, 5) };
+            create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload))
+        };
+        let _ = create(7, DEFAULT_SYMBOL).await.unwrap();
+        let _ = create(7, "XYZ").await.unwrap();
+        let _ = create(8, "XYZ").await.unwrap();
+
+        assert!(matches!(suspend_account_handler(State(Arc::clone(&state)), Path(7), HeaderMap::new()).await.unwrap_err(), ApiError::Unauthorized(_)));
+        let Json(suspension) = suspend_account_handler(State(Arc::clone(&state)), Path(7), admin_headers("s3cret")).await.unwrap();
+        assert_eq!(suspension.cancelled, vec![1, 2]);
+        assert!(state.market("XYZ").unwrap().order_book.lock().unwrap().find_order(3).is_some());
+        for id in [1, 2] {
+            assert!(state.market_of(id).is_none());
+            let reason: String = state.db_conn.lock().unwrap()
+                .query_row("SELECT cancel_reason FROM orders WHERE id = ?1 AND status = 'Cancelled'", params![id], |row| row.get(0))
+                .unwrap();
+            assert_eq!(reason, "Suspension");
+        }
+
+        assert!(matches!(create(7, "XYZ").await.unwrap_err(), ApiError::Forbidden(_)));
+        let _ = create(8, "XYZ").await.unwrap();
+        // Survives a restart
+        let restarted = build_state_with_clock(config, Arc::clone(&state.db_conn), false, Arc::clone(&state.clock)).unwrap();
+        assert!(restarted.is_suspended(Some(7)));
+
+        let Json(unsuspension) = unsuspend_account_handler(State(Arc::clone(&state)), Path(7), admin_headers("s3cret")).await.unwrap();
+        assert!(!unsuspension.suspended);
+        let (status, _, Json(response)) = create(7, "XYZ").await.unwrap();
+        assert_eq!((status, response.order.status), (StatusCode::CREATED, OrderStatus::Open));
+    }
+
+    #[tokio::test]
+    async fn test_only_the_owning_account_cancels_or_modifies_an_order() {
+        let state = test_state_with_config(Config { account_ownership: AccountOwnership::Forbid, ..Config::default() });
+        let as_account = |account_id: u64| {
+            let mut headers = HeaderMap::new();
+            headers.insert(ACCOUNT_ID_HEADER, account_id.to_string().parse().unwrap());
+            headers
+        };
+        let create = |price| {
+            let payload = CreateOrderPayload { account_id: Some(7), ..order_payload(Side::Buy, price, 5) };
+            create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload))
+        };
+        let _ = create(100).await.unwrap();
+        let _ = create(101).await.unwrap();
+
+        let refused = cancel_order_handler(State(Arc::clone(&state)), Path(1), as_account(8)).await.unwrap_err();
+        assert_eq!(refused.status(), StatusCode::FORBIDDEN);
+        let refused = cancel_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new()).await.unwrap_err();
+        assert_eq!(refused.status(), StatusCode::FORBIDDEN);
+        let modify = || Json(ModifyOrderPayload { quantity: 2.into(), price: None });
+        assert!(matches!(
+            modify_order_handler(State(Arc::clone(&state)), Path(1), as_account(8), modify()).await.unwrap_err(),
+            ApiError::Forbidden(_)
+        ));
+        // Refused before the book was touched
+        assert_eq!(state.default_market.order_book.lock().unwrap().find_order(1).unwrap().quantity, 5);
+
+        let (_, Json(modified)) = modify_order_handler(State(Arc::clone(&state)), Path(1), as_account(7), modify()).await.unwrap();
+        assert_eq!(modified.order.quantity, 2);
+        let (_, Json(cancelled)) = cancel_order_handler(State(Arc::clone(&state)), Path(1), as_account(7)).await.unwrap();
+        assert_eq!(cancelled.status, OrderStatus::Cancelled);
+
+        // Configured to hide: another account's orders, live or gone, look absent
+        let state = test_state_with_config(Config { account_ownership: AccountOwnership::Hide, ..Config::default() });
+        let create = |price| {
+            let payload = CreateOrderPayload { account_id: Some(7), ..order_payload(Side::Buy, price, 5) };
+            create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload))
+        };
+        let _ = create(100).await.unwrap();
+        let _ = create(101).await.unwrap();
+        let _ = cancel_order_handler(State(Arc::clone(&state)), Path(1), as_account(7)).await.unwrap();
+        for id in [1, 2] {
+            let refused = cancel_order_handler(State(Arc::clone(&state)), Path(id), as_account(8)).await.unwrap_err();
+            assert_eq!(refused.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_over_precise_prices_are_rejected_or_rounded_away_from_crossing() {
+        // Prices at four decimal places, XYZ quoted to two
+        let config = |price_policy| Config {
+            tick_size: TickSize::parse("0.0001").unwrap(),
+            price_precisions: HashMap::from([("XYZ".to_string(), 2)]),
+            price_policy,
+            ..Config::default()
+        };
+        let create = |state: &Arc<AppState>, side, price| {
+            let payload = CreateOrderPayload { symbol: "XYZ".to_string(), ..order_payload(side, price, 1) };
+            create_order_handler(State(Arc::clone(state)), HeaderMap::new(), Json(payload))
+        };
+
+        let rejecting = test_state_with_config(config(PricePolicy::Reject));
+        let ApiError::BadRequest(message) = create(&rejecting, Side::Buy, 1_001_234).await.unwrap_err() else { panic!("expected a 400") };
+        assert!(message.contains("100.1234 has more decimal places than XYZ's precision of 2"), "{}", message);
+        let (_, _, Json(exact)) = create(&rejecting, Side::Buy, 1_001_200).await.unwrap();
+        assert_eq!(exact.order.price, 1_001_200);
+
+        // 100.1234 rounds to 100.12 to buy and 100.13 to sell: both less
+        // aggressive, so the pair still doesn't cross
+        let rounding = test_state_with_config(config(PricePolicy::Round));
+        let (_, _, Json(bid)) = create(&rounding, Side::Buy, 1_001_234).await.unwrap();
+        let (_, _, Json(ask)) = create(&rounding, Side::Sell, 1_001_234).await.unwrap();
+        assert_eq!((bid.order.price, ask.order.price), (1_001_200, 1_001_300));
+        assert_eq!(ask.order.status, OrderStatus::Open);
+        assert!(matches!(create(&rounding, Side::Buy, 34).await.unwrap_err(), ApiError::BadRequest(_)));
+
+        let (_, Json(repriced)) = modify_order_handler(State(Arc::clone(&rounding)), Path(bid.order.id), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 1.into(), price: Some(1_001_099) }))
+            .await
+            .unwrap();
+        assert_eq!(repriced.order.price, 1_001_000);
+        assert_eq!(rounding.db_conn.lock().unwrap().query_row("SELECT price FROM orders WHERE id = ?1", params![bid.order.id], |row| row.get::<_, u64>(0)).unwrap(), 1_001_000);
+    }
+
+    #[tokio::test]
+    async fn test_recent_terminal_orders_are_served_from_cache() {
+        let state = test_state_with_config(Config { recent_orders_cache: Some(2), ..Config::default() });
+        let create = |side| {
+            let payload = order_payload(side, 100, 1);
+            create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload))
+        };
+        let get = |id| get_order_handler(State(Arc::clone(&state)), Path(id));
+        // Two trades, four filled orders; only the last two fit in the cache
+        for side in [Side::Sell, Side::Buy, Side::Sell, Side::Buy] {
+            let _ = create(side).await.unwrap();
+        }
+
+        // Evicted: read from the DB
+        let Json(first) = get(1).await.unwrap();
+        assert_eq!((first.status, first.quantity), (OrderStatus::Filled, 0));
+
+        // With the orders table gone, only the cache can answer
+        state.db_conn.lock().unwrap().execute("DROP TABLE orders", []).unwrap();
+        let Json(last) = get(4).await.unwrap();
+        assert_eq!((last.id, last.side, last.status, last.quantity), (4, Side::Buy, OrderStatus::Filled, 0));
+        assert_eq!(get(3).await.unwrap().0.status, OrderStatus::Filled);
+        assert!(matches!(get(1).await.unwrap_err(), ApiError::Internal(_)));
+
+        let metrics = state.metrics.render(false);
+        assert!(metrics.contains("oms_order_cache_lookups_total{book=\"live\",result=\"hit\"} 2\n"), "{}", metrics);
+        assert!(metrics.contains("oms_order_cache_lookups_total{book=\"live\",result=\"miss\"} 2\n"), "{}", metrics);
+        assert!(metrics.contains("oms_order_cache_hit_ratio{book=\"live\"} 0.5\n"), "{}", metrics);
+    }
+
+    #[tokio::test]
+    async fn test_trade_window_stats_use_the_time_index() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let state = build_state_with_clock(Config::default(), dummy_db_conn(), false, clock.clone()).unwrap();
+        let create = |side: Side, price: u64, quantity: u64| {
+            let payload = order_payload(side, price, quantity);
+            create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload))
+        };
+        for (at, price, quantity) in [(1_000, 100, 2), (2_000, 102, 1), (3_000, 99, 3)] {
+            clock.set(at);
+            let _ = create(Side::Sell, price, quantity).await.unwrap();
+            let _ = create(Side::Buy, price, quantity).await.unwrap();
+        }
+
+        let Json(stats) = trade_window_stats_handler(State(Arc::clone(&state)), Query(WindowQuery { from: Some(1_500), to: None }), Query(SymbolQuery::default()))
+            .await
+            .unwrap();
+        assert_eq!((stats.from, stats.to), (1_500, 3_000));
+        assert_eq!((stats.open, stats.high, stats.low, stats.close), (Some(102), Some(102), Some(99), Some(99)));
+        assert_eq!((stats.totals.trade_count, stats.totals.volume, stats.totals.notional), (2, 4, 102 + 99 * 3));
+
+        let err = trade_window_stats_handler(State(Arc::clone(&state)), Query(WindowQuery { from: Some(5), to: Some(4) }), Query(SymbolQuery::default()))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)), "{:?}", err);
+
+        // The window query is answered from the index, not a table scan
+        let conn = state.db_conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", trades_between_sql(TRADES_TABLE))).unwrap();
+        let plan: Vec<String> = stmt.query_map(params![false, default_symbol(), 0i64, 10i64], |row| row.get(3)).unwrap().map(Result::unwrap).collect();
+        assert!(plan.iter().any(|step| step.contains("trades_by_symbol_time")), "{:?}", plan);
+    }
+
+    #[tokio::test]
+    async fn test_dr_bundle_restores_identical_state() {
+        let config = Config { admin_token: Some("s3cret".to_string()), ..Config::default() };
+        let source = test_state_with_config(config.clone());
+        let orders = [
+            ("DEFAULT", 1, Side::Buy, 99, 10),
+            ("DEFAULT", 2, Side::Buy, 100, 5),
+            ("DEFAULT", 3, Side::Sell, 101, 7),
+            ("DEFAULT", 3, Side::Sell, 100, 2),
+            ("ABC", 1, Side::Sell, 50, 4),
+            ("ABC", 2, Side::Buy, 50, 3),
+        ];
+        for (symbol, account, side, price, quantity) in orders {
+            let payload = CreateOrderPayload { account_id: Some(account), symbol: symbol.to_string(), ..order_payload(side, price, quantity) };
+            let _ = create_order_handler(State(Arc::clone(&source)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+
+        let err = dr_snapshot_handler(State(Arc::clone(&source)), HeaderMap::new()).await.unwrap_err();
+        assert!(matches!(err, ApiError::Unauthorized(_)), "{:?}", err);
+        let Json(bundle) = dr_snapshot_handler(State(Arc::clone(&source)), admin_headers("s3cret")).await.unwrap();
+        // One bundle for every book, marked at the global trade sequence
+        assert_eq!((bundle.trade_seq, bundle.next_order_id), (3, 7));
+        let derived: Vec<_> = bundle.books.iter().map(|book| (book.symbol.as_str(), book.last_price, book.positions.clone())).collect();
+        assert_eq!(derived, vec![
+            ("ABC", Some(50), BTreeMap::from([(1, -3), (2, 3)])),
+            ("DEFAULT", Some(100), BTreeMap::from([(2, 2), (3, -2)])),
+        ]);
+        let wire = serde_json::to_string(&bundle).unwrap();
+
+        let target = test_state_with_config(config.clone());
+        let err = dr_restore_handler(State(Arc::clone(&target)), HeaderMap::new(), Json(serde_json::from_str(&wire).unwrap())).await.unwrap_err();
+        assert!(matches!(err, ApiError::Unauthorized(_)), "{:?}", err);
+        let status = dr_restore_handler(State(Arc::clone(&target)), admin_headers("s3cret"), Json(serde_json::from_str(&wire).unwrap()))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let restored = target.dr_snapshot();
+        assert_eq!((restored.trade_seq, restored.next_order_id), (bundle.trade_seq, bundle.next_order_id));
+        assert_eq!(serde_json::to_value(&restored.books).unwrap(), serde_json::to_value(&bundle.books).unwrap());
+        for symbol in ["ABC", "DEFAULT"] {
+            let (src, dst) = (source.market(symbol).unwrap(), target.market(symbol).unwrap());
+            let (src, dst) = (src.order_book.lock().unwrap(), dst.order_book.lock().unwrap());
+            assert_eq!(dst.top_of_book(), src.top_of_book());
+            let priority = |book: &OrderBook| book.bids.iter().chain(book.asks.iter()).map(|o| (o.id, o.timestamp)).collect::<Vec<_>>();
+            assert_eq!(priority(&dst), priority(&src));
+        }
+
+        // Trading carries on from the marker
+        let payload = CreateOrderPayload { account_id: Some(4), symbol: "ABC".to_string(), ..order_payload(Side::Buy, 50, 1) };
+        let (_, _, Json(created)) = create_order_handler(State(Arc::clone(&target)), HeaderMap::new(), Json(payload)).await.unwrap();
+        assert_eq!((created.order.id, created.fills.unwrap()[0].trade_seq), (7, 3));
+
+        // The restored orders survive a restart of the target
+        target.db_writes.drain().await;
+        let reloaded = build_state(config, Arc::clone(&target.db_conn), false).unwrap();
+        assert_eq!(reloaded.market("DEFAULT").unwrap().order_book.lock().unwrap().checksum(), bundle.books[1].checksum);
+
+        // A tampered bundle is refused, and no book is touched
+        let mut tampered: DrBundle = serde_json::from_str(&wire).unwrap();
+        tampered.books[1].bids[0].quantity += 1;
+        let before = target.dr_snapshot();
+        let err = dr_restore_handler(State(Arc::clone(&target)), admin_headers("s3cret"), Json(tampered)).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)), "{:?}", err);
+        assert_eq!(serde_json::to_value(&target.dr_snapshot().books).unwrap(), serde_json::to_value(&before.books).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_book_depth_aggregates_levels() {
+        let state = test_state();
+        let orders = [(Side::Buy, 99, 10), (Side::Buy, 100, 5), (Side::Buy, 99, 3), (Side::Buy, 98, 1), (Side::Sell, 103, 2), (Side::Sell, 102, 7), (Side::Sell, 102, 1)];
+        for (side, price, quantity) in orders {
+            let payload = order_payload(side, price, quantity);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        let level = |price, total_quantity, order_count| PriceLevel { price, total_quantity, order_count };
+
+        let Json(book) = book_depth_handler(State(Arc::clone(&state)), Query(DepthQuery::default()), Query(SymbolQuery::default())).await.unwrap();
+        assert_eq!(book.bids, vec![level(100, 5, 1), level(99, 13, 2), level(98, 1, 1)]);
+        assert_eq!(book.asks, vec![level(102, 8, 2), level(103, 2, 1)]);
+
+        let Json(top) = book_depth_handler(State(Arc::clone(&state)), Query(DepthQuery { depth: Some(1) }), Query(SymbolQuery::default())).await.unwrap();
+        assert_eq!(top.bids, vec![level(100, 5, 1)]);
+        assert_eq!(top.asks, vec![level(102, 8, 2)]);
+    }
+
+    #[tokio::test]
+    async fn test_level_deltas_rebuild_aggregated_book() {
+        let state = test_state();
+        let submit = |side, price, quantity| {
+            let payload = order_payload(side, price, quantity);
+            create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload))
+        };
+        for (side, price, quantity) in [(Side::Buy, 99, 10), (Side::Buy, 100, 5), (Side::Sell, 102, 7), (Side::Sell, 103, 3)] {
+            let _ = submit(side, price, quantity).await.unwrap();
+        }
+
+        // Client's aggregated copy as of `since`
+        let (since, mut local) = {
+            let book = state.default_market.order_book.lock().unwrap();
+            let mut local = std::collections::HashMap::new();
+            for side in [Side::Buy, Side::Sell] {
+                for level in book.levels(&side) {
+                    local.insert((side.clone(), level.price), (side.clone(), level));
+                }
+            }
+            (book.seq, local)
+        };
+
+        let _ = submit(Side::Buy, 100, 4).await.unwrap();
+        let _ = submit(Side::Sell, 99, 12).await.unwrap();
+        let _ = cancel_order_handler(State(Arc::clone(&state)), Path(4), HeaderMap::new()).await.unwrap();
+        let _ = submit(Side::Sell, 101, 2).await.unwrap();
+
+        let Json(update) = level_deltas_handler(State(Arc::clone(&state)), Query(DeltaQuery { since }), Query(SymbolQuery::default())).await.unwrap();
+        assert_eq!(update.seq, state.default_market.order_book.lock().unwrap().seq);
+        for delta in update.levels {
+            let key = (delta.side.clone(), delta.level.price);
+            if delta.level.total_quantity == 0 {
+                local.remove(&key);
+            } else {
+                local.insert(key, (delta.side, delta.level));
+            }
+        }
+        let rebuilt = level_checksum(local.into_values());
+        assert_eq!(rebuilt, update.checksum);
+        assert_eq!(rebuilt, state.default_market.order_book.lock().unwrap().level_checksum());
+    }
+
+    #[tokio::test]
+    async fn test_get_order_prefers_book_then_db() {
+        let state = test_state();
+        for (side, quantity) in [(Side::Sell, 10), (Side::Buy, 4)] {
+            let payload = order_payload(side, 100, quantity);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+
+        // Resting: live remaining quantity
+        let Json(resting) = get_order_handler(State(Arc::clone(&state)), Path(1)).await.unwrap();
+        assert_eq!((resting.quantity, resting.status), (6, OrderStatus::PartiallyFilled));
+
+        // Filled and gone from the book: served from the DB
+        let mut filled = None;
+        for _ in 0..100 {
+            let Json(view) = get_order_handler(State(Arc::clone(&state)), Path(2)).await.unwrap();
+            if view.status == OrderStatus::Filled {
+                filled = Some(view);
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(filled.expect("fill was not persisted").quantity, 0);
+
+        let missing = get_order_handler(State(Arc::clone(&state)), Path(99)).await.unwrap_err();
+        assert_eq!(missing, ApiError::NotFound("order 99 does not exist".to_string()));
+        let response = missing.into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, serde_json::json!({"error": "not_found", "message": "order 99 does not exist"}));
+    }
+
+    #[tokio::test]
+    async fn test_order_history_records_each_transition() {
+        let state = test_state_with_frozen_clock(Config::default());
+        for (side, quantity) in [(Side::Sell, 10), (Side::Buy, 4)] {
+            let payload = order_payload(side, 100, quantity);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        state.db_writes.drain().await;
+        let _ = modify_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 3.into(), price: None })).await.unwrap();
+        let _ = cancel_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new()).await.unwrap();
+
+        let event = |from_status, to_status, remaining_quantity, reason: &str| OrderEventView { from_status, to_status, remaining_quantity, reason: reason.to_string(), timestamp: 1_000 };
+        let Json(history) = order_history_handler(State(Arc::clone(&state)), Path(1)).await.unwrap();
+        assert_eq!(history, vec![
+            event(None, OrderStatus::Open, 10, "Created"),
+            event(Some(OrderStatus::Open), OrderStatus::PartiallyFilled, 6, "Fill"),
+            event(Some(OrderStatus::PartiallyFilled), OrderStatus::PartiallyFilled, 3, "Modified"),
+            event(Some(OrderStatus::PartiallyFilled), OrderStatus::Cancelled, 0, "UserRequest"),
+        ]);
+        let Json(history) = order_history_handler(State(Arc::clone(&state)), Path(2)).await.unwrap();
+        assert_eq!(history, vec![event(None, OrderStatus::Open, 4, "Created"), event(Some(OrderStatus::Open), OrderStatus::Filled, 0, "Fill")]);
+        assert_eq!(order_history_handler(State(Arc::clone(&state)), Path(99)).await.unwrap_err(), ApiError::order_not_found(99));
+
+        // Append-only
+        let conn = state.db_conn.lock().unwrap();
+        assert!(conn.execute("UPDATE order_events SET to_status = 'Open'", []).is_err());
+        assert!(conn.execute("DELETE FROM order_events", []).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_trade_seq_strictly_increases_across_matches() {
+        let state = test_state();
+        let limit = |side, price, quantity, symbol: &str| CreateOrderPayload { symbol: symbol.to_string(), ..order_payload(side, price, quantity) };
+        let mut seqs = Vec::new();
+        for symbol in ["DEFAULT", "ABC", "DEFAULT"] {
+            for price in [100, 101] {
+                let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(limit(Side::Sell, price, 2, symbol))).await.unwrap();
+            }
+            let (_, _, Json(response)) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(limit(Side::Buy, 101, 4, symbol))).await.unwrap();
+            seqs.extend(response.fills.unwrap().iter().map(|fill| fill.trade_seq));
+        }
+        assert_eq!(seqs.len(), 6);
+        assert!(seqs.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", seqs);
+
+        let mut persisted = Vec::new();
+        for _ in 0..100 {
+            persisted = {
+                let conn = state.db_conn.lock().unwrap();
+                let mut stmt = conn.prepare("SELECT trade_seq FROM trades WHERE paper = 0 ORDER BY trade_seq").unwrap();
+                let rows = stmt.query_map([], |row| row.get::<_, u64>(0)).unwrap();
+                rows.collect::<SqlResult<Vec<_>>>().unwrap()
+            };
+            if persisted.len() == seqs.len() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(persisted, seqs);
+
+        // A restart carries on from the last one persisted
+        let restarted = build_state(Config::default(), Arc::clone(&state.db_conn), false).unwrap();
+        assert_eq!(restarted.trade_seq.load(Ordering::Relaxed), seqs[5] + 1);
+    }
+
+    #[tokio::test]
+    async fn test_trade_prints_at_resting_price() {
+        // (resting side, resting price, aggressor price) -> the resting price
+        for (resting_side, resting_price, aggressor_price) in [(Side::Sell, 100, 105), (Side::Buy, 105, 100)] {
+            let state = test_state();
+            let aggressor_side = if resting_side == Side::Buy { Side::Sell } else { Side::Buy };
+            for (side, price) in [(resting_side.clone(), resting_price), (aggressor_side, aggressor_price)] {
+                let payload = order_payload(side, price, 10);
+                let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+            }
+            assert_eq!(state.default_market.order_book.lock().unwrap().totals.notional, resting_price as u128 * 10);
+
+            let mut trade = None;
+            for _ in 0..100 {
+                trade = state.db_conn.lock().unwrap()
+                    .query_row("SELECT bid_id, ask_id, price, quantity FROM trades WHERE paper = 0", [], |row| {
+                        Ok((row.get::<_, OrderId>(0)?, row.get::<_, OrderId>(1)?, row.get::<_, u64>(2)?, row.get::<_, u64>(3)?))
+                    })
+                    .ok();
+                if trade.is_some() {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            let (bid_id, ask_id) = if resting_side == Side::Buy { (1, 2) } else { (2, 1) };
+            assert_eq!(trade.expect("trade was not persisted"), (bid_id, ask_id, resting_price, 10), "{:?} resting", resting_side);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_priority_survives_backward_clock_step() {
+        let state = test_state();
+        let mut early = Order::new(1, Side::Sell, 100, 5);
+        early.timestamp = 2_000;
+        // The clock stepped back before the second order arrived
+        let mut late = Order::new(2, Side::Sell, 100, 5);
+        late.timestamp = 1_000;
+        {
+            let mut book = state.default_market.order_book.lock().unwrap();
+            let (first, _) = book.add_order(early);
+            let (second, _) = book.add_order(late);
+            assert!(first < second);
+        }
+
+        let payload = order_payload(Side::Buy, 100, 5);
+        let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        let ids: Vec<OrderId> = state.default_market.order_book.lock().unwrap().asks.iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_reload_orders_by_priority_not_timestamp() {
+        let db_conn = dummy_db_conn();
+        {
+            let conn = db_conn.lock().unwrap();
+            // Order 1 arrived second even though its wall-clock time is earlier
+            for (id, timestamp, priority) in [(1u64, 1_000u64, 8u64), (2, 2_000, 7)] {
+                conn.execute(
+                    "INSERT INTO orders (id, side, price, original_quantity, remaining_quantity, status, timestamp, priority) VALUES (?1, 'Sell', 100, 5, 5, 'Open', ?2, ?3)",
+                    params![id, timestamp.to_string(), priority],
+                ).unwrap();
+            }
+        }
+        let state = build_state(Config::default(), db_conn, false).unwrap();
+        let book = state.default_market.order_book.lock().unwrap();
+        let ids: Vec<OrderId> = book.asks.iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![2, 1]);
+        assert_eq!(book.next_priority, 9);
+    }
+
+    #[tokio::test]
+    async fn test_ensure_order_is_idempotent() {
+        let state = test_state_with_config(Config { admin_token: Some("s3cret".to_string()), ..Config::default() });
+        let headers = admin_headers("s3cret");
+        let ensure = |quantity, status| {
+            let payload = EnsureOrderPayload { id: 5, symbol: default_symbol(), side: Side::Buy, price: 100, quantity, status, group_id: None };
+            ensure_order_handler(State(Arc::clone(&state)), headers.clone(), Json(payload))
+        };
+        let db_row = || -> (String, u64) {
+            state.db_conn.lock().unwrap()
+                .query_row("SELECT status, remaining_quantity FROM orders WHERE id = 5", [], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap()
+        };
+
+        let Json(first) = ensure(10, OrderStatus::Open).await.unwrap();
+        assert!(first.changed);
+        let Json(second) = ensure(10, OrderStatus::Open).await.unwrap();
+        assert!(!second.changed);
+        {
+            let book = state.default_market.order_book.lock().unwrap();
+            assert_eq!(book.bids.len(), 1);
+            assert_eq!((book.bids.front().unwrap().id, book.bids.front().unwrap().quantity), (5, 10));
+        }
+        assert_eq!(db_row(), ("Open".to_string(), 10));
+        assert_eq!(state.next_order_id.load(Ordering::SeqCst), 6);
+
+        let Json(cancelled) = ensure(10, OrderStatus::Cancelled).await.unwrap();
+        assert!(cancelled.changed);
+        let Json(again) = ensure(10, OrderStatus::Cancelled).await.unwrap();
+        assert!(!again.changed);
+        assert!(state.default_market.order_book.lock().unwrap().bids.is_empty());
+        assert_eq!(db_row(), ("Cancelled".to_string(), 0));
+    }
+
+    fn admin_headers(token: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ADMIN_TOKEN_HEADER, HeaderValue::from_static(token));
+        headers
+    }
+
+    fn import_record(id: OrderId, side: Side, price: u64, original: u64, remaining: u64, status: OrderStatus) -> ImportOrderPayload {
+        ImportOrderPayload {
+            id, side, price,
+            symbol: default_symbol(),
+            original_quantity: original,
+            remaining_quantity: remaining,
+            status,
+            timestamp: None,
+            group_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_market_buy_sweeps_ask_levels() {
+        let state = test_state();
+        for price in [102, 100, 101] {
+            let payload = order_payload(Side::Sell, price, 5);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+
+        let market = |quantity| CreateOrderPayload { order_type: OrderType::Market, ..order_payload(Side::Buy, 0, quantity) };
+        let (_, _, Json(CreateOrderResponse { order: filled, .. })) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(market(12))).await.unwrap();
+        assert_eq!((filled.status, filled.quantity, filled.price), (OrderStatus::Filled, 0, 0));
+        {
+            let book = state.default_market.order_book.lock().unwrap();
+            let asks: Vec<(u64, u64)> = book.asks.iter().map(|o| (o.price, o.quantity)).collect();
+            assert_eq!(asks, vec![(102, 3)]);
+            assert!(book.bids.is_empty());
+            // 5 @ 100 + 5 @ 101 + 2 @ 102
+            assert_eq!(book.totals.volume, 12);
+            assert_eq!(book.totals.notional, 500 + 505 + 204);
+        }
+
+        // More than the book holds: the remainder is cancelled, never rested
+        let (_, _, Json(CreateOrderResponse { order: partial, .. })) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(market(10))).await.unwrap();
+        assert_eq!(partial.status, OrderStatus::Cancelled);
+        {
+            let book = state.default_market.order_book.lock().unwrap();
+            assert!(book.asks.is_empty());
+            assert!(book.bids.is_empty());
+        }
+        for _ in 0..100 {
+            let status: String = state.db_conn.lock().unwrap()
+                .query_row("SELECT status FROM orders WHERE id = ?1", params![partial.id], |row| row.get(0))
+                .unwrap();
+            if status == "Cancelled" {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("market remainder was not persisted as cancelled");
+    }
+
+    #[tokio::test]
+    async fn test_fok_just_short_of_liquidity_is_killed() {
+        let state = test_state();
+        for (price, quantity) in [(100, 5), (101, 4), (102, 50)] {
+            let payload = order_payload(Side::Sell, price, quantity);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        let (checksum, seq) = {
+            let book = state.default_market.order_book.lock().unwrap();
+            (book.checksum(), book.seq)
+        };
+
+        // 9 available at or below 101; one short
+        let fok = |quantity| CreateOrderPayload { time_in_force: TimeInForce::Fok, ..order_payload(Side::Buy, 101, quantity) };
+        let (code, _, Json(CreateOrderResponse { order: killed, .. })) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(fok(10))).await.unwrap();
+        assert_eq!((code, killed.status, killed.quantity), (StatusCode::OK, OrderStatus::Killed, 10));
+        {
+            let book = state.default_market.order_book.lock().unwrap();
+            assert_eq!((book.checksum(), book.seq), (checksum, seq));
+            assert_eq!(book.totals.trade_count, 0);
+        }
+        let rows: i64 = state.db_conn.lock().unwrap()
+            .query_row("SELECT COUNT(*) FROM orders WHERE id = ?1", params![killed.id], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 0);
+
+        let (code, _, Json(CreateOrderResponse { order: filled, .. })) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(fok(9))).await.unwrap();
+        assert_eq!((code, filled.status, filled.quantity), (StatusCode::CREATED, OrderStatus::Filled, 0));
+        let book = state.default_market.order_book.lock().unwrap();
+        let asks: Vec<(u64, u64)> = book.asks.iter().map(|o| (o.price, o.quantity)).collect();
+        assert_eq!(asks, vec![(102, 50)]);
+        assert!(book.bids.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_match_mode_switch_applies_to_later_matches_only() {
+        let state = test_state_with_frozen_clock(Config { admin_token: Some("s3cret".to_string()), ..Config::default() });
+        let create = |side, quantity| {
+            let payload = order_payload(side, 100, quantity);
+            create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload))
+        };
+        let get_mode = || get_match_mode_handler(State(Arc::clone(&state)), Path(default_symbol()));
+        let put_mode = |token, mode: &str| {
+            put_match_mode_handler(State(Arc::clone(&state)), Path(default_symbol()), admin_headers(token), Json(MatchModePayload { mode: mode.to_string() }))
+        };
+        let makers = |Json(response): Json<CreateOrderResponse>| response.fills.unwrap().iter().map(|fill| fill.counter_order_id).collect::<Vec<_>>();
+
+        // 1 and 2 rest, 2 larger; price-time fills the earlier one
+        let _ = create(Side::Sell, 2).await.unwrap();
+        let _ = create(Side::Sell, 5).await.unwrap();
+        let Json(status) = get_mode().await.unwrap();
+        assert_eq!((status.mode, status.since_seq), (MatchMode::PriceTime, 0));
+        assert_eq!(makers(create(Side::Buy, 2).await.unwrap().2), vec![1]);
+
+        let err = put_mode("wrong", "size_priority").await.unwrap_err();
+        assert!(matches!(err, ApiError::Unauthorized(_)), "{:?}", err);
+        let err = put_mode("s3cret", "largest_first").await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)), "{:?}", err);
+        assert_eq!(get_mode().await.unwrap().0.mode, MatchMode::PriceTime);
+
+        let Json(switched) = put_mode("s3cret", "size_priority").await.unwrap();
+        let seq = state.default_market.order_book.lock().unwrap().seq;
+        assert_eq!((switched.mode, switched.since_seq), (MatchMode::SizePriority, seq));
+        assert_eq!(get_mode().await.unwrap().0.since_seq, seq);
+
+        // 5 behind 2 and 4, but the largest, so it trades first now
+        let _ = create(Side::Sell, 1).await.unwrap();
+        let _ = create(Side::Sell, 8).await.unwrap();
+        assert_eq!(makers(create(Side::Buy, 3).await.unwrap().2), vec![5]);
+
+        // The trade made before the switch is as it was
+        let conn = state.db_conn.lock().unwrap();
+        let first: (u64, u64) = conn.query_row("SELECT ask_id, quantity FROM trades ORDER BY trade_id LIMIT 1", [], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        assert_eq!(first, (1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_symbol_limits_update_refuses_a_previously_valid_size() {
+        let config = Config { admin_token: Some("s3cret".to_string()), ..Config::default() };
+        let state = test_state_with_config(config.clone());
+        let create = |quantity| {
+            let payload = order_payload(Side::Buy, 100, quantity);
+            create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload))
+        };
+        let put_limits = |token, payload: SymbolLimitsPayload| {
+            put_symbol_limits_handler(State(Arc::clone(&state)), Path(default_symbol()), admin_headers(token), Json(payload))
+        };
+        let (status, _, _) = create(10).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let Json(unset) = get_symbol_limits_handler(State(Arc::clone(&state)), Path(default_symbol())).await.unwrap();
+        assert_eq!((unset.max_order_qty, unset.max_notional, unset.price_band_bps), (None, None, None));
+
+        let smaller = || SymbolLimitsPayload { max_order_qty: Some(5.into()), ..SymbolLimitsPayload::default() };
+        assert!(matches!(put_limits("wrong", smaller()).await.unwrap_err(), ApiError::Unauthorized(_)));
+        let err = put_limits("s3cret", SymbolLimitsPayload { max_order_qty: Some(0.into()), ..SymbolLimitsPayload::default() }).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)), "{:?}", err);
+        let err = put_limits("s3cret", SymbolLimitsPayload { price_band_bps: Some(MAX_PRICE_BAND_BPS + 1), ..SymbolLimitsPayload::default() }).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)), "{:?}", err);
+        let _ = create(10).await.unwrap();
+
+        let Json(updated) = put_limits("s3cret", smaller()).await.unwrap();
+        assert_eq!(updated.max_order_qty, Some(Fixed { units: 5, scale: 0 }));
+        assert_eq!(serde_json::to_value(&get_symbol_limits_handler(State(Arc::clone(&state)), Path(default_symbol())).await.unwrap().0).unwrap()["max_order_qty"], 5);
+        let err = create(10).await.unwrap_err();
+        assert!(matches!(&err, ApiError::Unprocessable(message) if message.contains("max order size 5")), "{:?}", err);
+        let batch = vec![order_payload(Side::Buy, 100, 5), order_payload(Side::Buy, 100, 10)];
+        let err = create_orders_batch_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(batch)).await.unwrap_err();
+        assert!(matches!(&err, ApiError::Unprocessable(message) if message.starts_with("order 1:")), "{:?}", err);
+        let (status, _, _) = create(5).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        // Orders accepted before the change rest on untouched
+        let quantities: Vec<u64> = state.default_market.order_book.lock().unwrap().bids.iter().map(|o| o.quantity).collect();
+        assert_eq!(quantities, vec![10, 10, 5]);
+
+        // Persisted, so a restart keeps them
+        let restarted = build_state_with_clock(config, Arc::clone(&state.db_conn), false, Arc::clone(&state.clock)).unwrap();
+        assert_eq!(restarted.symbol_limits(DEFAULT_SYMBOL).max_order_qty, Some(5));
+    }
+
+    #[tokio::test]
+    async fn test_market_maker_quote_wider_than_max_spread_is_flagged_or_rejected() {
+        let obligation = QuoteObligation::parse("7:1:4:10").unwrap();
+        assert_eq!(obligation, (7, QuoteObligation { min_spread: 1, max_spread: 4, min_size: 10 }));
+        assert_eq!(QuoteObligation::parse("7:5:4:10"), None);
+        for action in [ObligationAction::Reject, ObligationAction::Flag] {
+            let config = Config {
+                quote_obligations: HashMap::from([(DEFAULT_SYMBOL.to_string(), HashMap::from([obligation]))]),
+                obligation_action: action,
+                ..Config::default()
+            };
+            let state = test_state_with_config(config);
+            let quote = |side, price| {
+                let payload = CreateOrderPayload { account_id: Some(7), ..order_payload(side, price, 10) };
+                create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload))
+            };
+            let obligations = || account_obligations_handler(State(Arc::clone(&state)), Path(7));
+
+            // Half a quote is still being built, so it goes in, but shows as one-sided
+            let _ = quote(Side::Buy, 100).await.unwrap();
+            assert_eq!(obligations().await.0[0].breaches, vec![ObligationBreach::OneSided]);
+
+            // 6 apart against a max spread of 4
+            let wide = quote(Side::Sell, 106).await;
+            match action {
+                ObligationAction::Reject => {
+                    let err = wide.unwrap_err();
+                    assert!(matches!(&err, ApiError::Unprocessable(message) if message.contains("SpreadTooWide")), "{:?}", err);
+                    assert!(state.default_market.order_book.lock().unwrap().asks.is_empty());
+                    let _ = quote(Side::Sell, 104).await.unwrap();
+                    let Json(statuses) = obligations().await;
+                    assert_eq!((statuses[0].spread, statuses[0].breaches.clone()), (Some(4), vec![]));
+                }
+                ObligationAction::Flag => {
+                    let _ = wide.unwrap();
+                    let Json(statuses) = obligations().await;
+                    assert_eq!((statuses[0].bid, statuses[0].ask, statuses[0].spread), (Some(100), Some(106), Some(6)));
+                    assert_eq!(statuses[0].breaches, vec![ObligationBreach::SpreadTooWide]);
+                }
+            }
+            // Accounts without an obligation have nothing to report
+            assert!(account_obligations_handler(State(Arc::clone(&state)), Path(8)).await.0.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_latency_over_sla_sheds_new_orders_until_it_recovers() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let config = Config { latency_sla_nanos: Some(1_000_000), latency_sla_window_ms: Some(1_000), ..Config::default() };
+        let state = build_state_with_clock(config, dummy_db_conn(), false, clock.clone()).unwrap();
+        let create = || {
+            let payload = order_payload(Side::Buy, 100, 1);
+            create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload))
+        };
+        let (_, _, Json(resting)) = create().await.unwrap();
+
+        // A window of 5ms matches against a 1ms SLA
+        for _ in 0..100 {
+            state.metrics.matching.record(std::time::Duration::from_millis(5));
+        }
+        clock.set(1_000 + 1_000_000_000);
+        let err = create().await.unwrap_err();
+        assert!(matches!(err, ApiError::Unavailable(_)), "{:?}", err);
+        let Json(health) = healthz_handler(State(Arc::clone(&state))).await;
+        assert_eq!(health.status, "shedding");
+        assert!(health.load_shedding.as_ref().is_some_and(|shedding| shedding.active && shedding.last_p99_nanos > 1_000_000));
+
+        // Existing orders can still be cancelled
+        let (_, Json(cancelled)) = cancel_order_handler(State(Arc::clone(&state)), Path(resting.order.id), HeaderMap::new()).await.unwrap();
+        assert_eq!(cancelled.status, OrderStatus::Cancelled);
+
+        // Still within the window: still shedding
+        clock.set(1_000 + 1_500_000_000);
+        assert!(matches!(create().await.unwrap_err(), ApiError::Unavailable(_)));
+
+        // A quiet window later, order entry is back
+        clock.set(1_000 + 2_000_000_000);
+        let (status, _, _) = create().await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(healthz_handler(State(Arc::clone(&state))).await.0.status, "ok");
+    }
+
+    #[tokio::test]
+    async fn test_match_mode_is_configured_per_symbol() {
+        let config = Config { symbol_match_modes: HashMap::from([("ABC".to_string(), MatchMode::SizePriority)]), ..Config::default() };
+        let state = test_state_with_frozen_clock(config);
+        let create = |symbol: &str, side, quantity| {
+            let payload = CreateOrderPayload { symbol: symbol.to_string(), ..order_payload(side, 100, quantity) };
+            create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload))
+        };
+        let mut first_maker = Vec::new();
+        for symbol in [default_symbol().as_str(), "ABC"] {
+            let (_, _, Json(small)) = create(symbol, Side::Sell, 2).await.unwrap();
+            let (_, _, Json(large)) = create(symbol, Side::Sell, 5).await.unwrap();
+            let (_, _, Json(taker)) = create(symbol, Side::Buy, 2).await.unwrap();
+            let maker = taker.fills.unwrap()[0].counter_order_id;
+            first_maker.push(if maker == small.order.id { "earlier" } else if maker == large.order.id { "larger" } else { "neither" });
+        }
+        assert_eq!(first_maker, vec!["earlier", "larger"]);
+    }
+
+    #[tokio::test]
+    async fn test_spread_samples_follow_the_state_clock() {
+        let clock = Arc::new(MockClock::new(100));
+        let state = build_state_with_clock(Config::default(), dummy_db_conn(), false, clock.clone()).unwrap();
+        let limit = |side, price| order_payload(side, price, 1);
+
+        clock.set(200);
+        let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(limit(Side::Buy, 100))).await.unwrap();
+        clock.set(400);
+        let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(limit(Side::Sell, 104))).await.unwrap();
+        clock.set(1_000);
+
+        // Sampled from the book's opening at 100: not two-sided until 400, then so until now
+        let Json(stats) = spread_stats_handler(State(Arc::clone(&state)), Query(WindowQuery { from: None, to: None }), Query(SymbolQuery::default()))
+            .await
+            .unwrap();
+        assert_eq!((stats.to, stats.one_sided_nanos, stats.two_sided_nanos), (1_000, 300, 600));
+        assert_eq!(stats.average_spread, Some(4.0));
+    }
+
+    #[tokio::test]
+    async fn test_post_only_crossing_the_spread_is_rejected() {
+        let state = test_state();
+        let payload = |side, price, post_only| CreateOrderPayload { post_only, ..order_payload(side, price, 5) };
+        let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload(Side::Sell, 100, false))).await.unwrap();
+
+        let (code, _, Json(CreateOrderResponse { order: rejected, fills, .. })) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload(Side::Buy, 101, true))).await.unwrap();
+        assert_eq!((code, rejected.status, rejected.post_only), (StatusCode::OK, OrderStatus::Rejected, true));
+        assert!(fills.unwrap().is_empty());
+        let rows: i64 = state.db_conn.lock().unwrap()
+            .query_row("SELECT COUNT(*) FROM orders WHERE id = ?1", params![rejected.id], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 0);
+
+        let (code, _, Json(CreateOrderResponse { order: rested, .. })) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload(Side::Buy, 99, true))).await.unwrap();
+        assert_eq!((code, rested.status), (StatusCode::CREATED, OrderStatus::Open));
+        let book = state.default_market.order_book.lock().unwrap();
+        assert_eq!((book.best_bid(), book.best_ask()), (Some(99), Some(100)));
+    }
+
+    #[tokio::test]
+    async fn test_simulated_sweep_matches_real_submit() {
+        let state = test_state_with_frozen_clock(Config::default());
+        for (price, quantity) in [(100, 5), (101, 4), (101, 3), (103, 10)] {
+            let payload = order_payload(Side::Sell, price, quantity);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        // A create is answered just before its write is counted done
+        state.db_writes.drain().await;
+        let (checksum, seq, trade_count) = {
+            let book = state.default_market.order_book.lock().unwrap();
+            (book.checksum(), book.seq, book.totals.trade_count)
+        };
+        let count_rows = || -> i64 { state.db_conn.lock().unwrap().query_row("SELECT COUNT(*) FROM orders", [], |row| row.get(0)).unwrap() };
+        let rows = count_rows();
+
+        // Takes all 12 at or below 102, leaving 3 to rest at 102
+        let sweep = || order_payload(Side::Buy, 102, 15);
+        let Json(simulated) = simulate_order_handler(State(Arc::clone(&state)), Json(sweep())).await.unwrap();
+        assert_eq!((simulated.filled_quantity, simulated.remaining_quantity), (12, 3));
+        assert_eq!(simulated.order.status, OrderStatus::PartiallyFilled);
+        {
+            let book = state.default_market.order_book.lock().unwrap();
+            assert_eq!((book.checksum(), book.seq, book.totals.trade_count), (checksum, seq, trade_count));
+        }
+        assert_eq!(state.db_writes.pending(), 0);
+        assert_eq!(count_rows(), rows);
+
+        let (_, _, Json(actual)) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(sweep())).await.unwrap();
+        assert_eq!(simulated.fills, actual.fills.unwrap());
+        let book = state.default_market.order_book.lock().unwrap();
+        assert_eq!(book.find_order(actual.order.id).map(|o| o.quantity), Some(simulated.remaining_quantity));
+    }
+
+    #[tokio::test]
+    async fn test_forced_match_on_auction_only_book() {
+        let config = Config { auction_only: true, test_harness: true, admin_token: Some("s3cret".to_string()), ..Config::default() };
+        let state = test_state_with_frozen_clock(config);
+        for (side, price, quantity) in [(Side::Sell, 100, 5), (Side::Sell, 101, 5), (Side::Buy, 101, 8)] {
+            let payload = order_payload(side, price, quantity);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        assert_eq!(state.default_market.order_book.lock().unwrap().totals.trade_count, 0);
+
+        let Json(fills) = force_match_handler(State(Arc::clone(&state)), Query(SymbolQuery::default()), admin_headers("s3cret")).await.unwrap();
+        assert_eq!(fills, vec![
+            Fill { trade_id: 1, trade_seq: 1, executed_at: 1_000, symbol: default_symbol(), bid_id: 3, ask_id: 1, price: 100, quantity: 5 },
+            Fill { trade_id: 2, trade_seq: 2, executed_at: 1_000, symbol: default_symbol(), bid_id: 3, ask_id: 2, price: 101, quantity: 3 },
+        ]);
+        let book = state.default_market.order_book.lock().unwrap();
+        assert!(book.bids.is_empty());
+        assert_eq!(book.asks.front().unwrap().quantity, 2);
+    }
+
+    #[tokio::test]
+    async fn test_auction_trigger_requires_admin_token() {
+        let disabled = test_state_with_config(Config { auction_only: true, ..Config::default() });
+        let err = auction_handler(State(Arc::clone(&disabled)), Query(SymbolQuery::default()), admin_headers("s3cret")).await.unwrap_err();
+        assert!(matches!(err, ApiError::Forbidden(_)), "{:?}", err);
+
+        let state = test_state_with_config(Config { auction_only: true, admin_token: Some("s3cret".to_string()), ..Config::default() });
+        for side in [Side::Sell, Side::Buy] {
+            let payload = order_payload(side, 100, 5);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        for headers in [HeaderMap::new(), admin_headers("wrong")] {
+            let err = auction_handler(State(Arc::clone(&state)), Query(SymbolQuery::default()), headers).await.unwrap_err();
+            assert!(matches!(err, ApiError::Unauthorized(_)), "{:?}", err);
+        }
+        assert_eq!(state.default_market.order_book.lock().unwrap().totals.trade_count, 0);
+
+        let Json(result) = auction_handler(State(Arc::clone(&state)), Query(SymbolQuery::default()), admin_headers("s3cret")).await.unwrap();
+        assert_eq!((result.clearing_price, result.volume), (Some(100), 5));
+    }
+
+    #[tokio::test]
+    async fn test_split_adjusts_orders_and_cancels_dnr() {
+        let state = test_state_with_config(Config { admin_token: Some("s3cret".to_string()), ..Config::default() });
+        for (side, price, quantity, dnr) in [(Side::Buy, 101, 10, false), (Side::Buy, 100, 4, true), (Side::Sell, 103, 7, false)] {
+            let payload = CreateOrderPayload { dnr, ..order_payload(side, price, quantity) };
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+
+        let split = CorporateAction::Split { to: 2, from: 1 };
+        let Json(adjustments) = corporate_action_handler(State(Arc::clone(&state)), Query(SymbolQuery::default()), admin_headers("s3cret"), Json(split))
+            .await
+            .unwrap();
+        let outcomes: Vec<(OrderId, AdjustmentOutcome)> = adjustments.iter().map(|a| (a.order_id, a.outcome)).collect();
+        assert_eq!(outcomes, vec![(1, AdjustmentOutcome::Adjusted), (2, AdjustmentOutcome::Cancelled), (3, AdjustmentOutcome::Adjusted)]);
+
+        {
+            let book = state.default_market.order_book.lock().unwrap();
+            // Bid rounds down, ask rounds up
+            let bids: Vec<(OrderId, u64, u64)> = book.bids.iter().map(|o| (o.id, o.price, o.quantity)).collect();
+            let asks: Vec<(OrderId, u64, u64)> = book.asks.iter().map(|o| (o.id, o.price, o.quantity)).collect();
+            assert_eq!(bids, vec![(1, 50, 20)]);
+            assert_eq!(asks, vec![(3, 52, 14)]);
+            assert_eq!((book.best_bid(), book.best_ask()), (Some(50), Some(52)));
+        }
+
+        let conn = state.db_conn.lock().unwrap();
+        let row = |id: u64| -> (u64, u64, String) {
+            conn.query_row("SELECT price, remaining_quantity, status FROM orders WHERE id = ?1", params![id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+                .unwrap()
+        };
+        assert_eq!(row(1), (50, 20, "Open".to_string()));
+        assert_eq!(row(2).2, "Cancelled");
+        let audited: i64 = conn.query_row("SELECT COUNT(*) FROM corporate_action_log", [], |r| r.get(0)).unwrap();
+        assert_eq!(audited, 3);
+    }
+
+    #[tokio::test]
+    async fn test_import_rests_only_live_orders_without_matching() {
+        let state = test_state_with_config(Config { admin_token: Some("s3cret".to_string()), ..Config::default() });
+        let records = vec![
+            import_record(10, Side::Buy, 101, 10, 0, OrderStatus::Filled),
+            import_record(11, Side::Sell, 101, 10, 0, OrderStatus::Filled),
+            import_record(12, Side::Buy, 100, 10, 4, OrderStatus::PartiallyFilled),
+            import_record(13, Side::Sell, 102, 5, 5, OrderStatus::Open),
+            import_record(14, Side::Sell, 103, 5, 2, OrderStatus::Cancelled),
+        ];
+        let Json(summary) = import_orders_handler(State(Arc::clone(&state)), admin_headers("s3cret"), Json(records))
+            .await
+            .unwrap();
+        assert_eq!((summary.imported, summary.resting), (5, 2));
+
+        {
+            let book = state.default_market.order_book.lock().unwrap();
+            let bids: Vec<(OrderId, u64)> = book.bids.iter().map(|o| (o.id, o.quantity)).collect();
+            let asks: Vec<(OrderId, u64)> = book.asks.iter().map(|o| (o.id, o.quantity)).collect();
+            assert_eq!(bids, vec![(12, 4)]);
+            assert_eq!(asks, vec![(13, 5)]);
+            assert_eq!(book.totals.trade_count, 0);
+        }
+        assert_eq!(state.next_order_id.load(Ordering::SeqCst), 15);
+
+        let count: i64 = state.db_conn.lock().unwrap()
+            .query_row("SELECT COUNT(*) FROM orders", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 5);
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_inconsistent_batch() {
+        let state = test_state_with_config(Config { admin_token: Some("s3cret".to_string()), ..Config::default() });
+        let records = vec![
+            import_record(1, Side::Buy, 100, 10, 10, OrderStatus::Open),
+            // Filled but with quantity left
+            import_record(2, Side::Sell, 100, 10, 3, OrderStatus::Filled),
+        ];
+        let err = import_orders_handler(State(Arc::clone(&state)), admin_headers("s3cret"), Json(records))
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, ApiError::Unprocessable(message) if message.contains("order 2")), "{:?}", err);
+        assert!(state.default_market.order_book.lock().unwrap().bids.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ensure_order_requires_admin_token() {
+        let payload = || EnsureOrderPayload { id: 1, symbol: default_symbol(), side: Side::Buy, price: 100, quantity: 1, status: OrderStatus::Open, group_id: None };
+
+        let unconfigured = test_state();
+        let err = ensure_order_handler(State(unconfigured), HeaderMap::new(), Json(payload())).await.unwrap_err();
+        assert!(matches!(err, ApiError::Forbidden(_)), "{:?}", err);
+
+        let state = test_state_with_config(Config { admin_token: Some("s3cret".to_string()), ..Config::default() });
+        let err = ensure_order_handler(State(Arc::clone(&state)), admin_headers("wrong"), Json(payload())).await.unwrap_err();
+        assert!(matches!(err, ApiError::Unauthorized(_)), "{:?}", err);
+        assert!(state.default_market.order_book.lock().unwrap().bids.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_partially_filled_orders() {
+        let config = Config { shutdown_partials: ShutdownPartials::Cancel, ..Config::default() };
+        let state = test_state_with_config(config.clone());
+        for (side, price, quantity) in [(Side::Sell, 100, 10), (Side::Sell, 105, 10), (Side::Buy, 100, 4)] {
+            let payload = order_payload(side, price, quantity);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        // Let the detached fill write land before shutting down
+        for _ in 0..100 {
+            let status: String = state.db_conn.lock().unwrap()
+                .query_row("SELECT status FROM orders WHERE id = 1", [], |row| row.get(0))
+                .unwrap();
+            if status == "PartiallyFilled" {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(apply_shutdown_policy(&state).unwrap(), 1);
+
+        // Only the untouched order comes back
+        let restarted = build_state(config, Arc::clone(&state.db_conn), false).unwrap();
+        let ids: Vec<OrderId> = restarted.default_market.order_book.lock().unwrap().asks.iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_split_increase_keeps_original_priority() {
+        let state = test_state_with_config(Config { increase_mode: IncreaseMode::Split, ..Config::default() });
+        for quantity in [10, 4] {
+            let payload = order_payload(Side::Sell, 100, quantity);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+
+        let (_, Json(response)) = modify_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 15.into(), price: None }))
+            .await
+            .unwrap();
+        assert_eq!(response.order.id, 1);
+        assert_eq!(response.order.quantity, 10);
+        let linked = response.linked_order.expect("increase should be split");
+        assert_eq!((linked.quantity, linked.linked_to), (5, Some(1)));
+
+        // Original size first, the other order next, the extra last
+        let queue: Vec<(OrderId, u64)> = state.default_market.order_book.lock().unwrap().asks.iter().map(|o| (o.id, o.quantity)).collect();
+        assert_eq!(queue, vec![(1, 10), (2, 4), (linked.id, 5)]);
+
+        let payload = order_payload(Side::Buy, 100, 12);
+        let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        let queue: Vec<(OrderId, u64)> = state.default_market.order_book.lock().unwrap().asks.iter().map(|o| (o.id, o.quantity)).collect();
+        assert_eq!(queue, vec![(2, 2), (linked.id, 5)]);
+
+        // Decreases are still applied in place
+        let (_, Json(response)) = modify_order_handler(State(Arc::clone(&state)), Path(2), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 1.into(), price: None }))
+            .await
+            .unwrap();
+        assert!(response.linked_order.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_modify_increase_loses_priority() {
+        let state = test_state();
+        for quantity in [10, 4] {
+            let payload = order_payload(Side::Sell, 100, quantity);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        let _ = modify_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 12.into(), price: None })).await.unwrap();
+
+        let queue: Vec<(OrderId, u64)> = state.default_market.order_book.lock().unwrap().asks.iter().map(|o| (o.id, o.quantity)).collect();
+        assert_eq!(queue, vec![(2, 4), (1, 12)]);
+
+        // The requeued position is persisted, so a restart keeps it
+        let restarted = build_state(Config::default(), Arc::clone(&state.db_conn), false).unwrap();
+        let queue: Vec<OrderId> = restarted.default_market.order_book.lock().unwrap().asks.iter().map(|o| o.id).collect();
+        assert_eq!(queue, vec![2, 1]);
+
+        let payload = order_payload(Side::Buy, 100, 5);
+        let Json(CreateOrderResponse { fills, .. }) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap().2;
+        let counterparties: Vec<OrderId> = fills.unwrap().iter().map(|fill| fill.counter_order_id).collect();
+        assert_eq!(counterparties, vec![2, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_modify_decrease_keeps_priority() {
+        let state = test_state();
+        for quantity in [10, 4] {
+            let payload = order_payload(Side::Sell, 100, quantity);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        let priority_before = state.default_market.order_book.lock().unwrap().find_order(1).unwrap().priority;
+        let (_, Json(response)) = modify_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 6.into(), price: None })).await.unwrap();
+        assert_eq!(response.order.quantity, 6);
+
+        let book = state.default_market.order_book.lock().unwrap();
+        let queue: Vec<(OrderId, u64)> = book.asks.iter().map(|o| (o.id, o.quantity)).collect();
+        assert_eq!(queue, vec![(1, 6), (2, 4)]);
+        assert_eq!(book.find_order(1).unwrap().priority, priority_before);
+    }
+
+    #[tokio::test]
+    async fn test_reprice_across_spread_matches_and_persists() {
+        let state = test_state_with_frozen_clock(Config::default());
+        for (side, price, quantity) in [(Side::Sell, 101, 4), (Side::Buy, 99, 10)] {
+            let payload = order_payload(side, price, quantity);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+
+        let (_, Json(response)) = modify_order_handler(State(Arc::clone(&state)), Path(2), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 10.into(), price: Some(102) }))
+            .await
+            .unwrap();
+        // Trades at the resting ask's price, the rest bids at the new price
+        assert_eq!(response.fills, vec![OrderFill { counter_order_id: 1, price: 101, quantity: 4, quantity_scale: 0, trade_seq: 1, executed_at: 1_000 }]);
+        assert_eq!((response.order.price, response.order.quantity), (102, 6));
+        assert_eq!(response.order.status, OrderStatus::PartiallyFilled);
+        {
+            let book = state.default_market.order_book.lock().unwrap();
+            assert!(book.asks.is_empty());
+            assert_eq!(book.best_bid(), Some(102));
+        }
+
+        state.db_writes.drain().await;
+        let Json(stored) = get_order_handler(State(Arc::clone(&state)), Path(2)).await.unwrap();
+        assert_eq!((stored.price, stored.quantity), (102, 6));
+        assert_eq!(stored.status, OrderStatus::PartiallyFilled);
+    }
+
+    #[tokio::test]
+    async fn test_reprice_to_same_price_keeps_queue_position() {
+        let state = test_state();
+        for quantity in [10, 4] {
+            let payload = order_payload(Side::Sell, 100, quantity);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        let queue = |state: &AppState| state.default_market.order_book.lock().unwrap().asks.iter().map(|o| (o.id, o.price)).collect::<Vec<_>>();
+
+        let _ = modify_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 10.into(), price: Some(100) })).await.unwrap();
+        assert_eq!(queue(&state), vec![(1, 100), (2, 100)]);
+
+        // A real price change requeues, even back to the original level
+        let _ = modify_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 10.into(), price: Some(101) })).await.unwrap();
+        let _ = modify_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 10.into(), price: Some(100) })).await.unwrap();
+        assert_eq!(queue(&state), vec![(2, 100), (1, 100)]);
+    }
+
+    #[tokio::test]
+    async fn test_modify_throttled_within_min_interval() {
+        let interval = 1_000_000_000;
+        let state = test_state_with_config(Config { min_modify_interval_nanos: Some(interval), ..Config::default() });
+        let payload = order_payload(Side::Buy, 100, 10);
+        let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+
+        let modify = |quantity: u64| modify_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new(), Json(ModifyOrderPayload { quantity: quantity.into(), price: None }));
+        let _ = modify(9).await.unwrap();
+        let err = modify(8).await.unwrap_err();
+        assert!(matches!(&err, ApiError::TooManyRequests(message) if message.contains("retry after")), "{:?}", err);
+        assert_eq!(state.default_market.order_book.lock().unwrap().bids.front().unwrap().quantity, 9);
+
+        // Once the interval has passed the next modify goes through
+        *state.default_market.order_book.lock().unwrap().bids.front_mut().unwrap().last_modified_at.as_mut().unwrap() -= interval;
+        let (_, Json(response)) = modify(8).await.unwrap();
+        assert_eq!(response.order.quantity, 8);
+    }
+
+    #[tokio::test]
+    async fn test_modify_does_not_extend_max_lifetime() {
+        let lifetime = 1_000_000_000;
+        let state = test_state_with_config(Config { max_order_lifetime_nanos: Some(lifetime), ..Config::default() });
+        let payload = order_payload(Side::Buy, 100, 10);
+        let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+
+        // Modifies within the lifetime succeed
+        for quantity in [9, 8] {
+            let _ = modify_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new(), Json(ModifyOrderPayload { quantity: quantity.into(), price: None }))
+                .await
+                .unwrap();
+        }
+        // Pretend the order was created just past the deadline
+        state.default_market.order_book.lock().unwrap().bids.front_mut().unwrap().created_at -= lifetime + 1;
+        let err = modify_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 7.into(), price: None }))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::Gone(_)), "{:?}", err);
+        assert!(state.default_market.order_book.lock().unwrap().bids.is_empty());
+
+        let db_status: String = state.db_conn.lock().unwrap()
+            .query_row("SELECT status FROM orders WHERE id = 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(db_status, "Expired");
+    }
+
+    #[tokio::test]
+    async fn test_cancel_reason_user_versus_expiry() {
+        let lifetime = 1_000_000_000;
+        let state = test_state_with_config(Config { max_order_lifetime_nanos: Some(lifetime), ..Config::default() });
+        for price in [100, 99] {
+            let payload = order_payload(Side::Buy, price, 10);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+
+        let (_, Json(cancelled)) = cancel_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new()).await.unwrap();
+        assert_eq!(cancelled.cancel_reason, Some(CancelReason::UserRequest));
+
+        state.default_market.order_book.lock().unwrap().bids.front_mut().unwrap().created_at -= lifetime + 1;
+        let err = modify_order_handler(State(Arc::clone(&state)), Path(2), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 7.into(), price: None }))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::Gone(_)), "{:?}", err);
+
+        // Both are out of the book, so these come from the DB
+        let Json(user) = get_order_handler(State(Arc::clone(&state)), Path(1)).await.unwrap();
+        assert_eq!((user.status, user.cancel_reason), (OrderStatus::Cancelled, Some(CancelReason::UserRequest)));
+        let Json(expired) = get_order_handler(State(Arc::clone(&state)), Path(2)).await.unwrap();
+        assert_eq!((expired.status.clone(), expired.cancel_reason), (OrderStatus::Expired, Some(CancelReason::Expiry)));
+        assert_eq!(serde_json::to_value(&expired).unwrap()["cancel_reason"], "Expiry");
+    }
+
+    #[tokio::test]
+    async fn test_sweep_expires_orders_past_max_lifetime_without_modify() {
+        let lifetime = 1_000_000_000;
+        let clock = Arc::new(MockClock::new(0));
+        let config = Config { max_order_lifetime_nanos: Some(lifetime), ..Config::default() };
+        let state = build_state_with_clock(config, dummy_db_conn(), false, clock.clone()).unwrap();
+        let payload = order_payload(Side::Buy, 100, 10);
+        let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+
+        // Exactly at the deadline it still rests
+        clock.advance(lifetime as u64);
+        assert!(sweep_expired(&state).await.is_empty());
+        assert_eq!(state.default_market.order_book.lock().unwrap().bids.len(), 1);
+
+        clock.advance(1);
+        let expired = sweep_expired(&state).await;
+        assert_eq!(expired.iter().map(|o| (o.id, o.status.clone())).collect::<Vec<_>>(), vec![(1, OrderStatus::Expired)]);
+        assert!(state.default_market.order_book.lock().unwrap().bids.is_empty());
+        let Json(view) = get_order_handler(State(Arc::clone(&state)), Path(1)).await.unwrap();
+        assert_eq!((view.status, view.cancel_reason), (OrderStatus::Expired, Some(CancelReason::Expiry)));
+    }
+
+    #[tokio::test]
+    async fn test_order_events_published_in_book_order() {
+        let state = test_state_with_frozen_clock(Config::default());
+        let mut events = state.events.subscribe();
+        for (side, quantity) in [(Side::Sell, 10), (Side::Buy, 4)] {
+            let payload = order_payload(side, 100, quantity);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        let _ = modify_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 3.into(), price: None })).await.unwrap();
+        let _ = cancel_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new()).await.unwrap();
+
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            received.push(serde_json::to_value(&event).unwrap());
+        }
+        let types: Vec<&str> = received.iter().map(|e| e["type"].as_str().unwrap()).collect();
+        assert_eq!(types, vec!["order_added", "order_added", "trade", "order_modified", "order_cancelled"]);
+        assert_eq!(received[2], serde_json::json!({"type": "trade", "trade_id": 1, "trade_seq": 1, "executed_at": 1_000, "symbol": "DEFAULT", "bid_id": 2, "ask_id": 1, "price": 100, "quantity": 4}));
+        assert_eq!(received[3]["order"]["quantity"], 3);
+        assert_eq!(received[4]["order"]["status"], "Cancelled");
+    }
+
+    #[tokio::test]
+    async fn test_slow_subscriber_lags_instead_of_blocking() {
+        let state = test_state();
+        let mut slow = state.events.subscribe();
+        for i in 0..EVENT_BUS_CAPACITY as u64 + 10 {
+            let payload = order_payload(Side::Buy, 100 + i % 5, 1);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        assert!(matches!(slow.try_recv(), Err(broadcast::error::TryRecvError::Lagged(10))));
+        assert!(matches!(slow.try_recv(), Ok(BookEvent::OrderAdded { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_trade_stream_replays_after_last_event_id() {
+        use futures_util::StreamExt;
+        let state = test_state();
+        let cross = |state: Arc<AppState>| async move {
+            for side in [Side::Sell, Side::Buy] {
+                let payload = order_payload(side, 100, 5);
+                let _ = create_order_handler(State(state.clone()), HeaderMap::new(), Json(payload)).await.unwrap();
+            }
+        };
+        cross(Arc::clone(&state)).await;
+        cross(Arc::clone(&state)).await;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(LAST_EVENT_ID_HEADER, HeaderValue::from_static("1"));
+        let sse = trade_stream_handler(State(Arc::clone(&state)), Query(SymbolQuery::default()), headers).await.unwrap();
+        let mut body = sse.into_response().into_body().into_data_stream();
+        async fn next_frame(body: &mut axum::body::BodyDataStream) -> String {
+            let bytes = tokio::time::timeout(std::time::Duration::from_secs(2), body.next()).await.unwrap().unwrap().unwrap();
+            String::from_utf8(bytes.to_vec()).unwrap()
+        }
+
+        // Trade 1 was already seen; 2 comes from the table
+        let replayed = next_frame(&mut body).await;
+        assert!(replayed.contains("event: trade\n"), "{replayed}");
+        assert!(replayed.contains("id: 2\n"), "{replayed}");
+        assert!(replayed.contains("\"bid_id\":4,\"ask_id\":3"), "{replayed}");
+
+        cross(Arc::clone(&state)).await;
+        let live = next_frame(&mut body).await;
+        assert!(live.contains("id: 3\n"), "{live}");
+        assert!(live.contains("\"trade_id\":3"), "{live}");
+    }
+
+    // Upgrades only happen on a connection hyper is serving, so the WebSocket
+    // tests run the router on a real socket.
+    async fn serve_on_loopback(state: Arc<AppState>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = build_router(state).into_make_service_with_connect_info::<SocketAddr>();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    struct WsClient {
+        reader: ws::Reader<tokio::net::tcp::OwnedReadHalf>,
+        writer: tokio::net::tcp::OwnedWriteHalf,
+    }
+
+    impl WsClient {
+        // Sends the upgrade request and returns the response head with the
+        // socket, positioned at the first frame
+        async fn handshake(addr: SocketAddr) -> (String, tokio::net::TcpStream) {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let request = format!("GET /ws HTTP/1.1\r\nHost: {addr}\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n");
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                head.push(stream.read_u8().await.unwrap());
+            }
+            (String::from_utf8(head).unwrap(), stream)
+        }
+
+        async fn connect(addr: SocketAddr) -> WsClient {
+            let (head, stream) = WsClient::handshake(addr).await;
+            assert!(head.starts_with("HTTP/1.1 101"), "{head}");
+            assert!(head.contains("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="), "{head}");
+            let (reader, writer) = stream.into_split();
+            let mut client = WsClient { reader: ws::Reader::new(reader, false), writer };
+            // Once the pong is back the connection is subscribed to the bus
+            client.send(ws::Message::Ping(b"ready".to_vec())).await;
+            assert_eq!(client.recv().await, ws::Message::Pong(b"ready".to_vec()));
+            client
+        }
+
+        async fn send(&mut self, message: ws::Message) {
+            ws::write_message(&mut self.writer, &message, Some([7, 1, 3, 5])).await.unwrap();
+        }
+
+        async fn recv(&mut self) -> ws::Message {
+            tokio::time::timeout(std::time::Duration::from_secs(2), self.reader.next()).await.unwrap().unwrap()
+        }
+
+        async fn recv_json(&mut self) -> serde_json::Value {
+            match self.recv().await {
+                ws::Message::Text(text) => serde_json::from_str(&text).unwrap(),
+                other => panic!("expected a text message, got {other:?}"),
+            }
+        }
+
+        async fn send_json(&mut self, command: serde_json::Value) {
+            self.send(ws::Message::Text(command.to_string())).await;
+        }
+
+        // The next command reply, skipping book events
+        async fn recv_reply(&mut self) -> serde_json::Value {
+            loop {
+                let message = self.recv_json().await;
+                if message["type"] == "reply" {
+                    return message;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ws_pushes_tagged_book_events() {
+        let state = test_state_with_frozen_clock(Config::default());
+        let mut client = WsClient::connect(serve_on_loopback(Arc::clone(&state)).await).await;
 
-    let order_id = state.next_order_id.fetch_add(1, Ordering::Relaxed);
-    let new_order_obj = Order::new(
-        order_id,
-        payload.side.clone(),
-        payload.price,
-        payload.quantity,
-    );
-    let order_to_return = new_order_obj.clone();
-    let order_for_db = new_order_obj.clone();
-    let order_for_book = new_order_obj;
+        for (side, quantity) in [(Side::Sell, 10), (Side::Buy, 4)] {
+            let payload = order_payload(side, 100, quantity);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        let _ = cancel_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new()).await.unwrap();
 
-    {
-        let mut book_guard = state.order_book.lock().expect("Mutex lock failed for book");
-        tracing::debug!(order_id = order_id, "Acquired book lock for adding order");
-        book_guard.add_order(order_for_book, Arc::clone(&state.db_conn));
+        let mut types = Vec::new();
+        let mut trade = serde_json::Value::Null;
+        for _ in 0..4 {
+            let event = client.recv_json().await;
+            if event["type"] == "trade" {
+                trade = event.clone();
+            }
+            types.push(event["type"].as_str().unwrap().to_string());
+        }
+        assert_eq!(types, vec!["order_added", "order_added", "trade", "order_cancelled"]);
+        assert_eq!(trade, serde_json::json!({"type": "trade", "trade_id": 1, "trade_seq": 1, "executed_at": 1_000, "symbol": "DEFAULT", "bid_id": 2, "ask_id": 1, "price": 100, "quantity": 4}));
+
+        client.send(ws::Message::Close(Some(ws::CLOSE_NORMAL))).await;
+        assert_eq!(client.recv().await, ws::Message::Close(Some(ws::CLOSE_NORMAL)));
     }
-    tracing::debug!(order_id = order_id, "Released book lock after adding order");
 
-    let db_conn_clone: Arc<Mutex<Connection>> = Arc::clone(&state.db_conn);
-    task::spawn_blocking(move || {
-        let conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB insert");
-        tracing::debug!(order_id = order_for_db.id, "Acquired DB lock for INSERT");
-        conn_guard.execute(
-            "INSERT INTO orders (id, side, price, original_quantity, remaining_quantity, status, timestamp) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![
-                order_for_db.id,
-                format!("{:?}", order_for_db.side),
-                order_for_db.price,
-                order_for_db.quantity,
-                order_for_db.quantity,
-                format!("{:?}", order_for_db.status),
-                order_for_db.timestamp.to_string(), // STORE TIMESTAMP AS STRING
-            ],
-        )
-    })
-    .await
-    .map_err(|e| {
-        tracing::error!("Task join error for order insert: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?
-    .map_err(|e| {
-        tracing::error!("DB error inserting order {}: {}", order_id, e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    tracing::debug!(order_id = order_id, "DB INSERT successful");
+    #[tokio::test]
+    async fn test_ws_command_replies_carry_increasing_ack_seq() {
+        let state = test_state();
+        let mut client = WsClient::connect(serve_on_loopback(Arc::clone(&state)).await).await;
+        let order = |side: &str, quantity: u64| serde_json::json!({"side": side, "price": 100, "quantity": quantity});
 
-    Ok((StatusCode::CREATED, Json(order_to_return)))
-}
+        let mut acks = Vec::new();
+        for (request_id, command) in [
+            (1, serde_json::json!({"op": "create", "request_id": 1, "order": order("Sell", 10)})),
+            (2, serde_json::json!({"op": "create", "request_id": 2, "order": order("Buy", 4)})),
+            (3, serde_json::json!({"op": "modify", "request_id": 3, "order_id": 1, "quantity": 3})),
+        ] {
+            client.send_json(command).await;
+            let reply = client.recv_reply().await;
+            assert_eq!(reply["request_id"], request_id);
+            assert!(reply["status"] == 201 || reply["status"] == 200, "{reply}");
+            acks.push(reply["ack_seq"].as_u64().unwrap());
+        }
 
-async fn modify_order_handler(
-    State(state): State<Arc<AppState>>,
-    Path(order_id): Path<OrderId>,
-    Json(payload): Json<ModifyOrderPayload>,
-) -> Result<Json<Order>, StatusCode> {
-    tracing::info!(order_id = order_id, payload = ?payload, "Received modify order request");
+        // HTTP operations draw from the same sequence
+        let payload = order_payload(Side::Buy, 90, 1);
+        let (_, AckSeq(http_ack), _) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        acks.push(http_ack);
 
-    let modified_order_from_book = {
-        let mut book_guard = state.order_book.lock().expect("Mutex lock failed for book modify");
-        tracing::debug!(order_id = order_id, "Acquired book lock for modifying order");
-        book_guard.modify_order(order_id, payload.quantity)
-    };
-    tracing::debug!(order_id = order_id, "Released book lock after attempting modify");
+        client.send_json(serde_json::json!({"op": "cancel", "request_id": 4, "order_id": 1})).await;
+        let cancelled = client.recv_reply().await;
+        assert_eq!((cancelled["status"].as_u64(), cancelled["body"]["status"].as_str()), (Some(200), Some("Cancelled")));
+        acks.push(cancelled["ack_seq"].as_u64().unwrap());
+        assert!(acks.windows(2).all(|pair| pair[0] < pair[1]), "{acks:?}");
 
-    let order_for_db = match modified_order_from_book {
-        Some(order) => order,
-        None => return Err(StatusCode::NOT_FOUND),
-    };
+        // Rejections answer like the HTTP endpoint and use up no ack
+        client.send_json(serde_json::json!({"op": "cancel", "request_id": 5, "order_id": 99})).await;
+        let missing = client.recv_reply().await;
+        assert_eq!((missing["status"].as_u64(), missing["body"]["error"].as_str(), missing.get("ack_seq")), (Some(404), Some("not_found"), None));
+        client.send_json(serde_json::json!({"op": "explode"})).await;
+        assert_eq!(client.recv_reply().await["status"], 400);
+    }
 
-    let db_conn_clone: Arc<Mutex<Connection>> = Arc::clone(&state.db_conn);
-    let status_for_db = format!("{:?}", order_for_db.status);
-    let quantity_for_db = order_for_db.quantity;
-    let id_for_db = order_for_db.id;
+    #[tokio::test]
+    async fn test_ws_rejects_stale_commands_without_touching_the_book() {
+        // The clock reads 1_000 throughout
+        let state = test_state_with_frozen_clock(Config { ws_command_max_age_nanos: Some(500), ..Config::default() });
+        let mut client = WsClient::connect(serve_on_loopback(Arc::clone(&state)).await).await;
+        let order = serde_json::json!({"side": "Buy", "price": 100, "quantity": 5});
 
-    task::spawn_blocking(move || {
-        let conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB update (modify)");
-        tracing::debug!(order_id = id_for_db, "Acquired DB lock for UPDATE (modify)");
-        conn_guard.execute(
-            "UPDATE orders SET remaining_quantity = ?1, status = ?2 WHERE id = ?3",
-            params![quantity_for_db, status_for_db, id_for_db],
-        )
-    })
-    .await
-    .map_err(|e| {
-        tracing::error!("Task join error for order update (modify): {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?
-    .map_err(|e| {
-        tracing::error!("DB error updating order {} (modify): {}", order_id, e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    tracing::debug!(order_id = order_id, "DB UPDATE (modify) successful");
+        client.send_json(serde_json::json!({"op": "create", "request_id": 1, "expires_at": 999, "order": order})).await;
+        let expired = client.recv_reply().await;
+        assert_eq!((expired["status"].as_u64(), expired["body"]["error"].as_str()), (Some(410), Some("gone")), "{expired}");
+        assert!(expired["body"]["message"].as_str().unwrap().contains("expired at 999"), "{expired}");
 
-    Ok(Json(order_for_db))
-}
+        client.send_json(serde_json::json!({"op": "create", "request_id": 2, "sent_at": 100, "order": order})).await;
+        assert_eq!(client.recv_reply().await["status"], 410);
+        assert!(state.default_market.order_book.lock().unwrap().bids.is_empty());
+        assert_eq!(state.next_order_id.load(Ordering::SeqCst), 1);
 
-async fn cancel_order_handler(
-    State(state): State<Arc<AppState>>,
-    Path(order_id): Path<OrderId>,
-) -> Result<Json<Order>, StatusCode> {
-    tracing::info!(order_id = order_id, "Received cancel order request");
+        // Within the tolerance and not yet expired
+        client.send_json(serde_json::json!({"op": "create", "request_id": 3, "sent_at": 600, "expires_at": 1_000, "order": order})).await;
+        assert_eq!(client.recv_reply().await["status"], 201);
+    }
 
-    let cancelled_order_from_book = {
-        let mut book_guard = state.order_book.lock().expect("Mutex lock failed for book cancel");
-        tracing::debug!(order_id = order_id, "Acquired book lock for cancelling order");
-        book_guard.cancel_order(order_id)
-    };
-    tracing::debug!(order_id = order_id, "Released book lock after attempting cancel");
+    #[tokio::test]
+    async fn test_ws_order_commands_are_refused_in_read_only_mode() {
+        let state = test_state_with_config(Config { read_only: true, ..Config::default() });
+        let mut client = WsClient::connect(serve_on_loopback(Arc::clone(&state)).await).await;
 
-    let order_for_db = match cancelled_order_from_book {
-        Some(order) => order,
-        None => return Err(StatusCode::NOT_FOUND),
-    };
+        for (request_id, command) in [
+            (1, serde_json::json!({"op": "create", "request_id": 1, "order": {"side": "Buy", "price": 100, "quantity": 5}})),
+            (2, serde_json::json!({"op": "modify", "request_id": 2, "order_id": 1, "quantity": 3})),
+            (3, serde_json::json!({"op": "cancel", "request_id": 3, "order_id": 1})),
+        ] {
+            client.send_json(command).await;
+            let reply = client.recv_reply().await;
+            assert_eq!((reply["request_id"].as_u64(), reply["status"].as_u64(), reply.get("ack_seq")), (Some(request_id), Some(405), None), "{reply}");
+            assert_eq!(reply["body"]["error"], "read_only", "{reply}");
+        }
+        assert!(state.default_market.order_book.lock().unwrap().bids.is_empty());
+        assert_eq!(state.next_order_id.load(Ordering::SeqCst), 1);
 
-    let db_conn_clone: Arc<Mutex<Connection>> = Arc::clone(&state.db_conn);
-    let status_for_db = format!("{:?}", order_for_db.status);
-    let id_for_db = order_for_db.id;
+        // The feed itself is still open to a replica
+        client.send_json(serde_json::json!({"op": "subscribe", "request_id": 4, "symbol": "DEFAULT"})).await;
+        assert_eq!(client.recv_reply().await["status"], 200);
+    }
 
-    task::spawn_blocking(move || {
-        let conn_guard = db_conn_clone.lock().expect("Mutex lock failed for DB update (cancel)");
-        tracing::debug!(order_id = id_for_db, "Acquired DB lock for UPDATE (cancel)");
-        conn_guard.execute(
-            "UPDATE orders SET status = ?1, remaining_quantity = 0 WHERE id = ?2",
-            params![status_for_db, id_for_db],
-        )
-    })
-    .await
-    .map_err(|e| {
-        tracing::error!("Task join error for order update (cancel): {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?
-    .map_err(|e| {
-        tracing::error!("DB error updating order {} (cancel): {}", order_id, e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    tracing::debug!(order_id = order_id, "DB UPDATE (cancel) successful");
+    #[tokio::test]
+    async fn test_ws_connection_limit() {
+        let state = test_state_with_config(Config { ws_max_connections: Some(2), ..Config::default() });
+        let addr = serve_on_loopback(Arc::clone(&state)).await;
+        let open = |state: &AppState| state.metrics.ws_connections.load(Ordering::Relaxed);
 
-    Ok(Json(order_for_db))
-}
+        let first = WsClient::connect(addr).await;
+        let mut second = WsClient::connect(addr).await;
+        assert_eq!(open(&state), 2);
+        assert!(state.metrics.render(false).contains("oms_ws_connections{book=\"live\"} 2\n"));
+        let (head, _) = WsClient::handshake(addr).await;
+        assert!(head.starts_with("HTTP/1.1 503"), "{head}");
+        assert_eq!(open(&state), 2);
 
-// --- Unit Tests ---
-#[cfg(test)]
-mod tests {
-    use super::*;
+        // Dropped without a close frame, and closed cleanly: both give their slot back
+        drop(first);
+        second.send(ws::Message::Close(Some(ws::CLOSE_NORMAL))).await;
+        for _ in 0..200 {
+            if open(&state) == 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(open(&state), 0);
+        let _again = WsClient::connect(addr).await;
+        assert_eq!(open(&state), 1);
+    }
 
-    fn dummy_db_conn() -> Arc<Mutex<Connection>> {
-        Arc::new(Mutex::new(Connection::open_in_memory().unwrap()))
+    #[tokio::test]
+    async fn test_ws_duplicate_subscribe_delivers_once_and_unsubscribe_stops() {
+        for duplicate in [DuplicateSubscribe::Acknowledge, DuplicateSubscribe::Ignore] {
+            let state = test_state_with_config(Config { ws_duplicate_subscribe: duplicate, ..Config::default() });
+            let mut client = WsClient::connect(serve_on_loopback(Arc::clone(&state)).await).await;
+            let add = |symbol: &str, price: u64| serde_json::json!({"op": "create", "order": {"side": "Buy", "price": price, "quantity": 1, "symbol": symbol}});
+
+            client.send_json(serde_json::json!({"op": "subscribe", "request_id": 1, "symbol": "ACME"})).await;
+            assert_eq!(client.recv_json().await, serde_json::json!({"type": "reply", "request_id": 1, "status": 200, "body": {"symbol": "ACME", "subscribed": true}}));
+            client.send_json(serde_json::json!({"op": "subscribe", "request_id": 2, "symbol": "ACME"})).await;
+            if duplicate == DuplicateSubscribe::Acknowledge {
+                let again = client.recv_json().await;
+                assert_eq!(again["body"], serde_json::json!({"symbol": "ACME", "subscribed": true, "already_subscribed": true}));
+            }
+
+            // Two replies and ACME's event, once
+            client.send_json(add("OTHER", 90)).await;
+            client.send_json(add("ACME", 91)).await;
+            let mut received = Vec::new();
+            for _ in 0..3 {
+                let message = client.recv_json().await;
+                received.push(if message["type"] == "reply" { format!("reply {}", message["status"]) } else { format!("{} {}", message["type"].as_str().unwrap(), message["order"]["symbol"].as_str().unwrap()) });
+            }
+            received.sort();
+            assert_eq!(received, vec!["order_added ACME", "reply 201", "reply 201"], "{duplicate:?}");
+
+            client.send_json(serde_json::json!({"op": "unsubscribe", "request_id": 3, "symbol": "ACME"})).await;
+            assert_eq!(client.recv_json().await["body"], serde_json::json!({"symbol": "ACME", "subscribed": false}));
+            client.send_json(add("ACME", 92)).await;
+            let reply = client.recv_json().await;
+            assert_eq!((reply["type"].as_str(), reply["status"].as_u64()), (Some("reply"), Some(201)), "{reply}");
+            client.send_json(serde_json::json!({"op": "unsubscribe", "request_id": 4, "symbol": "ACME"})).await;
+            assert_eq!(client.recv_json().await["status"], 404);
+        }
     }
 
-    #[test]
-    fn test_order_creation() {
-        let order = Order::new(1, Side::Buy, 100, 50);
-        assert_eq!(order.id, 1);
-        assert_eq!(order.status, OrderStatus::Open);
+    #[tokio::test]
+    async fn test_ws_rejects_plain_get() {
+        use tower::ServiceExt;
+        let state = test_state();
+        let response = build_router(state)
+            .oneshot(axum::http::Request::builder().uri("/ws").body(axum::body::Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
 
-    #[test]
-    fn test_add_order_to_book() {
-        let mut book = OrderBook::new();
-        let db_conn = dummy_db_conn();
-        let buy_order = Order::new(1, Side::Buy, 100, 10);
-        book.add_order(buy_order.clone(), Arc::clone(&db_conn));
-        assert_eq!(book.bids.len(), 1);
-        assert_eq!(book.bids.front().unwrap().id, 1);
+    #[tokio::test]
+    async fn test_batch_create_matches_in_request_order() {
+        let state = test_state();
+        let batch = vec![
+            order_payload(Side::Sell, 101, 5),
+            order_payload(Side::Buy, 101, 3),
+            order_payload(Side::Buy, 99, 2),
+            CreateOrderPayload { order_type: OrderType::Market, ..order_payload(Side::Sell, 0, 4) },
+        ];
+        let (status, _, Json(views)) = create_orders_batch_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(batch)).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+
+        let summary: Vec<(OrderId, OrderStatus, u64)> = views.iter().map(|v| (v.id, v.status.clone(), v.quantity)).collect();
+        assert_eq!(summary, vec![
+            (1, OrderStatus::PartiallyFilled, 2),
+            (2, OrderStatus::Filled, 0),
+            (3, OrderStatus::Filled, 0),
+            (4, OrderStatus::Cancelled, 0),
+        ]);
+        assert_eq!(views[3].cancel_reason, Some(CancelReason::MarketRemainder));
+        assert_eq!(state.next_order_id.load(Ordering::SeqCst), 5);
+        let book = state.default_market.order_book.lock().unwrap();
+        assert_eq!((book.best_bid(), book.best_ask()), (None, Some(101)));
     }
 
-    #[test]
-    fn test_simple_match_full() {
-        let mut book = OrderBook::new();
-        let db_conn = dummy_db_conn();
-        let buy_order = Order::new(1, Side::Buy, 100, 10);
-        let sell_order = Order::new(2, Side::Sell, 100, 10);
-        book.add_order(buy_order, Arc::clone(&db_conn));
-        book.add_order(sell_order, Arc::clone(&db_conn));
-        assert!(book.bids.is_empty());
-        assert!(book.asks.is_empty());
+    #[tokio::test]
+    async fn test_batch_create_is_all_or_nothing() {
+        let state = test_state();
+        let batch = vec![
+            order_payload(Side::Buy, 100, 5),
+            order_payload(Side::Buy, 0, 5),
+        ];
+        let err = create_orders_batch_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(batch)).await.unwrap_err();
+        assert!(matches!(&err, ApiError::BadRequest(message) if message.starts_with("order 1:")), "{:?}", err);
+
+        assert_eq!(state.next_order_id.load(Ordering::SeqCst), 1);
+        assert!(state.default_market.order_book.lock().unwrap().bids.is_empty());
+        let rows: i64 = state.db_conn.lock().unwrap().query_row("SELECT COUNT(*) FROM orders", [], |row| row.get(0)).unwrap();
+        assert_eq!(rows, 0);
     }
 
-    #[test]
-    fn test_simple_match_partial_buy_fills() {
-        let mut book = OrderBook::new();
-        let db_conn = dummy_db_conn();
-        let buy_order = Order::new(1, Side::Buy, 100, 5);
-        let sell_order = Order::new(2, Side::Sell, 100, 10);
-        book.add_order(buy_order, Arc::clone(&db_conn));
-        book.add_order(sell_order, Arc::clone(&db_conn));
-        assert!(book.bids.is_empty());
-        assert_eq!(book.asks.len(), 1);
-        let ask_order = book.asks.front().unwrap();
-        assert_eq!(ask_order.id, 2);
-        assert_eq!(ask_order.quantity, 5);
-        assert_eq!(ask_order.status, OrderStatus::PartiallyFilled);
+    // Timing comparison; run with `cargo test --release -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn bench_batch_create_against_single_posts() {
+        const ORDERS: u64 = 1000;
+        let payload = |i: u64| order_payload(Side::Buy, 100 + i % 50, 1);
+
+        let state = test_state();
+        let started = std::time::Instant::now();
+        for i in 0..ORDERS {
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload(i))).await.unwrap();
+        }
+        let single = started.elapsed();
+
+        let state = test_state();
+        let started = std::time::Instant::now();
+        let (_, _, Json(views)) = create_orders_batch_handler(State(Arc::clone(&state)), HeaderMap::new(), Json((0..ORDERS).map(payload).collect())).await.unwrap();
+        let batch = started.elapsed();
+
+        assert_eq!(views.len() as u64, ORDERS);
+        tracing::info!(orders = ORDERS, single = ?single, batch = ?batch, "Batch create timing");
+        assert!(batch < single, "batch {:?} was not faster than single POSTs {:?}", batch, single);
     }
 
     #[test]
-    fn test_simple_match_partial_sell_fills() {
-        let mut book = OrderBook::new();
-        let db_conn = dummy_db_conn();
-        let buy_order = Order::new(1, Side::Buy, 100, 10);
-        let sell_order = Order::new(2, Side::Sell, 100, 5);
-        book.add_order(buy_order, Arc::clone(&db_conn));
-        book.add_order(sell_order, Arc::clone(&db_conn));
-        assert!(book.asks.is_empty());
-        assert_eq!(book.bids.len(), 1);
-        let bid_order = book.bids.front().unwrap();
-        assert_eq!(bid_order.id, 1);
-        assert_eq!(bid_order.quantity, 5);
-        assert_eq!(bid_order.status, OrderStatus::PartiallyFilled);
-    }
-
-     #[test]
-    fn test_no_match_price_gap() {
-        let mut book = OrderBook::new();
-        let db_conn = dummy_db_conn();
-        let _buy_order = Order::new(1, Side::Buy, 100, 10);
-        let _sell_order = Order::new(2, Side::Sell, 105, 10);
+    fn test_list_orders_filter_combinations() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        let rows = [
+            (1, Side::Buy, OrderStatus::Open),
+            (2, Side::Sell, OrderStatus::Open),
+            (3, Side::Buy, OrderStatus::Filled),
+            (4, Side::Sell, OrderStatus::Cancelled),
+            (5, Side::Buy, OrderStatus::Open),
+            (6, Side::Sell, OrderStatus::PartiallyFilled),
+        ];
+        for (id, side, status) in rows {
+            let order = Order { status, ..Order::new(id, side, 100, 10) };
+            insert_order_row(&conn, "INSERT", "orders", &order, 10).unwrap();
+        }
+
+        let list = |status: Option<&str>, side: Option<&str>, limit: Option<u32>| -> Vec<OrderId> {
+            let query = OrderListQuery { status: status.map(String::from), side: side.map(String::from), symbol: None, limit };
+            load_order_views(&conn, "orders", false, &query.filter().unwrap()).unwrap().iter().map(|v| v.id).collect()
+        };
+        assert_eq!(list(None, None, None), vec![6, 5, 4, 3, 2, 1]);
+        assert_eq!(list(Some("Open"), None, None), vec![5, 2, 1]);
+        assert_eq!(list(None, Some("Buy"), None), vec![5, 3, 1]);
+        assert_eq!(list(None, None, Some(2)), vec![6, 5]);
+        assert_eq!(list(Some("Open"), Some("Buy"), None), vec![5, 1]);
+        assert_eq!(list(Some("Open"), None, Some(1)), vec![5]);
+        assert_eq!(list(None, Some("Sell"), Some(2)), vec![6, 4]);
+        assert_eq!(list(Some("Open"), Some("Sell"), Some(5)), vec![2]);
+        assert_eq!(list(Some("Expired"), Some("Buy"), None), Vec::<OrderId>::new());
+    }
 
-        book.add_order(_buy_order.clone(), Arc::clone(&db_conn));
-        book.add_order(_sell_order.clone(), Arc::clone(&db_conn));
+    #[tokio::test]
+    async fn test_list_orders_rejects_unknown_filters() {
+        let state = test_state();
+        for (status, side, expected) in [
+            (Some("open"), None, "unknown status 'open'"),
+            (None, Some("Short"), "unknown side 'Short'"),
+            // Never spliced into SQL, so this is just an unknown value
+            (Some("Open' OR '1'='1"), None, "unknown status 'Open' OR '1'='1'"),
+        ] {
+            let query = OrderListQuery { status: status.map(String::from), side: side.map(String::from), symbol: None, limit: None };
+            let err = list_orders_handler(State(Arc::clone(&state)), Query(query)).await.unwrap_err();
+            assert_eq!(err, ApiError::BadRequest(expected.to_string()));
+        }
+        let Json(all) = list_orders_handler(State(Arc::clone(&state)), Query(OrderListQuery::default())).await.unwrap();
+        assert!(all.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_file_reads_wait_on_neither_writer_nor_each_other() {
+        let path = std::env::temp_dir().join(format!("oms_read_pool_{}.db", std::process::id()));
+        let cleanup = |path: &std::path::Path| {
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+            }
+        };
+        cleanup(&path);
+        let conn = Connection::open(&path).unwrap();
+        conn.pragma_update(None, "journal_mode", "WAL").unwrap();
+        init_schema(&conn).unwrap();
+        let state = build_state(Config::default(), Arc::new(Mutex::new(conn)), false).unwrap();
+        assert!(matches!(*state.read_pool, ReadPool::File { .. }));
+        for (side, price) in [(Side::Buy, 99), (Side::Sell, 101)] {
+            let payload = order_payload(side, price, 10);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+
+        // Writer mutex held mid-transaction, plus one read connection checked out
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let (writer, pool) = (Arc::clone(&state.db_conn), Arc::clone(&state.read_pool));
+        let blocker = std::thread::spawn(move || {
+            let mut conn_guard = writer.lock().unwrap();
+            let tx = conn_guard.transaction().unwrap();
+            tx.execute("UPDATE orders SET status = 'Cancelled' WHERE id = 1", []).unwrap();
+            let held = pool.get().unwrap();
+            let _: i64 = held.query_row("SELECT COUNT(*) FROM orders", [], |row| row.get(0)).unwrap();
+            locked_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+            drop(held);
+            tx.rollback().unwrap();
+        });
+        locked_rx.recv().unwrap();
+
+        let list = |status: &str| {
+            let query = OrderListQuery { status: Some(status.to_string()), ..OrderListQuery::default() };
+            list_orders_handler(State(Arc::clone(&state)), Query(query))
+        };
+        let (open, cancelled) = tokio::time::timeout(std::time::Duration::from_secs(2), async { tokio::join!(list("Open"), list("Cancelled")) })
+            .await
+            .expect("reads blocked behind the writer");
+        // Only committed state is visible
+        assert_eq!(open.unwrap().0.iter().map(|v| v.id).collect::<Vec<_>>(), vec![2, 1]);
+        assert!(cancelled.unwrap().0.is_empty());
 
-        assert_eq!(book.bids.len(), 1);
-        assert_eq!(book.asks.len(), 1);
-        assert_eq!(book.bids.front().unwrap().id, 1);
-        assert_eq!(book.asks.front().unwrap().id, 2);
+        release_tx.send(()).unwrap();
+        blocker.join().unwrap();
+        cleanup(&path);
     }
 
-    #[test]
-    fn test_match_with_better_price() {
-        let mut book = OrderBook::new();
-        let db_conn = dummy_db_conn();
-        let buy_order = Order::new(1, Side::Buy, 105, 10);
-        let sell_order = Order::new(2, Side::Sell, 100, 10);
+    #[tokio::test]
+    async fn test_ready_reports_open_orders_and_db_outage() {
+        let state = test_state();
+        assert_eq!(health_handler().await, "ok");
+        for (side, price) in [(Side::Buy, 99), (Side::Sell, 101)] {
+            let payload = order_payload(side, price, 10);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        let Json(ready) = ready_handler(State(Arc::clone(&state))).await.unwrap();
+        assert_eq!(ready.open_orders, 2);
 
-        book.add_order(buy_order, Arc::clone(&db_conn));
-        book.add_order(sell_order, Arc::clone(&db_conn));
+        state.db_conn.lock().unwrap().execute("DROP TABLE orders", []).unwrap();
+        let err = ready_handler(State(Arc::clone(&state))).await.unwrap_err();
+        assert_eq!(err, ApiError::Unavailable("database unavailable".to_string()));
+        assert_eq!(err.into_response().status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
 
-        assert!(book.bids.is_empty());
-        assert!(book.asks.is_empty());
+    #[tokio::test]
+    async fn test_metrics_count_order_lifecycle() {
+        let state = test_state();
+        for (side, price, quantity) in [(Side::Buy, 99, 10), (Side::Sell, 101, 5), (Side::Buy, 101, 3), (Side::Sell, 105, 1)] {
+            let payload = order_payload(side, price, quantity);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        let _ = modify_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 8.into(), price: None })).await.unwrap();
+        let _ = cancel_order_handler(State(Arc::clone(&state)), Path(4), HeaderMap::new()).await.unwrap();
+
+        // Rendered while the book is locked, so it can't be taking that lock
+        let text = {
+            let _book_guard = state.default_market.order_book.lock().unwrap();
+            state.metrics.render(state.paper)
+        };
+        let response = metrics_handler(State(Arc::clone(&state))).await.into_response();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/plain; version=0.0.4");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, text.as_bytes());
+        for line in [
+            "# TYPE oms_orders_created_total counter",
+            "oms_orders_created_total{book=\"live\"} 4",
+            "oms_orders_cancelled_total{book=\"live\"} 1",
+            "oms_orders_modified_total{book=\"live\"} 1",
+            "oms_trades_total{book=\"live\"} 1",
+            "oms_matched_volume_total{book=\"live\"} 3",
+            "# TYPE oms_resting_orders gauge",
+            "oms_resting_orders{book=\"live\",symbol=\"DEFAULT\",side=\"bid\"} 1",
+            "oms_resting_orders{book=\"live\",symbol=\"DEFAULT\",side=\"ask\"} 1",
+            "# TYPE oms_create_order_latency_seconds summary",
+            "oms_create_order_latency_seconds_count{book=\"live\",stage=\"matching\"} 4",
+            "oms_create_order_latency_seconds_count{book=\"live\",stage=\"receipt_to_match\"} 4",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {:?} in\n{}", line, text);
+        }
     }
 
     #[test]
-    fn test_multiple_matches_from_one_order() {
-        let mut book = OrderBook::new();
-        let db_conn = dummy_db_conn();
-        let sell_order1 = Order::new(1, Side::Sell, 100, 5);
-        let sell_order2 = Order::new(2, Side::Sell, 101, 15);
-        let buy_order = Order::new(3, Side::Buy, 101, 15);
-        book.add_order(sell_order1, Arc::clone(&db_conn));
-        book.add_order(sell_order2, Arc::clone(&db_conn));
-        book.add_order(buy_order, Arc::clone(&db_conn));
-        assert!(book.bids.is_empty());
-        assert_eq!(book.asks.len(), 1);
-        let ask_order = book.asks.front().unwrap();
-        assert_eq!(ask_order.id, 2);
-        assert_eq!(ask_order.quantity, 5);
-        assert_eq!(ask_order.status, OrderStatus::PartiallyFilled);
+    fn test_latency_histogram_quantiles_within_bucket_error() {
+        let histogram = LatencyHistogram::default();
+        assert_eq!(histogram.quantile(0.5), 0);
+        // 1us..=1000us, one each
+        for micros in 1..=1000u64 {
+            histogram.record(std::time::Duration::from_micros(micros));
+        }
+        assert_eq!(histogram.count(), 1000);
+        for (q, exact) in [(0.5, 500_000u64), (0.99, 990_000), (0.999, 999_000)] {
+            let reported = histogram.quantile(q);
+            assert!(reported >= exact && reported <= exact + exact / 16, "q{} reported {} for {}", q, reported, exact);
+        }
+
+        // Every value maps into a bucket whose bounds contain it
+        for nanos in [0, 1, 15, 16, 17, 31, 32, 1_000, 123_456_789, u64::MAX] {
+            let index = LatencyHistogram::bucket_index(nanos);
+            assert!(index < LATENCY_BUCKETS);
+            assert!(LatencyHistogram::bucket_upper(index) >= nanos);
+            assert!(index == 0 || LatencyHistogram::bucket_upper(index - 1) < nanos, "{}", nanos);
+        }
     }
 
     #[test]
-    fn test_modify_order_quantity_bid() {
-        let mut book = OrderBook::new();
-        let db_conn = dummy_db_conn();
-        let order1 = Order::new(1, Side::Buy, 100, 10);
-        book.add_order(order1, Arc::clone(&db_conn));
-        let result = book.modify_order(1, 5);
-        assert!(result.is_some());
-        assert_eq!(result.as_ref().unwrap().quantity, 5);
-        assert_eq!(book.bids.front().unwrap().quantity, 5);
+    fn test_bind_addr_falls_back_to_loopback_default() {
+        let default = SocketAddr::from(([127, 0, 0, 1], 3000));
+        assert_eq!(bind_addr(None), default);
+        assert_eq!(bind_addr(Some("0.0.0.0:8080")), SocketAddr::from(([0, 0, 0, 0], 8080)));
+        assert_eq!(bind_addr(Some("[::1]:9000")).to_string(), "[::1]:9000");
+        for bad in ["", "localhost:8080", "0.0.0.0", "0.0.0.0:99999"] {
+            assert_eq!(bind_addr(Some(bad)), default, "{:?}", bad);
+        }
     }
 
-     #[test]
-    fn test_modify_order_quantity_ask() {
-        let mut book = OrderBook::new();
+    #[tokio::test]
+    async fn test_db_path_env_and_in_memory_database() {
+        assert_eq!(db_path(None), DB_PATH);
+        assert_eq!(db_path(Some(" ".to_string())), DB_PATH);
+        assert_eq!(db_path(Some("/data/oms.db".to_string())), "/data/oms.db");
+
+        for read_only in [false, true] {
+            let conn = open_db(IN_MEMORY_DB_PATH, read_only).unwrap();
+            let state = build_state(Config { read_only, ..Config::default() }, Arc::new(Mutex::new(conn)), false).unwrap();
+            // Reads go through the writer, as there's no file to pool
+            assert!(matches!(*state.read_pool, ReadPool::Writer(_)));
+            let Json(ready) = ready_handler(State(state)).await.unwrap();
+            assert_eq!(ready.open_orders, 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_all_flushes_book_and_db() {
+        let state = test_state();
+        for (side, price, quantity) in [(Side::Buy, 99, 10), (Side::Sell, 101, 10), (Side::Buy, 101, 4), (Side::Sell, 102, 5)] {
+            let payload = order_payload(side, price, quantity);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+
+        // Order 3 filled against 2, which is left partially filled
+        let (_, Json(summary)) = cancel_all_orders_handler(State(Arc::clone(&state))).await.unwrap();
+        assert_eq!(summary.cancelled_count, 3);
+        assert_eq!(summary.order_ids, vec![1, 2, 4]);
+        {
+            let book = state.default_market.order_book.lock().unwrap();
+            assert!(book.bids.is_empty() && book.asks.is_empty());
+            assert_eq!(book.top_of_book().bid_price, 0);
+        }
+
+        let Json(partial) = get_order_handler(State(Arc::clone(&state)), Path(2)).await.unwrap();
+        assert_eq!((partial.status, partial.quantity, partial.cancel_reason), (OrderStatus::Cancelled, 0, Some(CancelReason::MassCancel)));
+        let cancelled: i64 = state.db_conn.lock().unwrap()
+            .query_row("SELECT COUNT(*) FROM orders WHERE status = 'Cancelled' AND cancel_reason = 'MassCancel'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(cancelled, 3);
+
+        let (_, Json(empty)) = cancel_all_orders_handler(State(Arc::clone(&state))).await.unwrap();
+        assert_eq!(empty.cancelled_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_filled_order_reports_filled_state() {
+        let state = test_state();
+        for side in [Side::Sell, Side::Buy] {
+            let payload = order_payload(side, 100, 10);
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        for _ in 0..100 {
+            let status: String = state.db_conn.lock().unwrap()
+                .query_row("SELECT status FROM orders WHERE id = 1", [], |row| row.get(0))
+                .unwrap();
+            if status == "Filled" {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let response = cancel_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new()).await.unwrap_err();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let view: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(view["id"], 1);
+        assert_eq!(view["status"], "Filled");
+        assert_eq!(view["quantity"], 0);
+
+        // Modifying it can't bring it back either
+        let err = modify_order_handler(State(Arc::clone(&state)), Path(1), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 5.into(), price: None })).await.unwrap_err();
+        assert_eq!(err, ApiError::Conflict("order 1 is already Filled".to_string()));
+        assert!(state.default_market.order_book.lock().unwrap().find_order(1).is_none());
+        let err = modify_order_handler(State(Arc::clone(&state)), Path(999), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 5.into(), price: None })).await.unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)), "{:?}", err);
+
+        // Never-seen ids are still a plain 404
+        let response = cancel_order_handler(State(Arc::clone(&state)), Path(999), HeaderMap::new()).await.unwrap_err();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_oco_cancellations_are_persisted() {
+        let state = test_state();
+        for (side, price, group_id) in [(Side::Sell, 110, Some(7)), (Side::Sell, 120, Some(7)), (Side::Buy, 110, None)] {
+            let payload = CreateOrderPayload { group_id, ..order_payload(side, price, 10) };
+            let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        }
+        // Wait for the detached persistence tasks to drain
+        for _ in 0..100 {
+            let status: String = state.db_conn.lock().unwrap()
+                .query_row("SELECT status FROM orders WHERE id = 2", [], |row| row.get(0))
+                .unwrap();
+            if status == "Cancelled" {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("OCO sibling was not persisted as cancelled");
+    }
+
+    #[tokio::test]
+    async fn test_paper_orders_are_isolated_from_live_tables() {
         let db_conn = dummy_db_conn();
-        let order1 = Order::new(1, Side::Sell, 105, 20);
-        book.add_order(order1, Arc::clone(&db_conn));
+        let live = build_state(Config::default(), Arc::clone(&db_conn), false).unwrap();
+        let paper = build_state(Config::default(), Arc::clone(&db_conn), true).unwrap();
+
+        let live_payload = order_payload(Side::Sell, 100, 10);
+        let _ = create_order_handler(State(Arc::clone(&live)), HeaderMap::new(), Json(live_payload)).await.unwrap();
+        for side in [Side::Sell, Side::Buy] {
+            let payload = order_payload(side, 100, 10);
+            let (_, _, Json(CreateOrderResponse { order, .. })) = create_order_handler(State(Arc::clone(&paper)), HeaderMap::new(), Json(payload)).await.unwrap();
+            assert!(order.paper);
+        }
+
+        // Paper orders matched each other, not the resting live ask
+        assert!(paper.default_market.order_book.lock().unwrap().asks.is_empty());
+        assert!(paper.default_market.order_book.lock().unwrap().bids.is_empty());
+        assert_eq!(live.default_market.order_book.lock().unwrap().asks.len(), 1);
+        assert_eq!(live.default_market.order_book.lock().unwrap().totals.trade_count, 0);
+
+        let count = |table: &str| -> i64 {
+            db_conn.lock().unwrap()
+                .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(count("orders"), 1);
+        assert_eq!(count("paper_orders"), 2);
+        let live_status: String = db_conn.lock().unwrap()
+            .query_row("SELECT status FROM orders WHERE id = 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(live_status, "Open");
+        // The paper fill and its audit trail have tables of their own; the live
+        // trades table is never touched and its audit log holds only the live order
+        assert_eq!((count("trades"), count("paper_trades")), (0, 1));
+        assert_eq!(count("order_events"), 1);
+        assert_eq!(count("paper_order_events"), 4);
+        let Json(history) = order_history_handler(State(Arc::clone(&paper)), Path(2)).await.unwrap();
+        assert_eq!(history.last().map(|event| event.reason.as_str()), Some("Fill"));
 
-        let result = book.modify_order(1, 15);
-        assert!(result.is_some());
-        assert_eq!(result.as_ref().unwrap().quantity, 15);
-        assert_eq!(book.asks.front().unwrap().quantity, 15);
+        // Paper rows left in the live tables by older versions are moved out
+        {
+            let conn = db_conn.lock().unwrap();
+            conn.execute("DROP TRIGGER order_events_no_delete", []).unwrap();
+            conn.execute("INSERT INTO trades (paper, trade_id, executed_at, bid_id, ask_id, price, quantity) VALUES (1, 9, '5', 8, 7, 100, 1)", []).unwrap();
+            conn.execute("INSERT INTO order_events (paper, order_id, to_status, remaining_quantity, reason, timestamp) VALUES (1, 8, 'Filled', 0, 'Fill', '5')", []).unwrap();
+            init_schema(&conn).unwrap();
+            assert!(conn.execute("DELETE FROM order_events", []).is_err());
+        }
+        assert_eq!((count("trades"), count("paper_trades")), (0, 2));
+        assert_eq!((count("order_events"), count("paper_order_events")), (1, 5));
     }
 
     #[test]
-    fn test_modify_order_not_found() {
-        let mut book = OrderBook::new();
-        let db_conn = dummy_db_conn(); // Not strictly needed here but good practice
-        let order1 = Order::new(1, Side::Buy, 100, 10);
-        // Order is not added to book, but modify_order works on the book content
-        // book.add_order(order1, Arc::clone(&db_conn)); // Let's test on an empty book
+    fn test_load_open_orders_expires_ancient_orders() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        let day: u128 = 86_400 * 1_000_000_000;
+        let now = 400 * day;
+        for (id, timestamp) in [(1u64, now - 365 * day), (2u64, now - day / 2)] {
+            conn.execute(
+                "INSERT INTO orders (id, side, price, original_quantity, remaining_quantity, status, timestamp) VALUES (?1, 'Buy', 100, 10, 10, 'Open', ?2)",
+                params![id, timestamp.to_string()],
+            ).unwrap();
+        }
+        // Entered long ago but modified recently: its age still counts from entry
+        conn.execute(
+            "INSERT INTO orders (id, side, price, original_quantity, remaining_quantity, status, timestamp, created_at) VALUES (3, 'Buy', 100, 10, 10, 'Open', ?1, ?2)",
+            params![(now - day / 2).to_string(), (now - 2 * day).to_string()],
+        ).unwrap();
 
-        let result = book.modify_order(order1.id, 5); // Use order1.id
-        assert!(result.is_none()); // If order1 was not added, it shouldn't be found
-    }
+        // Default: everything is loaded
+        let loaded = load_open_orders(&conn, false, &Config::default(), now).unwrap();
+        assert_eq!(loaded.orders.len(), 3);
+        assert_eq!(loaded.expired, 0);
 
+        let config = Config { max_order_age_nanos: Some(day), ..Config::default() };
+        let loaded = load_open_orders(&conn, false, &config, now).unwrap();
+        assert_eq!(loaded.orders.len(), 1);
+        assert_eq!(loaded.orders[0].id, 2);
+        assert_eq!(loaded.expired, 2);
+        let events: i64 = conn.query_row("SELECT COUNT(*) FROM order_events WHERE to_status = 'Expired'", [], |row| row.get(0)).unwrap();
+        assert_eq!(events, 2);
+
+        let (status, remaining): (String, u64) = conn
+            .query_row("SELECT status, remaining_quantity FROM orders WHERE id = 1", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(status, "Expired");
+        assert_eq!(remaining, 0);
+    }
 
     #[test]
-    fn test_modify_order_zero_quantity_cancels() {
-        let mut book = OrderBook::new();
-        let db_conn = dummy_db_conn();
-        let order1 = Order::new(1, Side::Buy, 100, 10);
-        book.add_order(order1, Arc::clone(&db_conn));
-        let result = book.modify_order(1, 0);
-        assert!(result.is_some());
-        assert_eq!(result.as_ref().unwrap().status, OrderStatus::Cancelled);
-        assert!(book.bids.is_empty());
+    fn test_load_open_orders_expires_lapsed_good_till_date() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        let now = 1_000_000;
+        for (id, expires_at) in [(1u64, Some(now - 1)), (2, Some(now + 1)), (3, None)] {
+            let mut order = Order::new(id, Side::Buy, 100, 10);
+            order.expires_at = expires_at;
+            insert_order_row(&conn, "INSERT", "orders", &order, order.quantity).unwrap();
+        }
+
+        let loaded = load_open_orders(&conn, false, &Config::default(), now).unwrap();
+        let ids: Vec<OrderId> = loaded.orders.iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![2, 3]);
+        assert_eq!(loaded.orders[0].expires_at, Some(now + 1));
+        assert_eq!(loaded.expired, 1);
+        let status: String = conn.query_row("SELECT status FROM orders WHERE id = 1", [], |row| row.get(0)).unwrap();
+        assert_eq!(status, "Expired");
     }
 
     #[test]
-    fn test_cancel_order_bid() {
-        let mut book = OrderBook::new();
-        let db_conn = dummy_db_conn();
-        let order1 = Order::new(1, Side::Buy, 100, 10);
-        let order2 = Order::new(2, Side::Buy, 99, 5);
-        book.add_order(order1.clone(), Arc::clone(&db_conn));
-        book.add_order(order2.clone(), Arc::clone(&db_conn));
-        let result = book.cancel_order(1);
-        assert!(result.is_some());
-        assert_eq!(result.as_ref().unwrap().status, OrderStatus::Cancelled);
-        assert_eq!(book.bids.len(), 1);
-        assert_eq!(book.bids.front().unwrap().id, 2);
+    fn test_load_open_orders_round_trips_type_and_time_in_force() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        let mut market = Order::new(1, Side::Sell, 0, 7);
+        market.order_type = OrderType::Market;
+        market.time_in_force = TimeInForce::Fok;
+        insert_order_row(&conn, "INSERT", ORDERS_TABLE, &market, 7).unwrap();
+        insert_order_row(&conn, "INSERT", ORDERS_TABLE, &Order::new(2, Side::Buy, 100, 5), 5).unwrap();
+        // Written by a newer version this one doesn't understand
+        conn.execute(
+            "INSERT INTO orders (id, side, price, original_quantity, remaining_quantity, status, timestamp, time_in_force) VALUES (3, 'Buy', 100, 10, 10, 'Open', '1', 'Gtd')",
+            [],
+        ).unwrap();
+
+        let loaded = load_open_orders(&conn, false, &Config::default(), now_nanos()).unwrap();
+        let orders: Vec<(OrderId, OrderType, TimeInForce)> = loaded.orders.iter().map(|o| (o.id, o.order_type, o.time_in_force)).collect();
+        assert_eq!(orders, vec![(1, OrderType::Market, TimeInForce::Fok), (2, OrderType::Limit, TimeInForce::Gtc)]);
+        assert_eq!(loaded.quarantined, 1);
     }
 
     #[test]
-    fn test_cancel_order_ask() {
-        let mut book = OrderBook::new();
+    fn test_recovery_summary_reports_quarantined_rows() {
         let db_conn = dummy_db_conn();
-        let order1 = Order::new(1, Side::Sell, 105, 10);
-        let order2 = Order::new(2, Side::Sell, 110, 5);
-        book.add_order(order1.clone(), Arc::clone(&db_conn));
-        book.add_order(order2.clone(), Arc::clone(&db_conn));
+        {
+            let conn = db_conn.lock().unwrap();
+            // Order 4 is still marked live with nothing left
+            let rows = [(1, "Buy", "100", 10), (2, "Sideways", "100", 10), (3, "Sell", "not-a-timestamp", 10), (4, "Sell", "100", 0), (9, "Sell", "100", 10)];
+            for (id, side, timestamp, remaining) in rows {
+                conn.execute(
+                    "INSERT INTO orders (id, side, price, original_quantity, remaining_quantity, status, timestamp) VALUES (?1, ?2, 100, 10, ?4, 'Open', ?3)",
+                    params![id, side, timestamp, remaining],
+                ).unwrap();
+            }
+        }
+
+        let state = build_state(Config::default(), Arc::clone(&db_conn), false).unwrap();
+        assert_eq!(state.recovery.orders_loaded, 2);
+        assert_eq!(state.recovery.rows_quarantined, 2);
+        assert_eq!(state.recovery.orders_expired, 0);
+        assert_eq!(state.recovery.reconciliation_corrections, 1);
+        assert_eq!(state.recovery.next_order_id, 10);
+        assert!(state.default_market.order_book.lock().unwrap().find_order(4).is_none());
+
+        let conn = db_conn.lock().unwrap();
+        let (loaded, quarantined, corrections): (i64, i64, i64) = conn
+            .query_row("SELECT orders_loaded, rows_quarantined, reconciliation_corrections FROM recovery_log ORDER BY id DESC LIMIT 1", [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap();
+        assert_eq!((loaded, quarantined, corrections), (2, 2, 1));
+        let status: String = conn.query_row("SELECT status FROM orders WHERE id = 4", [], |row| row.get(0)).unwrap();
+        assert_eq!(status, "Filled");
+    }
 
-        let result = book.cancel_order(1);
-        assert!(result.is_some());
-        assert_eq!(result.as_ref().unwrap().status, OrderStatus::Cancelled);
-        assert_eq!(book.asks.len(), 1);
-        assert_eq!(book.asks.front().unwrap().id, 2);
+    #[tokio::test]
+    async fn test_read_only_mode_rejects_mutations() {
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let state = test_state_with_config(Config { read_only: true, ..Config::default() });
+        let app = build_router(state);
+
+        let request = |method: &str, uri: &str| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{"side":"Buy","price":100,"quantity":10}"#))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request("GET", "/stats/spread")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.clone().oneshot(request("POST", "/orders")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"], "read_only");
+        assert!(json["message"].as_str().is_some_and(|message| message.contains("read-only")), "{json}");
+        let response = app.clone().oneshot(request("PUT", "/orders/1")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let response = app.oneshot(request("DELETE", "/orders/1")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn test_order_rate_limit_returns_429_per_client() {
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        // The clock is frozen, so spent tokens never come back
+        let state = test_state_with_frozen_clock(Config { order_rate_per_sec: Some(2), ..Config::default() });
+        let app = build_router(Arc::clone(&state));
+        let submit = |client: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/orders")
+                .header("content-type", "application/json")
+                .header(CLIENT_ID_HEADER, client)
+                .body(Body::from(r#"{"side":"Buy","price":100,"quantity":10}"#))
+                .unwrap()
+        };
+
+        for _ in 0..2 {
+            let response = app.clone().oneshot(submit("fast")).await.unwrap();
+            assert!(response.status().is_success());
+        }
+        for _ in 0..3 {
+            let response = app.clone().oneshot(submit("fast")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+        }
+        // Throttled submissions never reached the book; other clients are unaffected
+        assert_eq!(state.default_market.order_book.lock().unwrap().bids.len(), 2);
+        let response = app.oneshot(submit("other")).await.unwrap();
+        assert!(response.status().is_success());
+    }
+
+    #[tokio::test]
+    async fn test_order_rate_limit_charges_a_batch_per_order() {
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        // Frozen clock: a burst of 3 and nothing more
+        let state = test_state_with_frozen_clock(Config { order_rate_per_sec: Some(1), order_rate_burst: Some(3), ..Config::default() });
+        let app = build_router(Arc::clone(&state));
+        let batch = |client: &str, orders: usize| {
+            let order = r#"{"side":"Buy","price":100,"quantity":1}"#;
+            Request::builder()
+                .method("POST")
+                .uri("/orders/batch")
+                .header("content-type", "application/json")
+                .header(CLIENT_ID_HEADER, client)
+                .body(Body::from(format!("[{}]", vec![order; orders].join(","))))
+                .unwrap()
+        };
+        let resting = || state.default_market.order_book.lock().unwrap().bids.len();
+
+        let response = app.clone().oneshot(batch("desk", 2)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        // One token left: a batch of two is refused whole, not cut down to one
+        let response = app.clone().oneshot(batch("desk", 2)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+        assert_eq!(resting(), 2);
+        let response = app.clone().oneshot(batch("desk", 1)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(resting(), 3);
+
+        // More than the whole burst could never go through
+        let response = app.oneshot(batch("other", 4)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(resting(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_symbols_trade_in_separate_books() {
+        let state = test_state_with_frozen_clock(Config::default());
+        let limit = |side, price, symbol: &str| CreateOrderPayload { symbol: symbol.to_string(), ..order_payload(side, price, 5) };
+        let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(limit(Side::Sell, 100, "ABC"))).await.unwrap();
+        // Would cross the ABC ask, but rests in its own book
+        let (_, _, Json(xyz)) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(limit(Side::Buy, 101, "XYZ"))).await.unwrap();
+        assert!(xyz.fills.unwrap().is_empty());
+        assert_eq!(xyz.order.symbol, "XYZ");
+        let (_, _, Json(abc)) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(limit(Side::Buy, 100, "ABC"))).await.unwrap();
+        assert_eq!(abc.fills, Some(vec![OrderFill { counter_order_id: 1, price: 100, quantity: 5, quantity_scale: 0, trade_seq: 1, executed_at: 1_000 }]));
+
+        let symbols: Vec<String> = state.markets().iter().map(|m| m.symbol.clone()).collect();
+        assert_eq!(symbols, vec!["ABC", "DEFAULT", "XYZ"]);
+        assert!(state.default_market.order_book.lock().unwrap().bids.is_empty());
+        let _ = cancel_order_handler(State(Arc::clone(&state)), Path(2), HeaderMap::new()).await.unwrap();
+        assert!(state.market("XYZ").unwrap().order_book.lock().unwrap().bids.is_empty());
+
+        let mut batch = vec![order_payload(Side::Buy, 99, 1), order_payload(Side::Buy, 99, 1)];
+        batch[1].symbol = "XYZ".to_string();
+        let err = create_orders_batch_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(batch)).await.unwrap_err();
+        assert_eq!(err, ApiError::Unprocessable("order 1: a batch must be for a single symbol".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_good_till_date_order_expires_on_sweep() {
+        let state = test_state();
+        let mut events = state.events.subscribe();
+        let gtd = |expires_at| CreateOrderPayload { expires_at: Some(expires_at), ..order_payload(Side::Buy, 100, 5) };
+        let err = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(gtd(now_nanos() - 1))).await.unwrap_err();
+        assert!(matches!(&err, ApiError::BadRequest(message) if message.ends_with("is not in the future")), "{:?}", err);
+
+        let expires_at = now_nanos() + 50_000_000;
+        let (_, _, Json(created)) = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(gtd(expires_at))).await.unwrap();
+        assert_eq!(created.order.expires_at, Some(expires_at));
+        spawn_expiry_sweep(Arc::clone(&state), 10);
+        let Json(resting) = get_order_handler(State(Arc::clone(&state)), Path(created.order.id)).await.unwrap();
+        assert_eq!(resting.status, OrderStatus::Open);
+
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+        assert!(state.default_market.order_book.lock().unwrap().bids.is_empty());
+        let Json(expired) = get_order_handler(State(Arc::clone(&state)), Path(created.order.id)).await.unwrap();
+        assert_eq!((expired.status, expired.cancel_reason), (OrderStatus::Expired, Some(CancelReason::Expiry)));
+        let mut cancelled = None;
+        while let Ok(event) = events.try_recv() {
+            if let BookEvent::OrderCancelled { order } = event {
+                cancelled = Some(order);
+            }
+        }
+        assert_eq!(cancelled.map(|o| (o.id, o.status)), Some((created.order.id, OrderStatus::Expired)));
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_key_returns_original_order() {
+        let state = test_state();
+        let keyed = |key: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(IDEMPOTENCY_KEY_HEADER, HeaderValue::from_static(key));
+            headers
+        };
+        let limit = |quantity, account_id| CreateOrderPayload { account_id, ..order_payload(Side::Buy, 100, quantity) };
+
+        let (status, _, Json(first)) = create_order_handler(State(Arc::clone(&state)), keyed("retry-1"), Json(limit(5, None))).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, _, Json(again)) = create_order_handler(State(Arc::clone(&state)), keyed("retry-1"), Json(limit(5, None))).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(again.order, first.order);
+        assert_eq!(state.default_market.order_book.lock().unwrap().bids.len(), 1);
+        let count: i64 = state.db_conn.lock().unwrap().query_row("SELECT COUNT(*) FROM orders", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 1);
+
+        let err = create_order_handler(State(Arc::clone(&state)), keyed("retry-1"), Json(limit(6, None))).await.unwrap_err();
+        assert_eq!(err, ApiError::Unprocessable("Idempotency-Key was already used for a different order".to_string()));
+        // Another account's key of the same name is its own
+        let (status, _, Json(other)) = create_order_handler(State(Arc::clone(&state)), keyed("retry-1"), Json(limit(5, Some(7)))).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_ne!(other.order.id, first.order.id);
+
+        // Forgotten once the window has passed
+        let state = test_state_with_config(Config { idempotency_window_nanos: Some(1), ..Config::default() });
+        let (_, _, Json(first)) = create_order_handler(State(Arc::clone(&state)), keyed("retry-2"), Json(limit(5, None))).await.unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        let (status, _, Json(later)) = create_order_handler(State(Arc::clone(&state)), keyed("retry-2"), Json(limit(5, None))).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_ne!(later.order.id, first.order.id);
+    }
+
+    #[tokio::test]
+    async fn test_session_orders_cancelled_when_control_connection_closes() {
+        let state = test_state();
+        let session = |id: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CLIENT_SESSION_HEADER, HeaderValue::from_static(id));
+            headers
+        };
+        let limit = |price| order_payload(Side::Buy, price, 5);
+        let control = session_control_handler(State(Arc::clone(&state)), session("desk-1")).await.unwrap().into_response();
+        let second = session_control_handler(State(Arc::clone(&state)), session("desk-1")).await.unwrap().into_response();
+        for (headers, price) in [(session("desk-1"), 98), (HeaderMap::new(), 99), (session("desk-2"), 100)] {
+            let _ = create_order_handler(State(Arc::clone(&state)), headers, Json(limit(price))).await.unwrap();
+        }
+        let (_, _, Json(batch)) = create_orders_batch_handler(State(Arc::clone(&state)), session("desk-1"), Json(vec![limit(97)])).await.unwrap();
+
+        // Still one connection open
+        drop(control);
+        tokio::task::yield_now().await;
+        assert_eq!(state.default_market.order_book.lock().unwrap().bids.len(), 4);
+
+        // Cancelled in the background once the last connection goes
+        drop(second);
+        for order_id in [1, batch[0].id] {
+            let mut order = None;
+            for _ in 0..100 {
+                let Json(view) = get_order_handler(State(Arc::clone(&state)), Path(order_id)).await.unwrap();
+                if view.status == OrderStatus::Cancelled {
+                    order = Some(view);
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+            assert_eq!(order.and_then(|o| o.cancel_reason), Some(CancelReason::Disconnect));
+        }
+        // Neither the order without a session nor another session's is touched
+        let mut resting: Vec<OrderId> = state.default_market.order_book.lock().unwrap().bids.iter().map(|o| o.id).collect();
+        resting.sort_unstable();
+        assert_eq!(resting, vec![2, 3]);
+        assert!(!state.client_sessions.lock().unwrap().contains_key("desk-1"));
+
+        let err = session_control_handler(State(Arc::clone(&state)), HeaderMap::new()).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)), "{:?}", err);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_symbols_do_not_wait_on_each_others_books() {
+        let state = test_state();
+        let limit = |price, symbol: &str| CreateOrderPayload { symbol: symbol.to_string(), ..order_payload(Side::Buy, price, 5) };
+        let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(limit(100, "ABC"))).await.unwrap();
+
+        // ABC's book lock held throughout; XYZ orders still go all the way through
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let abc = state.market("ABC").unwrap();
+        let blocker = std::thread::spawn(move || {
+            let _book_guard = abc.order_book.lock().unwrap();
+            locked_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+        });
+        locked_rx.recv().unwrap();
+        let xyz = async {
+            let tasks: Vec<_> = (0..20).map(|i| tokio::spawn(create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(limit(90 + i, "XYZ"))))).collect();
+            let mut ids = Vec::new();
+            for task in tasks {
+                let (_, _, Json(response)) = task.await.unwrap().unwrap();
+                ids.push(response.order.id);
+            }
+            let _ = modify_order_handler(State(Arc::clone(&state)), Path(ids[0]), HeaderMap::new(), Json(ModifyOrderPayload { quantity: 2.into(), price: None })).await.unwrap();
+            let _ = cancel_order_handler(State(Arc::clone(&state)), Path(ids[1]), HeaderMap::new()).await.unwrap();
+            ids
+        };
+        let mut ids = tokio::time::timeout(std::time::Duration::from_secs(5), xyz).await.expect("XYZ blocked behind ABC's book");
+        release_tx.send(()).unwrap();
+        blocker.join().unwrap();
+
+        // Ids stay unique across symbols
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), 20);
+        assert!(!ids.contains(&1));
+        assert_eq!(state.market("XYZ").unwrap().order_book.lock().unwrap().bids.len(), 19);
+        assert_eq!(state.market_of(1).unwrap().symbol, "ABC");
+    }
+
+    #[tokio::test]
+    async fn test_book_snapshot_by_symbol_route() {
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let state = test_state();
+        let payload = CreateOrderPayload { symbol: "ABC".to_string(), ..order_payload(Side::Buy, 100, 5) };
+        let _ = create_order_handler(State(Arc::clone(&state)), HeaderMap::new(), Json(payload)).await.unwrap();
+        let app = build_router(state);
+        let get = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+        let response = app.clone().oneshot(get("/book/ABC")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let snapshot: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(snapshot["bids"][0]["symbol"], "ABC");
+        let response = app.clone().oneshot(get("/book/snapshot?symbol=ABC")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.clone().oneshot(get("/book/QQQ")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = app.oneshot(get("/book/snapshot?symbol=abc")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_backtest_replays_in_time_order_with_the_same_fills_at_any_speed() {
+        // Out of time order on purpose; ties keep file order
+        let file = r#"
+{"at":3000,"op":"market","id":4,"side":"Buy","quantity":2}
+{"at":1000,"op":"limit","id":1,"side":"Sell","price":100,"quantity":5}
+{"at":1000,"op":"limit","id":2,"side":"Sell","price":101,"quantity":5}
+
+{"at":2000,"op":"limit","id":3,"side":"Buy","price":101,"quantity":7}
+{"at":2500,"op":"cancel","id":1}
+"#;
+        let events = parse_backtest(file).unwrap();
+        assert_eq!(events.iter().map(|event| event.at).collect::<Vec<_>>(), vec![1000, 1000, 2000, 2500, 3000]);
+
+        let mut fills = Vec::new();
+        let count = run_backtest(&Config::default(), &events, ReplaySpeed::Max, |fill| fills.push(fill.clone())).await;
+        assert_eq!(count, 3);
+        // (executed_at, trade_seq, bid, ask, price, quantity); the cancel of the
+        // already filled order 1 is skipped
+        let trades: Vec<_> = fills.iter().map(|fill| (fill.executed_at, fill.trade_seq, fill.bid_id, fill.ask_id, fill.price, fill.quantity)).collect();
+        assert_eq!(trades, vec![(2000, 1, 3, 1, 100, 5), (2000, 2, 3, 2, 101, 2), (3000, 3, 4, 2, 101, 2)]);
+
+        // Paced at real time, the 2µs file plays out the same
+        let mut paced = Vec::new();
+        run_backtest(&Config::default(), &events, ReplaySpeed::Scaled(1.0), |fill| paced.push(fill.clone())).await;
+        assert_eq!(paced, fills);
+
+        assert!(parse_backtest(r#"{"at":1,"op":"stop","id":1}"#).unwrap_err().starts_with("line 1:"));
     }
 
     #[test]
-    fn test_cancel_order_not_found() {
-        let mut book = OrderBook::new();
-        // let db_conn = dummy_db_conn(); // Not needed if not adding orders
-        // let order1 = Order::new(1, Side::Buy, 100, 10);
-        // book.add_order(order1.clone(), Arc::clone(&db_conn));
+    fn test_backtest_quantities_are_read_at_the_symbols_scale() {
+        TEST_QUANTITY_SCALES.with(|scales| *scales.borrow_mut() = Some(HashMap::from([("XYZ".to_string(), 4)])));
+        let file = r#"
+{"at":1000,"op":"limit","id":1,"symbol":"XYZ","side":"Sell","price":100,"quantity":"1.5"}
+{"at":1000,"op":"market","id":2,"symbol":"XYZ","side":"Buy","quantity":2}
+{"at":1000,"op":"limit","id":3,"side":"Sell","price":100,"quantity":2}
+"#;
+        let quantities: Vec<u64> = parse_backtest(file).unwrap().into_iter().filter_map(|event| match event.action {
+            BacktestAction::Limit { quantity, .. } | BacktestAction::Market { quantity, .. } => Some(quantity),
+            BacktestAction::Cancel { .. } => None,
+        }).collect();
+        assert_eq!(quantities, vec![15000, 20000, 2]);
+
+        let too_fine = parse_backtest(r#"{"at":1,"op":"limit","id":1,"symbol":"XYZ","side":"Sell","price":100,"quantity":"0.00001"}"#).unwrap_err();
+        assert!(too_fine.starts_with("line 1:") && too_fine.contains("for XYZ"), "{}", too_fine);
+        assert!(parse_backtest(r#"{"at":1,"op":"limit","id":1,"side":"Sell","price":100,"quantity":"1.5"}"#).is_err());
+    }
 
-        let result = book.cancel_order(999); // Try to cancel on an empty book
-        assert!(result.is_none());
+    #[test]
+    fn test_backtest_args() {
+        let args = |list: &[&str]| backtest_args(list.iter().map(|arg| arg.to_string()));
+        assert_eq!(args(&[]), Ok(None));
+        assert_eq!(args(&["--backtest", "events.jsonl"]), Ok(Some(("events.jsonl".into(), ReplaySpeed::Max))));
+        assert_eq!(args(&["--speed", "10", "--backtest", "events.jsonl"]), Ok(Some(("events.jsonl".into(), ReplaySpeed::Scaled(10.0)))));
+        assert!(args(&["--backtest", "events.jsonl", "--speed", "0"]).is_err());
+        assert!(args(&["--speed", "max"]).is_err());
+        assert!(args(&["--backtest"]).is_err());
     }
 }
 // --- End Unit Tests ---
\ No newline at end of file
@@ -0,0 +1,2269 @@
+// Order book and matching. Nothing here knows about the DB or HTTP: what the
+// book changes on its own is queued as deltas, events and writes, which the
+// caller drains to publish and persist.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::{
+    aggregate_level, book_checksum, level_checksum, price_ticks, AdjustmentOutcome, BookDelta, BookEvent,
+    CancelReason, Clock, CorporateAction, Order, OrderAdjustment, OrderId, OrderStatus, OrderType,
+    OrderView, PriceLevel, Side, SystemClock, TickSize, TimeInForce, TopOfBook, DEFAULT_SYMBOL,
+};
+
+// --- OCO Groups ---
+
+// When a fill on one OCO group member cancels the others
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OcoPolicy {
+    #[default]
+    AnyFill,
+    FullFill,
+}
+
+impl OcoPolicy {
+    fn triggered_by(self, remaining_quantity: u64) -> bool {
+        match self {
+            OcoPolicy::AnyFill => true,
+            OcoPolicy::FullFill => remaining_quantity == 0,
+        }
+    }
+}
+
+// --- Match Price ---
+
+// Where a crossing pair trades between the two limits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchPrice {
+    // The resting order's price
+    #[default]
+    Maker,
+    // Halfway between bid and ask, on the nearest tick; a midpoint exactly
+    // between two ticks goes to the maker's side
+    Midpoint,
+}
+
+impl MatchPrice {
+    fn execution_price(self, bid_price: u64, ask_price: u64, bid_rested: bool, tick: u64) -> u64 {
+        let maker_price = if bid_rested { bid_price } else { ask_price };
+        if self == MatchPrice::Maker {
+            return maker_price;
+        }
+        // Counted in half ticks so the sum can't overflow or lose the half
+        let (sum, tick) = (bid_price as u128 + ask_price as u128, tick as u128);
+        let (whole, half) = (sum / (2 * tick), sum % (2 * tick));
+        let round_up = half > tick || (half == tick && bid_rested);
+        let mid = (whole + round_up as u128) * tick;
+        // Both limits sit on ticks, so the rounded midpoint stays between them
+        mid.clamp(ask_price as u128, bid_price as u128) as u64
+    }
+}
+
+// --- Matching Strategies ---
+
+// How an incoming order's quantity is shared out among the resting orders at
+// one price level. Returns one allocation per resting order, in queue order;
+// an allocation never exceeds what that order shows, and together they
+// cover as much of `incoming` as the level holds.
+pub trait MatchingStrategy: fmt::Debug + Send + Sync {
+    fn allocate(&self, resting: &[&Order], incoming: u64) -> Vec<u64>;
+}
+
+// FIFO: the front of the queue fills completely before the next order trades
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PriceTime;
+
+impl MatchingStrategy for PriceTime {
+    fn allocate(&self, resting: &[&Order], incoming: u64) -> Vec<u64> {
+        let mut left = incoming;
+        resting.iter().map(|order| {
+            let take = left.min(order.visible_quantity());
+            left -= take;
+            take
+        }).collect()
+    }
+}
+
+// In proportion to each resting order's size, rounded down; the units lost to
+// rounding go one apiece to orders in time priority
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProRata;
+
+impl MatchingStrategy for ProRata {
+    fn allocate(&self, resting: &[&Order], incoming: u64) -> Vec<u64> {
+        let total: u128 = resting.iter().map(|order| order.visible_quantity() as u128).sum();
+        if total <= incoming as u128 {
+            return resting.iter().map(|order| order.visible_quantity()).collect();
+        }
+        let mut shares: Vec<u64> = resting.iter()
+            .map(|order| (incoming as u128 * order.visible_quantity() as u128 / total) as u64)
+            .collect();
+        // Fewer than one unit per order is lost, and every share is below its
+        // order's size while the level holds more than `incoming`
+        let mut left = incoming - shares.iter().sum::<u64>();
+        for (share, order) in shares.iter_mut().zip(resting) {
+            if left == 0 {
+                break;
+            }
+            if *share < order.visible_quantity() {
+                *share += 1;
+                left -= 1;
+            }
+        }
+        shares
+    }
+}
+
+// Largest resting order first, ties in time priority, each filled completely
+// before the next trades. Rewards showing size over being early: a big order
+// that just arrived jumps everything smaller at its price, so small or early
+// liquidity can sit unfilled behind a stream of larger ones.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizePriority;
+
+impl MatchingStrategy for SizePriority {
+    fn allocate(&self, resting: &[&Order], incoming: u64) -> Vec<u64> {
+        let mut by_size: Vec<usize> = (0..resting.len()).collect();
+        // Stable, so equal sizes keep their queue order
+        by_size.sort_by_key(|&i| std::cmp::Reverse(resting[i].visible_quantity()));
+        let mut shares = vec![0; resting.len()];
+        let mut left = incoming;
+        for i in by_size {
+            shares[i] = left.min(resting[i].visible_quantity());
+            left -= shares[i];
+        }
+        shares
+    }
+}
+
+// The strategies a book can be configured with, by name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    #[default]
+    PriceTime,
+    ProRata,
+    SizePriority,
+}
+
+impl MatchMode {
+    pub fn parse(text: &str) -> Option<MatchMode> {
+        match text {
+            "price_time" => Some(MatchMode::PriceTime),
+            "pro_rata" => Some(MatchMode::ProRata),
+            "size_priority" => Some(MatchMode::SizePriority),
+            _ => None,
+        }
+    }
+
+    pub fn strategy(self) -> Arc<dyn MatchingStrategy> {
+        match self {
+            MatchMode::PriceTime => Arc::new(PriceTime),
+            MatchMode::ProRata => Arc::new(ProRata),
+            MatchMode::SizePriority => Arc::new(SizePriority),
+        }
+    }
+}
+
+// --- Trade Totals ---
+
+// Alert once an accumulator passes this fraction of its capacity.
+const TOTALS_ALERT_THRESHOLD: u128 = u128::MAX / 10 * 9;
+
+// Running totals over every execution. `price * quantity` of two u64s always
+// fits in a u128, so only the running sums need overflow checks.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradeTotals {
+    pub trade_count: u64,
+    pub volume: u128,
+    pub notional: u128,
+    // Set once any accumulator has saturated; totals are lower bounds from then on
+    pub saturated: bool,
+}
+
+impl TradeTotals {
+    pub fn record(&mut self, price: u64, quantity: u64) {
+        self.trade_count = self.trade_count.saturating_add(1);
+        self.volume = self.checked_accumulate("volume", self.volume, quantity as u128);
+        self.notional = self.checked_accumulate("notional", self.notional, price as u128 * quantity as u128);
+    }
+
+    fn checked_accumulate(&mut self, name: &str, total: u128, amount: u128) -> u128 {
+        match total.checked_add(amount) {
+            Some(sum) => {
+                if total < TOTALS_ALERT_THRESHOLD && sum >= TOTALS_ALERT_THRESHOLD {
+                    tracing::warn!(accumulator = name, total = %sum, "Trade total is approaching its u128 limit");
+                }
+                sum
+            }
+            None => {
+                tracing::error!(accumulator = name, "Trade total overflowed; saturating at u128::MAX");
+                self.saturated = true;
+                u128::MAX
+            }
+        }
+    }
+}
+
+// --- Matching ---
+
+// Best price and total quantity resting at it, per side. Maintained on every
+// book change so readers get the touch in O(1); only exhausting the best
+// level falls back to a scan of that side.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct TouchCache {
+    bid: Option<(u64, u64)>,
+    ask: Option<(u64, u64)>,
+}
+
+// Sanity checks on a crossing pair before any state is touched. A failure means
+// the book is already inconsistent, so matching it would only spread the damage.
+fn check_crossing(bid: &Order, ask: &Order) -> Result<(), String> {
+    for order in [bid, ask] {
+        if order.quantity == 0 {
+            return Err(format!("order {} is resting with zero quantity", order.id));
+        }
+        if order.status != OrderStatus::Open && order.status != OrderStatus::PartiallyFilled {
+            return Err(format!("order {} is resting with status {:?}", order.id, order.status));
+        }
+    }
+    Ok(())
+}
+
+// The aggressor opted into self-trade prevention and would trade with its own account
+fn is_self_trade(aggressor: &Order, resting: &Order) -> bool {
+    aggressor.stp && aggressor.account_id.is_some() && aggressor.account_id == resting.account_id
+}
+
+// One execution between a bid and an ask
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Fill {
+    // Per-book sequence, also the SSE event id for trade stream replay
+    pub trade_id: u64,
+    // Sequence across every book sharing the counter, for gap detection
+    pub trade_seq: u64,
+    // When it executed, unix nanos, from the book's clock
+    pub executed_at: u128,
+    pub symbol: String,
+    pub bid_id: OrderId,
+    pub ask_id: OrderId,
+    #[serde(with = "price_ticks")]
+    pub price: u64,
+    pub quantity: u64,
+}
+
+// A change made by matching rather than by the caller, which the caller must
+// persist. Queued in the order it happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BookWrite {
+    // One execution, with what is left of each side afterwards
+    // `bid_rested`: the bid was the maker, the ask the taker
+    Match { fill: Fill, bid_left: u64, bid_status: OrderStatus, ask_left: u64, ask_status: OrderStatus, bid_rested: bool },
+    // The aggressor's remainder, stopped by self-trade prevention
+    SelfTradeCancel { order_id: OrderId },
+    // The other members of an OCO group, once one of them filled
+    OcoCancel { group_id: u64, order_ids: Vec<OrderId> },
+    // Every order an auction traded, with its state afterwards, and the trades
+    Auction { clearing_price: u64, orders: Vec<(OrderId, u64, OrderStatus)>, fills: Vec<Fill> },
+    // An iceberg showed its next slice and went to the back of its level
+    Replenished { order_id: OrderId, priority: u64, timestamp: u128 },
+}
+
+// --- Batch Auction ---
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AuctionResult {
+    // None when the book doesn't cross
+    #[serde(with = "price_ticks::option")]
+    pub clearing_price: Option<u64>,
+    pub volume: u64,
+    pub fills: Vec<Fill>,
+}
+
+// --- Book Sides ---
+
+// One side of the book: a FIFO queue per price, plus an id -> price index so
+// cancels and modifies go straight to the order's level. Iterates in
+// price-time priority, best price first.
+#[derive(Debug, Clone)]
+pub struct BookSide {
+    side: Side,
+    levels: BTreeMap<u64, VecDeque<Order>>,
+    index: HashMap<OrderId, u64>,
+}
+
+impl BookSide {
+    fn new(side: Side) -> Self {
+        BookSide { side, levels: BTreeMap::new(), index: HashMap::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    // Price levels, best first
+    fn levels(&self) -> impl Iterator<Item = (&u64, &VecDeque<Order>)> + '_ {
+        let (desc, asc) = match self.side {
+            Side::Buy => (Some(self.levels.iter().rev()), None),
+            Side::Sell => (None, Some(self.levels.iter())),
+        };
+        desc.into_iter().flatten().chain(asc.into_iter().flatten())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Order> + '_ {
+        self.levels().flat_map(|(_, queue)| queue.iter())
+    }
+
+    fn best_price(&self) -> Option<u64> {
+        match self.side {
+            Side::Buy => self.levels.keys().next_back().copied(),
+            Side::Sell => self.levels.keys().next().copied(),
+        }
+    }
+
+    fn worst_price(&self) -> Option<u64> {
+        match self.side {
+            Side::Buy => self.levels.keys().next().copied(),
+            Side::Sell => self.levels.keys().next_back().copied(),
+        }
+    }
+
+    fn level(&self, price: u64) -> Option<&VecDeque<Order>> {
+        self.levels.get(&price)
+    }
+
+    pub fn front(&self) -> Option<&Order> {
+        self.level(self.best_price()?)?.front()
+    }
+
+    pub fn front_mut(&mut self) -> Option<&mut Order> {
+        let price = self.best_price()?;
+        self.levels.get_mut(&price)?.front_mut()
+    }
+
+    pub fn pop_front(&mut self) -> Option<Order> {
+        let price = self.best_price()?;
+        self.take(price, 0)
+    }
+
+    // Joins the back of its price level
+    pub fn push_back(&mut self, mut order: Order) {
+        order.show_slice();
+        self.index.insert(order.id, order.price);
+        self.levels.entry(order.price).or_default().push_back(order);
+    }
+
+    fn contains(&self, id: OrderId) -> bool {
+        self.index.contains_key(&id)
+    }
+
+    fn get(&self, id: OrderId) -> Option<&Order> {
+        let price = self.index.get(&id)?;
+        self.levels.get(price)?.iter().find(|o| o.id == id)
+    }
+
+    // Must not be used to change the price; remove and push_back instead
+    fn get_mut(&mut self, id: OrderId) -> Option<&mut Order> {
+        let price = self.index.get(&id)?;
+        self.levels.get_mut(price)?.iter_mut().find(|o| o.id == id)
+    }
+
+    fn remove(&mut self, id: OrderId) -> Option<Order> {
+        let price = *self.index.get(&id)?;
+        let position = self.levels.get(&price)?.iter().position(|o| o.id == id)?;
+        self.take(price, position)
+    }
+
+    fn take(&mut self, price: u64, position: usize) -> Option<Order> {
+        let queue = self.levels.get_mut(&price)?;
+        let order = queue.remove(position)?;
+        if queue.is_empty() {
+            self.levels.remove(&price);
+        }
+        self.index.remove(&order.id);
+        Some(order)
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&Order) -> bool) {
+        for queue in self.levels.values_mut() {
+            queue.retain(&mut keep);
+        }
+        self.levels.retain(|_, queue| !queue.is_empty());
+        self.index = self.iter().map(|o| (o.id, o.price)).collect();
+    }
+
+    // Re-queues every order by time priority within its level
+    fn sort(&mut self) {
+        let mut orders: Vec<Order> = std::mem::take(&mut self.levels).into_values().flatten().collect();
+        orders.sort_by_key(|o| (o.priority, o.timestamp, o.id));
+        self.index.clear();
+        for order in orders {
+            self.push_back(order);
+        }
+    }
+}
+
+// --- Order Book ---
+
+#[derive(Debug, Clone)]
+pub struct OrderBook {
+    pub symbol: String,
+    pub bids: BookSide,
+    pub asks: BookSide,
+    // Sequence of the last delta emitted by this book
+    pub seq: u64,
+    // Deltas not yet drained by the caller
+    deltas: Vec<BookDelta>,
+    // Events not yet drained by the caller
+    events: Vec<BookEvent>,
+    pub oco_policy: OcoPolicy,
+    pub match_price: MatchPrice,
+    // Shares each incoming order out across a resting price level
+    pub strategy: Arc<dyn MatchingStrategy>,
+    // Which named strategy that is, as last set through set_match_mode, and
+    // the book seq it was set at
+    match_mode: (MatchMode, u64),
+    // Midpoint executions are rounded to this
+    pub tick_size: TickSize,
+    pub totals: TradeTotals,
+    // Price of the latest execution
+    pub last_price: Option<u64>,
+    // Net quantity each account has bought less sold here, over every
+    // execution; orders without an account aren't counted
+    pub positions: BTreeMap<u64, i128>,
+    // Paper book: orders it creates itself are marked paper too
+    pub paper: bool,
+    touch: TouchCache,
+    // Batch-auction-only book: orders accumulate and only trade in run_auction
+    pub auction_only: bool,
+    pub next_priority: u64,
+    pub next_trade_id: u64,
+    // Writes not yet drained by the caller
+    writes: Vec<BookWrite>,
+    // Orders that left the book filled, cancelled or expired, in their final
+    // state as persisted (nothing remaining), not yet drained by the caller
+    departed: Vec<Order>,
+    // Stamps requeued orders and the ones the book creates itself
+    pub clock: Arc<dyn Clock>,
+    // Next trade sequence number; shared by every book of a state
+    pub trade_seq: Arc<AtomicU64>,
+}
+
+impl Default for OrderBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OrderBook {
+    pub fn new() -> Self {
+        OrderBook {
+            symbol: DEFAULT_SYMBOL.to_string(),
+            bids: BookSide::new(Side::Buy),
+            asks: BookSide::new(Side::Sell),
+            seq: 0,
+            deltas: Vec::new(),
+            events: Vec::new(),
+            oco_policy: OcoPolicy::default(),
+            match_price: MatchPrice::default(),
+            strategy: Arc::new(PriceTime),
+            match_mode: (MatchMode::PriceTime, 0),
+            tick_size: TickSize::default(),
+            totals: TradeTotals::default(),
+            last_price: None,
+            positions: BTreeMap::new(),
+            paper: false,
+            touch: TouchCache::default(),
+            auction_only: false,
+            next_priority: 1,
+            next_trade_id: 1,
+            writes: Vec::new(),
+            departed: Vec::new(),
+            clock: Arc::new(SystemClock),
+            trade_seq: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    fn side(&self, side: &Side) -> &BookSide {
+        match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        }
+    }
+
+    pub fn side_mut(&mut self, side: &Side) -> &mut BookSide {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+
+    // Everything derived from one execution besides the fill itself
+    fn record_execution(&mut self, price: u64, quantity: u64, bid_account: Option<u64>, ask_account: Option<u64>) {
+        self.totals.record(price, quantity);
+        self.last_price = Some(price);
+        for (account, signed) in [(bid_account, quantity as i128), (ask_account, -(quantity as i128))] {
+            if let Some(account) = account {
+                let position = self.positions.entry(account).or_default();
+                *position = position.saturating_add(signed);
+            }
+        }
+    }
+
+    // Numbers and stamps one execution
+    fn new_fill(&mut self, bid_id: OrderId, ask_id: OrderId, price: u64, quantity: u64) -> Fill {
+        let trade_id = self.next_trade_id;
+        self.next_trade_id += 1;
+        let trade_seq = self.trade_seq.fetch_add(1, Ordering::Relaxed);
+        Fill { trade_id, trade_seq, executed_at: self.clock.now_nanos(), symbol: self.symbol.clone(), bid_id, ask_id, price, quantity }
+    }
+
+    fn assign_priority(&mut self, order: &mut Order) {
+        order.priority = self.next_priority;
+        self.next_priority += 1;
+    }
+
+    // Continues the priority sequence after the resting orders, e.g. once a
+    // book has been loaded or restored.
+    pub fn reseed_priority(&mut self) {
+        let max = self.bids.iter().chain(self.asks.iter()).map(|o| o.priority).max().unwrap_or(0);
+        self.next_priority = max + 1;
+    }
+
+    // Scan one side for its best level. Used to (re)build the cache.
+    fn scan_touch(&self, side: &Side) -> Option<(u64, u64)> {
+        let (price, queue) = self.side(side).levels().next()?;
+        Some((*price, queue.iter().fold(0u64, |total, o| total.saturating_add(o.visible_quantity()))))
+    }
+
+    // Full rebuild, for books populated directly rather than through add_order
+    pub fn rebuild_touch(&mut self) {
+        self.touch = TouchCache { bid: self.scan_touch(&Side::Buy), ask: self.scan_touch(&Side::Sell) };
+    }
+
+    fn touch_level(&mut self, side: &Side) -> &mut Option<(u64, u64)> {
+        match side {
+            Side::Buy => &mut self.touch.bid,
+            Side::Sell => &mut self.touch.ask,
+        }
+    }
+
+    fn touch_added(&mut self, side: &Side, price: u64, quantity: u64) {
+        let improves = |best: u64| match side {
+            Side::Buy => price > best,
+            Side::Sell => price < best,
+        };
+        let level = self.touch_level(side);
+        match level {
+            Some((best, total)) if price == *best => *total = total.saturating_add(quantity),
+            Some((best, _)) if !improves(*best) => {}
+            _ => *level = Some((price, quantity)),
+        }
+    }
+
+    // Call after the order book itself reflects the removal.
+    fn touch_removed(&mut self, side: &Side, price: u64, quantity: u64) {
+        if let Some((best, total)) = self.touch_level(side) {
+            if price != *best {
+                return;
+            }
+            // A saturated total no longer says what is left, so rescan it
+            if *total > quantity && *total != u64::MAX {
+                *total -= quantity;
+                return;
+            }
+        }
+        // Best level exhausted (or the cache was empty): find the next one
+        let rescanned = self.scan_touch(side);
+        *self.touch_level(side) = rescanned;
+    }
+
+    fn emit_update(&mut self, order_id: OrderId, side: Side, price: u64, new_quantity: u64) {
+        self.seq += 1;
+        self.deltas.push(BookDelta::Update { seq: self.seq, order_id, side, price, new_quantity });
+    }
+
+    fn emit_remove(&mut self, order_id: OrderId, side: Side, price: u64) {
+        self.seq += 1;
+        self.deltas.push(BookDelta::Remove { seq: self.seq, order_id, side, price });
+    }
+
+    // Hand pending deltas to the caller for publishing
+    // The named strategy in use and the seq it took effect at
+    pub fn match_mode(&self) -> (MatchMode, u64) {
+        self.match_mode
+    }
+
+    // Matching only ever runs inside a &mut self call, so a switch lands
+    // between matches: fills already made keep the allocation they had
+    pub fn set_match_mode(&mut self, mode: MatchMode) {
+        self.match_mode = (mode, self.seq);
+        self.strategy = mode.strategy();
+    }
+
+    pub fn take_deltas(&mut self) -> Vec<BookDelta> {
+        std::mem::take(&mut self.deltas)
+    }
+
+    pub fn take_events(&mut self) -> Vec<BookEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // Hand pending writes to the caller for persisting
+    pub fn take_departed(&mut self) -> Vec<Order> {
+        std::mem::take(&mut self.departed)
+    }
+
+    pub fn take_writes(&mut self) -> Vec<BookWrite> {
+        std::mem::take(&mut self.writes)
+    }
+
+    // Best price on each side with the total quantity resting at it
+    pub fn top_of_book(&self) -> TopOfBook {
+        let (bid_price, bid_quantity) = self.touch.bid.unwrap_or((0, 0));
+        let (ask_price, ask_quantity) = self.touch.ask.unwrap_or((0, 0));
+        TopOfBook { seq: self.seq, bid_price, bid_quantity, ask_price, ask_quantity }
+    }
+
+    // Aggregated depth for one side, best price first
+    pub fn levels(&self, side: &Side) -> Vec<PriceLevel> {
+        self.side(side).levels().map(|(price, queue)| aggregate_level(*price, queue)).collect()
+    }
+
+    pub fn level_at(&self, side: &Side, price: u64) -> PriceLevel {
+        self.side(side).level(price).map_or(PriceLevel { price, total_quantity: 0, order_count: 0 }, |queue| aggregate_level(price, queue))
+    }
+
+    pub fn level_checksum(&self) -> u64 {
+        let bids = self.levels(&Side::Buy).into_iter().map(|level| (Side::Buy, level));
+        let asks = self.levels(&Side::Sell).into_iter().map(|level| (Side::Sell, level));
+        level_checksum(bids.chain(asks))
+    }
+
+    // Over what the book shows, so it matches the snapshot
+    pub fn checksum(&self) -> u64 {
+        book_checksum(self.bids.iter().chain(self.asks.iter()).map(|o| (o.id, o.side.clone(), o.price, o.visible_quantity())))
+    }
+
+    // Highest resting bid price, if any
+    pub fn best_bid(&self) -> Option<u64> {
+        self.touch.bid.map(|(price, _)| price)
+    }
+
+    // Lowest resting ask price, if any
+    pub fn best_ask(&self) -> Option<u64> {
+        self.touch.ask.map(|(price, _)| price)
+    }
+
+    // Queues an order without matching it. Returns its assigned time priority.
+    pub fn rest_order(&mut self, mut order: Order) -> u64 {
+        self.assign_priority(&mut order);
+        order.show_slice();
+        let priority = order.priority;
+        self.emit_update(order.id, order.side.clone(), order.price, order.visible_quantity());
+        self.touch_added(&order.side, order.price, order.visible_quantity());
+        // Fresh priority, so the back of its level is its place in the queue
+        self.side_mut(&order.side.clone()).push_back(order);
+        priority
+    }
+
+    // Puts both queues in price-time priority: bids by descending price, asks by
+    // ascending price, then by arrival sequence. For books built in bulk.
+    pub fn sort_queues(&mut self) {
+        self.bids.sort();
+        self.asks.sort();
+    }
+
+    // Returns the time priority assigned to the order and the fills it traded
+    // on arrival; the book was uncrossed before, so every fill involves it.
+    // An order refused on entry (see `entry_refusal`) is left out of the book
+    // entirely and gets priority 0 (assigned ones start at 1) and no fills.
+    pub fn add_order(&mut self, order: Order) -> (u64, Vec<Fill>) {
+        let order_id = order.id;
+        if let Some(refused) = self.entry_refusal(&order) {
+            tracing::info!(order_id = order_id, status = ?refused.status, cancel_reason = ?refused.cancel_reason, "Order refused on entry; book untouched");
+            return (0, Vec::new());
+        }
+        self.events.push(BookEvent::OrderAdded { order: OrderView::from(&order) });
+        let priority = self.rest_order(order);
+        if self.auction_only {
+            tracing::debug!(order_id = order_id, "Auction-only book; order queued for the next auction");
+            return (priority, Vec::new());
+        }
+        tracing::debug!(order_id = order_id, book = ?self, "Added order. Book state before match attempt");
+        let fills = self.try_match();
+        tracing::debug!(book = ?self, fills = fills.len(), "Book state after match attempt");
+        (priority, fills)
+    }
+
+    // Trades through the opposite side at any price; whatever is left once the
+    // book runs dry is cancelled rather than rested. Returns the final state and
+    // its fills. It is matched as a limit at the worst opposite price, so it
+    // appears briefly (update then remove) in the delta feed.
+    pub fn execute_market(&mut self, order: Order) -> (Order, Vec<Fill>) {
+        if let Some(refused) = self.entry_refusal(&order) {
+            tracing::info!(order_id = order.id, status = ?refused.status, cancel_reason = ?refused.cancel_reason, "Market order refused on entry; book untouched");
+            return (refused, Vec::new());
+        }
+        let worst = match order.side {
+            Side::Buy => self.asks.worst_price(),
+            Side::Sell => self.bids.worst_price(),
+        };
+        let Some(limit) = worst else {
+            tracing::info!(order_id = order.id, "Market order found an empty book; cancelled");
+            return (Order { status: OrderStatus::Cancelled, quantity: 0, cancel_reason: Some(CancelReason::MarketRemainder), ..order }, Vec::new());
+        };
+        let id = order.id;
+        let (_, fills) = self.add_order(Order { price: limit, ..order.clone() });
+        // Self-trade prevention may already have cancelled it mid-sweep
+        let stp_cancelled = self.events.iter().any(|event| matches!(
+            event,
+            BookEvent::OrderCancelled { order } if order.id == id && order.cancel_reason == Some(CancelReason::SelfTradePrevention)
+        ));
+        let final_order = if stp_cancelled {
+            Order { quantity: 0, status: OrderStatus::Cancelled, cancel_reason: Some(CancelReason::SelfTradePrevention), ..order }
+        } else {
+            match self.cancel_order(id, CancelReason::MarketRemainder) {
+                Some(rest) => {
+                    tracing::info!(order_id = id, unfilled = rest.quantity, "Market order exhausted the book; remainder cancelled");
+                    Order { quantity: 0, status: OrderStatus::Cancelled, cancel_reason: rest.cancel_reason, ..order }
+                }
+                None => Order { quantity: 0, status: OrderStatus::Filled, ..order },
+            }
+        };
+        // It left the book priced at its sweep limit; report it as entered
+        self.departed.retain(|departed| departed.id != id);
+        self.departed.push(final_order.clone());
+        (final_order, fills)
+    }
+
+    // What-if: runs the order through the same matching as add_order or
+    // execute_market, but on a copy of the book that is then thrown away with
+    // its deltas, events and writes. Returns the state the order would end in
+    // and every fill it would make; this book is left as it was.
+    pub fn simulate(&self, order: Order) -> (Order, Vec<Fill>) {
+        if let Some(refused) = self.entry_refusal(&order) {
+            return (refused, Vec::new());
+        }
+        let mut book = self.clone();
+        book.events.clear();
+        // A counter of its own, so the simulation uses up no real numbers
+        book.trade_seq = Arc::new(AtomicU64::new(self.trade_seq.load(Ordering::Relaxed)));
+        let id = order.id;
+        match order.order_type {
+            OrderType::Market => book.execute_market(order),
+            OrderType::Limit => {
+                let (_, fills) = book.add_order(order.clone());
+                if let Some(resting) = book.find_order(id) {
+                    return (resting.clone(), fills);
+                }
+                let cancel_reason = book.events.iter().find_map(|event| match event {
+                    BookEvent::OrderCancelled { order } if order.id == id => order.cancel_reason,
+                    _ => None,
+                });
+                let status = if cancel_reason.is_some() { OrderStatus::Cancelled } else { OrderStatus::Filled };
+                (Order { quantity: 0, status, cancel_reason, ..order }, fills)
+            }
+        }
+    }
+
+    // The state an order ends in if the book won't take it as it stands: a
+    // fill-or-kill short of its full quantity is killed, a post-only that would
+    // cross is rejected, and one that would trade but not its `min_qty` is
+    // cancelled. None if it can go in. Refused orders never touch the book.
+    // `min_qty` binds on entry only: an order that doesn't cross rests as
+    // usual and may later fill in smaller pieces.
+    pub fn entry_refusal(&self, order: &Order) -> Option<Order> {
+        if order.post_only && self.would_take_liquidity(order) {
+            return Some(Order { status: OrderStatus::Rejected, ..order.clone() });
+        }
+        if order.time_in_force == TimeInForce::Fok && self.fillable_quantity(order) < order.quantity {
+            return Some(Order { status: OrderStatus::Killed, ..order.clone() });
+        }
+        let takes = order.order_type == OrderType::Market || self.would_take_liquidity(order);
+        if order.min_qty.is_some_and(|min_qty| takes && self.fillable_quantity(order) < min_qty) {
+            return Some(Order { quantity: 0, status: OrderStatus::Cancelled, cancel_reason: Some(CancelReason::MinQuantity), ..order.clone() });
+        }
+        None
+    }
+
+    // Whether the order is priced through the best opposite price, so would
+    // trade on arrival rather than rest. What post-only orders are held to.
+    pub fn would_take_liquidity(&self, order: &Order) -> bool {
+        match order.side {
+            Side::Buy => self.best_ask().is_some_and(|ask| order.price >= ask),
+            Side::Sell => self.best_bid().is_some_and(|bid| order.price <= bid),
+        }
+    }
+
+    // Opposite-side quantity the order could trade against right now: everything
+    // at or better than its limit, or the whole side for a market order, up to
+    // the first resting order self-trade prevention would stop it at. Only
+    // meaningful on a continuous book, where the taker is ahead of its own side.
+    // Saturates at u64::MAX, which no single order can exceed.
+    pub fn fillable_quantity(&self, order: &Order) -> u64 {
+        let crosses = |resting: &Order| match (&order.side, order.order_type) {
+            (_, OrderType::Market) => true,
+            (Side::Buy, OrderType::Limit) => resting.price <= order.price,
+            (Side::Sell, OrderType::Limit) => resting.price >= order.price,
+        };
+        let opposite = match order.side {
+            Side::Buy => &self.asks,
+            Side::Sell => &self.bids,
+        };
+        opposite.iter()
+            .take_while(|resting| crosses(resting) && !is_self_trade(order, resting))
+            .fold(0u64, |total, o| total.saturating_add(o.quantity))
+    }
+
+    // Price maximising executable volume. Ties go to the smallest buy/sell
+    // imbalance, then to the lowest price. Returns (price, volume), volume
+    // saturating at u64::MAX. One pass over the levels, summed in u128 so no
+    // quantity mix can overflow.
+    fn clearing_price(&self) -> Option<(u64, u64)> {
+        let level_totals = |side: &BookSide| -> Vec<(u64, u128)> {
+            side.levels.iter().map(|(price, queue)| (*price, queue.iter().map(|o| o.quantity as u128).sum())).collect()
+        };
+        // Both ascending by price
+        let (bid_levels, ask_levels) = (level_totals(&self.bids), level_totals(&self.asks));
+        let mut candidates: Vec<u64> = bid_levels.iter().chain(&ask_levels).map(|(price, _)| *price).collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        // Walking up the prices, demand sheds the bids below and supply gains the asks at or below
+        let mut demand: u128 = bid_levels.iter().map(|(_, total)| total).sum();
+        let mut supply: u128 = 0;
+        let (mut below, mut at_or_below) = (bid_levels.iter().peekable(), ask_levels.iter().peekable());
+        let mut best: Option<(u64, u128, u128)> = None;
+        for price in candidates {
+            while let Some((_, total)) = below.next_if(|(level, _)| *level < price) {
+                demand -= total;
+            }
+            while let Some((_, total)) = at_or_below.next_if(|(level, _)| *level <= price) {
+                supply += total;
+            }
+            let volume = demand.min(supply);
+            if volume == 0 {
+                continue;
+            }
+            let imbalance = demand.abs_diff(supply);
+            let improves = match best {
+                None => true,
+                Some((_, best_volume, best_imbalance)) => {
+                    volume > best_volume || (volume == best_volume && imbalance < best_imbalance)
+                }
+            };
+            if improves {
+                best = Some((price, volume, imbalance));
+            }
+        }
+        best.map(|(price, volume, _)| (price, u64::try_from(volume).unwrap_or(u64::MAX)))
+    }
+
+    // Uniform-price call auction: every crossable order trades at the single
+    // clearing price, allocated in price-then-arrival priority on each side.
+    // Self-trade prevention treats the later arrival of a pair as the
+    // aggressor and cancels it, as continuous matching would have.
+    pub fn run_auction(&mut self) -> AuctionResult {
+        let Some((price, clearing_volume)) = self.clearing_price() else {
+            tracing::info!("Auction found no crossing orders");
+            return AuctionResult::default();
+        };
+        tracing::info!(clearing_price = price, volume = clearing_volume, "Running auction");
+
+        // Both sides already iterate in price-time priority
+        let bid_ids: Vec<OrderId> = self.bids.iter().take_while(|o| o.price >= price).map(|o| o.id).collect();
+        let ask_ids: Vec<OrderId> = self.asks.iter().take_while(|o| o.price <= price).map(|o| o.id).collect();
+
+        let mut fills = Vec::new();
+        let (mut b, mut a) = (0, 0);
+        while b < bid_ids.len() && a < ask_ids.len() {
+            let (Some(bid), Some(ask)) = (self.bids.get(bid_ids[b]), self.asks.get(ask_ids[a])) else {
+                tracing::error!(bid_id = bid_ids[b], ask_id = ask_ids[a], book = ?self, "Auction order missing from the book; aborting auction");
+                break;
+            };
+            let (aggressor, resting) = if bid.priority > ask.priority { (bid, ask) } else { (ask, bid) };
+            if is_self_trade(aggressor, resting) {
+                let (aggressor_id, bid_aggressed) = (aggressor.id, aggressor.side == Side::Buy);
+                tracing::info!(order_id = aggressor_id, resting_id = resting.id, account_id = ?aggressor.account_id, "Self-trade prevented in auction; cancelling later order");
+                self.cancel_self_trade(aggressor_id);
+                if bid_aggressed {
+                    b += 1;
+                } else {
+                    a += 1;
+                }
+                continue;
+            }
+            let (Some(bid), Some(ask)) = (self.bids.get_mut(bid_ids[b]), self.asks.get_mut(ask_ids[a])) else {
+                break;
+            };
+            let quantity = bid.quantity.min(ask.quantity);
+            bid.quantity -= quantity;
+            ask.quantity -= quantity;
+            bid.status = if bid.quantity == 0 { OrderStatus::Filled } else { OrderStatus::PartiallyFilled };
+            ask.status = if ask.quantity == 0 { OrderStatus::Filled } else { OrderStatus::PartiallyFilled };
+            let (bid_id, ask_id) = (bid.id, ask.id);
+            let (bid_account, ask_account) = (bid.account_id, ask.account_id);
+            let (bid_done, ask_done) = (bid.quantity == 0, ask.quantity == 0);
+            self.record_execution(price, quantity, bid_account, ask_account);
+            let fill = self.new_fill(bid_id, ask_id, price, quantity);
+            self.events.push(BookEvent::Trade(fill.clone()));
+            fills.push(fill);
+            if bid_done {
+                b += 1;
+            }
+            if ask_done {
+                a += 1;
+            }
+        }
+
+        // Every order that traded, with its post-auction state
+        let mut traded: Vec<Order> = Vec::new();
+        for fill in &fills {
+            for id in [fill.bid_id, fill.ask_id] {
+                // One cancelled by self-trade prevention after trading is already written off
+                if !traded.iter().any(|o| o.id == id) {
+                    if let Some(order) = self.find_order(id) {
+                        traded.push(order.clone());
+                    }
+                }
+            }
+        }
+        self.bids.retain(|o| o.quantity > 0);
+        self.asks.retain(|o| o.quantity > 0);
+        self.departed.extend(traded.iter().filter(|o| o.quantity == 0).cloned());
+        self.rebuild_touch();
+        for order in &traded {
+            if order.quantity == 0 {
+                self.emit_remove(order.id, order.side.clone(), order.price);
+            } else {
+                self.emit_update(order.id, order.side.clone(), order.price, order.visible_quantity());
+            }
+        }
+
+        self.writes.push(BookWrite::Auction {
+            clearing_price: price,
+            orders: traded.iter().map(|o| (o.id, o.quantity, o.status.clone())).collect(),
+            fills: fills.clone(),
+        });
+
+        for order in &traded {
+            if let Some(group_id) = order.group_id {
+                if self.oco_policy.triggered_by(order.quantity) {
+                    self.cancel_group(group_id, order.id);
+                }
+            }
+        }
+
+        let volume = fills.iter().fold(0u64, |total, fill| total.saturating_add(fill.quantity));
+        AuctionResult { clearing_price: Some(price), volume, fills }
+    }
+
+    pub fn try_match(&mut self) -> Vec<Fill> {
+        tracing::debug!("Attempting match...");
+        let mut fills = Vec::new();
+        // Icebergs that showed a new slice this cycle: requeued with a fresh
+        // priority, but still the resting side to whatever is sweeping them
+        let mut replenished: Vec<OrderId> = Vec::new();
+        while !self.bids.is_empty() && !self.asks.is_empty() {
+            let can_match = {
+                let best_bid = self.bids.front().unwrap();
+                let best_ask = self.asks.front().unwrap();
+                tracing::debug!(bid_price = best_bid.price, bid_qty = best_bid.quantity, ask_price = best_ask.price, ask_qty = best_ask.quantity, "Checking best bid/ask");
+                best_bid.price >= best_ask.price
+            };
+
+            if can_match {
+                let (best_bid, best_ask) = (self.bids.front().unwrap(), self.asks.front().unwrap());
+                let (bid_price, ask_price) = (best_bid.price, best_ask.price);
+                // The resting order is whichever side arrived first by time priority
+                let bid_rested = match (replenished.contains(&best_bid.id), replenished.contains(&best_ask.id)) {
+                    (true, false) => true,
+                    (false, true) => false,
+                    _ => (best_bid.priority, best_bid.timestamp) < (best_ask.priority, best_ask.timestamp),
+                };
+                let (aggressor, resting) = if bid_rested { (best_ask, best_bid) } else { (best_bid, best_ask) };
+                // A market order's limit is just the far side of the book, so
+                // there's no midpoint to speak of; it takes the maker's price
+                let match_price = if aggressor.order_type == OrderType::Market { MatchPrice::Maker } else { self.match_price };
+                let trade_price = match_price.execution_price(bid_price, ask_price, bid_rested, self.tick_size.units());
+
+                // The strategy splits the aggressor across the whole resting level
+                let aggressor_id = aggressor.id;
+                let level: Vec<&Order> = self.side(&resting.side).level(resting.price).into_iter().flatten().collect();
+                let allocation = self.strategy.allocate(&level, aggressor.quantity);
+                let allotted: Vec<(OrderId, u64)> = level.iter().zip(allocation)
+                    .filter(|(_, quantity)| *quantity > 0)
+                    .map(|(order, quantity)| (order.id, quantity))
+                    .collect();
+                if allotted.is_empty() {
+                    tracing::error!(order_id = aggressor_id, strategy = ?self.strategy, book = ?self, "Matching strategy allocated nothing; aborting match");
+                    return fills;
+                }
+
+                for (resting_id, quantity) in allotted {
+                    let (bid_id, ask_id) = if bid_rested { (resting_id, aggressor_id) } else { (aggressor_id, resting_id) };
+                    // An earlier fill's OCO cancels may have taken either one
+                    let (Some(bid), Some(ask)) = (self.bids.get(bid_id), self.asks.get(ask_id)) else {
+                        continue;
+                    };
+                    if let Err(reason) = check_crossing(bid, ask) {
+                        tracing::error!(reason = %reason, book = ?self, "Book corruption detected; aborting match");
+                        return fills;
+                    }
+                    let (aggressor, resting) = if bid_rested { (ask, bid) } else { (bid, ask) };
+                    if is_self_trade(aggressor, resting) {
+                        tracing::info!(order_id = aggressor_id, resting_id = resting_id, account_id = ?aggressor.account_id, "Self-trade prevented; cancelling aggressing order");
+                        self.cancel_self_trade(aggressor_id);
+                        break;
+                    }
+                    let resting_side = resting.side.clone();
+                    let Some(fill) = self.execute_fill(bid_id, ask_id, bid_rested, trade_price, quantity) else {
+                        return fills;
+                    };
+                    fills.push(fill);
+                    if self.side(&resting_side).get(resting_id).is_some_and(|o| o.display_qty.is_some() && o.slice_left == 0) {
+                        self.replenish(resting_id, &resting_side);
+                        replenished.push(resting_id);
+                    }
+                }
+            } else {
+                tracing::debug!("No match possible (bid price < ask price)");
+                break;
+            }
+        }
+        tracing::debug!(fills = fills.len(), "Finished matching cycle.");
+        fills
+    }
+
+    // Trades `quantity` between a resting bid and ask (at most what either has
+    // left) and applies it to both orders, the deltas, the writes and any OCO
+    // groups. None if the book is inconsistent and matching must stop.
+    fn execute_fill(&mut self, bid_id: OrderId, ask_id: OrderId, bid_rested: bool, trade_price: u64, quantity: u64) -> Option<Fill> {
+        let (bid, ask) = (self.bids.get(bid_id)?, self.asks.get(ask_id)?);
+        let (bid_price, ask_price) = (bid.price, ask.price);
+        let (bid_shown, ask_shown) = (bid.visible_quantity(), ask.visible_quantity());
+        let (bid_group, ask_group) = (bid.group_id, ask.group_id);
+        let (bid_account, ask_account) = (bid.account_id, ask.account_id);
+        tracing::info!(bid_id = bid_id, ask_id = ask_id, price = trade_price, "MATCH FOUND!");
+        let matched_quantity = quantity.min(bid.quantity).min(ask.quantity);
+        tracing::info!(quantity = matched_quantity, "Matched Quantity");
+        // A u64 underflow here would wrap to a huge resting quantity
+        let (Some(bid_left), Some(ask_left)) = (
+            bid.quantity.checked_sub(matched_quantity),
+            ask.quantity.checked_sub(matched_quantity),
+        ) else {
+            tracing::error!(bid_id = bid_id, ask_id = ask_id, quantity = matched_quantity, book = ?self, "Matched quantity exceeds available; aborting match");
+            return None;
+        };
+        self.record_execution(trade_price, matched_quantity, bid_account, ask_account);
+        let fill = self.new_fill(bid_id, ask_id, trade_price, matched_quantity);
+        self.events.push(BookEvent::Trade(fill.clone()));
+
+        // Everything below works from these locals; the orders are only
+        // written here, then removed by what's left, not re-read.
+        let fill_status = |left: u64| if left == 0 { OrderStatus::Filled } else { OrderStatus::PartiallyFilled };
+        let (bid_status, ask_status) = (fill_status(bid_left), fill_status(ask_left));
+        // Only the resting side eats into its iceberg slice; an aggressor trades
+        // its full size
+        if let Some(bid) = self.bids.get_mut(bid_id) {
+            bid.quantity = bid_left;
+            bid.status = bid_status.clone();
+            if bid_rested {
+                bid.slice_left = bid.slice_left.saturating_sub(matched_quantity);
+            }
+        }
+        if let Some(ask) = self.asks.get_mut(ask_id) {
+            ask.quantity = ask_left;
+            ask.status = ask_status.clone();
+            if !bid_rested {
+                ask.slice_left = ask.slice_left.saturating_sub(matched_quantity);
+            }
+        }
+        let bid_shown_after = self.bids.get(bid_id).map_or(0, Order::visible_quantity);
+        let ask_shown_after = self.asks.get(ask_id).map_or(0, Order::visible_quantity);
+
+        self.writes.push(BookWrite::Match { fill: fill.clone(), bid_left, bid_status, ask_left, ask_status, bid_rested });
+
+        if bid_left == 0 {
+            self.departed.extend(self.bids.remove(bid_id));
+            tracing::info!(order_id = bid_id, "Bid order fully filled and removed from memory.");
+        }
+        if ask_left == 0 {
+            self.departed.extend(self.asks.remove(ask_id));
+            tracing::info!(order_id = ask_id, "Ask order fully filled and removed from memory.");
+        }
+
+        self.touch_removed(&Side::Buy, bid_price, bid_shown - bid_shown_after);
+        self.touch_removed(&Side::Sell, ask_price, ask_shown - ask_shown_after);
+
+        // An iceberg between slices shows nothing until replenish publishes the next
+        if bid_left == 0 {
+            self.emit_remove(bid_id, Side::Buy, bid_price);
+        } else if bid_shown_after > 0 {
+            self.emit_update(bid_id, Side::Buy, bid_price, bid_shown_after);
+        }
+        if ask_left == 0 {
+            self.emit_remove(ask_id, Side::Sell, ask_price);
+        } else if ask_shown_after > 0 {
+            self.emit_update(ask_id, Side::Sell, ask_price, ask_shown_after);
+        }
+
+        for (group, filled_id, remaining) in [
+            (bid_group, bid_id, bid_left),
+            (ask_group, ask_id, ask_left),
+        ] {
+            if let Some(group_id) = group {
+                if self.oco_policy.triggered_by(remaining) {
+                    self.cancel_group(group_id, filled_id);
+                }
+            }
+        }
+        Some(fill)
+    }
+
+    // Shows an iceberg's next slice. Like any order joining its level it goes
+    // to the back of the queue, with a fresh priority and timestamp.
+    fn replenish(&mut self, id: OrderId, side: &Side) {
+        let Some(mut order) = self.side_mut(side).remove(id) else {
+            return;
+        };
+        order.show_slice();
+        order.timestamp = self.clock.now_nanos();
+        self.assign_priority(&mut order);
+        let shown = order.visible_quantity();
+        tracing::info!(order_id = id, shown = shown, hidden = order.quantity - shown, "Iceberg slice filled; replenished at the back of its level");
+        self.touch_added(side, order.price, shown);
+        self.emit_update(id, side.clone(), order.price, shown);
+        self.writes.push(BookWrite::Replenished { order_id: id, priority: order.priority, timestamp: order.timestamp });
+        self.side_mut(side).push_back(order);
+    }
+
+    // Cancels what is left of an order stopped by self-trade prevention.
+    fn cancel_self_trade(&mut self, order_id: OrderId) {
+        if self.cancel_order(order_id, CancelReason::SelfTradePrevention).is_none() {
+            return;
+        }
+        self.writes.push(BookWrite::SelfTradeCancel { order_id });
+    }
+
+    // Cancels every other resting member of an OCO group.
+    fn cancel_group(&mut self, group_id: u64, filled_id: OrderId) {
+        let sibling_ids: Vec<OrderId> = self.bids.iter().chain(self.asks.iter())
+            .filter(|o| o.group_id == Some(group_id) && o.id != filled_id)
+            .map(|o| o.id)
+            .collect();
+        if sibling_ids.is_empty() {
+            return;
+        }
+        tracing::info!(group_id = group_id, filled_id = filled_id, cancelled = ?sibling_ids, "OCO group triggered; cancelling siblings");
+        for id in &sibling_ids {
+            self.cancel_order(*id, CancelReason::OcoTriggered);
+        }
+        self.writes.push(BookWrite::OcoCancel { group_id, order_ids: sibling_ids });
+    }
+
+    fn touch_resized(&mut self, side: &Side, price: u64, old_quantity: u64, new_quantity: u64) {
+        if new_quantity > old_quantity {
+            self.touch_added(side, price, new_quantity - old_quantity);
+        } else {
+            self.touch_removed(side, price, old_quantity - new_quantity);
+        }
+    }
+
+    // Resizes a resting order under price-time priority: a decrease keeps its
+    // place in the queue, while an increase loses it and the order requeues at
+    // the back of its level with a fresh priority and timestamp, as on an
+    // exchange. (A price change, if ever supported, must always requeue.)
+    pub fn modify_order(&mut self, id: OrderId, new_quantity: u64) -> Option<Order> {
+        if new_quantity == 0 {
+            tracing::warn!(order_id = id, "Modification requested with quantity 0. Redirecting to cancel order.");
+            return self.cancel_order(id, CancelReason::UserRequest);
+        }
+        let Some(current) = self.find_order(id) else {
+            tracing::warn!(order_id = id, "Order not found for modification");
+            return None;
+        };
+        let (old_quantity, status) = (current.quantity, current.resized_status());
+        let now = self.clock.now_nanos();
+
+        let modified = if new_quantity > old_quantity {
+            tracing::info!(order_id = id, old_qty = old_quantity, new_qty = new_quantity, "Increasing order quantity; requeueing at the back of its level");
+            let mut order = self.remove_order(id, status)?;
+            order.quantity = new_quantity;
+            order.timestamp = now;
+            order.last_modified_at = Some(now);
+            order.priority = self.rest_order(order.clone());
+            order
+        } else {
+            tracing::info!(order_id = id, old_qty = old_quantity, new_qty = new_quantity, "Reducing order quantity in place");
+            let order = self.find_order_mut(id)?;
+            let old_shown = order.visible_quantity();
+            order.quantity = new_quantity;
+            order.last_modified_at = Some(now);
+            order.status = status;
+            let modified = order.clone();
+            self.touch_resized(&modified.side, modified.price, old_shown, modified.visible_quantity());
+            self.emit_update(id, modified.side.clone(), modified.price, modified.visible_quantity());
+            modified
+        };
+        self.events.push(BookEvent::OrderModified { order: OrderView::from(&modified) });
+        Some(modified)
+    }
+
+    // Moves a resting order to a new price. A reprice always requeues: the
+    // order goes to the back of its new level with a fresh priority and
+    // timestamp, as a cancel-replace would, but keeps its id. Doesn't match;
+    // the caller persists the new price first, then calls `rematch`.
+    pub fn reprice_order(&mut self, id: OrderId, new_price: u64, new_quantity: u64) -> Option<Order> {
+        let status = self.find_order(id)?.resized_status();
+        let mut order = self.remove_order(id, status)?;
+        tracing::info!(order_id = id, old_price = order.price, new_price = new_price, new_qty = new_quantity, "Repricing order; requeueing at its new level");
+        let now = self.clock.now_nanos();
+        order.price = new_price;
+        order.quantity = new_quantity;
+        order.timestamp = now;
+        order.last_modified_at = Some(now);
+        order.priority = self.rest_order(order.clone());
+        self.events.push(BookEvent::OrderModified { order: OrderView::from(&order) });
+        Some(order)
+    }
+
+    // Matches whatever a reprice left crossed. An auction-only book leaves
+    // it for the next auction.
+    pub fn rematch(&mut self) -> Vec<Fill> {
+        if self.auction_only {
+            return Vec::new();
+        }
+        self.try_match()
+    }
+
+    // Increase that keeps the original's time priority: the original keeps its
+    // size and the extra rests as a new order, linked to it, at the back.
+    pub fn split_increase(&mut self, id: OrderId, new_quantity: u64, linked_id: OrderId) -> Option<(Order, Order)> {
+        if new_quantity <= self.find_order(id)?.quantity {
+            return None;
+        }
+        let now = self.clock.now_nanos();
+        let original = self.find_order_mut(id)?;
+        original.last_modified_at = Some(now);
+        let original = original.clone();
+        let mut extra = Order::with_clock(linked_id, original.side.clone(), original.price, new_quantity - original.quantity, &*self.clock);
+        extra.group_id = original.group_id;
+        extra.paper = self.paper;
+        extra.dnr = original.dnr;
+        extra.linked_to = Some(id);
+        tracing::info!(order_id = id, linked_id = linked_id, extra_qty = extra.quantity, "Splitting increase into a linked order at the back");
+        self.events.push(BookEvent::OrderAdded { order: OrderView::from(&extra) });
+        extra.priority = self.rest_order(extra.clone());
+        Some((original, extra))
+    }
+
+    pub fn cancel_order(&mut self, id: OrderId, reason: CancelReason) -> Option<Order> {
+        tracing::info!(order_id = id, reason = ?reason, "Attempting to cancel order");
+        let mut order = self.remove_order(id, OrderStatus::Cancelled)?;
+        order.cancel_reason = Some(reason);
+        self.events.push(BookEvent::OrderCancelled { order: OrderView::from(&order) });
+        self.departed.push(Order { quantity: 0, ..order.clone() });
+        Some(order)
+    }
+
+    // Cancels every resting order. Returns the cancelled ids, bids first.
+    pub fn cancel_all(&mut self, reason: CancelReason) -> Vec<OrderId> {
+        let ids: Vec<OrderId> = self.bids.iter().chain(self.asks.iter()).map(|o| o.id).collect();
+        for id in &ids {
+            self.cancel_order(*id, reason);
+        }
+        tracing::info!(cancelled = ids.len(), reason = ?reason, "Cancelled all resting orders");
+        ids
+    }
+
+    // Cancels every resting order entered by `account_id`
+    pub fn cancel_account(&mut self, account_id: u64, reason: CancelReason) -> Vec<OrderId> {
+        let ids: Vec<OrderId> = self.bids.iter().chain(self.asks.iter())
+            .filter(|o| o.account_id == Some(account_id))
+            .map(|o| o.id)
+            .collect();
+        for id in &ids {
+            self.cancel_order(*id, reason);
+        }
+        tracing::info!(account_id = account_id, cancelled = ids.len(), reason = ?reason, "Cancelled an account's resting orders");
+        ids
+    }
+
+    pub fn expire_order(&mut self, id: OrderId) -> Option<Order> {
+        tracing::info!(order_id = id, "Expiring order");
+        let mut order = self.remove_order(id, OrderStatus::Expired)?;
+        order.cancel_reason = Some(CancelReason::Expiry);
+        self.events.push(BookEvent::OrderCancelled { order: OrderView::from(&order) });
+        self.departed.push(Order { quantity: 0, ..order.clone() });
+        Some(order)
+    }
+
+    // Expires every resting order whose good-till-date is at or before `now`,
+    // or that has outlived `max_lifetime` since it was first created
+    pub fn expire_due(&mut self, now: u128, max_lifetime: Option<u128>) -> Vec<Order> {
+        let due: Vec<OrderId> = self.bids.iter().chain(self.asks.iter())
+            .filter(|o| {
+                o.expires_at.is_some_and(|expires_at| expires_at <= now)
+                    || max_lifetime.is_some_and(|lifetime| now.saturating_sub(o.created_at) > lifetime)
+            })
+            .map(|o| o.id)
+            .collect();
+        due.into_iter().filter_map(|id| self.expire_order(id)).collect()
+    }
+
+    // Takes a resting order out of the book with the given terminal status
+    fn remove_order(&mut self, id: OrderId, status: OrderStatus) -> Option<Order> {
+        if let Some(mut order) = self.bids.remove(id) {
+            order.status = status;
+            tracing::info!(order_id = id, status = ?order.status, "Removed bid order from memory.");
+            self.emit_remove(id, order.side.clone(), order.price);
+            self.touch_removed(&order.side, order.price, order.visible_quantity());
+            return Some(order);
+        }
+        if let Some(mut order) = self.asks.remove(id) {
+            order.status = status;
+            tracing::info!(order_id = id, status = ?order.status, "Removed ask order from memory.");
+            self.emit_remove(id, order.side.clone(), order.price);
+            self.touch_removed(&order.side, order.price, order.visible_quantity());
+            return Some(order);
+        }
+        tracing::warn!(order_id = id, "Order not found for removal in memory.");
+        None
+    }
+
+    // Makes the book hold `desired`, or not hold it if its status is terminal.
+    // Returns whether anything changed, and fills in the priority it rests with.
+    // Never matches: reconciliation mirrors an authoritative source that has
+    // already done its own matching.
+    pub fn ensure_order(&mut self, desired: &mut Order) -> bool {
+        let resting = matches!(desired.status, OrderStatus::Open | OrderStatus::PartiallyFilled);
+        let current = self.find_order(desired.id).cloned();
+        match current {
+            None if !resting => false,
+            Some(_) if !resting => {
+                self.remove_order(desired.id, desired.status.clone());
+                true
+            }
+            Some(current) if current.side == desired.side && current.price == desired.price => {
+                desired.priority = current.priority;
+                if current.quantity == desired.quantity && current.status == desired.status {
+                    return false;
+                }
+                tracing::info!(order_id = desired.id, old_qty = current.quantity, new_qty = desired.quantity, "Ensure: resizing order in place");
+                let Some(order) = self.find_order_mut(desired.id) else {
+                    return false;
+                };
+                order.quantity = desired.quantity;
+                order.status = desired.status.clone();
+                let shown = order.visible_quantity();
+                self.touch_resized(&current.side, current.price, current.visible_quantity(), shown);
+                self.emit_update(desired.id, current.side, current.price, shown);
+                true
+            }
+            current => {
+                // New, or moved to another price/side: queue at the back
+                if current.is_some() {
+                    self.remove_order(desired.id, OrderStatus::Cancelled);
+                }
+                tracing::info!(order_id = desired.id, "Ensure: queueing order");
+                desired.paper = self.paper;
+                desired.priority = self.rest_order(desired.clone());
+                true
+            }
+        }
+    }
+
+    // Adjusts resting orders for a corporate action. Splits rescale every order
+    // (an iceberg's display size with it) and cancel DNR orders. Dividends
+    // reduce non-DNR bid prices and leave DNR bids. New prices land on the tick
+    // grid, buys rounding down and sells up so the book can't become crossed.
+    // An order whose price or size would round to zero, or outgrow u64, is
+    // cancelled.
+    pub fn apply_corporate_action(&mut self, action: &CorporateAction) -> Vec<OrderAdjustment> {
+        let tick = self.tick_size.units().max(1) as u128;
+        let on_tick = |side: &Side, price: u128| match side {
+            Side::Buy => price / tick * tick,
+            Side::Sell => price.div_ceil(tick) * tick,
+        };
+        let mut adjustments = Vec::new();
+        for order in self.bids.iter().chain(self.asks.iter()) {
+            let target = match action {
+                CorporateAction::Split { to, from } => {
+                    if order.dnr {
+                        None
+                    } else {
+                        let scaled = order.price as u128 * *from as u128;
+                        let price = match order.side {
+                            Side::Buy => on_tick(&order.side, scaled / *to as u128),
+                            Side::Sell => on_tick(&order.side, scaled.div_ceil(*to as u128)),
+                        };
+                        let rescale = |quantity: u64| quantity as u128 * *to as u128 / *from as u128;
+                        let display_qty = order.display_qty.map(|display_qty| u64::try_from(rescale(display_qty).max(1))).transpose();
+                        match (u64::try_from(price), u64::try_from(rescale(order.quantity)), display_qty) {
+                            (Ok(price), Ok(quantity), Ok(display_qty)) => Some((price, quantity, display_qty)),
+                            _ => None,
+                        }
+                    }
+                }
+                CorporateAction::CashDividend { amount } => {
+                    if order.side != Side::Buy || order.dnr {
+                        continue;
+                    }
+                    let price = on_tick(&order.side, order.price.saturating_sub(*amount) as u128) as u64;
+                    Some((price, order.quantity, order.display_qty))
+                }
+            };
+            let (outcome, new_price, new_quantity, new_display_qty) = match target {
+                Some((price, quantity, display_qty)) if price > 0 && quantity > 0 => (AdjustmentOutcome::Adjusted, price, quantity, display_qty),
+                _ => (AdjustmentOutcome::Cancelled, order.price, 0, order.display_qty),
+            };
+            adjustments.push(OrderAdjustment {
+                order_id: order.id,
+                outcome,
+                old_price: order.price,
+                new_price,
+                old_quantity: order.quantity,
+                new_quantity,
+                new_display_qty,
+            });
+        }
+
+        for adjustment in &adjustments {
+            match adjustment.outcome {
+                AdjustmentOutcome::Cancelled => {
+                    self.cancel_order(adjustment.order_id, CancelReason::CorporateAction);
+                }
+                AdjustmentOutcome::Adjusted => {
+                    let Some(side) = self.find_order(adjustment.order_id).map(|o| o.side.clone()) else {
+                        continue;
+                    };
+                    // Re-keyed at its new price; sort_queues below restores time priority
+                    let Some(mut order) = self.side_mut(&side).remove(adjustment.order_id) else {
+                        continue;
+                    };
+                    // An iceberg's current slice rescales with the order
+                    if let (Some(old), Some(new)) = (order.display_qty, adjustment.new_display_qty) {
+                        let slice_left = order.slice_left as u128 * new as u128 / old as u128;
+                        order.slice_left = u64::try_from(slice_left).unwrap_or(new).min(new);
+                    }
+                    order.price = adjustment.new_price;
+                    order.quantity = adjustment.new_quantity;
+                    order.display_qty = adjustment.new_display_qty;
+                    order.show_slice();
+                    let shown = order.visible_quantity();
+                    self.side_mut(&side).push_back(order);
+                    // Price moved, so publish as leaving the old level and joining the new
+                    self.emit_remove(adjustment.order_id, side.clone(), adjustment.old_price);
+                    self.emit_update(adjustment.order_id, side, adjustment.new_price, shown);
+                }
+            }
+        }
+        self.sort_queues();
+        self.rebuild_touch();
+        tracing::info!(action = ?action, adjusted = adjustments.len(), "Applied corporate action to resting orders");
+        adjustments
+    }
+
+    pub fn find_order(&self, id: OrderId) -> Option<&Order> {
+        self.bids.get(id).or_else(|| self.asks.get(id))
+    }
+
+    fn find_order_mut(&mut self, id: OrderId) -> Option<&mut Order> {
+        if self.bids.contains(id) {
+            self.bids.get_mut(id)
+        } else {
+            self.asks.get_mut(id)
+        }
+    }
+}
+
+
+// --- Unit Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{default_symbol, MockClock};
+
+    #[test]
+    fn test_add_order_to_book() {
+        let mut book = OrderBook::new();
+        let buy_order = Order::new(1, Side::Buy, 100, 10);
+        book.add_order(buy_order.clone());
+        assert_eq!(book.bids.len(), 1);
+        assert_eq!(book.bids.front().unwrap().id, 1);
+    }
+
+    #[test]
+    fn test_simple_match_full() {
+        let mut book = OrderBook::new();
+        let buy_order = Order::new(1, Side::Buy, 100, 10);
+        let sell_order = Order::new(2, Side::Sell, 100, 10);
+        book.add_order(buy_order);
+        book.add_order(sell_order);
+        assert!(book.bids.is_empty());
+        assert!(book.asks.is_empty());
+    }
+
+    #[test]
+    fn test_simple_match_partial_buy_fills() {
+        let mut book = OrderBook::new();
+        let buy_order = Order::new(1, Side::Buy, 100, 5);
+        let sell_order = Order::new(2, Side::Sell, 100, 10);
+        book.add_order(buy_order);
+        book.add_order(sell_order);
+        assert!(book.bids.is_empty());
+        assert_eq!(book.asks.len(), 1);
+        let ask_order = book.asks.front().unwrap();
+        assert_eq!(ask_order.id, 2);
+        assert_eq!(ask_order.quantity, 5);
+        assert_eq!(ask_order.status, OrderStatus::PartiallyFilled);
+    }
+
+    #[test]
+    fn test_simple_match_partial_sell_fills() {
+        let mut book = OrderBook::new();
+        let buy_order = Order::new(1, Side::Buy, 100, 10);
+        let sell_order = Order::new(2, Side::Sell, 100, 5);
+        book.add_order(buy_order);
+        book.add_order(sell_order);
+        assert!(book.asks.is_empty());
+        assert_eq!(book.bids.len(), 1);
+        let bid_order = book.bids.front().unwrap();
+        assert_eq!(bid_order.id, 1);
+        assert_eq!(bid_order.quantity, 5);
+        assert_eq!(bid_order.status, OrderStatus::PartiallyFilled);
+    }
+
+    #[test]
+    fn test_no_match_price_gap() {
+        let mut book = OrderBook::new();
+        let _buy_order = Order::new(1, Side::Buy, 100, 10);
+        let _sell_order = Order::new(2, Side::Sell, 105, 10);
+
+        book.add_order(_buy_order.clone());
+        book.add_order(_sell_order.clone());
+
+        assert_eq!(book.bids.len(), 1);
+        assert_eq!(book.asks.len(), 1);
+        assert_eq!(book.bids.front().unwrap().id, 1);
+        assert_eq!(book.asks.front().unwrap().id, 2);
+    }
+
+    #[test]
+    fn test_match_with_better_price() {
+        let mut book = OrderBook::new();
+        let buy_order = Order::new(1, Side::Buy, 105, 10);
+        let sell_order = Order::new(2, Side::Sell, 100, 10);
+
+        book.add_order(buy_order);
+        book.add_order(sell_order);
+
+        assert!(book.bids.is_empty());
+        assert!(book.asks.is_empty());
+    }
+
+    #[test]
+    fn test_multiple_matches_from_one_order() {
+        let mut book = OrderBook::new();
+        let sell_order1 = Order::new(1, Side::Sell, 100, 5);
+        let sell_order2 = Order::new(2, Side::Sell, 101, 15);
+        let buy_order = Order::new(3, Side::Buy, 101, 15);
+        book.add_order(sell_order1);
+        book.add_order(sell_order2);
+        book.add_order(buy_order);
+        assert!(book.bids.is_empty());
+        assert_eq!(book.asks.len(), 1);
+        let ask_order = book.asks.front().unwrap();
+        assert_eq!(ask_order.id, 2);
+        assert_eq!(ask_order.quantity, 5);
+        assert_eq!(ask_order.status, OrderStatus::PartiallyFilled);
+    }
+
+    #[test]
+    fn test_modify_order_quantity_bid() {
+        let mut book = OrderBook::new();
+        let order1 = Order::new(1, Side::Buy, 100, 10);
+        book.add_order(order1);
+        let result = book.modify_order(1, 5);
+        assert!(result.is_some());
+        assert_eq!(result.as_ref().unwrap().quantity, 5);
+        assert_eq!(book.bids.front().unwrap().quantity, 5);
+    }
+
+    #[test]
+    fn test_modify_order_quantity_ask() {
+        let mut book = OrderBook::new();
+        let order1 = Order::new(1, Side::Sell, 105, 20);
+        book.add_order(order1);
+
+        let result = book.modify_order(1, 15);
+        assert!(result.is_some());
+        assert_eq!(result.as_ref().unwrap().quantity, 15);
+        assert_eq!(book.asks.front().unwrap().quantity, 15);
+    }
+
+    #[test]
+    fn test_modify_order_not_found() {
+        let mut book = OrderBook::new();
+        let order1 = Order::new(1, Side::Buy, 100, 10);
+        // Order is not added to book, but modify_order works on the book content
+        // book.add_order(order1); // Let's test on an empty book
+
+        let result = book.modify_order(order1.id, 5); // Use order1.id
+        assert!(result.is_none()); // If order1 was not added, it shouldn't be found
+    }
+
+    #[test]
+    fn test_modify_order_zero_quantity_cancels() {
+        let mut book = OrderBook::new();
+        let order1 = Order::new(1, Side::Buy, 100, 10);
+        book.add_order(order1);
+        let result = book.modify_order(1, 0);
+        assert!(result.is_some());
+        assert_eq!(result.as_ref().unwrap().status, OrderStatus::Cancelled);
+        assert!(book.bids.is_empty());
+    }
+
+    #[test]
+    fn test_cancel_order_bid() {
+        let mut book = OrderBook::new();
+        let order1 = Order::new(1, Side::Buy, 100, 10);
+        let order2 = Order::new(2, Side::Buy, 99, 5);
+        book.add_order(order1.clone());
+        book.add_order(order2.clone());
+        let result = book.cancel_order(1, CancelReason::UserRequest);
+        assert!(result.is_some());
+        assert_eq!(result.as_ref().unwrap().status, OrderStatus::Cancelled);
+        assert_eq!(book.bids.len(), 1);
+        assert_eq!(book.bids.front().unwrap().id, 2);
+    }
+
+    #[test]
+    fn test_cancel_order_ask() {
+        let mut book = OrderBook::new();
+        let order1 = Order::new(1, Side::Sell, 105, 10);
+        let order2 = Order::new(2, Side::Sell, 110, 5);
+        book.add_order(order1.clone());
+        book.add_order(order2.clone());
+
+        let result = book.cancel_order(1, CancelReason::UserRequest);
+        assert!(result.is_some());
+        assert_eq!(result.as_ref().unwrap().status, OrderStatus::Cancelled);
+        assert_eq!(book.asks.len(), 1);
+        assert_eq!(book.asks.front().unwrap().id, 2);
+    }
+
+    #[test]
+    fn test_cancel_order_not_found() {
+        let mut book = OrderBook::new();
+        // let order1 = Order::new(1, Side::Buy, 100, 10);
+        // book.add_order(order1.clone());
+
+        let result = book.cancel_order(999, CancelReason::UserRequest); // Try to cancel on an empty book
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_deltas_applied_to_snapshot_reproduce_book() {
+        use std::collections::HashMap;
+
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 100, 10));
+        book.add_order(Order::new(2, Side::Sell, 105, 10));
+        book.take_deltas();
+
+        // Client initialises from a snapshot...
+        let mut local: HashMap<OrderId, (Side, u64, u64)> = book.bids.iter().chain(book.asks.iter())
+            .map(|o| (o.id, (o.side.clone(), o.price, o.quantity)))
+            .collect();
+        let mut local_seq = book.seq;
+
+        // ...then the server keeps trading
+        book.add_order(Order::new(3, Side::Sell, 100, 4)); // partial fill of 1
+        book.add_order(Order::new(4, Side::Buy, 99, 7));
+        book.modify_order(2, 3);
+        book.cancel_order(4, CancelReason::UserRequest);
+        book.add_order(Order::new(5, Side::Sell, 100, 6)); // fills rest of 1
+
+        for delta in book.take_deltas() {
+            assert_eq!(delta.seq(), local_seq + 1, "deltas must be gap-free");
+            local_seq = delta.seq();
+            match delta {
+                BookDelta::Update { order_id, side, price, new_quantity, .. } => {
+                    local.insert(order_id, (side, price, new_quantity));
+                }
+                BookDelta::Remove { order_id, .. } => {
+                    local.remove(&order_id);
+                }
+            }
+        }
+
+        assert_eq!(local_seq, book.seq);
+        let local_checksum = book_checksum(local.into_iter().map(|(id, (side, price, qty))| (id, side, price, qty)));
+        assert_eq!(local_checksum, book.checksum());
+        assert_ne!(book.checksum(), OrderBook::new().checksum());
+    }
+
+    fn grouped_order(id: OrderId, side: Side, price: u64, quantity: u64, group_id: u64) -> Order {
+        let mut order = Order::new(id, side, price, quantity);
+        order.group_id = Some(group_id);
+        order
+    }
+
+    #[test]
+    fn test_oco_fill_cancels_other_leg() {
+        let mut book = OrderBook::new();
+        book.add_order(grouped_order(1, Side::Sell, 110, 10, 7));
+        book.add_order(grouped_order(2, Side::Sell, 120, 10, 7));
+        book.add_order(Order::new(3, Side::Sell, 130, 10));
+
+        // Partial fill of leg 1 is enough under the default policy
+        book.add_order(Order::new(4, Side::Buy, 110, 4));
+
+        let ask_ids: Vec<OrderId> = book.asks.iter().map(|o| o.id).collect();
+        assert_eq!(ask_ids, vec![1, 3]);
+        assert_eq!(book.asks.front().unwrap().quantity, 6);
+    }
+
+    #[test]
+    fn test_oco_full_fill_policy_ignores_partial_fills() {
+        let mut book = OrderBook::new();
+        book.oco_policy = OcoPolicy::FullFill;
+        book.add_order(grouped_order(1, Side::Sell, 110, 10, 7));
+        book.add_order(grouped_order(2, Side::Sell, 120, 10, 7));
+
+        book.add_order(Order::new(3, Side::Buy, 110, 4));
+        assert_eq!(book.asks.len(), 2, "partial fill must not cancel the other leg");
+
+        book.add_order(Order::new(4, Side::Buy, 110, 6));
+        assert!(book.asks.is_empty(), "full fill of leg 1 cancels leg 2");
+    }
+
+    #[test]
+    fn test_matching_queues_writes_for_the_caller() {
+        let mut book = OrderBook::new();
+        book.add_order(grouped_order(1, Side::Sell, 110, 10, 7));
+        book.add_order(grouped_order(2, Side::Sell, 120, 10, 7));
+        assert!(book.take_writes().is_empty(), "resting alone changes nothing the caller didn't");
+
+        let (_, fills) = book.add_order(Order::new(3, Side::Buy, 110, 4));
+        assert_eq!(book.take_writes(), vec![
+            BookWrite::Match { fill: fills[0].clone(), bid_left: 0, bid_status: OrderStatus::Filled, ask_left: 6, ask_status: OrderStatus::PartiallyFilled, bid_rested: false },
+            BookWrite::OcoCancel { group_id: 7, order_ids: vec![2] },
+        ]);
+        assert!(book.take_writes().is_empty());
+    }
+
+    fn account_order(id: OrderId, side: Side, price: u64, quantity: u64, account_id: u64, stp: bool) -> Order {
+        Order { account_id: Some(account_id), stp, ..Order::new(id, side, price, quantity) }
+    }
+
+    #[test]
+    fn test_self_trade_prevention_cancels_aggressor() {
+        let mut book = OrderBook::new();
+        book.add_order(account_order(1, Side::Sell, 100, 5, 8, false));
+        book.add_order(account_order(2, Side::Sell, 100, 10, 7, false));
+
+        // Trades with the other account first, then stops at its own order
+        book.add_order(account_order(3, Side::Buy, 101, 15, 7, true));
+        assert!(book.bids.is_empty());
+        assert_eq!(book.asks.iter().map(|o| (o.id, o.quantity)).collect::<Vec<_>>(), vec![(2, 10)]);
+        assert_eq!(book.totals.trade_count, 1);
+        let cancelled = book.take_events().into_iter().find_map(|event| match event {
+            BookEvent::OrderCancelled { order } => Some(order),
+            _ => None,
+        }).unwrap();
+        assert_eq!((cancelled.id, cancelled.quantity, cancelled.cancel_reason), (3, 10, Some(CancelReason::SelfTradePrevention)));
+
+        // Off by default: the same account trades with itself
+        book.add_order(account_order(4, Side::Buy, 100, 4, 7, false));
+        assert_eq!(book.asks.front().unwrap().quantity, 6);
+        assert_eq!(book.totals.trade_count, 2);
+
+        // A market sweep stopped the same way is reported cancelled, not filled
+        let market = Order { order_type: OrderType::Market, ..account_order(5, Side::Buy, 0, 20, 7, true) };
+        let (result, _) = book.execute_market(market);
+        assert_eq!((result.status, result.cancel_reason), (OrderStatus::Cancelled, Some(CancelReason::SelfTradePrevention)));
+        assert_eq!(book.asks.len(), 1);
+    }
+
+    #[test]
+    fn test_self_trade_prevention_stops_a_reprice_into_a_cross() {
+        let mut book = OrderBook::new();
+        book.add_order(account_order(1, Side::Sell, 101, 5, 7, false));
+        book.add_order(account_order(2, Side::Buy, 99, 5, 7, true));
+        book.take_writes();
+
+        // Requeued with a fresh priority, the repriced bid is the aggressor
+        book.reprice_order(2, 101, 5).unwrap();
+        assert!(book.rematch().is_empty());
+        assert!(book.bids.is_empty());
+        assert_eq!(book.asks.iter().map(|o| (o.id, o.quantity)).collect::<Vec<_>>(), vec![(1, 5)]);
+        assert_eq!(book.totals.trade_count, 0);
+        assert!(book.take_writes().contains(&BookWrite::SelfTradeCancel { order_id: 2 }));
+        let cancelled = book.take_events().into_iter().find_map(|event| match event {
+            BookEvent::OrderCancelled { order } => Some(order),
+            _ => None,
+        }).unwrap();
+        assert_eq!((cancelled.id, cancelled.cancel_reason), (2, Some(CancelReason::SelfTradePrevention)));
+    }
+
+    #[test]
+    fn test_post_only_buy_through_the_ask_is_rejected() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 5));
+        let checksum = book.checksum();
+
+        let (priority, fills) = book.add_order(Order { post_only: true, ..Order::new(2, Side::Buy, 101, 5) });
+        assert_eq!((priority, fills.len()), (0, 0));
+        assert_eq!(book.checksum(), checksum);
+        assert!(book.bids.is_empty() && book.take_writes().is_empty());
+        let (rejected, _) = book.simulate(Order { post_only: true, ..Order::new(2, Side::Buy, 100, 5) });
+        assert_eq!(rejected.status, OrderStatus::Rejected);
+
+        // One tick below the ask it rests as a maker
+        let (_, fills) = book.add_order(Order { post_only: true, ..Order::new(3, Side::Buy, 99, 5) });
+        assert!(fills.is_empty());
+        assert_eq!(book.best_bid(), Some(99));
+    }
+
+    #[test]
+    fn test_midpoint_match_price_rounds_to_the_tick() {
+        let mut book = OrderBook { match_price: MatchPrice::Midpoint, ..OrderBook::new() };
+        book.add_order(Order::new(1, Side::Sell, 100, 5));
+        let (_, fills) = book.add_order(Order::new(2, Side::Buy, 110, 2));
+        assert_eq!(fills[0].price, 105);
+
+        // 107.5 is half a tick from both 105 and 110; the maker's side wins
+        book.tick_size = TickSize::parse("5").unwrap();
+        let (_, fills) = book.add_order(Order::new(3, Side::Buy, 115, 1));
+        assert_eq!(fills[0].price, 105);
+        book.add_order(Order::new(4, Side::Buy, 115, 4));
+        let (_, fills) = book.add_order(Order::new(5, Side::Sell, 100, 1));
+        assert_eq!((fills[0].bid_id, fills[0].price), (4, 110));
+
+        // Market orders still take the maker's price
+        let (_, fills) = book.execute_market(Order { order_type: OrderType::Market, ..Order::new(6, Side::Sell, 0, 1) });
+        assert_eq!(fills[0].price, 115);
+    }
+
+    // 10, 20 and 30 resting at 100, in that time order
+    fn level_of_three(strategy: Arc<dyn MatchingStrategy>) -> OrderBook {
+        let mut book = OrderBook { strategy, ..OrderBook::new() };
+        for (id, quantity) in [(1, 10), (2, 20), (3, 30)] {
+            book.add_order(Order::new(id, Side::Sell, 100, quantity));
+        }
+        book
+    }
+
+    #[test]
+    fn test_price_time_fills_the_level_front_first() {
+        let mut book = level_of_three(Arc::new(PriceTime));
+        let (_, fills) = book.add_order(Order::new(4, Side::Buy, 100, 25));
+        let executions: Vec<(OrderId, u64)> = fills.iter().map(|fill| (fill.ask_id, fill.quantity)).collect();
+        assert_eq!(executions, vec![(1, 10), (2, 15)]);
+        assert_eq!(book.asks.iter().map(|o| (o.id, o.quantity)).collect::<Vec<_>>(), vec![(2, 5), (3, 30)]);
+    }
+
+    #[test]
+    fn test_pro_rata_splits_by_size_with_leftovers_in_time_order() {
+        let mut book = level_of_three(Arc::new(ProRata));
+        // 25 of 60 is 4.17, 8.33 and 12.5; the unit lost rounding down goes to the front
+        let (_, fills) = book.add_order(Order::new(4, Side::Buy, 100, 25));
+        let executions: Vec<(OrderId, u64)> = fills.iter().map(|fill| (fill.ask_id, fill.quantity)).collect();
+        assert_eq!(executions, vec![(1, 5), (2, 8), (3, 12)]);
+        assert_eq!(book.asks.iter().map(|o| (o.id, o.quantity)).collect::<Vec<_>>(), vec![(1, 5), (2, 12), (3, 18)]);
+
+        // More than the level holds takes all of it, and the rest goes on to rest
+        let (_, fills) = book.add_order(Order::new(5, Side::Buy, 100, 40));
+        assert_eq!(fills.iter().map(|fill| fill.quantity).sum::<u64>(), 35);
+        assert!(book.asks.is_empty());
+        assert_eq!(book.find_order(5).map(|o| o.quantity), Some(5));
+    }
+
+    #[test]
+    fn test_size_priority_fills_the_largest_order_first() {
+        let mut book = level_of_three(Arc::new(SizePriority));
+        // Order 4 arrives last but is the largest at the level
+        book.add_order(Order::new(4, Side::Sell, 100, 40));
+        let (_, fills) = book.add_order(Order::new(5, Side::Buy, 100, 50));
+        let executions: Vec<(OrderId, u64)> = fills.iter().map(|fill| (fill.ask_id, fill.quantity)).collect();
+        assert_eq!(executions, vec![(3, 10), (4, 40)]);
+        assert_eq!(book.asks.iter().map(|o| (o.id, o.quantity)).collect::<Vec<_>>(), vec![(1, 10), (2, 20), (3, 20)]);
+
+        // Equal sizes go in time order
+        let (_, fills) = book.add_order(Order::new(6, Side::Buy, 100, 30));
+        let executions: Vec<(OrderId, u64)> = fills.iter().map(|fill| (fill.ask_id, fill.quantity)).collect();
+        assert_eq!(executions, vec![(2, 20), (3, 10)]);
+    }
+
+    #[test]
+    fn test_departed_orders_are_reported_in_their_final_state() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 3));
+        book.add_order(Order::new(2, Side::Sell, 101, 5));
+        book.cancel_order(2, CancelReason::UserRequest);
+        // Sweeps its limit of 100 and has the rest cancelled, but is reported as entered
+        let (_, fills) = book.execute_market(Order { order_type: OrderType::Market, ..Order::new(3, Side::Buy, 0, 4) });
+        assert_eq!(fills.len(), 1);
+
+        let departed: Vec<(OrderId, OrderStatus, u64, u64)> = book.take_departed().iter().map(|o| (o.id, o.status.clone(), o.price, o.quantity)).collect();
+        assert_eq!(departed, vec![(2, OrderStatus::Cancelled, 101, 0), (1, OrderStatus::Filled, 100, 0), (3, OrderStatus::Cancelled, 0, 0)]);
+        assert!(book.take_departed().is_empty());
+    }
+
+    #[test]
+    fn test_iceberg_sweep_replenishes_behind_the_level() {
+        let mut book = OrderBook::new();
+        book.add_order(Order { display_qty: Some(10), ..Order::new(1, Side::Sell, 100, 50) });
+        book.add_order(Order::new(2, Side::Sell, 100, 20));
+        assert_eq!(book.levels(&Side::Sell)[0].total_quantity, 30);
+
+        // Sweeping the visible 30 takes the slice, then the order behind it;
+        // the next slice waits at the back rather than filling the rest
+        let (_, fills) = book.add_order(Order::new(3, Side::Buy, 100, 25));
+        let executions: Vec<(OrderId, u64)> = fills.iter().map(|fill| (fill.ask_id, fill.quantity)).collect();
+        assert_eq!(executions, vec![(1, 10), (2, 15)]);
+        assert_eq!(book.asks.iter().map(|o| (o.id, o.quantity)).collect::<Vec<_>>(), vec![(2, 5), (1, 40)]);
+        assert_eq!((book.top_of_book().ask_quantity, book.levels(&Side::Sell)[0].total_quantity), (15, 15));
+        assert!(book.take_writes().iter().any(|write| matches!(write, BookWrite::Replenished { order_id: 1, .. })));
+
+        // Alone at the level it keeps replenishing, and is still the maker
+        let (_, fills) = book.add_order(Order::new(4, Side::Buy, 105, 25));
+        let executions: Vec<(OrderId, u64, u64)> = fills.iter().map(|fill| (fill.ask_id, fill.price, fill.quantity)).collect();
+        assert_eq!(executions, vec![(2, 100, 5), (1, 100, 10), (1, 100, 10)]);
+        assert_eq!((book.find_order(1).unwrap().quantity, book.top_of_book().ask_quantity), (20, 10));
+    }
+
+    #[test]
+    fn test_min_qty_just_below_and_just_above_available() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 4));
+        book.add_order(Order::new(2, Side::Sell, 101, 3));
+        book.add_order(Order::new(3, Side::Sell, 103, 10));
+        let with_min = |id, quantity, min_qty| Order { min_qty: Some(min_qty), ..Order::new(id, Side::Buy, 101, quantity) };
+
+        // 7 available at or below 101: a minimum of 8 trades nothing
+        let (refused, _) = book.simulate(with_min(4, 10, 8));
+        assert_eq!((refused.status, refused.cancel_reason), (OrderStatus::Cancelled, Some(CancelReason::MinQuantity)));
+        let checksum = book.checksum();
+        let (priority, fills) = book.add_order(with_min(4, 10, 8));
+        assert_eq!((priority, fills.len(), book.checksum()), (0, 0, checksum));
+
+        // A minimum of 6 takes both levels and rests the remainder
+        let (_, fills) = book.add_order(with_min(5, 10, 6));
+        assert_eq!(fills.iter().map(|fill| fill.quantity).sum::<u64>(), 7);
+        assert_eq!(book.find_order(5).map(|o| o.quantity), Some(3));
+
+        // Nothing to cross: the minimum doesn't apply and it rests whole
+        let (_, fills) = book.add_order(Order { min_qty: Some(5), ..Order::new(6, Side::Buy, 99, 5) });
+        assert!(fills.is_empty() && book.find_order(6).is_some());
+    }
+
+    #[test]
+    fn test_fifo_with_mock_clock() {
+        let clock = Arc::new(MockClock::ticking(1_000, 10));
+        let mut book = OrderBook::new();
+        book.clock = clock.clone();
+        for id in 1..=3 {
+            book.add_order(Order::with_clock(id, Side::Sell, 100, 5, &*clock));
+        }
+        let queue: Vec<(OrderId, u128)> = book.asks.iter().map(|o| (o.id, o.timestamp)).collect();
+        assert_eq!(queue, vec![(1, 1_000), (2, 1_010), (3, 1_020)]);
+
+        // An increase requeues 1 behind 3, stamped by the book's clock
+        let modified = book.modify_order(1, 6).unwrap();
+        assert_eq!((modified.timestamp, modified.last_modified_at), (1_030, Some(1_030)));
+
+        let (_, fills) = book.add_order(Order::with_clock(4, Side::Buy, 100, 16, &*clock));
+        let takes: Vec<(OrderId, u64)> = fills.iter().map(|fill| (fill.ask_id, fill.quantity)).collect();
+        assert_eq!(takes, vec![(2, 5), (3, 5), (1, 6)]);
+    }
+
+    #[test]
+    fn test_best_ask_matches_first_regardless_of_arrival() {
+        let mut book = OrderBook::new();
+        for (id, price) in [(1, 105), (2, 101), (3, 103), (4, 101)] {
+            book.add_order(Order::new(id, Side::Sell, price, 5));
+        }
+        let queue: Vec<(OrderId, u64)> = book.asks.iter().map(|o| (o.id, o.price)).collect();
+        assert_eq!(queue, vec![(2, 101), (4, 101), (3, 103), (1, 105)]);
+
+        book.add_order(Order::new(5, Side::Buy, 105, 12));
+        let remaining: Vec<(OrderId, u64)> = book.asks.iter().map(|o| (o.id, o.quantity)).collect();
+        assert_eq!(remaining, vec![(3, 3), (1, 5)]);
+        assert!(book.bids.is_empty());
+    }
+
+    #[test]
+    fn test_exact_fill_then_partial_leaves_no_empty_orders() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 5));
+        book.add_order(Order::new(2, Side::Sell, 101, 5));
+
+        // Takes all of ask 1 and part of ask 2, and is itself filled exactly
+        book.add_order(Order::new(3, Side::Buy, 101, 8));
+        assert_eq!(book.totals.trade_count, 2);
+        assert!(book.bids.is_empty());
+        let asks: Vec<(OrderId, u64, OrderStatus)> = book.asks.iter().map(|o| (o.id, o.quantity, o.status.clone())).collect();
+        assert_eq!(asks, vec![(2, 2, OrderStatus::PartiallyFilled)]);
+        assert!(book.bids.iter().chain(book.asks.iter()).all(|o| o.quantity > 0));
+    }
+
+    #[test]
+    fn test_cancel_finds_order_through_level_index() {
+        let mut book = OrderBook::new();
+        for id in 1..=1_000 {
+            book.add_order(Order::new(id, Side::Sell, 100 + id % 10, 1));
+        }
+        assert_eq!(book.asks.len(), 1_000);
+        assert_eq!(book.asks.levels.len(), 10);
+
+        // Emptying a level removes it, and the touch moves to the next one
+        for id in (10..=1_000).step_by(10) {
+            assert_eq!(book.cancel_order(id, CancelReason::UserRequest).unwrap().price, 100);
+        }
+        assert!(book.find_order(10).is_none());
+        assert_eq!(book.asks.levels.len(), 9);
+        assert_eq!(book.asks.index.len(), 900);
+        assert_eq!((book.best_ask(), book.asks.front().map(|o| o.id)), (Some(101), Some(1)));
+
+        book.add_order(Order::new(1_001, Side::Buy, 101, 2));
+        let front: Vec<OrderId> = book.asks.iter().take(2).map(|o| o.id).collect();
+        assert_eq!(front, vec![21, 31]);
+        assert!(book.find_order(1).is_none() && book.find_order(11).is_none());
+    }
+
+    #[test]
+    fn test_best_bid_matches_first_regardless_of_arrival() {
+        let mut book = OrderBook::new();
+        for (id, price) in [(1, 100), (2, 105), (3, 102), (4, 105)] {
+            book.add_order(Order::new(id, Side::Buy, price, 5));
+        }
+        let queue: Vec<OrderId> = book.bids.iter().map(|o| o.id).collect();
+        assert_eq!(queue, vec![2, 4, 3, 1]);
+
+        book.add_order(Order::new(5, Side::Sell, 101, 7));
+        let remaining: Vec<(OrderId, u64)> = book.bids.iter().map(|o| (o.id, o.quantity)).collect();
+        assert_eq!(remaining, vec![(4, 3), (3, 5), (1, 5)]);
+        assert_eq!(book.best_bid(), Some(105));
+    }
+
+    #[test]
+    fn test_corrupt_order_aborts_match() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 10));
+        // Bypass add_order so the bad state lands in the book untouched
+        let mut corrupt = Order::new(2, Side::Buy, 100, 0);
+        corrupt.status = OrderStatus::Filled;
+        book.bids.push_back(corrupt);
+
+        book.try_match();
+        assert_eq!(book.totals.trade_count, 0);
+        assert_eq!(book.bids.len(), 1);
+        assert_eq!(book.asks.front().unwrap().quantity, 10);
+        assert_eq!(book.asks.front().unwrap().status, OrderStatus::Open);
+    }
+
+    #[test]
+    fn test_top_of_book_aggregates_best_level() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 99, 5));
+        book.add_order(Order::new(2, Side::Buy, 100, 3));
+        book.add_order(Order::new(3, Side::Buy, 100, 4));
+        let quote = book.top_of_book();
+        assert_eq!((quote.bid_price, quote.bid_quantity), (100, 7));
+        assert_eq!((quote.ask_price, quote.ask_quantity), (0, 0));
+        assert_eq!(quote.seq, book.seq);
+    }
+
+    #[test]
+    fn test_touch_cache_matches_fresh_scan() {
+        let mut book = OrderBook::new();
+        // Small deterministic LCG so the sequence is reproducible
+        let mut rng_state: u64 = 0x2545F4914F6CDD1D;
+        let mut next = |bound: u64| {
+            rng_state = rng_state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (rng_state >> 33) % bound
+        };
+
+        for id in 1..=2_000u64 {
+            match next(4) {
+                0 | 1 => {
+                    let side = if next(2) == 0 { Side::Buy } else { Side::Sell };
+                    book.add_order(Order::new(id, side, 95 + next(10), 1 + next(20)));
+                }
+                2 => {
+                    book.modify_order(next(id), next(25));
+                }
+                _ => {
+                    book.cancel_order(next(id), CancelReason::UserRequest);
+                }
+            }
+            assert_eq!(book.touch.bid, book.scan_touch(&Side::Buy), "bid touch diverged after op {}", id);
+            assert_eq!(book.touch.ask, book.scan_touch(&Side::Sell), "ask touch diverged after op {}", id);
+        }
+    }
+
+    fn auction_book() -> OrderBook {
+        let mut book = OrderBook::new();
+        book.auction_only = true;
+        let orders = [
+            Order::new(1, Side::Buy, 102, 10),
+            Order::new(2, Side::Buy, 101, 5),
+            Order::new(3, Side::Buy, 100, 10),
+            Order::new(4, Side::Sell, 99, 5),
+            Order::new(5, Side::Sell, 100, 10),
+            Order::new(6, Side::Sell, 102, 10),
+        ];
+        for order in orders {
+            book.add_order(order);
+        }
+        book
+    }
+
+    #[test]
+    fn test_auction_only_book_does_not_match_continuously() {
+        let book = auction_book();
+        assert_eq!(book.bids.len(), 3);
+        assert_eq!(book.asks.len(), 3);
+        assert_eq!(book.totals.trade_count, 0);
+    }
+
+    #[test]
+    fn test_auction_uniform_clearing_price() {
+        let mut book = auction_book();
+        book.clock = Arc::new(MockClock::new(7));
+
+        // Volume is 15 at both 100 and 101; 101 leaves no imbalance (15 vs 15)
+        assert_eq!(book.clearing_price(), Some((101, 15)));
+
+        let result = book.run_auction();
+        assert_eq!(result.clearing_price, Some(101));
+        assert_eq!(result.volume, 15);
+        let fill = |trade_id, bid_id, ask_id, quantity| Fill { trade_id, trade_seq: trade_id, executed_at: 7, symbol: default_symbol(), bid_id, ask_id, price: 101, quantity };
+        assert_eq!(result.fills, vec![fill(1, 1, 4, 5), fill(2, 1, 5, 5), fill(3, 2, 5, 5)]);
+
+        let bid_ids: Vec<OrderId> = book.bids.iter().map(|o| o.id).collect();
+        let ask_ids: Vec<OrderId> = book.asks.iter().map(|o| o.id).collect();
+        assert_eq!(bid_ids, vec![3]);
+        assert_eq!(ask_ids, vec![6]);
+        assert_eq!(book.best_bid(), Some(100));
+        assert_eq!(book.best_ask(), Some(102));
+
+        // Nothing left to cross
+        assert_eq!(book.run_auction(), AuctionResult::default());
+    }
+
+    #[test]
+    fn test_clearing_price_sums_past_u64() {
+        let mut book = OrderBook::new();
+        book.auction_only = true;
+        book.add_order(Order::new(1, Side::Buy, 101, u64::MAX));
+        book.add_order(Order::new(2, Side::Buy, 100, u64::MAX));
+        book.add_order(Order::new(3, Side::Sell, 100, u64::MAX));
+        book.add_order(Order::new(4, Side::Sell, 99, u64::MAX));
+
+        // Demand and supply at 100 are both 2 * u64::MAX; the volume reported saturates
+        assert_eq!(book.clearing_price(), Some((100, u64::MAX)));
+    }
+
+    #[test]
+    fn test_split_rounds_to_tick_rescales_icebergs_and_cancels_overflow() {
+        let mut book = OrderBook::new();
+        book.tick_size = TickSize::parse("5").unwrap();
+        book.add_order(Order { display_qty: Some(3), slice_left: 3, ..Order::new(1, Side::Buy, 100, 9) });
+        book.add_order(Order::new(2, Side::Sell, 105, 9));
+        book.add_order(Order::new(3, Side::Sell, 200, u64::MAX / 2));
+
+        let adjustments = book.apply_corporate_action(&CorporateAction::Split { to: 3, from: 1 });
+        let outcomes: Vec<(OrderId, AdjustmentOutcome, u64)> = adjustments.iter().map(|a| (a.order_id, a.outcome, a.new_price)).collect();
+        // 100/3 rounds down to 30, 105/3 = 35 is already on a tick
+        assert_eq!(outcomes, vec![(1, AdjustmentOutcome::Adjusted, 30), (2, AdjustmentOutcome::Adjusted, 35), (3, AdjustmentOutcome::Cancelled, 200)]);
+
+        let iceberg = book.bids.front().unwrap();
+        assert_eq!((iceberg.quantity, iceberg.display_qty, iceberg.visible_quantity()), (27, Some(9), 9));
+        assert_eq!(adjustments[0].new_display_qty, Some(9));
+        assert_eq!(book.asks.iter().map(|o| o.id).collect::<Vec<_>>(), vec![2]);
+
+        // 30 * 4/3 = 40 for the bid; 35 * 4/3 rounds up to 47, then up onto the grid at 50
+        book.apply_corporate_action(&CorporateAction::Split { to: 3, from: 4 });
+        assert_eq!((book.best_bid(), book.best_ask()), (Some(40), Some(50)));
+    }
+
+    #[test]
+    fn test_level_and_fillable_quantities_saturate() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, u64::MAX));
+        book.add_order(Order::new(2, Side::Sell, 100, 5));
+        book.add_order(Order::new(3, Side::Sell, 101, 5));
+
+        let taker = Order { time_in_force: TimeInForce::Fok, ..Order::new(4, Side::Buy, 101, u64::MAX) };
+        assert_eq!(book.fillable_quantity(&taker), u64::MAX);
+        assert_eq!(book.levels(&Side::Sell)[0].total_quantity, u64::MAX);
+        assert_eq!(book.top_of_book().ask_quantity, u64::MAX);
+
+        // Leaving the saturated level rescans it rather than subtracting from the cap
+        book.cancel_order(1, CancelReason::UserRequest);
+        assert_eq!(book.top_of_book().ask_quantity, 5);
+    }
+
+    #[test]
+    fn test_auction_self_trade_prevention_cancels_later_order() {
+        let mut book = OrderBook::new();
+        book.auction_only = true;
+        book.add_order(account_order(1, Side::Sell, 100, 5, 7, false));
+        book.add_order(account_order(2, Side::Buy, 100, 5, 7, true));
+        book.add_order(account_order(3, Side::Buy, 100, 5, 8, false));
+        book.take_writes();
+
+        let result = book.run_auction();
+        assert_eq!(result.fills.iter().map(|f| (f.bid_id, f.ask_id, f.quantity)).collect::<Vec<_>>(), vec![(3, 1, 5)]);
+        assert_eq!(result.volume, 5);
+        assert!(book.asks.is_empty() && book.bids.is_empty());
+        assert!(book.take_writes().contains(&BookWrite::SelfTradeCancel { order_id: 2 }));
+    }
+
+    #[test]
+    fn test_trade_totals_exceed_u64_without_overflow() {
+        let mut totals = TradeTotals::default();
+        let price = u64::MAX / 2;
+        totals.record(price, 3);
+        totals.record(price, u64::MAX);
+
+        let expected = price as u128 * 3 + price as u128 * u64::MAX as u128;
+        assert_eq!(totals.notional, expected);
+        assert!(totals.notional > u64::MAX as u128);
+        assert_eq!(totals.volume, 3 + u64::MAX as u128);
+        assert_eq!(totals.trade_count, 2);
+        assert!(!totals.saturated);
+    }
+
+    #[test]
+    fn test_trade_totals_saturate_instead_of_wrapping() {
+        let mut totals = TradeTotals { notional: u128::MAX - 1, ..TradeTotals::default() };
+        totals.record(10, 10);
+        assert_eq!(totals.notional, u128::MAX);
+        assert!(totals.saturated);
+    }
+
+    #[test]
+    fn test_matching_accumulates_totals() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 5));
+        book.add_order(Order::new(2, Side::Sell, 101, 5));
+        book.add_order(Order::new(3, Side::Buy, 101, 8));
+        assert_eq!(book.totals.trade_count, 2);
+        assert_eq!(book.totals.volume, 8);
+        assert_eq!(book.totals.notional, 100 * 5 + 101 * 3);
+    }
+}
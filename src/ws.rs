@@ -0,0 +1,258 @@
+// Just enough of RFC 6455 for the /ws feed: the upgrade handshake's accept
+// key and a message codec. The server sends unmasked frames and requires
+// masked ones; the tests' client does the reverse. No extensions.
+
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// Appended to the client's Sec-WebSocket-Key before hashing
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// Largest message a peer may send, after reassembling its fragments
+pub const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+// Close codes we send
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    // With the peer's close code, if it sent one
+    Close(Option<u16>),
+}
+
+// Sec-WebSocket-Accept for a client's Sec-WebSocket-Key
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), ACCEPT_GUID).as_bytes()))
+}
+
+fn protocol_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Reads whole messages, reassembling fragments. Not cancel safe: a read
+// dropped part way loses its bytes, so give it a task of its own.
+pub struct Reader<R> {
+    inner: R,
+    // Whether the peer must mask its frames (true when reading a client)
+    masked: bool,
+    // Opcode and payload so far of a fragmented message
+    partial: Option<(u8, Vec<u8>)>,
+}
+
+impl<R: AsyncRead + Unpin> Reader<R> {
+    pub fn new(inner: R, masked: bool) -> Self {
+        Reader { inner, masked, partial: None }
+    }
+
+    pub async fn next(&mut self) -> io::Result<Message> {
+        loop {
+            let (fin, opcode, payload) = self.read_frame().await?;
+            match opcode {
+                OP_CLOSE | OP_PING | OP_PONG => {
+                    if !fin {
+                        return Err(protocol_error("fragmented control frame"));
+                    }
+                    return Ok(match opcode {
+                        OP_CLOSE => Message::Close(payload.get(..2).map(|code| u16::from_be_bytes([code[0], code[1]]))),
+                        OP_PING => Message::Ping(payload),
+                        _ => Message::Pong(payload),
+                    });
+                }
+                OP_TEXT | OP_BINARY if self.partial.is_none() => self.partial = Some((opcode, payload)),
+                OP_CONTINUATION if self.partial.is_some() => {
+                    let (_, buffered) = self.partial.as_mut().expect("partial message checked above");
+                    if buffered.len() + payload.len() > MAX_MESSAGE_LEN {
+                        return Err(protocol_error("message too long"));
+                    }
+                    buffered.extend_from_slice(&payload);
+                }
+                _ => return Err(protocol_error("unexpected opcode")),
+            }
+            if fin {
+                let (opcode, payload) = self.partial.take().expect("message started above");
+                return if opcode == OP_TEXT {
+                    String::from_utf8(payload).map(Message::Text).map_err(|_| protocol_error("text message is not UTF-8"))
+                } else {
+                    Ok(Message::Binary(payload))
+                };
+            }
+        }
+    }
+
+    async fn read_frame(&mut self) -> io::Result<(bool, u8, Vec<u8>)> {
+        let mut head = [0u8; 2];
+        self.inner.read_exact(&mut head).await?;
+        if head[0] & 0x70 != 0 {
+            return Err(protocol_error("reserved bits set"));
+        }
+        if (head[1] & 0x80 != 0) != self.masked {
+            return Err(protocol_error(if self.masked { "client frame not masked" } else { "server frame masked" }));
+        }
+        let len = match head[1] & 0x7F {
+            126 => u64::from(self.inner.read_u16().await?),
+            127 => self.inner.read_u64().await?,
+            len => u64::from(len),
+        };
+        if len > MAX_MESSAGE_LEN as u64 {
+            return Err(protocol_error("message too long"));
+        }
+        let mut mask = [0u8; 4];
+        if self.masked {
+            self.inner.read_exact(&mut mask).await?;
+        }
+        let mut payload = vec![0u8; len as usize];
+        self.inner.read_exact(&mut payload).await?;
+        if self.masked {
+            apply_mask(&mut payload, mask);
+        }
+        Ok((head[0] & 0x80 != 0, head[0] & 0x0F, payload))
+    }
+}
+
+fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+// Writes `message` as a single frame, masked with `mask` when given
+pub async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Message, mask: Option<[u8; 4]>) -> io::Result<()> {
+    let (opcode, mut payload) = match message {
+        Message::Text(text) => (OP_TEXT, text.as_bytes().to_vec()),
+        Message::Binary(data) => (OP_BINARY, data.clone()),
+        Message::Ping(data) => (OP_PING, data.clone()),
+        Message::Pong(data) => (OP_PONG, data.clone()),
+        Message::Close(code) => (OP_CLOSE, code.map(|code| code.to_be_bytes().to_vec()).unwrap_or_default()),
+    };
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    let mut frame = Vec::with_capacity(payload.len() + 14);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        len if len < 126 => frame.push(mask_bit | len as u8),
+        len if len <= usize::from(u16::MAX) => {
+            frame.push(mask_bit | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(mask_bit | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    if let Some(mask) = mask {
+        frame.extend_from_slice(&mask);
+        apply_mask(&mut payload, mask);
+    }
+    frame.extend_from_slice(&payload);
+    writer.write_all(&frame).await?;
+    writer.flush().await
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| group | u32::from(byte) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(group >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+// FIPS 180-1. Only ever hashes handshake keys, so simple beats fast here.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64).wrapping_mul(8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut words = [0u32; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let next = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = next;
+        }
+        for (total, value) in state.iter_mut().zip([a, b, c, d, e]) {
+            *total = total.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_key_matches_rfc_example() {
+        // RFC 6455 section 1.3
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"a"), "YQ==");
+    }
+
+    #[tokio::test]
+    async fn test_masked_fragmented_message_round_trips() {
+        let mut wire = Vec::new();
+        let long = "x".repeat(300);
+        write_message(&mut wire, &Message::Text(long.clone()), Some([1, 2, 3, 4])).await.unwrap();
+        write_message(&mut wire, &Message::Ping(b"hi".to_vec()), Some([9, 9, 9, 9])).await.unwrap();
+        // "ab" sent as two fragments with a pong in between
+        wire.extend_from_slice(&[0x01, 0x81, 0, 0, 0, 0, b'a']);
+        wire.extend_from_slice(&[0x8A, 0x80, 0, 0, 0, 0]);
+        wire.extend_from_slice(&[0x80, 0x81, 0, 0, 0, 0, b'b']);
+
+        let mut reader = Reader::new(wire.as_slice(), true);
+        assert_eq!(reader.next().await.unwrap(), Message::Text(long));
+        assert_eq!(reader.next().await.unwrap(), Message::Ping(b"hi".to_vec()));
+        assert_eq!(reader.next().await.unwrap(), Message::Pong(Vec::new()));
+        assert_eq!(reader.next().await.unwrap(), Message::Text("ab".to_string()));
+
+        // A server must refuse unmasked client frames
+        let mut unmasked = Vec::new();
+        write_message(&mut unmasked, &Message::Text("hi".to_string()), None).await.unwrap();
+        assert_eq!(Reader::new(unmasked.as_slice(), true).next().await.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,54 @@
+// End-to-end matching through the library's public API alone: no DB, no HTTP.
+
+use low_latency_oms::{BookWrite, CancelReason, Order, OrderBook, OrderStatus, OrderType, Side};
+
+// A few asks over three levels, best first: 5 @ 100, 4 + 3 @ 101, 10 @ 103
+fn ladder() -> OrderBook {
+    let mut book = OrderBook::new();
+    for (id, price, quantity) in [(1, 100, 5), (2, 101, 4), (3, 101, 3), (4, 103, 10)] {
+        let (_, fills) = book.add_order(Order::new(id, Side::Sell, price, quantity));
+        assert!(fills.is_empty());
+    }
+    book
+}
+
+#[test]
+fn limit_sweep_fills_in_price_time_order_and_rests_the_remainder() {
+    let mut book = ladder();
+    let (_, fills) = book.add_order(Order::new(5, Side::Buy, 102, 15));
+
+    let executions: Vec<(u64, u64, u64)> = fills.iter().map(|fill| (fill.ask_id, fill.price, fill.quantity)).collect();
+    assert_eq!(executions, vec![(1, 100, 5), (2, 101, 4), (3, 101, 3)]);
+    assert!(fills.iter().all(|fill| fill.bid_id == 5));
+
+    // 3 left over rests as the new best bid; the untouched level stays best ask
+    assert_eq!((book.best_bid(), book.best_ask()), (Some(102), Some(103)));
+    let rest = book.find_order(5).unwrap();
+    assert_eq!((rest.quantity, rest.status.clone()), (3, OrderStatus::PartiallyFilled));
+
+    // Every fill is handed back for the caller to persist
+    let writes = book.take_writes();
+    assert_eq!(writes.len(), 3);
+    assert!(writes.iter().zip(&fills).all(|(write, fill)| matches!(write, BookWrite::Match { fill: written, .. } if written == fill)));
+}
+
+#[test]
+fn market_order_takes_the_book_and_cancels_what_is_left() {
+    let mut book = ladder();
+    let market = Order { order_type: OrderType::Market, ..Order::new(5, Side::Buy, 0, 30) };
+    let (result, fills) = book.execute_market(market);
+
+    assert_eq!(fills.iter().map(|fill| fill.quantity).sum::<u64>(), 22);
+    assert_eq!((result.status, result.cancel_reason), (OrderStatus::Cancelled, Some(CancelReason::MarketRemainder)));
+    assert_eq!((book.best_bid(), book.best_ask()), (None, None));
+}
+
+#[test]
+fn cancelled_order_no_longer_trades() {
+    let mut book = ladder();
+    assert!(book.cancel_order(1, CancelReason::UserRequest).is_some());
+
+    let (_, fills) = book.add_order(Order::new(5, Side::Buy, 101, 2));
+    assert_eq!(fills.len(), 1);
+    assert_eq!((fills[0].ask_id, fills[0].price), (2, 101));
+}